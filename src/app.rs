@@ -1,12 +1,13 @@
 
-use crate::commands::handle_command;
+use crate::commands::{handle_command, CommandState};
 use crate::nm_config::{WorkflowConfig, save_all_nm, AgentType, AgentRow};
 use crate::runner::{AppCommand, AppEvent};
 use crate::create_ui;
 use crate::workflow_ui;
+use crate::model_picker::{self, ModelPicker};
 use ratatui::text::{Line, Span};
 use ratatui::style::{Style, Color, Modifier};
-use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
 use ratatui::layout::{Layout, Constraint, Position, Rect};
 use ratatui::Frame;
 use std::collections::HashMap;
@@ -16,12 +17,84 @@ use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use unicode_segmentation::UnicodeSegmentation;
 use std::collections::VecDeque;
-use crossterm::event::Event::Key;
+use chrono::{DateTime, Utc};
+use crossterm::event::Event::{Key, Mouse};
 
 
+/// Braille frames cycled by the run spinner
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Scroll offset meaning "stick to the newest line", clamped at render time
+pub const SCROLL_TO_BOTTOM: u16 = u16::MAX;
+
+/// Auto-save interval when `--autosave-secs` isn't given
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Smallest terminal the chat layout fits in (8-row input plus a few message rows)
+const MIN_WIDTH: u16 = 20;
+const MIN_HEIGHT: u16 = 12;
+/// The create form lists every field, so it needs more room
+const MIN_CREATE_WIDTH: u16 = 40;
+const MIN_CREATE_HEIGHT: u16 = 12;
+
+/// Two clicks on the same workflow within this window confirm it, like Enter
+const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+
 pub struct ChatMessage {
     pub from: &'static str,
     pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub agent: Option<String>, // producing agent's name, shown instead of `from` when set
+}
+
+impl ChatMessage {
+    pub fn new(from: &'static str, text: String) -> Self {
+        Self { from, text, timestamp: Utc::now(), agent: None }
+    }
+
+    /// A message produced by the named agent
+    pub fn from_agent(from: &'static str, agent: String, text: String) -> Self {
+        Self { agent: Some(agent), ..Self::new(from, text) }
+    }
+
+    /// `[14:03:22] ` prefix shown when timestamps are on; UTC to line up with the trace log
+    pub fn time_prefix(&self) -> String {
+        format!("[{}] ", self.timestamp.format("%H:%M:%S"))
+    }
+}
+
+/// First line of a fresh (or cleared) chat
+pub fn welcome_message() -> ChatMessage {
+    ChatMessage::new(
+        "system",
+        "Welcome to Neonmachines! Type your message or use /help for commands.".to_string(),
+    )
+}
+
+/// Empty the chat down to the welcome line, keeping the old messages for one undo
+pub fn clear_messages(messages: &mut Vec<ChatMessage>, cleared: &mut Vec<ChatMessage>) {
+    *cleared = std::mem::replace(messages, vec![welcome_message()]);
+}
+
+/// Workflow snapshots kept for `/undo`
+pub const MAX_UNDO_DEPTH: usize = 20;
+
+/// Remember the workflow stored under `key` before an edit, dropping the oldest
+/// snapshot once there are more than MAX_UNDO_DEPTH
+pub fn push_undo(history: &mut Vec<(String, WorkflowConfig)>, key: &str, cfg: &WorkflowConfig) {
+    history.push((key.to_string(), cfg.clone()));
+    if history.len() > MAX_UNDO_DEPTH {
+        history.remove(0);
+    }
+}
+
+/// Put the latest snapshot back, returning the restored workflow's name, or `None`
+/// when there is nothing to undo
+pub fn undo_edit(history: &mut Vec<(String, WorkflowConfig)>, workflows: &mut HashMap<String, WorkflowConfig>) -> Option<String> {
+    let (key, cfg) = history.pop()?;
+    let name = cfg.name.clone();
+    workflows.insert(key, cfg);
+    Some(name)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,23 +105,34 @@ pub enum Mode {
     Options,
     Dashboard,
     InteractiveChat,
+    ModelPicker,
 }
 
 pub struct App {
     pub mode: Mode,
     pub messages: Vec<ChatMessage>,
+    pub cleared_messages: Vec<ChatMessage>, // last cleared chat, restored by `/clear undo`
+    pub show_timestamps: bool,              // prefix messages with their time, toggled by `/timestamps`
+    pub show_reasoning: bool,               // expand model reasoning instead of one line, toggled by `/reasoning`
+    pub role_styles: crate::theme::RoleStyles, // message colors by role or agent name, from the theme file
     pub input: String,
     pub cursor_g: usize,
-    pub messages_scroll: u16,
+    pub messages_scroll: u16,   // top visible row of the wrapped message view
+    pub messages_max_scroll: u16, // largest useful scroll offset from the last render
+    pub message_rows: Vec<u16>,   // first wrapped row of each message in the last render, for agent jumps
     pub is_running: bool,
     pub spinner_status: String,
+    pub progress: Option<(usize, usize)>, // traversals done and expected in the current run, shown as a gauge
     pub last_spinner_tick: Instant,
+    pub spinner_frame: usize,
     pub tx: UnboundedSender<AppCommand>,
     pub rx: UnboundedReceiver<AppEvent>,
     pub workflows: HashMap<String, WorkflowConfig>,
     pub active_workflow: String,
     pub workflow_list: Vec<String>,
     pub workflow_index: usize,
+    pub workflow_area: Rect,            // where the workflow list was last drawn, for mouse hit-testing
+    pub last_workflow_click: Option<(usize, Instant)>,
     pub create_focus: usize,
     pub create_input: String,
     pub options_input: String,
@@ -61,6 +145,15 @@ pub struct App {
     pub history_index: usize,
     pub saved_input: String,
     pub variables: HashMap<String, String>, // Store user-defined variables
+    pub autosave_interval: Option<Duration>, // how often edited workflows are saved, `None` never
+    pub last_autosave: Instant,
+    saved_snapshot: String, // `.nm` text of the workflows as last saved or loaded
+    autosave_failing: bool, // the last auto-save failed and was already reported
+    pub pending_edit: Option<std::path::PathBuf>, // file to open in $EDITOR once the main loop can suspend the TUI
+    pub model_picker: ModelPicker, // models listed by `/model`, with the highlighted one
+    model_list: Vec<String>,       // models offered by the picker, gathered once per session
+    pub edit_history: Vec<(String, WorkflowConfig)>, // workflows as they were before each edit, restored by `/undo`
+    undo_field: Option<usize>,     // Create mode field whose keystrokes the latest snapshot covers
 }
 
 impl App {
@@ -76,22 +169,29 @@ impl App {
         
         Self {
             mode: Mode::Chat,
-            messages: vec![ChatMessage {
-                from: "system",
-                text: "Welcome to Neonmachines! Type your message or use /help for commands.".to_string(),
-            }],
+            messages: vec![welcome_message()],
+            cleared_messages: Vec::new(),
+            show_timestamps: false,
+            show_reasoning: false,
+            role_styles: crate::theme::RoleStyles::default(),
             input: String::new(),
             cursor_g: 0,
             messages_scroll: 0,
+            messages_max_scroll: 0,
+            message_rows: Vec::new(),
             is_running: true,
             spinner_status: String::new(),
+            progress: None,
             last_spinner_tick: Instant::now(),
+            spinner_frame: 0,
             tx,
             rx,
             workflows,
             active_workflow,
             workflow_list,
             workflow_index,
+            workflow_area: Rect::default(),
+            last_workflow_click: None,
             create_focus: 0,
             create_input: String::new(),
             options_input: String::new(),
@@ -104,26 +204,99 @@ impl App {
             history_index: 0,
             saved_input: String::new(),
             variables: HashMap::new(), // Initialize empty variables map
+            autosave_interval: Some(DEFAULT_AUTOSAVE_INTERVAL),
+            last_autosave: Instant::now(),
+            saved_snapshot: String::new(),
+            autosave_failing: false,
+            pending_edit: None,
+            model_picker: ModelPicker::default(),
+            model_list: Vec::new(),
+            edit_history: Vec::new(),
+            undo_field: None,
+        }
+        .with_saved_snapshot()
+    }
+
+    fn with_saved_snapshot(mut self) -> Self {
+        self.saved_snapshot = self.workflows_snapshot();
+        self
+    }
+
+    fn sorted_workflows(&self) -> Vec<WorkflowConfig> {
+        let mut all: Vec<WorkflowConfig> = self.workflows.values().cloned().collect();
+        all.sort_by(|a, b| a.name.cmp(&b.name));
+        all
+    }
+
+    /// Workflows in name order as `.nm` text, so equal sets compare equal
+    fn workflows_snapshot(&self) -> String {
+        crate::nm_config::render_all_nm(&self.sorted_workflows())
+    }
+
+    /// Save the workflows once `autosave_interval` has passed, if they changed since
+    /// the last save. Called every tick of the main loop.
+    pub fn autosave_if_due(&mut self) {
+        self.autosave_with(save_all_nm);
+    }
+
+    fn autosave_with<F: FnOnce(&[WorkflowConfig]) -> std::io::Result<()>>(&mut self, save: F) -> bool {
+        let Some(interval) = self.autosave_interval else {
+            return false;
+        };
+        if self.last_autosave.elapsed() < interval {
+            return false;
+        }
+        self.last_autosave = Instant::now();
+        let snapshot = self.workflows_snapshot();
+        if snapshot == self.saved_snapshot {
+            return false;
+        }
+        let all = self.sorted_workflows();
+        match save(&all) {
+            Ok(()) => {
+                tracing::debug!("Auto-saved {} workflows", all.len());
+                self.saved_snapshot = snapshot;
+                self.autosave_failing = false;
+                true
+            }
+            Err(e) => {
+                tracing::warn!("Auto-save failed: {}", e);
+                // Retried every interval, but only the first failure in a row is shown
+                if !self.autosave_failing {
+                    self.add_message("warning", format!("Auto-save failed: {}. Edits stay unsaved until /save or the next auto-save succeeds.", e));
+                }
+                self.autosave_failing = true;
+                false
+            }
         }
     }
 
     /// ✅ Save all workflows on exit
-    pub fn persist_on_exit(&self) {
+    pub async fn persist_on_exit(&self) {
         let all: Vec<WorkflowConfig> = self.workflows.values().cloned().collect();
         let _ = save_all_nm(&all);
         
         // Save command history
         let _ = self.save_history_to_file();
+
+        // Save historical metrics
+        if let Some(metrics) = &self.metrics_collector {
+            let _ = metrics.lock().await.snapshot_and_save().await;
+        }
     }
 
     pub fn tick_spinner(&mut self) {
-        if self.is_running && self.last_spinner_tick.elapsed() >= Duration::from_millis(120) {
+        if self.is_running
+            && !self.spinner_status.is_empty()
+            && self.last_spinner_tick.elapsed() >= Duration::from_millis(120)
+        {
             self.last_spinner_tick = Instant::now();
+            self.spinner_frame = (self.spinner_frame + 1) % SPINNER_FRAMES.len();
         }
     }
 
     pub fn on_event(&mut self, ev: crossterm::event::Event) -> bool {
-        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
         
         // Handle key events immediately without blocking
         match ev {
@@ -132,13 +305,46 @@ impl App {
                 return true;
             }
             Key(KeyEvent { code: KeyCode::Char('l'), modifiers: KeyModifiers::CONTROL, .. }) => {
-                // Clear screen with Ctrl+L
+                // Clear messages with Ctrl+L (undo with /clear undo)
+                clear_messages(&mut self.messages, &mut self.cleared_messages);
                 self.messages_scroll = 0;
             }
             Key(KeyEvent { code: KeyCode::Char('d'), modifiers: KeyModifiers::CONTROL, .. }) => {
                 // Ctrl+D to quit (alternative to Ctrl+C)
                 return true;
             }
+            Key(KeyEvent { code: KeyCode::Char('u'), modifiers: KeyModifiers::CONTROL, .. }) if self.edits_main_input() => {
+                // Ctrl+U deletes to start of line
+                self.kill_to_line_start();
+            }
+            Key(KeyEvent { code: KeyCode::Char('k'), modifiers: KeyModifiers::CONTROL, .. }) if self.edits_main_input() => {
+                // Ctrl+K deletes to end of line
+                self.kill_to_line_end();
+            }
+            Key(KeyEvent { code: KeyCode::Char('w'), modifiers: KeyModifiers::CONTROL, .. }) if self.edits_main_input() => {
+                // Ctrl+W deletes the previous word
+                self.delete_prev_word();
+            }
+            Key(KeyEvent { code: KeyCode::Char('a'), modifiers: KeyModifiers::CONTROL, .. }) if self.edits_main_input() => {
+                // Ctrl+A jumps to start of line
+                self.cursor_g = self.line_start_g();
+            }
+            Key(KeyEvent { code: KeyCode::Char('e'), modifiers: KeyModifiers::CONTROL, .. }) if self.edits_main_input() => {
+                // Ctrl+E jumps to end of line
+                self.cursor_g = self.line_end_g();
+            }
+            Key(KeyEvent { code: KeyCode::Char('o'), modifiers: KeyModifiers::CONTROL, .. }) if self.mode == Mode::Create => {
+                // Ctrl+O opens the focused agent's first POML file in $EDITOR
+                self.edit_focused_poml();
+            }
+            Key(KeyEvent { code: KeyCode::Char('p'), modifiers: KeyModifiers::CONTROL, .. }) if self.mode == Mode::Chat => {
+                // Ctrl+P scrolls to the previous agent output
+                self.jump_to_agent_message(false);
+            }
+            Key(KeyEvent { code: KeyCode::Char('n'), modifiers: KeyModifiers::CONTROL, .. }) if self.mode == Mode::Chat => {
+                // Ctrl+N scrolls to the next agent output
+                self.jump_to_agent_message(true);
+            }
             Key(KeyEvent { code: KeyCode::Char(c), .. }) => {
                 // Handle character input based on mode
                 match self.mode {
@@ -148,7 +354,7 @@ impl App {
                             self.handle_create_input(c);
                         }
                     }
-                    Mode::Workflow => {
+                    Mode::Workflow | Mode::ModelPicker => {
                         // Handle workflow mode input - ignore character input, only handle navigation
                     }
                     Mode::Options => {
@@ -171,13 +377,10 @@ impl App {
                         self.handle_create_submit();
                     }
                     Mode::Workflow => {
-                        // Select the current workflow and exit workflow mode
-                        if self.workflow_index < self.workflow_list.len() {
-                            let selected_workflow = self.workflow_list[self.workflow_index].clone();
-                            self.add_message("system", format!("Selected workflow: '{}'", selected_workflow));
-                            self.active_workflow = selected_workflow;
-                        }
-                        self.mode = Mode::Chat;
+                        self.confirm_workflow_selection();
+                    }
+                    Mode::ModelPicker => {
+                        self.confirm_model_selection();
                     }
                     Mode::Options => {
                         self.handle_options_submit();
@@ -195,6 +398,7 @@ impl App {
                     Mode::Options => {
                         self.handle_options_backspace();
                     }
+                    Mode::ModelPicker => {}
                     _ => {
                         self.backspace();
                     }
@@ -254,6 +458,9 @@ impl App {
                             }
                         }
                     }
+                    Mode::ModelPicker => {
+                        self.model_picker.up();
+                    }
                     _ => {
                         self.move_cursor_up();
                     }
@@ -278,6 +485,9 @@ impl App {
                             self.cursor_g = self.input.graphemes(true).count();
                         }
                     }
+                    Mode::ModelPicker => {
+                        self.model_picker.down();
+                    }
                     _ => {
                         self.move_cursor_down();
                     }
@@ -286,8 +496,8 @@ impl App {
             Key(KeyEvent { code: KeyCode::PageUp, .. }) => {
                 match self.mode {
                     Mode::Chat => {
-                        // Scroll messages up by 10 lines
-                        self.messages_scroll = self.messages_scroll.saturating_sub(10);
+                        // Scroll messages up by 10 rows
+                        self.messages_scroll = self.messages_scroll.min(self.messages_max_scroll).saturating_sub(10);
                     }
                     _ => {}
                 }
@@ -295,8 +505,8 @@ impl App {
             Key(KeyEvent { code: KeyCode::PageDown, .. }) => {
                 match self.mode {
                     Mode::Chat => {
-                        // Scroll messages down by 10 lines
-                        self.messages_scroll = (self.messages_scroll + 10).min(self.messages.len() as u16);
+                        // Scroll messages down by 10 rows
+                        self.messages_scroll = self.messages_scroll.saturating_add(10).min(self.messages_max_scroll);
                     }
                     _ => {}
                 }
@@ -314,7 +524,7 @@ impl App {
                 match self.mode {
                     Mode::Chat => {
                         // Scroll to bottom of messages (newest)
-                        self.messages_scroll = self.messages.len() as u16;
+                        self.messages_scroll = SCROLL_TO_BOTTOM;
                     }
                     _ => {}
                 }
@@ -322,11 +532,12 @@ impl App {
             Key(KeyEvent { code: KeyCode::Esc, .. }) => {
                 // Exit special modes
                 match self.mode {
-                    Mode::Create | Mode::Workflow | Mode::Options => {
+                    Mode::Create | Mode::Workflow | Mode::Options | Mode::ModelPicker => {
                         self.add_message("system", format!("Exited {} mode", match self.mode {
                             Mode::Create => "create",
                             Mode::Workflow => "workflow", 
                             Mode::Options => "options",
+                            Mode::ModelPicker => "model picker",
                             _ => "unknown"
                         }));
                         self.mode = Mode::Chat;
@@ -334,6 +545,11 @@ impl App {
                     Mode::Chat => {
                         // In chat mode, Esc does nothing or could clear input
                     }
+                    Mode::InteractiveChat => {
+                        let _ = self.tx.send(AppCommand::EndChat);
+                        self.add_message("system", "Left interactive chat".to_string());
+                        self.mode = Mode::Chat;
+                    }
                     _ => {
                         self.mode = Mode::Chat;
                     }
@@ -343,7 +559,7 @@ impl App {
                 // Tab completion for commands
                 if self.mode == Mode::Chat && self.input.starts_with('/') {
                     // Simple tab completion logic
-                    let commands = vec!["help", "workflow", "create", "run", "chat", "history", "agent", "scroll"];
+                    let commands = vec!["help", "workflow", "create", "run", "chat", "history", "agent", "scroll", "keyring", "replay", "clear", "timestamps", "version", "log", "graph", "poml", "workflows", "describe", "reasoning", "retry", "tools", "profile", "snippet", "edit", "compare", "model", "undo"];
                     let input = self.input.to_lowercase();
                     for cmd in commands {
                         if cmd.starts_with(&input[1..]) {
@@ -354,6 +570,11 @@ impl App {
                     }
                 }
             }
+            Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column, row, .. })
+                if self.mode == Mode::Workflow =>
+            {
+                self.click_workflow(column, row);
+            }
             crossterm::event::Event::Paste(text) => {
                 // Handle paste events - treat pasted content as a single input
                 self.insert_paste_content(&text);
@@ -368,12 +589,109 @@ impl App {
     }
 
     pub fn add_message(&mut self, from: &'static str, text: String) {
-        self.messages.push(ChatMessage { from, text });
+        self.push_message(ChatMessage::new(from, text));
+    }
+
+    /// Add a message labelled with the agent that produced it
+    pub fn add_agent_message(&mut self, from: &'static str, agent: Option<String>, text: String) {
+        self.push_message(ChatMessage::from_agent(from, agent.unwrap_or_else(|| "agent".to_string()), text));
+    }
+
+    fn push_message(&mut self, message: ChatMessage) {
+        self.messages.push(message);
         if self.messages.len() > 100 { // Keep the message list from growing indefinitely
             self.messages.remove(0);
         }
         // Auto-scroll to show the latest message
-        self.messages_scroll = self.messages.len() as u16;
+        self.messages_scroll = SCROLL_TO_BOTTOM;
+    }
+
+    /// The command running the active workflow again, as `/run <active>` would, after
+    /// `--watch` saw `changed`
+    pub fn rerun_command(&mut self, changed: &std::path::Path) -> Option<AppCommand> {
+        let Some(cfg) = self.workflows.get(&self.active_workflow).cloned() else {
+            self.add_message("system", format!("Workflow '{}' not found", self.active_workflow));
+            return None;
+        };
+        let prompt = crate::commands::resolve_run_prompt(&cfg, &[]);
+        self.add_message(
+            "system",
+            format!("{} changed, re-running workflow '{}'", changed.display(), cfg.name),
+        );
+        Some(AppCommand::RunWorkflow {
+            workflow_name: cfg.name.clone(),
+            prompt,
+            cfg,
+            start_agent: self.selected_agent.map(|idx| idx as i32),
+            variables: Some(self.variables.clone()),
+        })
+    }
+
+    /// Queue the first POML file of the agent focused in Create mode for editing
+    fn edit_focused_poml(&mut self) {
+        let file = self.create_focus.checked_sub(create_ui::BASE_FIELDS).and_then(|field| {
+            let cfg = self.workflows.get(&self.active_workflow)?;
+            let row = cfg.rows.get(field / create_ui::AGENT_FIELDS)?;
+            crate::nm_config::poml_files(row).into_iter().next()
+        });
+        match file {
+            Some(file) => self.pending_edit = Some(std::path::Path::new("prompts").join(file)),
+            None => self.add_message("system", "Focus an agent with a role:file mapping to edit its POML file.".to_string()),
+        }
+    }
+
+    /// Report how the editor exited and pick up the edited file: a config file is
+    /// reloaded, a POML file is read again by the next run of each workflow using it
+    pub fn finish_edit(&mut self, path: &std::path::Path, status: Result<std::process::ExitStatus, String>) {
+        match status {
+            Err(e) => {
+                self.add_message("error", format!("Could not start the editor for {}: {}. Set $EDITOR to the editor to use.", path.display(), e));
+                return;
+            }
+            Ok(status) if !status.success() => {
+                self.add_message("warning", format!("Editor exited with {} for {}", status, path.display()));
+            }
+            Ok(_) => {}
+        }
+        if path == crate::nm_config::config_path() {
+            match crate::nm_config::load_all_nm() {
+                Ok(loaded) => {
+                    self.workflows = loaded.into_iter().map(|cfg| (cfg.name.clone(), cfg)).collect();
+                    self.workflow_list = self.workflows.keys().cloned().collect();
+                    if !self.workflows.contains_key(&self.active_workflow) {
+                        self.active_workflow = self.workflow_list.first().cloned().unwrap_or_default();
+                    }
+                    self.workflow_index = self.workflow_list.iter().position(|w| w == &self.active_workflow).unwrap_or(0);
+                    self.saved_snapshot = self.workflows_snapshot();
+                    self.add_message("system", format!("Reloaded {} workflows from {}", self.workflows.len(), path.display()));
+                }
+                Err(e) => self.add_message("error", format!("Failed to reload {}: {}", path.display(), e)),
+            }
+            return;
+        }
+        let file = path.strip_prefix("prompts").unwrap_or(path).to_string_lossy().to_string();
+        let mut users: Vec<&String> = self
+            .workflows
+            .values()
+            .filter(|cfg| cfg.rows.iter().any(|row| crate::nm_config::poml_files(row).contains(&file)))
+            .map(|cfg| &cfg.name)
+            .collect();
+        users.sort();
+        let text = if users.is_empty() {
+            format!("Edited {}", path.display())
+        } else {
+            let names: Vec<&str> = users.iter().map(|n| n.as_str()).collect();
+            format!("Edited {}; the next run of {} uses the new version", path.display(), names.join(", "))
+        };
+        self.add_message("system", text);
+    }
+
+    /// Scroll so the next (or previous) agent message starts at the top of the view
+    pub fn jump_to_agent_message(&mut self, forward: bool) {
+        let current = self.messages_scroll.min(self.messages_max_scroll);
+        if let Some(row) = agent_jump_target(&self.messages, &self.message_rows, current, forward) {
+            self.messages_scroll = row.min(self.messages_max_scroll);
+        }
     }
 
     pub fn insert_char(&mut self, c: char) {
@@ -404,6 +722,64 @@ impl App {
         }
     }
 
+    /// Whether key presses edit the main chat input in the current mode
+    fn edits_main_input(&self) -> bool {
+        !matches!(self.mode, Mode::Create | Mode::Workflow | Mode::Options | Mode::ModelPicker)
+    }
+
+    /// Grapheme index of the start of the line containing the cursor
+    fn line_start_g(&self) -> usize {
+        let gs: Vec<&str> = self.input.graphemes(true).collect();
+        let mut g = self.cursor_g.min(gs.len());
+        while g > 0 && gs[g - 1] != "\n" {
+            g -= 1;
+        }
+        g
+    }
+
+    /// Grapheme index of the end of the line containing the cursor
+    fn line_end_g(&self) -> usize {
+        let gs: Vec<&str> = self.input.graphemes(true).collect();
+        let mut g = self.cursor_g.min(gs.len());
+        while g < gs.len() && gs[g] != "\n" {
+            g += 1;
+        }
+        g
+    }
+
+    /// Remove graphemes in `from..to` and leave the cursor at `from`
+    fn delete_graphemes(&mut self, from: usize, to: usize) {
+        let l = byte_idx_for_g(&self.input, from);
+        let r = byte_idx_for_g(&self.input, to);
+        self.input.replace_range(l..r, "");
+        self.cursor_g = from;
+    }
+
+    pub fn kill_to_line_start(&mut self) {
+        let start = self.line_start_g();
+        self.delete_graphemes(start, self.cursor_g);
+    }
+
+    pub fn kill_to_line_end(&mut self) {
+        let end = self.line_end_g();
+        self.delete_graphemes(self.cursor_g, end);
+    }
+
+    /// Delete whitespace before the cursor, then the word before that
+    pub fn delete_prev_word(&mut self) {
+        let gs: Vec<&str> = self.input.graphemes(true).collect();
+        let end = self.cursor_g.min(gs.len());
+        let is_space = |g: &str| g.chars().all(char::is_whitespace);
+        let mut start = end;
+        while start > 0 && is_space(gs[start - 1]) {
+            start -= 1;
+        }
+        while start > 0 && !is_space(gs[start - 1]) {
+            start -= 1;
+        }
+        self.delete_graphemes(start, end);
+    }
+
     /// Enhanced cursor movement for multi-line input
     pub fn move_cursor_left(&mut self) {
         if self.cursor_g > 0 {
@@ -527,30 +903,68 @@ impl App {
         let line = self.input.clone();
         self.input.clear();
 
+        // Keys typed into /keyring set are never echoed or saved to history
+        let is_secret = line.starts_with("/keyring set");
+
         // ✅ Treat the entire input (even multi-line) as one message
-        self.add_message("you", line.clone());
+        if is_secret {
+            let masked: Vec<&str> = line.split_whitespace().take(3).collect();
+            self.add_message("you", format!("{} ****", masked.join(" ")));
+        } else {
+            self.add_message("you", line.clone());
+        }
 
         if line.starts_with('/') {
             // Add command to history
-            self.add_to_history(&line);
+            if !is_secret {
+                self.add_to_history(&line);
+            }
             
-            // Pass the correct arguments including selected_agent and mutable mode reference
+            let mut saved = false;
             handle_command(
                 &line,
-                &mut self.workflows,
-                &mut self.active_workflow,
-                &self.tx,
-                &mut self.messages,
-                &mut self.selected_agent, // Pass the mutable reference
-                &mut self.mode,          // Pass the mutable mode reference
-                &mut self.variables,     // Pass the variables reference
-                &mut self.messages_scroll, // Pass the messages_scroll reference
+                CommandState {
+                    workflows: &mut self.workflows,
+                    active_workflow: &mut self.active_workflow,
+                    tx: &self.tx,
+                    messages: &mut self.messages,
+                    selected_agent: &mut self.selected_agent,
+                    mode: &mut self.mode,
+                    variables: &mut self.variables,
+                    messages_scroll: &mut self.messages_scroll,
+                    cleared_messages: &mut self.cleared_messages,
+                    show_timestamps: &mut self.show_timestamps,
+                    show_reasoning: &mut self.show_reasoning,
+                    options_input: &mut self.options_input,
+                    pending_edit: &mut self.pending_edit,
+                    edit_history: &mut self.edit_history,
+                    undo_field: &mut self.undo_field,
+                    saved: &mut saved,
+                },
             );
+            if saved {
+                self.saved_snapshot = self.workflows_snapshot();
+                self.autosave_failing = false;
+            }
+            if self.mode == Mode::ModelPicker {
+                self.open_model_picker();
+            }
         } else {
             // ... (rest of the else block for non-command input)
             if let Some(cfg) = self.workflows.get(&self.active_workflow) {
                 // Convert Option<usize> to Option<i32> before sending
                 let start_agent_i32: Option<i32> = self.selected_agent.map(|i| i as i32);
+                if self.mode == Mode::InteractiveChat {
+                    // Goes to the live chat graph instead of a fresh run
+                    let _ = self.tx.send(AppCommand::ChatMessage {
+                        workflow_name: cfg.name.clone(),
+                        prompt: line.clone(),
+                        cfg: cfg.clone(),
+                        start_agent: start_agent_i32,
+                        variables: Some(self.variables.clone()),
+                    });
+                    return;
+                }
                 let _ = self.tx.send(AppCommand::RunWorkflow {
                     workflow_name: cfg.name.clone(),
                     prompt: line.clone(),
@@ -565,7 +979,22 @@ impl App {
         }
     }
 
-    pub fn render(&self, f: &mut Frame) {
+    pub fn render(&mut self, f: &mut Frame) {
+        let area = f.area();
+        let (min_w, min_h) = match self.mode {
+            Mode::Create => (MIN_CREATE_WIDTH, MIN_CREATE_HEIGHT),
+            _ => (MIN_WIDTH, MIN_HEIGHT),
+        };
+        if area.width < min_w || area.height < min_h {
+            // Normal rendering resumes on the next draw after a resize
+            let msg = Paragraph::new(format!("Terminal too small\nneed {}x{}", min_w, min_h))
+                .style(Style::default().fg(Color::Yellow))
+                .alignment(ratatui::prelude::Alignment::Center)
+                .wrap(Wrap { trim: true });
+            f.render_widget(msg, area);
+            return;
+        }
+
         // Handle different modes
         match self.mode {
             Mode::Create => {
@@ -576,6 +1005,7 @@ impl App {
             Mode::Workflow => {
                 // Workflow mode layout - full screen for workflow selection
                 let area = f.area();
+                self.workflow_area = area;
                 self.render_workflow_mode(f, area);
             }
             Mode::Options => {
@@ -583,34 +1013,59 @@ impl App {
                 let area = f.area();
                 self.render_options_mode(f, area);
             }
+            Mode::ModelPicker => {
+                let area = f.area();
+                if let Some(cfg) = self.workflows.get(&self.active_workflow) {
+                    model_picker::render_model_picker(f, &self.model_picker, cfg, area);
+                }
+            }
             _ => {
                 // Normal chat mode layout
                 let chunks = Layout::default()
                     .direction(ratatui::layout::Direction::Vertical)
                     .constraints([
                         Constraint::Min(1), // Messages area
+                        Constraint::Length(if self.progress.is_some() { 1 } else { 0 }), // Run progress
                         Constraint::Length(8), // Input area
                     ])
                     .split(f.area());
                 
                 let main_area = chunks[0];
-                let input_area = chunks[1];
+                let input_area = chunks[2];
+
+                if let Some((current, total)) = self.progress {
+                    let gauge = Gauge::default()
+                        .gauge_style(Style::default().fg(Color::Cyan).bg(Color::DarkGray))
+                        .ratio(progress_ratio(current, total))
+                        .label(format!("Traversal {}/{}", current, total));
+                    f.render_widget(gauge, chunks[1]);
+                }
                 
                 // Render messages
                 let mut lines = Vec::new();
+                let mut line_starts = Vec::with_capacity(self.messages.len());
                 for m in &self.messages {
-                    let style = match m.from {
-                        "you" => Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
-                        "system" => Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC),
-                        "progress" => Style::default().fg(Color::Yellow),
-                        "agent" => Style::default().fg(Color::Green),
-                        "error" => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-                        _ => Style::default().fg(Color::White),
-                    };
+                    line_starts.push(lines.len());
+                    let style = self.role_styles.style_for(m.from);
                     
+                    let time_prefix = if self.show_timestamps { m.time_prefix() } else { String::new() };
+                    if m.from == "reasoning" {
+                        let label = m.agent.as_deref().unwrap_or("agent");
+                        lines.extend(reasoning_lines(label, &m.text, &time_prefix, style, self.show_reasoning));
+                        continue;
+                    }
                     for (i, part) in m.text.lines().enumerate() {
-                        if i == 0 {
+                        if let (0, Some(label)) = (i, &m.agent) {
+                            // Label with the producing agent's name instead of the generic "agent"
+                            // and its own color when the theme gives it one
+                            lines.push(Line::from(vec![
+                                Span::styled(time_prefix.clone(), Style::default().fg(Color::DarkGray)),
+                                Span::styled(format!("{}: ", label), self.role_styles.get(label).unwrap_or(style)),
+                                Span::raw(part),
+                            ]));
+                        } else if i == 0 {
                             lines.push(Line::from(vec![
+                                Span::styled(time_prefix.clone(), Style::default().fg(Color::DarkGray)),
                                 Span::styled(format!("{}: ", m.from), style),
                                 Span::raw(part),
                             ]));
@@ -623,20 +1078,33 @@ impl App {
                     }
                 }
                 
-                // Calculate scrollbar position
-                let content_height = lines.len() as u16;
+                // Scroll by wrapped rows, clamped so the last row stays at the bottom
+                let inner_width = main_area.width.saturating_sub(2); // Subtract 2 for borders
+                let mut row = 0usize;
+                self.message_rows = line_starts
+                    .iter()
+                    .zip(line_starts.iter().skip(1).chain(std::iter::once(&lines.len())))
+                    .map(|(start, end)| {
+                        let first = row.min(u16::MAX as usize) as u16;
+                        row += wrapped_row_count(&lines[*start..*end], inner_width);
+                        first
+                    })
+                    .collect();
+                let content_height = wrapped_row_count(&lines, inner_width).min(u16::MAX as usize) as u16;
                 let viewport_height = main_area.height.saturating_sub(2); // Subtract 2 for borders
-                let scroll_ratio = if content_height > 0 {
-                    (self.messages_scroll as f32 / content_height as f32).min(1.0)
+                self.messages_max_scroll = content_height.saturating_sub(viewport_height);
+                self.messages_scroll = self.messages_scroll.min(self.messages_max_scroll);
+                let scroll_ratio = if self.messages_max_scroll > 0 {
+                    self.messages_scroll as f32 / self.messages_max_scroll as f32
                 } else {
                     0.0
                 };
-                let scrollbar_position = (scroll_ratio * viewport_height as f32) as u16;
+                let scrollbar_position = (scroll_ratio * viewport_height.saturating_sub(1) as f32) as u16;
                 
                 let para = Paragraph::new(lines)
                     .block(Block::default()
                         .borders(Borders::ALL)
-                        .title("💬 Messages")
+                        .title(crate::banner::title("💬", "Messages"))
                         .title_style(Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD)))
                     .wrap(Wrap { trim: false })
                     .scroll((self.messages_scroll, 0));
@@ -672,7 +1140,7 @@ impl App {
                 
                 let metrics_block = Block::default()
                     .borders(Borders::ALL)
-                    .title("📊 Performance Metrics")
+                    .title(crate::banner::title("📊", "Performance Metrics"))
                     .title_style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD));
                     
                 let metrics_para = Paragraph::new(metrics_text)
@@ -691,9 +1159,19 @@ impl App {
                 f.render_widget(metrics_para, metrics_area);
                 
                 // Enhanced multi-line input rendering with better styling
+                let input_title = if self.spinner_status.is_empty() {
+                    crate::banner::title("✍️", "Input (Enter=submit, Shift+Enter=newline, Ctrl+C=quit)")
+                } else {
+                    format!(
+                        "{} {} | {}",
+                        crate::banner::spinner(SPINNER_FRAMES[self.spinner_frame], self.spinner_frame),
+                        self.spinner_status,
+                        crate::banner::title("✍️", "Input")
+                    )
+                };
                 let input_block = Block::default()
                     .borders(Borders::ALL)
-                    .title("✍️ Input (Enter=submit, Shift+Enter=newline, Ctrl+C=quit)")
+                    .title(input_title)
                     .title_style(Style::default().fg(Color::Green).add_modifier(Modifier::BOLD));
                     
                 let input = Paragraph::new(self.input.as_str())
@@ -728,24 +1206,51 @@ impl App {
         while let Ok(ev) = self.rx.try_recv() {
             match ev {
                 AppEvent::Log(line) => {
-                    self.add_message("progress", line);
+                    if let Some(pretty) = format_tool_result(&line) {
+                        self.add_message("tool", pretty);
+                    } else {
+                        self.add_message("progress", line);
+                    }
+                }
+                AppEvent::LeveledLog { level, msg } => {
+                    self.add_message("progress", format!("[{}] {}", level.as_str(), msg));
                 }
                 AppEvent::RunStart(name) => {
+                    self.progress = None;
                     self.spinner_status = format!("Running workflow '{}'", name);
                     self.add_message("system", format!("Starting run for workflow '{}'…", name));
                 }
-                AppEvent::RunResult(line) => {
-                    self.spinner_status.clear();
-                    self.add_message("agent", format!("Result: {}", line));
+                AppEvent::RunResult { agent, text } => {
+                    self.add_agent_message("agent", agent, text);
+                }
+                AppEvent::Reasoning { agent, text } => {
+                    self.add_agent_message("reasoning", agent, text);
                 }
                 AppEvent::RunEnd(name) => {
+                    self.progress = None;
                     self.spinner_status.clear();
                     self.add_message("system", format!("Run for '{}' completed.", name));
                 }
+                AppEvent::Warning(line) => {
+                    self.add_message("warning", line);
+                }
                 AppEvent::Error(line) => {
+                    self.progress = None;
                     self.spinner_status.clear();
                     self.add_message("error", line);
                 }
+                AppEvent::RoutingTrace(trace) => {
+                    let rows = self
+                        .workflows
+                        .get(&self.active_workflow)
+                        .map(|cfg| cfg.rows.as_slice())
+                        .unwrap_or_default();
+                    let path = crate::runner::format_routing_trace(&trace, rows);
+                    self.add_message("system", format!("Route: {}", path));
+                }
+                AppEvent::Progress { current, total } => {
+                    self.progress = Some((current, total));
+                }
             }
         }
     }
@@ -806,10 +1311,25 @@ impl App {
                 // Working Directory - append to end
                 self.create_input.push(c);
             }
+            6 => {
+                // System Preamble - append to end (\n is unescaped on submit)
+                self.create_input.push(c);
+            }
+            7 => {
+                // Max Tokens - applied on submit
+                self.create_input.push(c);
+            }
+            8 => {
+                // Stop Sequences - applied on submit
+                self.create_input.push(c);
+            }
             _ => {
                 // Handle agent-specific fields
-                let agent_idx = (self.create_focus - 6) / 5;
-                let field_type = (self.create_focus - 6) % 5;
+                let agent_idx = (self.create_focus - create_ui::BASE_FIELDS) / create_ui::AGENT_FIELDS;
+                let field_type = (self.create_focus - create_ui::BASE_FIELDS) % create_ui::AGENT_FIELDS;
+                if create_ui::LIVE_AGENT_FIELDS.contains(&field_type) {
+                    self.record_edit();
+                }
                 
                 if let Some(cfg) = self.workflows.get_mut(&self.active_workflow) {
                     if agent_idx < cfg.rows.len() {
@@ -840,6 +1360,23 @@ impl App {
                                 self.create_input.push(c);
                                 cfg.rows[agent_idx].on_failure = self.create_input.parse().ok();
                             }
+                            5 => {
+                                // Vote Count - append to end
+                                self.create_input.push(c);
+                                cfg.rows[agent_idx].vote_count = self.create_input.parse().unwrap_or(1).max(1);
+                            }
+                            6 => {
+                                // Output Injections - applied on submit
+                                self.create_input.push(c);
+                            }
+                            7 => {
+                                // Max Tokens - applied on submit
+                                self.create_input.push(c);
+                            }
+                            8 => {
+                                // Stop Sequences - applied on submit
+                                self.create_input.push(c);
+                            }
                             _ => {}
                         }
                     }
@@ -849,6 +1386,9 @@ impl App {
     }
 
     pub fn handle_create_submit(&mut self) {
+        self.record_edit();
+        // The next edit of this field is a new undo step
+        self.undo_field = None;
         // Submit create mode input and save workflow
         if let Some(cfg) = self.workflows.get_mut(&self.active_workflow) {
             // Update workflow based on focus field
@@ -871,10 +1411,16 @@ impl App {
                 }
                 4 => cfg.maximum_traversals = self.create_input.parse().unwrap_or(20),
                 5 => cfg.working_dir = self.create_input.clone(),
+                6 => {
+                    let preamble = crate::nm_config::unescape_nm_value(self.create_input.trim());
+                    cfg.system_preamble = if preamble.is_empty() { None } else { Some(preamble) };
+                }
+                7 => cfg.max_tokens = parse_max_tokens(&self.create_input),
+                8 => cfg.stop = parse_stop_sequences(&self.create_input),
                 _ => {
                     // Handle agent-specific fields
-                    let agent_idx = (self.create_focus - 6) / 5;
-                    let field_type = (self.create_focus - 6) % 5;
+                    let agent_idx = (self.create_focus - create_ui::BASE_FIELDS) / create_ui::AGENT_FIELDS;
+                    let field_type = (self.create_focus - create_ui::BASE_FIELDS) % create_ui::AGENT_FIELDS;
                     
                     if agent_idx < cfg.rows.len() {
                         // Extract the input value before the match to avoid borrow checker issues
@@ -902,6 +1448,12 @@ impl App {
                             2 => cfg.rows[agent_idx].max_iterations = input_value.parse().unwrap_or(3),
                             3 => cfg.rows[agent_idx].on_success = input_value.parse().ok(),
                             4 => cfg.rows[agent_idx].on_failure = input_value.parse().ok(),
+                            5 => cfg.rows[agent_idx].vote_count = input_value.parse().unwrap_or(1).max(1),
+                            6 => {
+                                cfg.rows[agent_idx].output_injections = crate::nm_config::split_injections(&input_value);
+                            }
+                            7 => cfg.rows[agent_idx].max_tokens = parse_max_tokens(&input_value),
+                            8 => cfg.rows[agent_idx].stop = parse_stop_sequences(&input_value),
                             _ => {}
                         }
                     }
@@ -926,6 +1478,18 @@ impl App {
         // Don't change mode - stay in Mode::Create
     }
 
+    /// Snapshot the active workflow for `/undo` before the focused field changes it.
+    /// Keystrokes in one field up to its submit are one edit.
+    fn record_edit(&mut self) {
+        if self.undo_field == Some(self.create_focus) {
+            return;
+        }
+        if let Some(cfg) = self.workflows.get(&self.active_workflow) {
+            push_undo(&mut self.edit_history, &self.active_workflow, cfg);
+            self.undo_field = Some(self.create_focus);
+        }
+    }
+
     pub fn handle_create_backspace(&mut self) {
         if !self.create_input.is_empty() {
             self.create_input.pop();
@@ -934,19 +1498,17 @@ impl App {
 
     pub fn handle_create_left(&mut self) {
         // Navigate to previous agent in create mode
-        if self.create_focus >= 11 { // Only allow navigation if we're past the first agent
-            self.create_focus -= 1; // Move left by 5 fields to previous agent
-            self.create_input.clear(); // Clear input for new field
+        if self.create_focus >= create_ui::BASE_FIELDS + create_ui::AGENT_FIELDS { // Only allow navigation if we're past the first agent
+            self.focus_create_field(self.create_focus - 1); // Move left by 5 fields to previous agent
         }
     }
 
     pub fn handle_create_right(&mut self) {
         // Navigate to next agent in create mode
         if let Some(cfg) = self.workflows.get(&self.active_workflow) {
-            let max_focus = 6 + (cfg.rows.len() * 5); // 6 base fields + 5 per agent
-            if self.create_focus < max_focus - 5 { // Don't go past the last agent
-                self.create_focus += 1; // Move right by 5 fields to next agent
-                self.create_input.clear(); // Clear input for new field
+            let max_focus = create_ui::BASE_FIELDS + (cfg.rows.len() * create_ui::AGENT_FIELDS);
+            if self.create_focus < max_focus - create_ui::AGENT_FIELDS { // Don't go past the last agent
+                self.focus_create_field(self.create_focus + 1); // Move right by 5 fields to next agent
             }
         }
     }
@@ -954,23 +1516,28 @@ impl App {
     pub fn handle_create_up(&mut self) {
         // Navigate up in create mode (previous field in same column)
         if self.create_focus >= 1 {
-            self.create_focus -= 1; // Move up by 5 fields to stay in same column
-            self.create_input.clear();
+            self.focus_create_field(self.create_focus - 1); // Move up by 5 fields to stay in same column
         }
     }
 
     pub fn handle_create_down(&mut self) {
         // Navigate down in create mode (next field in same column)
         if let Some(cfg) = self.workflows.get(&self.active_workflow) {
-            let max_focus = 6 + (cfg.rows.len() * 5); // 6 base fields + 5 per agent
+            let max_focus = create_ui::BASE_FIELDS + (cfg.rows.len() * create_ui::AGENT_FIELDS);
             // Only move down if not already at the bottom
             if self.create_focus < max_focus - 1 { // -1 to prevent overflow
-                self.create_focus += 1; // Move down by 1 field
-                self.create_input.clear(); // Clear input for new field
+                self.focus_create_field(self.create_focus + 1); // Move down by 1 field
             }
         }
     }
 
+    /// Move the Create mode focus, starting the new field with empty input and a new undo step
+    fn focus_create_field(&mut self, focus: usize) {
+        self.create_focus = focus;
+        self.create_input.clear();
+        self.undo_field = None;
+    }
+
     /// Parse agent type from string
     fn parse_agent_type(&self, input: &str) -> AgentType {
         match input.to_lowercase().as_str() {
@@ -1006,6 +1573,70 @@ impl App {
         }
     }
 
+    /// Select the highlighted workflow and go back to chat
+    fn confirm_workflow_selection(&mut self) {
+        if self.workflow_index < self.workflow_list.len() {
+            let selected_workflow = self.workflow_list[self.workflow_index].clone();
+            self.add_message("system", format!("Selected workflow: '{}'", selected_workflow));
+            self.active_workflow = selected_workflow;
+        }
+        self.mode = Mode::Chat;
+    }
+
+    /// List the models for the active workflow, gathering them on first use
+    fn open_model_picker(&mut self) {
+        let Some(cfg) = self.workflows.get(&self.active_workflow) else {
+            self.add_message("system", "No active workflow selected. Use /workflow to select one.".to_string());
+            self.mode = Mode::Chat;
+            return;
+        };
+        if self.model_list.is_empty() {
+            let pricing = crate::pricing::PricingTable::load(std::path::Path::new(crate::pricing::PRICING_FILE))
+                .unwrap_or_else(|_| crate::pricing::PricingTable::builtin());
+            self.model_list = model_picker::available_models(self.workflows.values(), &pricing);
+        }
+        self.model_picker = ModelPicker::open(self.model_list.clone(), &cfg.model);
+    }
+
+    /// Apply the highlighted model to the active workflow and save
+    fn confirm_model_selection(&mut self) {
+        self.mode = Mode::Chat;
+        let Some(cfg) = self.workflows.get_mut(&self.active_workflow) else {
+            return;
+        };
+        let before = cfg.clone();
+        let Some(previous) = self.model_picker.apply(cfg) else {
+            let text = format!("Workflow '{}' keeps model {}", cfg.name, cfg.model);
+            self.add_message("system", text);
+            return;
+        };
+        let text = format!("Workflow '{}' now uses {} (was {})", cfg.name, cfg.model, previous);
+        push_undo(&mut self.edit_history, &self.active_workflow, &before);
+        let all: Vec<WorkflowConfig> = self.workflows.values().cloned().collect();
+        match save_all_nm(&all) {
+            Ok(()) => self.add_message("system", text),
+            Err(e) => self.add_message("system", format!("{}, but saving failed: {}", text, e)),
+        }
+    }
+
+    /// A click highlights the workflow under the mouse; a second click on it confirms
+    fn click_workflow(&mut self, column: u16, row: u16) {
+        let Some(index) = workflow_ui::workflow_index_at(self.workflow_area, self.workflow_list.len(), column, row) else {
+            return;
+        };
+        let double = self
+            .last_workflow_click
+            .is_some_and(|(last, at)| last == index && at.elapsed() < DOUBLE_CLICK);
+        self.workflow_index = index;
+        self.active_workflow = self.workflow_list[index].clone();
+        if double {
+            self.last_workflow_click = None;
+            self.confirm_workflow_selection();
+        } else {
+            self.last_workflow_click = Some((index, Instant::now()));
+        }
+    }
+
     /// Render workflow mode UI
     pub fn render_workflow_mode(&self, f: &mut Frame, area: Rect) {
         // Convert workflow_list to the expected type for the workflow_ui function
@@ -1027,7 +1658,7 @@ impl App {
             .split(area);
 
         // Title
-        let title = Paragraph::new("🔧 Options Mode")
+        let title = Paragraph::new(crate::banner::title("🔧", "Options Mode"))
             .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
             .alignment(ratatui::prelude::Alignment::Center);
         f.render_widget(title, chunks[0]);
@@ -1050,7 +1681,7 @@ impl App {
         // Input area
         let input_block = Block::default()
             .borders(Borders::ALL)
-            .title("📝 Input")
+            .title(crate::banner::title("📝", "Input"))
             .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
             
         let input_para = Paragraph::new(self.options_input.as_str())
@@ -1170,6 +1801,11 @@ impl App {
     }
 }
 
+/// Fill of the run progress gauge, which only accepts 0.0..=1.0
+fn progress_ratio(current: usize, total: usize) -> f64 {
+    if total == 0 { 0.0 } else { (current as f64 / total as f64).clamp(0.0, 1.0) }
+}
+
 fn grapheme_boundaries(s: &str) -> Vec<usize> {
     let mut idxs = vec![0];
     for (i, _) in s.grapheme_indices(true) {
@@ -1185,3 +1821,475 @@ fn byte_idx_for_g(s: &str, g: usize) -> usize {
     let v = grapheme_boundaries(s);
     *v.get(g).unwrap_or(&s.len())
 }
+
+/// Pretty-print the JSON body of a `[TOOL][name] result = {...}` log line
+fn format_tool_result(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("[TOOL][")?;
+    let (name, rest) = rest.split_once(']')?;
+    let (label, body) = rest.split_once(" = ")?;
+    let value: serde_json::Value = serde_json::from_str(body.trim()).ok()?;
+    if !value.is_object() && !value.is_array() {
+        return None;
+    }
+    let pretty = serde_json::to_string_pretty(&value).ok()?;
+    Some(format!("[{}]{}:\n{}", name, label, pretty))
+}
+
+/// A Create-mode max tokens entry; blank or 0 means the provider default
+fn parse_max_tokens(input: &str) -> Option<u32> {
+    input.trim().parse::<u32>().ok().filter(|n| *n > 0)
+}
+
+/// A Create-mode stop sequence entry: `;`-separated markers, `\n` for a newline
+fn parse_stop_sequences(input: &str) -> Vec<String> {
+    input
+        .split(';')
+        .filter(|s| !s.is_empty())
+        .map(crate::nm_config::unescape_nm_value)
+        .collect()
+}
+
+/// Lines for `label`'s reasoning `text`, dimmed throughout. Collapsed it is just the
+/// first line and how many more there are.
+fn reasoning_lines<'a>(label: &str, text: &'a str, time_prefix: &str, style: Style, expanded: bool) -> Vec<Line<'a>> {
+    let mut parts = text.lines();
+    let first = parts.next().unwrap_or("");
+    let mut header = vec![
+        Span::styled(time_prefix.to_string(), Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{} reasoning: ", label), style),
+        Span::styled(first, style),
+    ];
+    if !expanded {
+        let hidden = parts.count();
+        if hidden > 0 {
+            header.push(Span::styled(format!(" [+{} lines, /reasoning on]", hidden), style));
+        }
+        return vec![Line::from(header)];
+    }
+    std::iter::once(Line::from(header))
+        .chain(parts.map(|part| Line::from(vec![Span::raw("   "), Span::styled(part, style)])))
+        .collect()
+}
+
+/// Row an agent-message jump scrolls to: the first agent message starting below
+/// `scroll` going forward, the last one starting above it going back. `rows` holds
+/// each message's first wrapped row.
+fn agent_jump_target(messages: &[ChatMessage], rows: &[u16], scroll: u16, forward: bool) -> Option<u16> {
+    let mut agent_rows = messages
+        .iter()
+        .zip(rows)
+        .filter(|(m, _)| m.from == "agent")
+        .map(|(_, row)| *row);
+    if forward {
+        agent_rows.find(|row| *row > scroll)
+    } else {
+        agent_rows.filter(|row| *row < scroll).last()
+    }
+}
+
+/// Number of rows `lines` occupy when word-wrapped to `width` columns
+fn wrapped_row_count(lines: &[Line], width: u16) -> usize {
+    let width = width.max(1) as usize;
+    lines
+        .iter()
+        .map(|line| {
+            let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+            wrapped_rows(&text, width)
+        })
+        .sum()
+}
+
+fn wrapped_rows(text: &str, width: usize) -> usize {
+    let mut rows = 1;
+    let mut col = 0;
+    for word in text.split_word_bounds() {
+        let w = Span::raw(word).width();
+        if col + w <= width {
+            col += w;
+        } else if word.trim().is_empty() {
+            // Whitespace at a wrap point starts the next row
+            rows += 1;
+            col = 0;
+        } else if w <= width {
+            rows += 1;
+            col = w;
+        } else {
+            // Words longer than a row are broken at the edge
+            for g in word.graphemes(true) {
+                let gw = Span::raw(g).width();
+                if col + gw > width {
+                    rows += 1;
+                    col = 0;
+                }
+                col += gw;
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app(input: &str, cursor_g: usize) -> App {
+        let (tx, _rx_cmd) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_evt, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = App::new(tx, rx, HashMap::new(), "default".to_string(), None);
+        app.input = input.to_string();
+        app.cursor_g = cursor_g;
+        app
+    }
+
+    #[test]
+    fn test_dirty_workflows_autosave_after_interval() {
+        let mut app = test_app("", 0);
+        app.autosave_interval = Some(Duration::from_secs(60));
+        let mut saves = Vec::new();
+
+        // Nothing changed, so nothing to save even once the interval passed
+        app.last_autosave = Instant::now() - Duration::from_secs(61);
+        assert!(!app.autosave_with(|all| {
+            saves.push(all.len());
+            Ok(())
+        }));
+
+        app.workflows.insert("draft".into(), WorkflowConfig { name: "draft".into(), ..WorkflowConfig::default() });
+        assert!(!app.autosave_with(|_| panic!("saved before the interval passed")));
+
+        app.last_autosave = Instant::now() - Duration::from_secs(61);
+        assert!(app.autosave_with(|all| {
+            saves.push(all.len());
+            Ok(())
+        }));
+        assert_eq!(saves, vec![1]);
+
+        // Saved state is clean again, and a disabled timer never saves
+        app.last_autosave = Instant::now() - Duration::from_secs(61);
+        assert!(!app.autosave_with(|_| panic!("saved an unchanged state")));
+        app.workflows.clear();
+        app.autosave_interval = None;
+        assert!(!app.autosave_with(|_| panic!("saved with auto-save off")));
+    }
+
+    #[test]
+    fn test_autosave_failure_warns_once_per_streak() {
+        let mut app = test_app("", 0);
+        app.autosave_interval = Some(Duration::from_secs(60));
+        app.workflows.insert("draft".into(), WorkflowConfig { name: "draft".into(), ..WorkflowConfig::default() });
+        let warnings = |app: &App| app.messages.iter().filter(|m| m.from == "warning").count();
+        let fail = |_: &[WorkflowConfig]| -> std::io::Result<()> {
+            Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read-only"))
+        };
+
+        for _ in 0..3 {
+            app.last_autosave = Instant::now() - Duration::from_secs(61);
+            assert!(!app.autosave_with(fail));
+        }
+        assert_eq!(warnings(&app), 1);
+        assert!(app.messages.last().unwrap().text.contains("read-only"));
+
+        // A success ends the streak, so the next failure is reported again
+        app.workflows.insert("other".into(), WorkflowConfig { name: "other".into(), ..WorkflowConfig::default() });
+        app.last_autosave = Instant::now() - Duration::from_secs(61);
+        assert!(app.autosave_with(|_| Ok(())));
+        app.workflows.remove("other");
+        app.last_autosave = Instant::now() - Duration::from_secs(61);
+        assert!(!app.autosave_with(fail));
+        assert_eq!(warnings(&app), 2);
+    }
+
+    #[test]
+    fn test_undo_restores_workflow_before_edit() {
+        let mut app = test_app("", 0);
+        let mut cfg = WorkflowConfig { name: "draft".into(), ..WorkflowConfig::default() };
+        cfg.rows[0].files = "role:system:a.poml".into();
+        app.workflows.insert("draft".into(), cfg);
+        app.active_workflow = "draft".into();
+        let before = serde_json::to_value(&app.workflows["draft"]).unwrap();
+
+        // Each key typed into the first agent's Files field changes the workflow
+        app.create_focus = create_ui::BASE_FIELDS + 1;
+        for c in ";b".chars() {
+            app.handle_create_input(c);
+        }
+        assert_eq!(app.workflows["draft"].rows[0].files, "role:system:a.poml;b");
+        assert_eq!(app.edit_history.len(), 1, "one field edit is one undo step");
+
+        assert_eq!(undo_edit(&mut app.edit_history, &mut app.workflows).as_deref(), Some("draft"));
+        assert_eq!(serde_json::to_value(&app.workflows["draft"]).unwrap(), before);
+        assert!(undo_edit(&mut app.edit_history, &mut app.workflows).is_none());
+
+        // Leaving the field and coming back starts a new step
+        for c in ['c', 'd'] {
+            app.handle_create_down();
+            app.handle_create_up();
+            app.handle_create_input(c);
+        }
+        assert_eq!(app.edit_history.len(), 2);
+        app.edit_history.clear();
+
+        // Only the latest MAX_UNDO_DEPTH snapshots are kept
+        for i in 0..MAX_UNDO_DEPTH + 5 {
+            let snapshot = WorkflowConfig { maximum_traversals: i, ..WorkflowConfig::default() };
+            push_undo(&mut app.edit_history, "draft", &snapshot);
+        }
+        assert_eq!(app.edit_history.len(), MAX_UNDO_DEPTH);
+        assert_eq!(app.edit_history[0].1.maximum_traversals, 5);
+    }
+
+    #[test]
+    fn test_workflow_click_maps_rows_to_entries() {
+        // 3 workflows listed in a 40x10 box at (2, 1): the border takes row 1 and column 2
+        let area = Rect::new(2, 1, 40, 10);
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 3, 2), Some(0));
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 20, 4), Some(2));
+        // Below the last entry, on the border, or outside the box
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 20, 5), None);
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 20, 1), None);
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 2, 3), None);
+        assert_eq!(workflow_ui::workflow_index_at(area, 3, 50, 3), None);
+
+        let mut app = test_app("", 0);
+        app.workflow_list = vec!["a".into(), "b".into(), "c".into()];
+        app.workflow_area = area;
+        app.mode = Mode::Workflow;
+        app.click_workflow(10, 3);
+        assert_eq!(app.workflow_index, 1);
+        assert_eq!(app.mode, Mode::Workflow);
+        app.click_workflow(10, 3);
+        assert_eq!(app.active_workflow, "b");
+        assert_eq!(app.mode, Mode::Chat);
+    }
+
+    #[test]
+    fn test_delete_prev_word_unicode() {
+        // "héllo wörld 👩‍👩‍👧 " is 15 graphemes; the family emoji is a single one
+        let mut app = test_app("héllo wörld 👩‍👩‍👧 ", 15);
+        app.delete_prev_word();
+        assert_eq!(app.input, "héllo wörld ");
+        assert_eq!(app.cursor_g, 12);
+
+        app.delete_prev_word();
+        assert_eq!(app.input, "héllo ");
+        assert_eq!(app.cursor_g, 6);
+
+        // Cursor in the middle of the text only removes the word before it
+        let mut app = test_app("日本語 テキスト", 3);
+        app.delete_prev_word();
+        assert_eq!(app.input, " テキスト");
+        assert_eq!(app.cursor_g, 0);
+    }
+
+    #[test]
+    fn test_kill_line_shortcuts() {
+        let mut app = test_app("first\nsécond line", 9);
+        app.kill_to_line_start();
+        assert_eq!(app.input, "first\nond line");
+        assert_eq!(app.cursor_g, 6);
+
+        app.kill_to_line_end();
+        assert_eq!(app.input, "first\n");
+
+        let mut app = test_app("first\nsécond", 2);
+        assert_eq!(app.line_end_g(), 5);
+        app.cursor_g = 8;
+        assert_eq!(app.line_start_g(), 6);
+    }
+
+    #[test]
+    fn test_agent_jump_skips_other_messages() {
+        let messages: Vec<ChatMessage> = ["system", "agent", "tool", "progress", "agent", "warning"]
+            .iter()
+            .map(|from| ChatMessage::new(*from, "text".into()))
+            .collect();
+        let rows = [0, 2, 7, 9, 12, 20];
+
+        assert_eq!(agent_jump_target(&messages, &rows, 0, true), Some(2));
+        assert_eq!(agent_jump_target(&messages, &rows, 2, true), Some(12));
+        assert_eq!(agent_jump_target(&messages, &rows, 12, true), None);
+        assert_eq!(agent_jump_target(&messages, &rows, 20, false), Some(12));
+        assert_eq!(agent_jump_target(&messages, &rows, 12, false), Some(2));
+        assert_eq!(agent_jump_target(&messages, &rows, 2, false), None);
+    }
+
+    #[test]
+    fn test_wrapped_row_count_fixed_width() {
+        let lines = vec![
+            Line::from("short"),
+            Line::from("the quick brown fox jumps"),
+            Line::from(""),
+            Line::from(vec![Span::raw("agent: "), Span::raw("abcdefghijklmnopqrstuvwxyz")]),
+        ];
+        // "short" = 1, "the quick / brown fox / jumps" = 3, "" = 1,
+        // "agent: " + 26 letters broken at 10 columns = 4
+        assert_eq!(wrapped_row_count(&lines, 10), 9);
+        assert_eq!(wrapped_row_count(&lines, 80), 4);
+
+        // Wide characters take two columns each
+        assert_eq!(wrapped_row_count(&[Line::from("日本語日本語")], 4), 3);
+    }
+
+    #[test]
+    fn test_format_tool_result_pretty_prints_json() {
+        let line = r#"[TOOL][ls] result = {"status":"success","entries":["a.txt","b"]}"#;
+        let pretty = format_tool_result(line).unwrap();
+        assert_eq!(
+            pretty,
+            "[ls] result:\n{\n  \"entries\": [\n    \"a.txt\",\n    \"b\"\n  ],\n  \"status\": \"success\"\n}"
+        );
+
+        // Non-JSON bodies and other log lines are left alone
+        assert!(format_tool_result("[TOOL][pwd] result = /home/user").is_none());
+        assert!(format_tool_result("[Agent 1] Starting run").is_none());
+    }
+
+    #[test]
+    fn test_spinner_frame_advances_while_running() {
+        let mut app = test_app("", 0);
+        app.last_spinner_tick = Instant::now() - Duration::from_millis(200);
+        app.tick_spinner();
+        assert_eq!(app.spinner_frame, 0, "idle spinner should not animate");
+
+        app.spinner_status = "Running workflow 'default'".to_string();
+        app.tick_spinner();
+        assert_eq!(app.spinner_frame, 1);
+
+        // Ticks faster than the frame interval are ignored
+        app.tick_spinner();
+        assert_eq!(app.spinner_frame, 1);
+
+        app.last_spinner_tick = Instant::now() - Duration::from_millis(200);
+        app.tick_spinner();
+        assert_eq!(app.spinner_frame, 2);
+    }
+
+    #[test]
+    fn test_tiny_terminal_shows_too_small_message() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let mut app = test_app("", 0);
+        app.add_message("system", "hello".to_string());
+
+        let mut terminal = Terminal::new(TestBackend::new(10, 4)).unwrap();
+        terminal.draw(|f| app.render(f)).unwrap();
+        let text: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(text.contains("Terminal"));
+        assert!(!text.contains("Messages"));
+
+        // Normal layout comes back once there is room
+        terminal.backend_mut().resize(60, 20);
+        terminal.draw(|f| app.render(f)).unwrap();
+        let text: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(text.contains("Messages"));
+    }
+
+    #[test]
+    fn test_run_result_is_labeled_with_agent_name() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let (tx, _rx_cmd) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_evt, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = App::new(tx, rx, HashMap::new(), "default".to_string(), None);
+        tx_evt.send(AppEvent::RunResult { agent: Some("Planner".to_string()), text: "step one".to_string() }).unwrap();
+        tx_evt.send(AppEvent::RunResult { agent: None, text: "done".to_string() }).unwrap();
+        futures_util::FutureExt::now_or_never(app.poll_async()).unwrap();
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|f| app.render(f)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect();
+        assert!(rows.iter().any(|r| r.contains("Planner: step one")));
+        assert!(rows.iter().any(|r| r.contains("agent: done")));
+        assert!(!rows.iter().any(|r| r.contains("Result:")));
+    }
+
+    #[test]
+    fn test_themed_role_renders_with_configured_color() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let mut app = test_app("", 0);
+        app.role_styles = crate::theme::RoleStyles::from_json(
+            r##"{"roles": {"Planner": {"color": "blue", "modifiers": ["bold"]}, "warning": {"color": "#ff8800"}}}"##,
+        )
+        .unwrap();
+        app.add_agent_message("agent", Some("Planner".into()), "step one".to_string());
+        app.add_agent_message("agent", Some("Coder: v2".into()), "step two".to_string());
+        app.add_message("warning", "careful".to_string());
+        app.add_message("custom", "odd one".to_string());
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|f| app.render(f)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let style_of = |text: &str| {
+            (0..buffer.area.height)
+                .find_map(|y| {
+                    let row: String = (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect();
+                    row.find(text).map(|i| buffer[(row[..i].chars().count() as u16, y)].style())
+                })
+                .unwrap()
+        };
+
+        assert_eq!(style_of("Planner:").fg, Some(Color::Blue));
+        assert!(style_of("Planner:").add_modifier.contains(Modifier::BOLD));
+        // Agents without their own entry keep the agent color
+        assert_eq!(style_of("Coder: v2: step two").fg, Some(Color::Green));
+        assert_eq!(style_of("warning:").fg, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(style_of("custom:").fg, Some(Color::White));
+
+        assert!(crate::theme::RoleStyles::from_json(r#"{"roles": {"you": {"color": "neon"}}}"#).is_err());
+    }
+
+    #[test]
+    fn test_clear_leaves_only_welcome_message() {
+        let mut app = test_app("", 0);
+        app.add_message("system", "first".to_string());
+        app.add_agent_message("agent", Some("Agent1".into()), "second".to_string());
+
+        app.input = "/clear".to_string();
+        app.submit();
+        assert_eq!(app.messages.len(), 1);
+        assert_eq!(app.messages[0].text, welcome_message().text);
+        assert_eq!(app.messages_scroll, 0);
+
+        // One level of undo brings the old chat back
+        app.input = "/clear undo".to_string();
+        app.submit();
+        assert!(app.messages.iter().any(|m| m.agent.as_deref() == Some("Agent1") && m.text == "second"));
+    }
+
+    #[test]
+    fn test_timestamp_prefix_shown_when_enabled() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let mut app = test_app("", 0);
+        let before = Utc::now();
+        app.add_message("system", "tick".to_string());
+        let message = app.messages.last().unwrap();
+        assert!(message.timestamp >= before && message.timestamp <= Utc::now());
+        let prefix = message.time_prefix();
+        assert_eq!(prefix.len(), "[14:03:22] ".len());
+
+        let render_rows = |app: &mut App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|f| app.render(f)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..buffer.area.height)
+                .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+                .collect::<Vec<String>>()
+        };
+        assert!(!render_rows(&mut app).iter().any(|r| r.contains(&prefix)));
+
+        app.input = "/timestamps on".to_string();
+        app.submit();
+        assert!(app.show_timestamps);
+        let expected = format!("{}system: tick", prefix);
+        assert!(render_rows(&mut app).iter().any(|r| r.contains(&expected)));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tells apart the temp files of writes running at once in this process
+static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+/// Write `contents` to `path` through a temporary file renamed over it, so readers see
+/// either the old contents or the new ones
+pub fn write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    write_chunks_with(path, [contents], |file, chunk| file.write_all(chunk))
+        .map(|_| ())
+        .map_err(|(e, _)| e)
+}
+
+/// `write` for contents given in `chunks`, each handed to `write_chunk` (tests make it
+/// fail part way). Returns the bytes written; on failure, the error and the bytes written
+/// before it, with the temp file removed and `path` left as it was.
+///
+/// A symlink at `path` is followed, so the file it points to is replaced, not the link.
+pub fn write_chunks_with<'a, F>(
+    path: &Path,
+    chunks: impl IntoIterator<Item = &'a [u8]>,
+    mut write_chunk: F,
+) -> Result<usize, (std::io::Error, usize)>
+where
+    F: FnMut(&mut fs::File, &[u8]) -> std::io::Result<()>,
+{
+    let path = resolve_symlink(path);
+    let temp = temp_path(&path);
+    let mut written = 0;
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        if let Ok(meta) = fs::metadata(&path) {
+            fs::set_permissions(&temp, meta.permissions())?;
+        }
+        for chunk in chunks {
+            write_chunk(&mut file, chunk)?;
+            written += chunk.len();
+        }
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    result.map(|_| written).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        (e, written)
+    })
+}
+
+/// The file a symlink at `path` points to; `path` itself for anything else, including
+/// a link to nothing
+fn resolve_symlink(path: &Path) -> PathBuf {
+    let is_link = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink());
+    match is_link {
+        true => fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+        false => path.to_path_buf(),
+    }
+}
+
+/// `.<name>.<pid>.<n>.tmp` beside `path`, unique across processes and concurrent writes
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let n = NEXT_TEMP.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{}.{}.{}.tmp", name, std::process::id(), n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writes_replace_the_symlink_target_and_leave_no_temp_files() {
+        let dir = std::env::temp_dir().join(format!("nm_atomic_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("config.nm");
+        let link = dir.join("link.nm");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // Writes running at once each get their own temp file
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let link = link.clone();
+                std::thread::spawn(move || write(&link, format!("writer {}", i).as_bytes()).unwrap())
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(fs::read_to_string(&target).unwrap().starts_with("writer "));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "temp files should be gone");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set from `--no-emoji`; read wherever a banner or block title is drawn
+static NO_EMOJI: AtomicBool = AtomicBool::new(false);
+
+/// Spinner frames used instead of the braille ones under `--no-emoji`
+const ASCII_SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+
+pub fn set_no_emoji(no_emoji: bool) {
+    NO_EMOJI.store(no_emoji, Ordering::Relaxed);
+}
+
+/// A block title with its emoji, or just the text under `--no-emoji`
+pub fn title(emoji: &str, text: &str) -> String {
+    if NO_EMOJI.load(Ordering::Relaxed) {
+        text.to_string()
+    } else {
+        format!("{} {}", emoji, text)
+    }
+}
+
+/// `frame`, or an ASCII spinner frame for the same tick under `--no-emoji`
+pub fn spinner(frame: &'static str, tick: usize) -> &'static str {
+    if NO_EMOJI.load(Ordering::Relaxed) {
+        ASCII_SPINNER[tick % ASCII_SPINNER.len()]
+    } else {
+        frame
+    }
+}
+
+/// Lines printed when the web interface starts; none at all in quiet mode
+pub fn web_banner(host: &str, port: u16, quiet: bool, no_emoji: bool) -> Vec<String> {
+    if quiet {
+        return Vec::new();
+    }
+    let (rocket, pin) = if no_emoji { ("==>", "   ") } else { ("🚀", "📍") };
+    vec![
+        format!("{} Starting Neonmachines Web Interface", rocket),
+        format!("{} URL: http://{}:{}/", pin, host, port),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_mode_prints_no_banner() {
+        assert!(web_banner("127.0.0.1", 3000, true, false).is_empty());
+        assert!(web_banner("127.0.0.1", 3000, true, true).is_empty());
+
+        let plain = web_banner("127.0.0.1", 3000, false, true);
+        assert_eq!(plain.len(), 2);
+        assert!(plain.iter().all(|line| line.is_ascii()));
+        assert!(plain[1].contains("http://127.0.0.1:3000/"));
+        assert!(!web_banner("127.0.0.1", 3000, false, false)[0].is_ascii());
+    }
+}
@@ -1,7 +1,14 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 use std::path::PathBuf;
 use crate::error::{NeonmachinesError, NeonmachinesResult};
 
+/// Names accepted by `--theme`
+pub const THEMES: &[&str] = &["default", "dark", "light"];
+
+/// Image types `--avatar` may point at
+pub const AVATAR_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg"];
+
 #[derive(Parser, Debug)]
 #[command(
     name = "neonmachines",
@@ -53,6 +60,10 @@ pub struct Cli {
     #[arg(long)]
     pub avatar: Option<PathBuf>,
 
+    /// Token the web UI's websocket and API routes require
+    #[arg(long)]
+    pub auth_token: Option<String>,
+
     /// Rate limit requests per minute
     #[arg(long, default_value = "60")]
     pub rate_limit: u32,
@@ -76,6 +87,42 @@ pub struct Cli {
     /// Enable experimental features
     #[arg(long)]
     pub experimental: bool,
+
+    /// Prefix chat messages with the time they arrived
+    #[arg(long)]
+    pub show_timestamps: bool,
+
+    /// Skip metrics collection for lower-overhead runs
+    #[arg(long)]
+    pub no_metrics: bool,
+
+    /// Reuse LLM responses for identical requests (stored in .neonmachines_data/llm_cache)
+    #[arg(long)]
+    pub cache: bool,
+
+    /// How long cached responses stay valid, in seconds
+    #[arg(long, default_value = "3600")]
+    pub cache_ttl: u64,
+
+    /// Suppress startup banners and other decorative output
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Use plain ASCII instead of emoji in banners and TUI titles
+    #[arg(long)]
+    pub no_emoji: bool,
+
+    /// Re-run the active workflow whenever this file or directory changes (TUI)
+    #[arg(long)]
+    pub watch: Option<PathBuf>,
+
+    /// Never call the LLM provider: agents only answer from --cache, /describe stays structural
+    #[arg(long)]
+    pub no_network: bool,
+
+    /// Save edited workflows every N seconds (TUI); 0 turns auto-save off
+    #[arg(long, default_value = "30")]
+    pub autosave_secs: u64,
 }
 
 #[derive(Subcommand, Debug)]
@@ -117,6 +164,10 @@ pub enum Commands {
         #[arg(long)]
         avatar: Option<PathBuf>,
 
+        /// Token the websocket and API routes require
+        #[arg(long)]
+        auth_token: Option<String>,
+
         /// Theme name
         #[arg(long, default_value = "default")]
         theme: String,
@@ -140,7 +191,7 @@ pub enum Commands {
         #[arg(long)]
         list_providers: bool,
 
-        /// Show current configuration
+        /// Print the effective configuration as JSON
         #[arg(long)]
         show: bool,
 
@@ -248,9 +299,87 @@ pub enum Commands {
         #[arg(long)]
         quick: bool,
     },
+
+    /// Run each non-empty line of a file as a prompt through one workflow
+    Batch {
+        /// File with one prompt per line
+        file: PathBuf,
+
+        /// Workflow to run (defaults to the first loaded workflow)
+        #[arg(short, long)]
+        workflow: Option<String>,
+
+        /// Results file (JSON lines; defaults to <file>.results.jsonl)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Write runnable example workflows to config.nm and their POML files to prompts/
+    Examples,
+
+    /// Built-in tools agents can call
+    Tools {
+        /// List each tool with its description and parameters
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Generate a shell completion script
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
 impl Cli {
+    /// Everything a run would actually use: flags merged with defaults, the loaded
+    /// workflows, and what was detected in the environment
+    pub fn effective_config(&self, workflows: &[crate::nm_config::WorkflowConfig]) -> serde_json::Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "config_file": crate::nm_config::config_path(),
+            "prompts_dir": "prompts",
+            "provider": {
+                "name": crate::secrets::DEFAULT_PROVIDER,
+                "base_url": crate::agents::CHAT_COMPLETIONS_URL,
+            },
+            "rate_limit": {
+                "enabled": self.enable_rate_limit,
+                "requests_per_minute": self.rate_limit,
+            },
+            "llm_cache": {
+                "enabled": self.cache,
+                "ttl_secs": self.cache_ttl,
+                "dir": crate::llm_cache::CACHE_DIR,
+            },
+            "logging": {
+                "level": self.log_level,
+                "verbose": self.verbose,
+                "file": self.log_file,
+            },
+            "web": {
+                "host": self.get_host(),
+                "port": self.get_port(),
+                "auth_required": self.get_auth_token().is_some(),
+            },
+            "theme": self.theme,
+            "environment": {
+                "api_key_set": std::env::var("API_KEY").is_ok_and(|key| !key.is_empty()),
+                "current_dir": std::env::current_dir().ok(),
+                "extensions_dir": crate::nmmcp::get_extensions_directory(),
+            },
+            "workflows": workflows,
+        })
+    }
+
+    /// Write the completion script for `shell` to `out`
+    pub fn write_completions(shell: Shell, out: &mut dyn std::io::Write) {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(shell, &mut cmd, name, out);
+    }
+
     pub fn get_mode(&self) -> AppMode {
         if self.web {
             AppMode::Web
@@ -283,6 +412,51 @@ impl Cli {
             .unwrap_or(self.host.clone())
     }
 
+    /// Theme from the `web`/`tui` subcommand or `--theme`
+    pub fn get_theme(&self) -> String {
+        self.command
+            .as_ref()
+            .and_then(|cmd| match cmd {
+                Commands::Tui { theme, .. } | Commands::Web { theme, .. } => Some(theme.clone()),
+                _ => None,
+            })
+            .unwrap_or(self.theme.clone())
+    }
+
+    /// Avatar image from the `web` subcommand or `--avatar`, if any
+    pub fn get_avatar(&self) -> Option<PathBuf> {
+        self.command
+            .as_ref()
+            .and_then(|cmd| match cmd {
+                Commands::Web { avatar, .. } => avatar.clone(),
+                _ => None,
+            })
+            .or_else(|| self.avatar.clone())
+    }
+
+    /// Web auth token from the `web` subcommand or `--auth-token`; `None` leaves the UI open
+    pub fn get_auth_token(&self) -> Option<String> {
+        self.command
+            .as_ref()
+            .and_then(|cmd| match cmd {
+                Commands::Web { auth_token, .. } => auth_token.clone(),
+                _ => None,
+            })
+            .or_else(|| self.auth_token.clone())
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Config file from the subcommand or `--config-file`, if any
+    pub fn get_config_file(&self) -> Option<PathBuf> {
+        self.command
+            .as_ref()
+            .and_then(|cmd| match cmd {
+                Commands::Tui { config, .. } | Commands::Web { config, .. } => config.clone(),
+                _ => None,
+            })
+            .or_else(|| self.config_file.clone())
+    }
+
     /// Validate CLI configuration and return appropriate error if invalid
     pub fn validate(&self) -> NeonmachinesResult<()> {
         // Validate log level
@@ -305,6 +479,36 @@ impl Cli {
             )));
         }
 
+        let theme = self.get_theme();
+        if !THEMES.contains(&theme.as_str()) {
+            return Err(NeonmachinesError::cli(format!(
+                "Invalid theme: {}. Must be one of: {}",
+                theme,
+                THEMES.join(", ")
+            )));
+        }
+
+        // The avatar is served to browsers, so it has to be an image file
+        if let Some(avatar) = self.get_avatar() {
+            let is_image = avatar
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| AVATAR_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_image {
+                return Err(NeonmachinesError::cli(format!(
+                    "Avatar must be an image ({}): {}",
+                    AVATAR_EXTENSIONS.join(", "),
+                    avatar.display()
+                )));
+            }
+            if !avatar.is_file() {
+                return Err(NeonmachinesError::file_system(format!(
+                    "Avatar file does not exist: {}",
+                    avatar.display()
+                )));
+            }
+        }
+
         // Validate rate limit
         if self.rate_limit == 0 {
             return Err(NeonmachinesError::cli(
@@ -408,3 +612,34 @@ pub enum AppMode {
     Config,
     Command,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_config_lists_known_fields() {
+        let cli = Cli::parse_from(["neonmachines", "--rate-limit", "30", "--log-level", "debug"]);
+        let mut workflow = crate::nm_config::WorkflowConfig::default();
+        workflow.name = "print_config_workflow".into();
+
+        let config = cli.effective_config(&[workflow]);
+        assert_eq!(config["rate_limit"]["requests_per_minute"], 30);
+        assert_eq!(config["logging"]["level"], "debug");
+        assert_eq!(config["provider"]["name"], crate::secrets::DEFAULT_PROVIDER);
+        assert_eq!(config["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(config["workflows"][0]["name"], "print_config_workflow");
+        assert_eq!(config["workflows"][0]["rows"][0]["agent_type"], "Agent");
+        assert!(config["environment"].get("api_key_set").is_some());
+    }
+
+    #[test]
+    fn test_bash_completions_are_generated() {
+        let mut out = Vec::new();
+        Cli::write_completions(Shell::Bash, &mut out);
+        let script = String::from_utf8(out).unwrap();
+        assert!(!script.is_empty());
+        assert!(script.contains("neonmachines"));
+        assert!(script.contains("completions"));
+    }
+}
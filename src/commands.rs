@@ -1,20 +1,48 @@
-use crate::nm_config::{save_all_nm, WorkflowConfig};
+use crate::nm_config::{remove_agent, save_all_nm, save_all_to, AgentRow, AgentType, WorkflowConfig};
 use crate::runner::AppCommand;
-use crate::app::{ChatMessage, Mode};
+use crate::app::{clear_messages, push_undo, undo_edit, ChatMessage, Mode};
 use tokio::sync::mpsc::UnboundedSender;
 use std::collections::HashMap;
 
-pub fn handle_command(
-    line: &str,
-    workflows: &mut HashMap<String, WorkflowConfig>,
-    active_workflow: &mut String,
-    tx: &UnboundedSender<AppCommand>,
-    messages: &mut Vec<ChatMessage>,
-    selected_agent: &mut Option<usize>,
-    mode: &mut Mode,
-    variables: &mut HashMap<String, String>,
-    messages_scroll: &mut u16,
-) {
+/// The parts of the app's state a slash command can read or change
+pub struct CommandState<'a> {
+    pub workflows: &'a mut HashMap<String, WorkflowConfig>,
+    pub active_workflow: &'a mut String,
+    pub tx: &'a UnboundedSender<AppCommand>,
+    pub messages: &'a mut Vec<ChatMessage>,
+    pub selected_agent: &'a mut Option<usize>,
+    pub mode: &'a mut Mode,
+    pub variables: &'a mut HashMap<String, String>,
+    pub messages_scroll: &'a mut u16,
+    pub cleared_messages: &'a mut Vec<ChatMessage>,
+    pub show_timestamps: &'a mut bool,
+    pub show_reasoning: &'a mut bool,
+    pub options_input: &'a mut String,
+    pub pending_edit: &'a mut Option<std::path::PathBuf>,
+    pub edit_history: &'a mut Vec<(String, WorkflowConfig)>,
+    pub undo_field: &'a mut Option<usize>,
+    pub saved: &'a mut bool, // set once `/save` has written config.nm
+}
+
+pub fn handle_command(line: &str, state: CommandState<'_>) {
+    let CommandState {
+        workflows,
+        active_workflow,
+        tx,
+        messages,
+        selected_agent,
+        mode,
+        variables,
+        messages_scroll,
+        cleared_messages,
+        show_timestamps,
+        show_reasoning,
+        options_input,
+        pending_edit,
+        edit_history,
+        undo_field,
+        saved,
+    } = state;
     let mut it = line.split_whitespace();
     let cmd = it.next().unwrap_or("");
     match cmd {
@@ -24,27 +52,15 @@ pub fn handle_command(
                     cfg.working_dir = path.to_string();
                     let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
                     let _ = save_all_nm(&all);
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Working directory set to '{}'", path),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Working directory set to '{}'", path)));
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "No active workflow selected.".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
                 }
             } else {
                 if let Some(cfg) = workflows.get(active_workflow) {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Current working directory: {}", cfg.working_dir),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Current working directory: {}", cfg.working_dir)));
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "No active workflow selected.".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
                 }
             }
         }
@@ -60,18 +76,11 @@ pub fn handle_command(
                             variables: Some(variables.clone()),
                         });
                     }
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Running all workflows".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "Running all workflows".into()));
                 } else if let Some(cfg) = workflows.get(name).cloned() {
-                    // Collect the rest of the line as optional prompt
-                    let custom_prompt: String = it.collect::<Vec<&str>>().join(" ");
-                    let prompt = if custom_prompt.is_empty() {
-                        "Run".to_string()
-                    } else {
-                        custom_prompt
-                    };
+                    // The rest of the line is either a prompt or key=value template slots
+                    let args: Vec<&str> = it.collect();
+                    let prompt = resolve_run_prompt(&cfg, &args);
 
                     let _ = tx.send(AppCommand::RunWorkflow {
                         workflow_name: cfg.name.clone(),
@@ -81,35 +90,63 @@ pub fn handle_command(
                         variables: Some(variables.clone()),
                     });
                     *active_workflow = name.to_string();
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Running workflow '{}' with prompt: {}", name, prompt),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Running workflow '{}' with prompt: {}", name, prompt)));
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Workflow '{}' not found", name),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", name)));
                 }
             } else {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: "Usage: /run <workflow>|all [optional prompt]".into(),
-                });
+                messages.push(ChatMessage::new("system", "Usage: /run <workflow>|all [optional prompt]".into()));
+            }
+        }
+        "/compare" => {
+            let (Some(first), Some(second)) = (it.next(), it.next()) else {
+                messages.push(ChatMessage::new("system", "Usage: /compare <workflow A> <workflow B> <prompt>".into()));
+                return;
+            };
+            let prompt = it.collect::<Vec<_>>().join(" ");
+            match (workflows.get(first), workflows.get(second)) {
+                (Some(a), Some(b)) if !prompt.is_empty() => {
+                    let _ = tx.send(AppCommand::CompareWorkflows {
+                        prompt: prompt.clone(),
+                        first: a.clone(),
+                        second: b.clone(),
+                        variables: Some(variables.clone()),
+                    });
+                    messages.push(ChatMessage::new("system", format!("Comparing '{}' and '{}' on: {}", first, second, prompt)));
+                }
+                (Some(_), Some(_)) => {
+                    messages.push(ChatMessage::new("system", "Usage: /compare <workflow A> <workflow B> <prompt>".into()));
+                }
+                (None, _) => messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", first))),
+                (_, None) => messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", second))),
+            }
+        }
+        "/save" if line.split_whitespace().nth(1) == Some("as") => {
+            let path = it.skip(1).collect::<Vec<_>>().join(" ");
+            if path.is_empty() {
+                messages.push(ChatMessage::new("system", "Usage: /save as <path>".into()));
+                return;
+            }
+            // Snapshot only: the active config file stays where it is
+            let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+            let path = std::path::PathBuf::from(path);
+            match save_all_to(&all, &path).and_then(|_| std::fs::metadata(&path)) {
+                Ok(meta) => messages.push(ChatMessage::new("system", format!(
+                    "Saved {} workflows to {} ({} bytes)",
+                    all.len(),
+                    path.display(),
+                    meta.len()
+                ))),
+                Err(e) => messages.push(ChatMessage::new("system", format!("Save error: {}", e))),
             }
         }
         "/save" => {
             let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
             if let Err(e) = save_all_nm(&all) {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: format!("Save error: {}", e),
-                });
+                messages.push(ChatMessage::new("system", format!("Save error: {}", e)));
             } else {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: "Saved all workflows to config.nm".into(),
-                });
+                *saved = true;
+                messages.push(ChatMessage::new("system", "Saved all workflows to config.nm".into()));
             }
         }
         "/create" => {
@@ -117,48 +154,43 @@ pub fn handle_command(
                 // ✅ If workflow exists, edit it. Otherwise, create new.
                 if workflows.contains_key(name) {
                     *active_workflow = name.to_string();
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Editing existing workflow '{}'", name),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Editing existing workflow '{}'", name)));
                 } else {
                     let mut new_cfg = WorkflowConfig::default();
                     new_cfg.name = name.to_string();
                     workflows.insert(name.to_string(), new_cfg);
                     *active_workflow = name.to_string();
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Created new workflow '{}'", name),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Created new workflow '{}'", name)));
                 }
             } else {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: "Entering create workflow mode".into(),
-                });
+                messages.push(ChatMessage::new("system", "Entering create workflow mode".into()));
             }
             *mode = Mode::Create;
         }
+        "/workflows" => {
+            messages.push(ChatMessage::new("system", workflows_summary(workflows, active_workflow)));
+        }
         "/workflow" => {
-            messages.push(ChatMessage {
-                from: "system",
-                text: "Entering workflow selection mode".into(),
-            });
+            messages.push(ChatMessage::new("system", "Entering workflow selection mode".into()));
             *mode = Mode::Workflow;
         }
         "/options" => {
-            messages.push(ChatMessage {
-                from: "system",
-                text: "Entering options mode - type your input to send to poml template".into(),
-            });
+            messages.push(ChatMessage::new("system", "Entering options mode - type your input to send to poml template".into()));
             *mode = Mode::Options;
         }
         "/chat" => {
-            messages.push(ChatMessage {
-                from: "system",
-                text: "Entering interactive chat mode with current workflow. Type your message directly.".into(),
-            });
-            *mode = Mode::InteractiveChat;
+            if it.next() == Some("off") {
+                if *mode == Mode::InteractiveChat {
+                    let _ = tx.send(AppCommand::EndChat);
+                    *mode = Mode::Chat;
+                    messages.push(ChatMessage::new("system", "Left interactive chat. Messages run the workflow from scratch again.".into()));
+                } else {
+                    messages.push(ChatMessage::new("system", "Interactive chat is not active.".into()));
+                }
+            } else {
+                messages.push(ChatMessage::new("system", "Entering interactive chat mode with current workflow. Type your message directly; agents keep their history until /chat off or Esc.".into()));
+                *mode = Mode::InteractiveChat;
+            }
         }
         "/agent" => {
             let mut parts = it;
@@ -170,61 +202,147 @@ pub fn handle_command(
                             .iter()
                             .enumerate()
                             .map(|(i, row)| {
-                                format!("{}. {:?} - {}", i, row.agent_type, row.files)
+                                match &row.display_name {
+                                    Some(name) => format!("{}. {} ({:?}) - {}", i, name, row.agent_type, row.files),
+                                    None => format!("{}. {:?} - {}", i, row.agent_type, row.files),
+                                }
                             })
                             .collect();
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: format!(
+                        messages.push(ChatMessage::new(
+                            "system",
+                            format!(
                                 "Available agents in workflow '{}':\n{}",
                                 active_workflow,
                                 agent_list.join("\n")
                             ),
-                        });
+                        ));
                     } else {
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: "No active workflow selected.".into(),
-                        });
+                        messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
+                    }
+                } else if agent_num == "add" {
+                    let type_name = parts.next().unwrap_or("agent");
+                    match (AgentType::from_name(type_name), workflows.get_mut(active_workflow)) {
+                        (Some(agent_type), Some(cfg)) => {
+                            cfg.rows.push(AgentRow {
+                                agent_type,
+                                ..AgentRow::default()
+                            });
+                            let count = cfg.rows.len();
+                            let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                            let text = match save_all_nm(&all) {
+                                Ok(()) => format!("Added {:?} agent {}. Workflow now has {} agents.", agent_type, count - 1, count),
+                                Err(e) => format!("Added agent but failed to save: {}", e),
+                            };
+                            messages.push(ChatMessage::new("system", text));
+                        }
+                        (None, _) => {
+                            messages.push(ChatMessage::new("system", format!("Unknown agent type '{}'. Use agent, validator or parallel.", type_name)));
+                        }
+                        (_, None) => {
+                            messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
+                        }
+                    }
+                } else if agent_num == "remove" {
+                    let index = parts.next().and_then(|s| s.parse::<usize>().ok());
+                    if let (Some(index), Some(cfg)) = (index, workflows.get_mut(active_workflow)) {
+                        if cfg.rows.len() <= 1 {
+                            messages.push(ChatMessage::new("system", "Cannot remove the only agent in a workflow.".into()));
+                        } else if remove_agent(cfg, index).is_some() {
+                            let count = cfg.rows.len();
+                            if selected_agent.is_some_and(|idx| idx >= index) {
+                                *selected_agent = None;
+                            }
+                            let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                            let text = match save_all_nm(&all) {
+                                Ok(()) => format!("Removed agent {}. Workflow now has {} agents.", index, count),
+                                Err(e) => format!("Removed agent but failed to save: {}", e),
+                            };
+                            messages.push(ChatMessage::new("system", text));
+                        } else {
+                            messages.push(ChatMessage::new(
+                                "system",
+                                format!(
+                                    "Agent {} not found. Workflow has {} agents (0-indexed).",
+                                    index,
+                                    cfg.rows.len()
+                                ),
+                            ));
+                        }
+                    } else if index.is_none() {
+                        messages.push(ChatMessage::new("system", "Usage: /agent remove <index>".into()));
+                    } else {
+                        messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
+                    }
+                } else if agent_num == "name" {
+                    let index = parts.next().and_then(|s| s.parse::<usize>().ok());
+                    let name = parts.collect::<Vec<&str>>().join(" ");
+                    match (index, workflows.get_mut(active_workflow)) {
+                        (Some(index), Some(cfg)) if index < cfg.rows.len() => {
+                            cfg.rows[index].display_name = if name.is_empty() { None } else { Some(name.clone()) };
+                            let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                            let text = match save_all_nm(&all) {
+                                Ok(()) if name.is_empty() => format!("Cleared the display name of agent {}.", index),
+                                Ok(()) => format!("Agent {} output will be labeled '{}'.", index, name),
+                                Err(e) => format!("Renamed agent but failed to save: {}", e),
+                            };
+                            messages.push(ChatMessage::new("system", text));
+                        }
+                        (Some(index), Some(cfg)) => {
+                            messages.push(ChatMessage::new(
+                                "system",
+                                format!(
+                                    "Agent {} not found. Workflow has {} agents (0-indexed).",
+                                    index,
+                                    cfg.rows.len()
+                                ),
+                            ));
+                        }
+                        (None, _) => {
+                            messages.push(ChatMessage::new("system", "Usage: /agent name <index> [display name]".into()));
+                        }
+                        (_, None) => {
+                            messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
+                        }
                     }
                 } else if agent_num == "none" {
                     *selected_agent = None;
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Cleared agent selection. Will use default workflow routing.".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "Cleared agent selection. Will use default workflow routing.".into()));
                 } else if let Ok(agent_idx) = agent_num.parse::<usize>() {
-                    if let Some(cfg) = workflows.get(active_workflow) {
+                    let make_default = parts.next() == Some("default");
+                    if let Some(cfg) = workflows.get_mut(active_workflow) {
                         if agent_idx < cfg.rows.len() {
                             *selected_agent = Some(agent_idx);
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: format!(
+                            messages.push(ChatMessage::new(
+                                "system",
+                                format!(
                                     "Selected agent {} for chat. Messages will be routed to this agent.",
                                     agent_idx
                                 ),
-                            });
+                            ));
+                            if make_default {
+                                cfg.active_agent_index = agent_idx;
+                                let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                                let _ = save_all_nm(&all);
+                                messages.push(ChatMessage::new(
+                                    "system",
+                                    format!("Agent {} is now where '{}' starts when no agent is selected.", agent_idx, active_workflow),
+                                ));
+                            }
                         } else {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: format!(
+                            messages.push(ChatMessage::new(
+                                "system",
+                                format!(
                                     "Agent {} not found. Workflow has {} agents (0-indexed).",
                                     agent_idx,
                                     cfg.rows.len()
                                 ),
-                            });
+                            ));
                         }
                     } else {
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: "No active workflow selected.".into(),
-                        });
+                        messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
                     }
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Invalid agent number. Use /agent <number> or /agent none.".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "Invalid agent number. Use /agent <number> or /agent none.".into()));
                 }
             } else {
                 if let Some(_cfg) = workflows.get(active_workflow) {
@@ -233,15 +351,61 @@ pub fn handle_command(
                     } else {
                         "Currently selected: Default routing".to_string()
                     };
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Usage: /agent <number|none|list>\n{}", current),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Usage: /agent <number|none|list|add [type]|remove <index>>\n{}", current)));
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Usage: /agent <number|none|list>".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "Usage: /agent <number|none|list|add [type]|remove <index>>".into()));
+                }
+            }
+        }
+        "/replay" => {
+            let name = it.next().map(|n| n.to_string()).unwrap_or_else(|| active_workflow.clone());
+            if let Some(cfg) = workflows.get(&name) {
+                let path = crate::shared_history::run_record_path(&name);
+                match crate::shared_history::load_run_record(&path) {
+                    Ok(Some(record)) => {
+                        messages.push(ChatMessage::new("system", format!("[replay] Re-running workflow '{}' with prompt: {}", name, record.prompt)));
+                        let _ = tx.send(replay_command(cfg, &record, variables));
+                    }
+                    Ok(None) => {
+                        messages.push(ChatMessage::new("system", format!("No previous run recorded for workflow '{}'", name)));
+                    }
+                    Err(e) => {
+                        messages.push(ChatMessage::new("system", format!("Failed to load run history for '{}': {}", name, e)));
+                    }
+                }
+            } else {
+                messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", name)));
+            }
+        }
+        "/tools" => {
+            let listing = crate::tools::describe_tools(&crate::tools::builtin_tool_specs());
+            messages.push(ChatMessage::new("system", format!("Built-in tools:\n\n{}", listing.trim_end())));
+        }
+        "/retry" => {
+            messages.push(ChatMessage::new("system", "Retrying the last workflow run...".to_string()));
+            let _ = tx.send(AppCommand::Retry);
+        }
+        "/keyring" => {
+            match (it.next(), it.next(), it.next()) {
+                (Some("set"), Some(provider), Some(key)) => {
+                    let text = match crate::secrets::keyring_set(provider, key) {
+                        Ok(()) => format!("Stored API key for '{}' in the system keyring", provider),
+                        Err(e) => format!("Failed to store key: {}", e),
+                    };
+                    messages.push(ChatMessage::new("system", text));
+                }
+                (Some(state @ ("on" | "off")), None, None) => {
+                    if let Some(cfg) = workflows.get_mut(active_workflow) {
+                        cfg.use_keyring = state == "on";
+                        let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                        let _ = save_all_nm(&all);
+                        messages.push(ChatMessage::new("system", format!("Keyring lookup turned {} for '{}'", state, active_workflow)));
+                    } else {
+                        messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
+                    }
+                }
+                _ => {
+                    messages.push(ChatMessage::new("system", "Usage: /keyring set <provider> <key> | /keyring on|off".into()));
                 }
             }
         }
@@ -254,15 +418,9 @@ pub fn handle_command(
                             agent_index: Some(agent_idx as i32),
                             cfg,
                         });
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: format!("Requested history for agent {}", agent_idx),
-                        });
+                        messages.push(ChatMessage::new("system", format!("Requested history for agent {}", agent_idx)));
                     } else {
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: "Usage: /history <agent_index>|all".into(),
-                        });
+                        messages.push(ChatMessage::new("system", "Usage: /history <agent_index>|all".into()));
                     }
                 } else {
                     let _ = tx.send(AppCommand::ShowHistory {
@@ -270,16 +428,10 @@ pub fn handle_command(
                         agent_index: None,
                         cfg,
                     });
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Requested history for all agents".into(),
-                    });
+                    messages.push(ChatMessage::new("system", "Requested history for all agents".into()));
                 }
             } else {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: "No active workflow selected.".into(),
-                });
+                messages.push(ChatMessage::new("system", "No active workflow selected.".into()));
             }
         }
         "/trace" => {
@@ -290,30 +442,18 @@ pub fn handle_command(
                         // Create trace log file to enable tracing
                         let trace_file_path = "neonmachines/.neonmachines_data/trace.log";
                         if let Err(e) = std::fs::File::create(trace_file_path) {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: format!("Failed to enable tracing: {}", e),
-                            });
+                            messages.push(ChatMessage::new("system", format!("Failed to enable tracing: {}", e)));
                         } else {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: "Tracing enabled. AI API calls will be logged to .neonmachines_data/trace.log".to_string(),
-                            });
+                            messages.push(ChatMessage::new("system", "Tracing enabled. AI API calls will be logged to .neonmachines_data/trace.log".to_string()));
                         }
                     }
                     "off" | "disable" => {
                         // Remove trace log file to disable tracing
                         let trace_file_path = "neonmachines/.neonmachines_data/trace.log";
                         if let Err(e) = std::fs::remove_file(trace_file_path) {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: format!("Failed to disable tracing: {}", e),
-                            });
+                            messages.push(ChatMessage::new("system", format!("Failed to disable tracing: {}", e)));
                         } else {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: "Tracing disabled".to_string(),
-                            });
+                            messages.push(ChatMessage::new("system", "Tracing disabled".to_string()));
                         }
                     }
                     "status" => {
@@ -323,47 +463,33 @@ pub fn handle_command(
                         } else {
                             "disabled"
                         };
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: format!("Tracing is {}", status),
-                        });
+                        messages.push(ChatMessage::new("system", format!("Tracing is {}", status)));
                     }
                     "show" => {
-                        let trace_file_path = "neonmachines/.neonmachines_data/trace.log";
+                        let trace_file_path = crate::error::TRACE_LOG;
                         if std::path::Path::new(trace_file_path).exists() {
+                            let args: Vec<&str> = parts.collect();
                             match std::fs::read_to_string(trace_file_path) {
-                                Ok(content) => {
-                                    messages.push(ChatMessage {
-                                        from: "system",
-                                        text: format!("Trace log:\n\n{}", content),
-                                    });
-                                }
+                                Ok(content) => match filter_trace(&content, &args, chrono::Utc::now()) {
+                                    Ok(shown) => {
+                                        messages.push(ChatMessage::new("system", format!("Trace log:\n\n{}", shown)));
+                                    }
+                                    Err(usage) => messages.push(ChatMessage::new("system", usage)),
+                                },
                                 Err(e) => {
-                                    messages.push(ChatMessage {
-                                        from: "system",
-                                        text: format!("Failed to read trace log: {}", e),
-                                    });
+                                    messages.push(ChatMessage::new("system", format!("Failed to read trace log: {}", e)));
                                 }
                             }
                         } else {
-                            messages.push(ChatMessage {
-                                from: "system",
-                                text: "Tracing is disabled. No trace log available.".to_string(),
-                            });
+                            messages.push(ChatMessage::new("system", "Tracing is disabled. No trace log available.".to_string()));
                         }
                     }
                     _ => {
-                        messages.push(ChatMessage {
-                            from: "system",
-                            text: "Usage: /trace [on|off|status|show]".to_string(),
-                        });
+                        messages.push(ChatMessage::new("system", "Usage: /trace [on|off|status|show [last N | since <duration> | grep <pattern>]]".to_string()));
                     }
                 }
             } else {
-                messages.push(ChatMessage {
-                    from: "system",
-                    text: "Usage: /trace [on|off|status|show]".to_string(),
-                });
+                messages.push(ChatMessage::new("system", "Usage: /trace [on|off|status|show [last N | since <duration> | grep <pattern>]]".to_string()));
             }
         }
         "/let" => {
@@ -387,55 +513,342 @@ pub fn handle_command(
                     
                     // Store the variable
                     variables.insert(var_name.to_string(), clean_value.to_string());
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Set variable '{}' to '{}'", var_name, clean_value),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Set variable '{}' to '{}'", var_name, clean_value)));
                 } else {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "Invalid format. Use: /let variable_name=\"value\"".to_string(),
-                    });
+                    messages.push(ChatMessage::new("system", "Invalid format. Use: /let variable_name=\"value\"".to_string()));
                 }
             } else {
                 // List all variables if no assignment
                 if variables.is_empty() {
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: "No variables set.".to_string(),
-                    });
+                    messages.push(ChatMessage::new("system", "No variables set.".to_string()));
                 } else {
                     let var_list: Vec<String> = variables
                         .iter()
                         .map(|(k, v)| format!("{} = \"{}\"", k, v))
                         .collect();
-                    messages.push(ChatMessage {
-                        from: "system",
-                        text: format!("Variables:\n{}", var_list.join("\n")),
-                    });
+                    messages.push(ChatMessage::new("system", format!("Variables:\n{}", var_list.join("\n"))));
                 }
             }
         }
         "/help" => {
             help_command(messages);
         }
+        "/clear" => {
+            if it.next() == Some("undo") {
+                if cleared_messages.is_empty() {
+                    messages.push(ChatMessage::new("system", "Nothing to restore.".to_string()));
+                } else {
+                    *messages = std::mem::take(cleared_messages);
+                    *messages_scroll = crate::app::SCROLL_TO_BOTTOM;
+                }
+            } else {
+                clear_messages(messages, cleared_messages);
+                *messages_scroll = 0;
+            }
+        }
+        "/timestamps" => {
+            match it.next() {
+                Some("on") => *show_timestamps = true,
+                Some("off") => *show_timestamps = false,
+                _ => *show_timestamps = !*show_timestamps,
+            }
+            let state = if *show_timestamps { "on" } else { "off" };
+            messages.push(ChatMessage::new("system", format!("Message timestamps {}.", state)));
+        }
+        "/profile" => {
+            let on = match it.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => !crate::profiler::enabled(),
+            };
+            crate::profiler::set_enabled(on);
+            let text = if on {
+                "Profiling on: runs end with a breakdown of time spent rendering POML, calling the model, running tools and routing."
+            } else {
+                "Profiling off."
+            };
+            messages.push(ChatMessage::new("system", text.to_string()));
+        }
+        "/reasoning" => {
+            match it.next() {
+                Some("on") => *show_reasoning = true,
+                Some("off") => *show_reasoning = false,
+                _ => *show_reasoning = !*show_reasoning,
+            }
+            let state = if *show_reasoning { "expanded" } else { "collapsed" };
+            messages.push(ChatMessage::new("system", format!("Model reasoning {}.", state)));
+        }
+        "/log" => {
+            match (it.next(), it.next()) {
+                (Some("level"), Some(level)) => match crate::logging::set_level(level) {
+                    Ok(effective) => messages.push(ChatMessage::new("system", format!("Log level set to {}", effective))),
+                    Err(e) => messages.push(ChatMessage::new("system", e)),
+                },
+                (Some("level"), None) => {
+                    let current = crate::logging::current_level().unwrap_or_else(|| "not initialized".to_string());
+                    messages.push(ChatMessage::new("system", format!("Log level is {}", current)));
+                }
+                _ => {
+                    messages.push(ChatMessage::new("system", "Usage: /log level [trace|debug|info|warn|error|off]".into()));
+                }
+            }
+        }
+        "/describe" => {
+            let name = it.next().map(str::to_string).unwrap_or_else(|| active_workflow.clone());
+            match workflows.get(&name) {
+                Some(cfg) => {
+                    let _ = tx.send(AppCommand::DescribeWorkflow { cfg: cfg.clone() });
+                    messages.push(ChatMessage::new("system", format!("Describing workflow '{}'…", name)));
+                }
+                None => messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", name))),
+            }
+        }
+        "/graph" => {
+            let name = it.next().map(str::to_string).unwrap_or_else(|| active_workflow.clone());
+            match workflows.get(&name) {
+                Some(cfg) => messages.push(ChatMessage::new("system", crate::nm_config::render_graph(cfg))),
+                None => messages.push(ChatMessage::new("system", format!("Workflow '{}' not found", name))),
+            }
+        }
+        "/model" => match it.next() {
+            Some(model) => {
+                if let Some(cfg) = workflows.get_mut(active_workflow) {
+                    push_undo(edit_history, active_workflow, cfg);
+                    cfg.model = model.to_string();
+                    let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                    let text = match save_all_nm(&all) {
+                        Ok(()) => format!("Workflow '{}' now uses {}", active_workflow, model),
+                        Err(e) => format!("Workflow '{}' now uses {} but saving failed: {}", active_workflow, model, e),
+                    };
+                    messages.push(ChatMessage::new("system", text));
+                } else {
+                    messages.push(ChatMessage::new("system", "No active workflow selected. Use /workflow to select one.".into()));
+                }
+            }
+            // The app fills the picker when it sees the mode change
+            None => *mode = Mode::ModelPicker,
+        },
+        "/undo" => match undo_edit(edit_history, workflows) {
+            Some(name) => {
+                // The field being typed in starts a new undo step
+                *undo_field = None;
+                let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                let text = match save_all_nm(&all) {
+                    Ok(()) => format!("Restored workflow '{}' to before the last edit", name),
+                    Err(e) => format!("Restored workflow '{}' but saving failed: {}", name, e),
+                };
+                messages.push(ChatMessage::new("system", text));
+            }
+            None => messages.push(ChatMessage::new("system", "Nothing to undo.".into())),
+        },
+        "/edit" => match it.next() {
+            Some(file) => {
+                let path = edit_target(file);
+                // Pending edits would be lost when the file is reloaded afterwards
+                if path == crate::nm_config::config_path() {
+                    let all: Vec<WorkflowConfig> = workflows.values().cloned().collect();
+                    if let Err(e) = save_all_nm(&all) {
+                        messages.push(ChatMessage::new("system", format!("Failed to save workflows before editing: {}", e)));
+                        return;
+                    }
+                }
+                *pending_edit = Some(path);
+            }
+            None => messages.push(ChatMessage::new("system", "Usage: /edit <file>".into())),
+        },
+        "/snippet" => match (it.next(), it.next(), it.next()) {
+            (Some("list"), _, _) => {
+                messages.push(ChatMessage::new("system", format!("POML snippets:\n{}", crate::snippets::list())));
+            }
+            (Some("add"), Some(name), Some(file)) => {
+                let text = match crate::snippets::write_snippet(name, std::path::Path::new("prompts"), file) {
+                    Ok(path) => format!("Wrote snippet '{}' to {}", name, path.display()),
+                    Err(e) => e,
+                };
+                messages.push(ChatMessage::new("system", text));
+            }
+            (Some("add"), Some(name), None) => match crate::snippets::find(name) {
+                Some(body) => {
+                    if !options_input.is_empty() && !options_input.ends_with('\n') {
+                        options_input.push('\n');
+                    }
+                    options_input.push_str(body);
+                    *mode = Mode::Options;
+                    messages.push(ChatMessage::new("system", format!("Added snippet '{}' to the options input.", name)));
+                }
+                None => messages.push(ChatMessage::new("system", format!("Unknown snippet '{}'. Use /snippet list to see them.", name))),
+            },
+            _ => messages.push(ChatMessage::new("system", "Usage: /snippet list | /snippet add <name> [file]".into())),
+        },
+        "/poml" => match (it.next(), it.next()) {
+            (Some("vars"), Some(file)) => messages.push(ChatMessage::new("system", poml_vars_report(file))),
+            _ => messages.push(ChatMessage::new("system", "Usage: /poml vars <file>".into())),
+        },
+        "/version" => {
+            messages.push(ChatMessage::new("system", build_info()));
+        }
         "/scroll" => {
             // Scroll to the newest line of text
-            *messages_scroll = messages.len() as u16;
-            messages.push(ChatMessage {
-                from: "system",
-                text: "Scrolled to the newest message.".to_string(),
-            });
+            *messages_scroll = crate::app::SCROLL_TO_BOTTOM;
+            messages.push(ChatMessage::new("system", "Scrolled to the newest message.".to_string()));
         }
         _ => {
-            messages.push(ChatMessage {
-                from: "system",
-                text: "Unknown command. Type /help for available commands.".into(),
-            });
+            messages.push(ChatMessage::new("system", "Unknown command. Type /help for available commands.".into()));
         }
     }
 }
 
+/// File `/edit` opens: a path that exists or names the config file as given,
+/// anything else as a POML file under `prompts/`
+fn edit_target(file: &str) -> std::path::PathBuf {
+    let path = std::path::PathBuf::from(file);
+    if path.exists() || path == crate::nm_config::config_path() {
+        path
+    } else {
+        std::path::Path::new("prompts").join(file)
+    }
+}
+
+/// One line per workflow, sorted by name, with the active one starred and annotated
+fn workflows_summary(workflows: &HashMap<String, WorkflowConfig>, active_workflow: &str) -> String {
+    if workflows.is_empty() {
+        return "No workflows loaded.".to_string();
+    }
+    let mut names: Vec<&String> = workflows.keys().collect();
+    names.sort();
+    let mut summary = format!("Workflows ({}):", names.len());
+    for name in names {
+        let cfg = &workflows[name];
+        let active = name == active_workflow;
+        summary.push_str(&format!(
+            "\n{} {} - {}, {} agent{}, maximum_traversals:{}{}",
+            if active { "*" } else { " " },
+            name,
+            cfg.model,
+            cfg.rows.len(),
+            if cfg.rows.len() == 1 { "" } else { "s" },
+            cfg.maximum_traversals,
+            if active { " (active)" } else { "" }
+        ));
+    }
+    summary
+}
+
+/// The `<let>` variables in `prompts/<file>`, marking the ones filled in automatically
+fn poml_vars_report(file: &str) -> String {
+    let path = std::path::Path::new("prompts").join(file);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => return format!("Failed to read {}: {}", path.display(), e),
+    };
+    let vars = crate::agents::poml_variables(&content);
+    if vars.is_empty() {
+        return format!("{} declares no <let> variables", file);
+    }
+    let mut report = format!("Variables in {}:", file);
+    for var in vars {
+        let injected = if var.auto_injected { " (auto-injected)" } else { "" };
+        report.push_str(&format!("\n  {}{} = {:?}", var.name, injected, var.content));
+    }
+    report
+}
+
+const TRACE_SHOW_USAGE: &str = "Usage: /trace show [last N | since <duration> | grep <pattern>], e.g. since 10m";
+
+/// The trace lines `/trace show <args>` asks for: the last N, those written within a
+/// duration of `now`, or those matching a regex (a plain substring if it isn't one).
+/// Entries end with a `Timestamp:` line, which dates every line before it; lines after
+/// the last one take its time.
+fn filter_trace(content: &str, args: &[&str], now: chrono::DateTime<chrono::Utc>) -> Result<String, String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let kept: Vec<&str> = match args {
+        [] => lines,
+        ["last", n] => {
+            let n = n.parse::<usize>().map_err(|_| TRACE_SHOW_USAGE.to_string())?;
+            lines[lines.len().saturating_sub(n)..].to_vec()
+        }
+        ["since", duration] => {
+            let cutoff = now - parse_since(duration).ok_or_else(|| TRACE_SHOW_USAGE.to_string())?;
+            let mut time = None;
+            let mut dated: Vec<(&str, Option<chrono::DateTime<chrono::Utc>>)> = Vec::with_capacity(lines.len());
+            for line in lines.iter().rev() {
+                if let Some(stamp) = line.strip_prefix("Timestamp: ") {
+                    time = parse_trace_time(stamp).or(time);
+                }
+                dated.push((*line, time));
+            }
+            let last = lines.iter().rev().find_map(|l| l.strip_prefix("Timestamp: ").and_then(parse_trace_time));
+            dated.into_iter()
+                .rev()
+                .filter(|(_, t)| t.or(last).is_some_and(|t| t >= cutoff))
+                .map(|(line, _)| line)
+                .collect()
+        }
+        ["grep", pattern @ ..] if !pattern.is_empty() => {
+            let pattern = pattern.join(" ");
+            let re = regex::Regex::new(&pattern).unwrap_or_else(|_| regex::Regex::new(&regex::escape(&pattern)).unwrap());
+            lines.into_iter().filter(|line| re.is_match(line)).collect()
+        }
+        _ => return Err(TRACE_SHOW_USAGE.to_string()),
+    };
+    if kept.is_empty() {
+        return Ok("(no matching trace lines)".to_string());
+    }
+    Ok(kept.join("\n"))
+}
+
+/// `30s`, `10m`, `2h` or `1d`; a bare number is seconds
+fn parse_since(value: &str) -> Option<chrono::Duration> {
+    let (digits, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+    let n = digits.parse::<i64>().ok()?;
+    match unit {
+        "" | "s" => chrono::Duration::try_seconds(n),
+        "m" => chrono::Duration::try_minutes(n),
+        "h" => chrono::Duration::try_hours(n),
+        "d" => chrono::Duration::try_days(n),
+        _ => None,
+    }
+}
+
+/// A `Timestamp:` value as written by `chrono::Utc::now()`, e.g. `2026-10-16 11:04:16.123 UTC`
+fn parse_trace_time(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let value = value.trim().trim_end_matches(" UTC");
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").ok().map(|t| t.and_utc())
+}
+
+/// `Neonmachines v<version> (<commit>, <profile>)`
+pub fn version_line() -> String {
+    format!(
+        "Neonmachines v{} ({}, {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_HASH"),
+        env!("BUILD_PROFILE")
+    )
+}
+
+/// Whether `python <args>` runs and exits successfully
+fn python_succeeds(args: &[&str]) -> bool {
+    std::process::Command::new("python")
+        .args(args)
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+fn build_info() -> String {
+    let available = |ok: bool| if ok { "available" } else { "not found" };
+    let python = python_succeeds(&["--version"]);
+    let poml = python && python_succeeds(&["-m", "poml", "--help"]);
+    format!(
+        "{}\nversion: {}\ncommit: {}\nprofile: {}\npython: {}\npoml: {}",
+        version_line(),
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_HASH"),
+        env!("BUILD_PROFILE"),
+        available(python),
+        available(poml),
+    )
+}
+
 fn help_command_fullscreen() -> String {
     let help_text = r#"
 🚀 NEONMACHINES - AI WORKFLOW ORCHESTRATION FRAMEWORK
@@ -444,23 +857,55 @@ fn help_command_fullscreen() -> String {
 
 /cwd [path]          - Show or set working directory
 /run [workflow|all] [prompt] - Run a workflow or all workflows
+/run <workflow> key=value... - Fill the workflow's default prompt template
 /save                - Save all workflows to config.nm
+/save as <path>      - Write all workflows to another file, keeping config.nm active
 /create [name]       - Create or edit a workflow
 /workflow            - Enter workflow selection mode
+/workflows           - List workflows with model, agent count and traversal limit
 /options             - Enter options mode for poml template input
-/chat                - Enter interactive chat mode
+/chat [off]          - Chat with a live workflow graph, or leave it
 /agent [number|none|list] - Select agent for routing
+/agent <number> default  - Also make it the workflow's start agent
+/agent add [type]        - Append an agent (agent|validator|parallel)
+/agent remove <index>    - Remove an agent and fix its routes
+/agent name <index> [name] - Label an agent's output, e.g. Planner
+/keyring set <provider> <key> - Store an API key in the system keyring
+/keyring on|off      - Use the keyring for this workflow's API key
+/replay [workflow]   - Re-run the last recorded prompt of a workflow
+/retry               - Re-send the last workflow run unchanged
+/tools               - List the built-in tools agents can call
+/compare <a> <b> <prompt> - Run two workflows on the same prompt and compare them
+/edit <file>         - Open a POML file (under prompts/) or config.nm in $EDITOR
+/model [id]          - Pick the active workflow's model from a list, or set it directly
+/undo                - Revert the last workflow edit (create mode or /model)
+/profile [on|off]    - Time each phase of the next runs
+/snippet list        - Show the bundled POML snippets
+/snippet add <name> [file] - Add a snippet to the options input, or write it to prompts/<file>
 /history [agent|all] - Show execution history
 /trace [on|off|show] - Enable/disable/view tracing
+/trace show last N | since 10m | grep <regex> - Filter the trace
 /let [name=value]    - Set or list variables
 /scroll              - Scroll to the newest line of text
+/clear [undo]        - Clear the chat, or restore what was last cleared
+/timestamps [on|off] - Show the time each message arrived
+/reasoning [on|off]  - Expand or collapse model reasoning
+/version             - Show version and build details for bug reports
+/graph [workflow]    - Show who routes to whom on success/failure
+/describe [workflow] - Summarize a workflow in plain English
+/poml vars <file>    - List the <let> variables a prompt file declares
+/log level [level]   - Show or change log verbosity without restarting
 /help                - Show this help message (you're here!)
 
 🎮 NAVIGATION:
 Enter - Submit message
 Shift+Enter - Insert newline
 Ctrl+C or Ctrl+D - Quit
-Ctrl+L - Clear screen
+Ctrl+L - Clear messages (/clear undo restores them)
+Ctrl+A / Ctrl+E - Jump to start / end of line
+Ctrl+U / Ctrl+K - Delete to start / end of line
+Ctrl+W - Delete previous word
+Ctrl+P / Ctrl+N - Scroll to previous / next agent output
 Tab - Command completion
 
 💡 EXAMPLES:
@@ -487,29 +932,106 @@ Press any key to continue...
     help_text.to_string()
 }
 
+/// Replace each `{{key}}` in `template` with its value; unknown slots are left as-is
+fn fill_template(template: &str, values: &HashMap<String, String>) -> String {
+    let mut out = template.to_string();
+    for (key, value) in values {
+        out = out.replace(&format!("{{{{{}}}}}", key), value);
+    }
+    out
+}
+
+/// Prompt for `/run <workflow> [args]`: `key=value` args fill slots of the default
+/// prompt, any other words replace it, and with neither the template (or "Run") is used
+pub fn resolve_run_prompt(cfg: &WorkflowConfig, args: &[&str]) -> String {
+    let template = cfg.default_prompt.as_deref().unwrap_or("Run");
+    let mut slots = HashMap::new();
+    let mut words = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if template.contains(&format!("{{{{{}}}}}", key)) => {
+                slots.insert(key.to_string(), value.trim_matches('"').to_string());
+            }
+            _ => words.push(*arg),
+        }
+    }
+    if words.is_empty() {
+        fill_template(template, &slots)
+    } else {
+        fill_template(&words.join(" "), &slots)
+    }
+}
+
+/// Build a run that repeats a recorded prompt, labeled as a replay
+fn replay_command(
+    cfg: &WorkflowConfig,
+    record: &crate::shared_history::RunRecord,
+    variables: &HashMap<String, String>,
+) -> AppCommand {
+    AppCommand::RunWorkflow {
+        workflow_name: format!("{} (replay)", cfg.name),
+        prompt: record.prompt.clone(),
+        cfg: cfg.clone(),
+        start_agent: record.start_agent,
+        variables: Some(variables.clone()),
+    }
+}
+
 fn help_command(messages: &mut Vec<ChatMessage>) {
     let help_text = r#"
 Available commands:
 
 /cwd [path]          - Show or set working directory
 /run [workflow|all] [prompt] - Run a workflow or all workflows
+/run <workflow> key=value... - Fill the workflow's default prompt template
 /save                - Save all workflows to config.nm
+/save as <path>      - Write all workflows to another file, keeping config.nm active
 /create [name]       - Create or edit a workflow
 /workflow            - Enter workflow selection mode
+/workflows           - List workflows with model, agent count and traversal limit
 /options             - Enter options mode for poml template input
-/chat                - Enter interactive chat mode
+/chat [off]          - Chat with a live workflow graph, or leave it
 /agent [number|none|list] - Select agent for routing
+/agent <number> default  - Also make it the workflow's start agent
+/agent add [type]        - Append an agent (agent|validator|parallel)
+/agent remove <index>    - Remove an agent and fix its routes
+/agent name <index> [name] - Label an agent's output, e.g. Planner
+/keyring set <provider> <key> - Store an API key in the system keyring
+/keyring on|off      - Use the keyring for this workflow's API key
+/replay [workflow]   - Re-run the last recorded prompt of a workflow
+/retry               - Re-send the last workflow run unchanged
+/tools               - List the built-in tools agents can call
+/compare <a> <b> <prompt> - Run two workflows on the same prompt and compare them
+/edit <file>         - Open a POML file (under prompts/) or config.nm in $EDITOR
+/model [id]          - Pick the active workflow's model from a list, or set it directly
+/undo                - Revert the last workflow edit (create mode or /model)
+/profile [on|off]    - Time each phase of the next runs
+/snippet list        - Show the bundled POML snippets
+/snippet add <name> [file] - Add a snippet to the options input, or write it to prompts/<file>
 /history [agent|all] - Show execution history
 /trace [on|off|show] - Enable/disable/view tracing
+/trace show last N | since 10m | grep <regex> - Filter the trace
 /let [name=value]    - Set or list variables
 /scroll              - Scroll to the newest line of text
+/clear [undo]        - Clear the chat, or restore what was last cleared
+/timestamps [on|off] - Show the time each message arrived
+/reasoning [on|off]  - Expand or collapse model reasoning
+/version             - Show version and build details for bug reports
+/graph [workflow]    - Show who routes to whom on success/failure
+/describe [workflow] - Summarize a workflow in plain English
+/poml vars <file>    - List the <let> variables a prompt file declares
+/log level [level]   - Show or change log verbosity without restarting
 /help                - Show this help message
 
 Navigation:
 Enter - Submit message
 Shift+Enter - Insert newline
 Ctrl+C or Ctrl+D - Quit
-Ctrl+L - Clear screen
+Ctrl+L - Clear messages (/clear undo restores them)
+Ctrl+A / Ctrl+E - Jump to start / end of line
+Ctrl+U / Ctrl+K - Delete to start / end of line
+Ctrl+W - Delete previous word
+Ctrl+P / Ctrl+N - Scroll to previous / next agent output
 Tab - Command completion
 
 Examples:
@@ -521,8 +1043,200 @@ Examples:
 /trace on - Enable API call tracing
 /let primary_task="fix security vulnerabilities" - Set primary task
 "#;
-    messages.push(ChatMessage {
-        from: "system",
-        text: help_text.to_string(),
-    });
+    messages.push(ChatMessage::new("system", help_text.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_history::{load_run_record, save_run_record, RunRecord};
+
+    #[test]
+    fn test_trace_show_filters_by_pattern_last_and_since() {
+        let trace = "\nAPI Request Started - Model: a/one, Temperature: 0.7\nTimestamp: 2026-10-16 09:00:00.5 UTC\n\
+API Request Failed - Duration: 1s, Error: timeout\nTimestamp: 2026-10-16 09:00:01 UTC\n\
+\nAPI Request Started - Model: b/two, Temperature: 0.2\nTimestamp: 2026-10-16 09:55:00.25 UTC\n\
+API Request Succeeded - Duration: 2s, Response: {}\nTimestamp: 2026-10-16 09:55:02 UTC\n";
+        let now = parse_trace_time("2026-10-16 10:00:00 UTC").unwrap();
+
+        let started = filter_trace(trace, &["grep", "Started"], now).unwrap();
+        assert_eq!(started.lines().count(), 2);
+        assert!(started.contains("a/one") && started.contains("b/two"));
+        // Regex syntax works, and an invalid regex is matched literally
+        assert_eq!(filter_trace(trace, &["grep", "Model:", "b/.*"], now).unwrap().lines().count(), 1);
+        assert!(filter_trace(trace, &["grep", "{}"], now).unwrap().contains("Succeeded"));
+
+        let last = filter_trace(trace, &["last", "2"], now).unwrap();
+        assert_eq!(last, "API Request Succeeded - Duration: 2s, Response: {}\nTimestamp: 2026-10-16 09:55:02 UTC");
+
+        // Only the second call happened in the last 10 minutes
+        let recent = filter_trace(trace, &["since", "10m"], now).unwrap();
+        assert!(recent.contains("b/two") && !recent.contains("a/one") && !recent.contains("timeout"));
+        assert!(filter_trace(trace, &["since", "2h"], now).unwrap().contains("timeout"));
+
+        assert!(filter_trace(trace, &["since", "soon"], now).is_err());
+        assert!(filter_trace(trace, &["last"], now).is_err());
+    }
+
+    #[test]
+    fn test_replay_reuses_stored_prompt() {
+        let path = std::env::temp_dir().join(format!("nm_replay_2359_{}.json", std::process::id()));
+        let record = RunRecord {
+            workflow: "default".into(),
+            prompt: "summarize the README".into(),
+            start_agent: Some(1),
+            messages: Vec::new(),
+        };
+        save_run_record(&record, &path).unwrap();
+        let loaded = load_run_record(&path).unwrap().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let cfg = WorkflowConfig::default();
+        match replay_command(&cfg, &loaded, &HashMap::new()) {
+            AppCommand::RunWorkflow { workflow_name, prompt, start_agent, .. } => {
+                assert_eq!(prompt, "summarize the README");
+                assert_eq!(start_agent, Some(1));
+                assert_eq!(workflow_name, "default (replay)");
+            }
+            _ => panic!("expected a RunWorkflow command"),
+        }
+
+        assert!(load_run_record(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_run_prompt_template_and_fallback() {
+        let mut cfg = WorkflowConfig::default();
+        assert_eq!(resolve_run_prompt(&cfg, &[]), "Run");
+        assert_eq!(resolve_run_prompt(&cfg, &["fix", "x=1"]), "fix x=1");
+
+        cfg.default_prompt = Some("Review {{file}} for {{issue}}".into());
+        assert_eq!(resolve_run_prompt(&cfg, &[]), "Review {{file}} for {{issue}}");
+        assert_eq!(
+            resolve_run_prompt(&cfg, &["file=src/main.rs", "issue=\"panics\""]),
+            "Review src/main.rs for panics"
+        );
+        // Free text replaces the template, slots still apply to it
+        assert_eq!(resolve_run_prompt(&cfg, &["Summarize", "{{file}}", "file=a.rs"]), "Summarize a.rs");
+    }
+
+    #[test]
+    fn test_version_matches_crate_version() {
+        let manifest = include_str!("../Cargo.toml");
+        let version = manifest
+            .lines()
+            .find_map(|line| line.strip_prefix("version = \""))
+            .and_then(|rest| rest.strip_suffix('"'))
+            .expect("package version in Cargo.toml");
+        assert_eq!(env!("CARGO_PKG_VERSION"), version);
+        assert!(version_line().starts_with(&format!("Neonmachines v{} (", version)));
+    }
+
+    #[test]
+    fn test_chat_off_ends_interactive_session() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut workflows = HashMap::new();
+        let mut active = "default".to_string();
+        let mut messages = Vec::new();
+        let mut mode = Mode::Chat;
+        let mut run = |line: &str, mode: &mut Mode, messages: &mut Vec<ChatMessage>| {
+            handle_command(
+                line,
+                CommandState {
+                    workflows: &mut workflows,
+                    active_workflow: &mut active,
+                    tx: &tx,
+                    messages,
+                    selected_agent: &mut None,
+                    mode,
+                    variables: &mut HashMap::new(),
+                    messages_scroll: &mut 0,
+                    cleared_messages: &mut Vec::new(),
+                    show_timestamps: &mut false,
+                    show_reasoning: &mut false,
+                    options_input: &mut String::new(),
+                    pending_edit: &mut None,
+                    edit_history: &mut Vec::new(),
+                    undo_field: &mut None,
+                    saved: &mut false,
+                },
+            )
+        };
+
+        run("/chat", &mut mode, &mut messages);
+        assert_eq!(mode, Mode::InteractiveChat);
+        assert!(rx.try_recv().is_err());
+
+        run("/chat off", &mut mode, &mut messages);
+        assert_eq!(mode, Mode::Chat);
+        assert!(matches!(rx.try_recv(), Ok(AppCommand::EndChat)));
+
+        // Nothing to tear down once chat is already off
+        run("/chat off", &mut mode, &mut messages);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(messages.last().unwrap().text, "Interactive chat is not active.");
+    }
+
+    #[test]
+    fn test_workflows_summary_lists_sorted_with_active() {
+        let mut review = WorkflowConfig::default();
+        review.name = "review".into();
+        review.model = "test/model".into();
+        review.maximum_traversals = 5;
+        let mut build = WorkflowConfig::default();
+        build.name = "build".into();
+        build.model = "openai/gpt-4o-mini".into();
+        build.rows = vec![AgentRow::default(), AgentRow::default()];
+        let workflows = HashMap::from([("review".to_string(), review), ("build".to_string(), build)]);
+
+        assert_eq!(
+            workflows_summary(&workflows, "review"),
+            "Workflows (2):\n  build - openai/gpt-4o-mini, 2 agents, maximum_traversals:20\n* review - test/model, 1 agent, maximum_traversals:5 (active)"
+        );
+        assert_eq!(workflows_summary(&HashMap::new(), "default"), "No workflows loaded.");
+    }
+
+    #[test]
+    fn test_save_as_writes_snapshot() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut cfg = WorkflowConfig::default();
+        cfg.name = "snapshot".into();
+        cfg.model = "openai/gpt-4o-mini".into();
+        let mut workflows = HashMap::from([("snapshot".to_string(), cfg)]);
+        let mut messages = Vec::new();
+        let path = std::env::temp_dir().join(format!("nm_save_as_{}.nm", std::process::id()));
+
+        handle_command(
+            &format!("/save as {}", path.display()),
+            CommandState {
+                workflows: &mut workflows,
+                active_workflow: &mut "snapshot".to_string(),
+                tx: &tx,
+                messages: &mut messages,
+                selected_agent: &mut None,
+                mode: &mut Mode::Chat,
+                variables: &mut HashMap::new(),
+                messages_scroll: &mut 0,
+                cleared_messages: &mut Vec::new(),
+                show_timestamps: &mut false,
+                show_reasoning: &mut false,
+                options_input: &mut String::new(),
+                pending_edit: &mut None,
+                edit_history: &mut Vec::new(),
+                undo_field: &mut None,
+                saved: &mut false,
+            },
+        );
+
+        let bytes = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(
+            messages.last().unwrap().text,
+            format!("Saved 1 workflows to {} ({} bytes)", path.display(), bytes)
+        );
+        let reloaded = crate::nm_config::load_all_from(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded[0].name, "snapshot");
+        assert_eq!(reloaded[0].model, "openai/gpt-4o-mini");
+    }
 }
@@ -5,6 +5,15 @@ use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, Paragraph};
 use ratatui::Frame;
 
+/// Number of workflow-level fields shown before the agent rows
+pub const BASE_FIELDS: usize = 9;
+/// Number of editable fields per agent row
+pub const AGENT_FIELDS: usize = 9;
+/// Agent fields, by position in a row as `render_create` lays them out, that change
+/// the workflow on every keystroke: type, files, max iterations, on success, on
+/// failure and vote count. The rest are applied on submit.
+pub const LIVE_AGENT_FIELDS: [usize; 6] = [0, 1, 2, 3, 4, 5];
+
 pub fn render_create(
     f: &mut Frame,
     cfg: &WorkflowConfig,
@@ -15,7 +24,7 @@ pub fn render_create(
     let mut lines = Vec::<Line>::new();
 
     lines.push(Line::from(vec![Span::styled(
-        "Hint: add role:file mappings like role:system:security.poml;role:user:query.poml",
+        "Hint: add role:file mappings like role:system:security.poml;role:user:query.poml (Ctrl+O edits the focused agent's POML)",
         Style::default().fg(Color::Gray),
     )]));
 
@@ -115,13 +124,69 @@ pub fn render_create(
         wd_style,
     )]));
 
+    // System preamble (newlines shown as \n)
+    let pre_style = if focus == 6 {
+        Style::default().fg(Color::Black).bg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let pre_val = if focus == 6 && !input.is_empty() {
+        input.to_string()
+    } else {
+        cfg.system_preamble
+            .as_deref()
+            .map(crate::nm_config::escape_nm_value)
+            .unwrap_or_default()
+    };
+    lines.push(Line::from(vec![Span::styled(
+        format!("System Preamble: {}", pre_val),
+        pre_style,
+    )]));
+
+    // Max tokens per completion (empty for the provider default)
+    let tokens_style = if focus == 7 {
+        Style::default().fg(Color::Black).bg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let tokens_val = if focus == 7 && !input.is_empty() {
+        input.to_string()
+    } else {
+        cfg.max_tokens.map(|n| n.to_string()).unwrap_or_default()
+    };
+    lines.push(Line::from(vec![Span::styled(
+        format!("Max Tokens: {}", tokens_val),
+        tokens_style,
+    )]));
+
+    // Stop sequences, `;`-separated with newlines shown as \n
+    let stop_style = if focus == 8 {
+        Style::default().fg(Color::Black).bg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let stop_val = if focus == 8 && !input.is_empty() {
+        input.to_string()
+    } else {
+        stop_list(&cfg.stop)
+    };
+    lines.push(Line::from(vec![Span::styled(
+        format!("Stop Sequences: {}", stop_val),
+        stop_style,
+    )]));
+
     // Agents
     for (i, row) in cfg.rows.iter().enumerate() {
-        let type_focus = focus == (i * 5 + 6);
-        let files_focus = focus == (i * 5 + 7);
-        let max_focus = focus == (i * 5 + 8);
-        let success_focus = focus == (i * 5 + 9);
-        let failure_focus = focus == (i * 5 + 10);
+        let base = i * AGENT_FIELDS + BASE_FIELDS;
+        let type_focus = focus == base;
+        let files_focus = focus == base + 1;
+        let max_focus = focus == base + 2;
+        let success_focus = focus == base + 3;
+        let failure_focus = focus == base + 4;
+        let votes_focus = focus == base + 5;
+        let outputs_focus = focus == base + 6;
+        let tokens_focus = focus == base + 7;
+        let stop_focus = focus == base + 8;
 
         let type_style = if type_focus {
             Style::default().fg(Color::Black).bg(Color::Cyan)
@@ -183,6 +248,46 @@ pub fn render_create(
             format!("  On Failure {}: {}", i + 1, failure_val),
             if failure_focus { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() },
         )]));
+
+        let votes_val = if votes_focus && !input.is_empty() {
+            input.to_string()
+        } else {
+            row.vote_count.to_string()
+        };
+        lines.push(Line::from(vec![Span::styled(
+            format!("  Votes {}: {}", i + 1, votes_val),
+            if votes_focus { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() },
+        )]));
+
+        let outputs_val = if outputs_focus && !input.is_empty() {
+            input.to_string()
+        } else {
+            crate::nm_config::join_injections(&row.output_injections)
+        };
+        lines.push(Line::from(vec![Span::styled(
+            format!("  Outputs {}: {}", i + 1, outputs_val),
+            if outputs_focus { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() },
+        )]));
+
+        let tokens_val = if tokens_focus && !input.is_empty() {
+            input.to_string()
+        } else {
+            row.max_tokens.map(|n| n.to_string()).unwrap_or_default()
+        };
+        lines.push(Line::from(vec![Span::styled(
+            format!("  Max Tokens {}: {}", i + 1, tokens_val),
+            if tokens_focus { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() },
+        )]));
+
+        let stop_val = if stop_focus && !input.is_empty() {
+            input.to_string()
+        } else {
+            stop_list(&row.stop)
+        };
+        lines.push(Line::from(vec![Span::styled(
+            format!("  Stop {}: {}", i + 1, stop_val),
+            if stop_focus { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() },
+        )]));
     }
 
     let p = Paragraph::new(Text::from(lines)).block(
@@ -192,4 +297,9 @@ pub fn render_create(
             .border_style(Style::default().fg(Color::Blue)),
     );
     f.render_widget(p, area);
-}
\ No newline at end of file
+}
+
+/// Stop sequences as typed in Create mode: `;`-separated, newlines as `\n`
+fn stop_list(stop: &[String]) -> String {
+    stop.iter().map(|s| crate::nm_config::escape_nm_value(s)).collect::<Vec<_>>().join(";")
+}
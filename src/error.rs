@@ -3,6 +3,9 @@ use tracing::info;
 use tracing::warn;
 use tracing::debug;
 use tracing::error;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Error, Debug, Clone)]
 pub enum NeonmachinesError {
@@ -24,6 +27,9 @@ pub enum NeonmachinesError {
     #[error("Rate limiting error: {0}")]
     RateLimit(String),
 
+    #[error("HTTP {status}: {message}")]
+    Http { status: u16, message: String },
+
     #[error("POML execution error: {0}")]
     PomlExecution(String),
 
@@ -179,8 +185,13 @@ pub struct RetryConfig {
     pub base_delay_ms: u64,
     pub max_delay_ms: u64,
     pub backoff_factor: f64,
+    pub budget: Option<RetryBudget>,
+    pub request_timeout: Option<Duration>, // each attempt fails as a retryable timeout past this
 }
 
+/// Per-request timeout used when a workflow doesn't set `request_timeout_secs`
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+
 impl Default for RetryConfig {
     fn default() -> Self {
         RetryConfig {
@@ -188,10 +199,58 @@ impl Default for RetryConfig {
             base_delay_ms: 1000,
             max_delay_ms: 10000,
             backoff_factor: 2.0,
+            budget: None,
+            request_timeout: Some(Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS)),
         }
     }
 }
 
+/// Await `request`, giving up with a retryable timeout error once `timeout` passes
+pub async fn with_request_timeout<T, F>(timeout: Option<Duration>, request: F) -> Result<T, NeonmachinesError>
+where
+    F: std::future::Future<Output = Result<T, NeonmachinesError>>,
+{
+    match timeout {
+        Some(limit) => tokio::time::timeout(limit, request).await.unwrap_or_else(|_| {
+            Err(NeonmachinesError::Network(format!("request timeout after {}s", limit.as_secs_f64())))
+        }),
+        None => request.await,
+    }
+}
+
+/// Retries shared by every call in one workflow run; once spent, calls make a
+/// single attempt and fail fast instead of retrying
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    remaining: Arc<AtomicUsize>,
+    exhausted_reported: Arc<AtomicBool>,
+}
+
+impl RetryBudget {
+    pub fn new(retries: usize) -> Self {
+        RetryBudget {
+            remaining: Arc::new(AtomicUsize::new(retries)),
+            exhausted_reported: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Take one retry from the budget, `false` once it is spent
+    pub fn try_consume(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining.load(Ordering::SeqCst) == 0
+    }
+
+    /// `true` for the first caller after the budget ran out, so it is reported once
+    pub fn take_exhausted_notice(&self) -> bool {
+        self.is_exhausted() && !self.exhausted_reported.swap(true, Ordering::SeqCst)
+    }
+}
+
 /// Error types that are safe to retry
 #[derive(Debug, Clone)]
 pub enum RetryableErrorType {
@@ -202,8 +261,33 @@ pub enum RetryableErrorType {
     ResourceExhausted,
 }
 
-/// Check if an error is retryable
-pub fn is_retryable_error<E: std::fmt::Debug>(error: &E) -> Option<RetryableErrorType> {
+/// Retry decision for an HTTP status: transient statuses are retried, client errors
+/// such as a bad request or a rejected key never are
+pub fn classify_status(status: u16) -> Option<RetryableErrorType> {
+    match status {
+        408 => Some(RetryableErrorType::TimeoutError),
+        429 => Some(RetryableErrorType::RateLimited),
+        503 => Some(RetryableErrorType::ResourceExhausted),
+        500 | 502 | 504 => Some(RetryableErrorType::TemporaryFailure),
+        _ => None,
+    }
+}
+
+/// Check if an error is retryable. Structured errors are classified by HTTP status
+/// or error kind; the substring heuristic is only a fallback for opaque errors.
+pub fn is_retryable_error<E: std::fmt::Debug + 'static>(error: &E) -> Option<RetryableErrorType> {
+    if let Some(err) = (error as &dyn std::any::Any).downcast_ref::<NeonmachinesError>() {
+        match err {
+            NeonmachinesError::Http { status, .. } => return classify_status(*status),
+            NeonmachinesError::Network(msg) if msg.contains("timeout") || msg.contains("timed out") => {
+                return Some(RetryableErrorType::TimeoutError);
+            }
+            NeonmachinesError::Network(_) => return Some(RetryableErrorType::NetworkError),
+            NeonmachinesError::RateLimit(_) => return Some(RetryableErrorType::RateLimited),
+            _ => {}
+        }
+    }
+
     let error_str = format!("{:?}", error);
     
     if error_str.contains("network") || error_str.contains("connection") || error_str.contains("timeout") {
@@ -228,7 +312,7 @@ pub async fn retry_with_backoff<T, F, E>(
 ) -> Result<T, E>
 where
     F: Fn() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, E>> + Send>>,
-    E: std::fmt::Debug + Clone,
+    E: std::fmt::Debug + Clone + 'static,
 {
     let mut delay_ms = config.base_delay_ms;
     let mut last_error: Option<E> = None;
@@ -260,6 +344,12 @@ where
                     );
 
                     if attempt < config.max_attempts - 1 {
+                        if let Some(budget) = &config.budget {
+                            if !budget.try_consume() {
+                                warn!("Retry budget exhausted, not retrying");
+                                break;
+                            }
+                        }
                         tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
                         delay_ms = (delay_ms as f64 * config.backoff_factor).min(config.max_delay_ms as f64) as u64;
                     }
@@ -359,7 +449,7 @@ pub async fn retry_with_circuit_breaker<T, F, E>(
 ) -> Result<T, E>
 where
     F: Fn() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, E>> + Send>>,
-    E: std::fmt::Debug + Clone + From<String>,
+    E: std::fmt::Debug + Clone + From<String> + 'static,
 {
     // Check if circuit breaker is open
     if !circuit_breaker.should_allow_request() {
@@ -383,21 +473,129 @@ where
     result
 }
 
+/// Model vendors known to honor `seed`; OpenRouter forwards it, but other
+/// upstreams drop it silently
+pub const SEED_PROVIDERS: &[&str] = &["openai/", "mistralai/", "deepseek/", "x-ai/"];
+
+/// Whether a `seed` sent for `model` makes its answers reproducible
+pub fn seed_honored(model: &str) -> bool {
+    SEED_PROVIDERS.iter().any(|prefix| model.starts_with(prefix))
+}
+
+/// Optional parameters of a chat completion request. Each is left out of the body
+/// when unset, since not every provider accepts them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatRequestOptions {
+    pub seed: Option<u64>,                          // sent only to make answers reproducible
+    pub max_tokens: Option<u32>,                    // cap on completion tokens
+    pub stop: Vec<String>,                          // sequences that end the completion
+    pub response_format: Option<serde_json::Value>, // e.g. `{"type": "json_object"}`
+}
+
+/// Body of a chat completion request
+pub fn chat_request_body(
+    model: &str,
+    temperature: f32,
+    options: &ChatRequestOptions,
+    messages: &[llmgraph::models::tools::Message],
+    tools: Option<&[llmgraph::models::tools::Tool]>,
+) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "model": model,
+        "temperature": temperature,
+        "messages": messages,
+    });
+    if let Some(tools) = tools.filter(|tools| !tools.is_empty()) {
+        body["tools"] = serde_json::json!(tools);
+    }
+    if let Some(seed) = options.seed {
+        body["seed"] = serde_json::json!(seed);
+    }
+    if let Some(max_tokens) = options.max_tokens {
+        body["max_tokens"] = serde_json::json!(max_tokens);
+    }
+    if !options.stop.is_empty() {
+        body["stop"] = serde_json::json!(options.stop);
+    }
+    if let Some(format) = &options.response_format {
+        body["response_format"] = format.clone();
+    }
+    body
+}
+
+/// Send a chat completion request, returning the parsed reply, its `usage` field and
+/// any separate reasoning (each `null` when the provider sends none)
+async fn post_chat_request(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: &serde_json::Value,
+) -> Result<(llmgraph::models::tools::LLMResponse, serde_json::Value, serde_json::Value), NeonmachinesError> {
+    let response = client
+        .post(url)
+        .bearer_auth(api_key)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| {
+            let kind = if e.is_timeout() { "timeout" } else { "connection" };
+            NeonmachinesError::Network(format!("Chat request {} error: {}", kind, e))
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(NeonmachinesError::Http {
+            status: status.as_u16(),
+            message: format!("Chat request failed: {}", text),
+        });
+    }
+
+    let reply = response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| NeonmachinesError::Unexpected(format!("Invalid chat response: {}", e)))?;
+    let usage = reply["usage"].clone();
+    // `LLMResponse` has no field for a separate reasoning trace (OpenRouter's `reasoning`,
+    // DeepSeek's `reasoning_content`), so it is picked out before parsing drops it
+    let message = &reply["choices"][0]["message"];
+    let reasoning = [&message["reasoning"], &message["reasoning_content"]]
+        .into_iter()
+        .find(|r| r.as_str().is_some_and(|r| !r.trim().is_empty()))
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+    let parsed = serde_json::from_value(reply)
+        .map_err(|e| NeonmachinesError::Unexpected(format!("Invalid chat response: {}", e)))?;
+    Ok((parsed, usage, reasoning))
+}
+
+/// Trace log written while tracing is on (`/trace on` creates it)
+pub const TRACE_LOG: &str = "neonmachines/.neonmachines_data/trace.log";
+
+/// Add an entry to the end of the trace log, keeping earlier calls for `/trace show`
+fn append_trace(entry: String) -> std::io::Result<()> {
+    use std::io::Write;
+    std::fs::OpenOptions::new().append(true).open(TRACE_LOG)?.write_all(entry.as_bytes())
+}
+
 /// Wrapper for generating API responses with retry logic
+#[allow(clippy::too_many_arguments)]
 pub async fn generate_with_retry(
     base_url: String,
     api_key: String,
     model: String,
     temperature: f32,
+    options: ChatRequestOptions,
     messages: Vec<llmgraph::models::tools::Message>,
     tools: Option<Vec<llmgraph::models::tools::Tool>>,
     retry_config: Option<RetryConfig>,
     circuit_breaker: Option<&mut CircuitBreaker>,
 ) -> Result<serde_json::Value, NeonmachinesError> {
     let config = retry_config.unwrap_or_else(RetryConfig::default);
+    let client = reqwest::Client::new();
     
     // Check if tracing is enabled and log the request
-    let trace_enabled = std::path::Path::new("neonmachines/.neonmachines_data/trace.log").exists();
+    let trace_enabled = std::path::Path::new(TRACE_LOG).exists();
     let start_time = std::time::Instant::now();
     
     if trace_enabled {
@@ -405,15 +603,13 @@ pub async fn generate_with_retry(
             "API Request Started - Model: {}, Temperature: {}, Messages: {}, Tools: {:?}",
             model, temperature, messages.len(), tools.is_some()
         );
-        if let Err(e) = std::fs::write(
-            "neonmachines/.neonmachines_data/trace.log", 
-            format!("\n{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())
-        ) {
+        if let Err(e) = append_trace(format!("\n{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())) {
             warn!("Failed to write to trace log: {}", e);
         }
     }
     
-    // Create a simple retry wrapper for generate_full_response
+    // Requests are sent directly rather than through llmgraph, which can't pass
+    // `seed` or hand back the `usage` counts cost estimates are based on
     let operation = || {
         let base_url = base_url.clone();
         let api_key = api_key.clone();
@@ -421,26 +617,28 @@ pub async fn generate_with_retry(
         let model_for_api = model.clone(); // Clone here for the API call
         let messages = messages.clone();
         let tools = tools.clone();
+        let options = options.clone();
+        let request_timeout = config.request_timeout;
+        let client = client.clone();
         
         Box::pin(async move {
-            let result = llmgraph::generate::generate::generate_full_response(
-                base_url,
-                api_key,
-                model_for_api,
-                temperature,
-                messages,
-                tools,
-            )
-            .await;
+            let request = async {
+                let body = chat_request_body(&model_for_api, temperature, &options, &messages, tools.as_deref());
+                post_chat_request(&client, &base_url, &api_key, &body).await
+            };
+            let result = with_request_timeout(request_timeout, request).await;
             
             match result {
-                Ok(response) => {
+                Ok((response, usage, reasoning)) => {
                     // Convert LLMResponse to Value for easier handling
                     let response_json = serde_json::json!({
                         "success": true,
                         "response": response,
+                        "usage": usage,
+                        "reasoning": reasoning,
                         "model": model_for_response,
-                        "temperature": temperature
+                        "temperature": temperature,
+                        "seed": options.seed
                     });
                     
                     // Log successful response if tracing is enabled
@@ -451,10 +649,7 @@ pub async fn generate_with_retry(
                             duration,
                             serde_json::to_string(&response_json).unwrap_or_else(|_| "Failed to serialize".to_string())
                         );
-                        if let Err(e) = std::fs::write(
-                            "neonmachines/.neonmachines_data/trace.log", 
-                            format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())
-                        ) {
+                        if let Err(e) = append_trace(format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())) {
                             warn!("Failed to write to trace log: {}", e);
                         }
                     }
@@ -469,30 +664,22 @@ pub async fn generate_with_retry(
                             "API Request Failed - Duration: {:?}, Error: {}",
                             duration, e
                         );
-                        if let Err(e) = std::fs::write(
-                            "neonmachines/.neonmachines_data/trace.log", 
-                            format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())
-                        ) {
+                        if let Err(e) = append_trace(format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())) {
                             warn!("Failed to write to trace log: {}", e);
                         }
                     }
-                    Err::<serde_json::Value, NeonmachinesError>(NeonmachinesError::Unexpected(format!("API call failed: {}", e)))
+                    Err::<serde_json::Value, NeonmachinesError>(e)
                 }
             }
         }) as std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value, NeonmachinesError>> + Send>>
     };
 
-    // Apply retry logic with optional circuit breaker
+    // Apply retry logic with optional circuit breaker. The last error is returned as is,
+    // so callers can still tell a rejected request from a struggling provider.
     let final_result = if let Some(mut cb) = circuit_breaker {
-        match retry_with_circuit_breaker(&mut cb, &config, operation).await {
-            Ok(result) => Ok(result),
-            Err(e) => Err(NeonmachinesError::Unexpected(format!("Circuit breaker error: {}", e)))
-        }
+        retry_with_circuit_breaker(&mut cb, &config, operation).await
     } else {
-        match retry_with_backoff(&config, operation).await {
-            Ok(result) => Ok(result),
-            Err(e) => Err(NeonmachinesError::Unexpected(format!("Retry error: {}", e)))
-        }
+        retry_with_backoff(&config, operation).await
     };
     
     // Log the final result
@@ -511,13 +698,224 @@ pub async fn generate_with_retry(
             trace_message.push_str(&format!("\nError: {}", e));
         }
         
-        if let Err(e) = std::fs::write(
-            "neonmachines/.neonmachines_data/trace.log", 
-            format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())
-        ) {
+        if let Err(e) = append_trace(format!("{}\nTimestamp: {}\n", trace_message, chrono::Utc::now())) {
             warn!("Failed to write to trace log: {}", e);
         }
     }
     
     final_result
 }
+
+/// Request embeddings for `input` from an OpenAI-compatible `/embeddings` endpoint,
+/// retrying transient failures like chat calls do
+pub async fn embed_with_retry(
+    base_url: String,
+    api_key: String,
+    model: String,
+    input: String,
+    retry_config: Option<RetryConfig>,
+) -> Result<serde_json::Value, NeonmachinesError> {
+    let config = retry_config.unwrap_or_default();
+    let client = reqwest::Client::new();
+
+    let operation = || {
+        let client = client.clone();
+        let base_url = base_url.clone();
+        let api_key = api_key.clone();
+        let body = serde_json::json!({ "model": model, "input": input });
+        let request_timeout = config.request_timeout;
+
+        Box::pin(async move {
+            // Structured errors let `is_retryable_error` decide on the status or error kind
+            let mut request = client.post(&base_url).bearer_auth(api_key).json(&body);
+            if let Some(limit) = request_timeout {
+                request = request.timeout(limit);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| {
+                    let kind = if e.is_timeout() { "timeout" } else { "connection" };
+                    NeonmachinesError::Network(format!("Embedding request {} error: {}", kind, e))
+                })?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                return Err(NeonmachinesError::Http {
+                    status: status.as_u16(),
+                    message: format!("Embedding request failed: {}", text),
+                });
+            }
+
+            response
+                .json::<serde_json::Value>()
+                .await
+                .map_err(|e| NeonmachinesError::Unexpected(format!("Invalid embedding response: {}", e)))
+        }) as std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value, NeonmachinesError>> + Send>>
+    };
+
+    retry_with_backoff(&config, operation).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_budget_caps_total_attempts() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let config = RetryConfig {
+            max_attempts: 3,
+            base_delay_ms: 1,
+            max_delay_ms: 1,
+            backoff_factor: 1.0,
+            budget: Some(RetryBudget::new(2)),
+            request_timeout: None,
+        };
+        let operation = || {
+            let attempts = attempts.clone();
+            Box::pin(async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<(), String>("network unreachable".to_string())
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), String>> + Send>>
+        };
+
+        // Two retries are spent by the first call, later calls get one attempt each
+        for _ in 0..3 {
+            assert!(retry_with_backoff(&config, operation).await.is_err());
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 5);
+
+        let budget = config.budget.as_ref().unwrap();
+        assert!(budget.take_exhausted_notice());
+        assert!(!budget.take_exhausted_notice());
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_times_out_and_is_retried() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let config = RetryConfig {
+            max_attempts: 2,
+            base_delay_ms: 1,
+            max_delay_ms: 1,
+            backoff_factor: 1.0,
+            budget: None,
+            request_timeout: Some(Duration::from_millis(20)),
+        };
+        // An endpoint that takes far longer than the timeout to answer
+        let operation = || {
+            let attempts = attempts.clone();
+            let limit = config.request_timeout;
+            Box::pin(async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                with_request_timeout(limit, async {
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    Ok(())
+                })
+                .await
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), NeonmachinesError>> + Send>>
+        };
+
+        let started = std::time::Instant::now();
+        let err = retry_with_backoff(&config, operation).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(err.to_string().contains("timeout"));
+        assert!(is_retryable_error(&err).is_some());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_embed_request_to_stalled_server_times_out() {
+        // Accepts connections into the backlog but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/embeddings", listener.local_addr().unwrap());
+        let config = RetryConfig {
+            max_attempts: 1,
+            request_timeout: Some(Duration::from_millis(200)),
+            ..RetryConfig::default()
+        };
+
+        let err = embed_with_retry(url, "key".into(), "model".into(), "text".into(), Some(config))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timeout"), "{}", err);
+        drop(listener);
+    }
+
+    #[test]
+    fn test_http_status_decides_retry() {
+        let http = |status| NeonmachinesError::Http { status, message: "timeout while reading prompt".into() };
+        for status in [408, 429, 500, 502, 503, 504] {
+            assert!(is_retryable_error(&http(status)).is_some(), "{} should retry", status);
+        }
+        // The message mentions "timeout", but the status says the request itself was bad
+        for status in [400, 401, 403, 404, 422] {
+            assert!(is_retryable_error(&http(status)).is_none(), "{} should not retry", status);
+        }
+        assert!(matches!(is_retryable_error(&http(429)), Some(RetryableErrorType::RateLimited)));
+        assert!(matches!(
+            is_retryable_error(&NeonmachinesError::Network("request timeout after 1s".into())),
+            Some(RetryableErrorType::TimeoutError)
+        ));
+        assert!(matches!(
+            is_retryable_error(&NeonmachinesError::Network("connection refused".into())),
+            Some(RetryableErrorType::NetworkError)
+        ));
+        // Opaque errors still fall back to the text
+        assert!(is_retryable_error(&"upstream returned 429".to_string()).is_some());
+        assert!(is_retryable_error(&"invalid api key".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_seed_is_sent_in_chat_request() {
+        let messages = vec![llmgraph::models::tools::Message {
+            role: "user".into(),
+            content: Some("hi".into()),
+            tool_calls: None,
+        }];
+
+        let body = chat_request_body("openai/gpt-4o", 0.2, &ChatRequestOptions { seed: Some(42), ..Default::default() }, &messages, None);
+        let serialized = serde_json::to_string(&body).unwrap();
+        assert!(serialized.contains("\"seed\":42"));
+        assert_eq!(body["messages"][0]["content"], "hi");
+
+        let unseeded = chat_request_body("openai/gpt-4o", 0.2, &ChatRequestOptions::default(), &messages, None);
+        assert!(unseeded.get("seed").is_none());
+
+        assert!(seed_honored("openai/gpt-4o"));
+        assert!(!seed_honored("z-ai/glm-4.5"));
+    }
+
+    #[test]
+    fn test_max_tokens_is_sent_only_when_set() {
+        let messages = vec![llmgraph::models::tools::Message {
+            role: "user".into(),
+            content: Some("hi".into()),
+            tool_calls: None,
+        }];
+
+        let capped = chat_request_body("openai/gpt-4o", 0.2, &ChatRequestOptions { max_tokens: Some(512), ..Default::default() }, &messages, None);
+        assert_eq!(capped["max_tokens"], 512);
+
+        let uncapped = chat_request_body("openai/gpt-4o", 0.2, &ChatRequestOptions::default(), &messages, None);
+        assert!(uncapped.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn test_stop_sequences_are_sent_only_when_set() {
+        let messages = vec![llmgraph::models::tools::Message {
+            role: "user".into(),
+            content: Some("hi".into()),
+            tool_calls: None,
+        }];
+
+        let stop = ChatRequestOptions { stop: vec!["\n\n###".to_string(), "END".to_string()], ..Default::default() };
+        let stopped = chat_request_body("openai/gpt-4o", 0.2, &stop, &messages, None);
+        assert_eq!(stopped["stop"], serde_json::json!(["\n\n###", "END"]));
+        assert!(serde_json::to_string(&stopped).unwrap().contains("\"stop\":[\"\\n\\n###\",\"END\"]"));
+
+        let unstopped = chat_request_body("openai/gpt-4o", 0.2, &ChatRequestOptions::default(), &messages, None);
+        assert!(unstopped.get("stop").is_none());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::nm_config::{append_missing_to, AgentRow, AgentType, WorkflowConfig};
+use std::fs;
+use std::path::Path;
+
+/// POML files used by the example workflows, written under `prompts/`
+const EXAMPLE_PROMPTS: &[(&str, &str)] = &[
+    (
+        "example_chat_system.poml",
+        "<poml>\n<SystemMessage>\nYou are a friendly assistant. Answer clearly and briefly, and ask a question back when the request is ambiguous.\n</SystemMessage>\n</poml>\n",
+    ),
+    (
+        "example_user_input.poml",
+        "<poml>\n<p>\n{{nminput}}\n</p>\n</poml>\n",
+    ),
+    (
+        "example_researcher.poml",
+        "<poml>\n<role>You are a researcher.</role>\n<task>Collect the key facts needed to answer the request. List them as short bullet points; don't write the final answer.</task>\n</poml>\n",
+    ),
+    (
+        "example_summarizer.poml",
+        "<poml>\n<role>You are an editor.</role>\n<task>Turn the researcher's bullet points into a single clear paragraph that answers the original request.</task>\n</poml>\n",
+    ),
+    (
+        "example_writer.poml",
+        "<poml>\n<role>You are a writer.</role>\n<task>Write a haiku about the topic given. If feedback from a reviewer is included, fix every point it raises.</task>\n</poml>\n",
+    ),
+    (
+        "example_reviewer.poml",
+        "<poml>\n<role>You are a strict reviewer.</role>\n<task>Check that the text is a haiku with 5, 7 and 5 syllables. Reply only with JSON: {\"valid\": true} when it is, or {\"valid\": false, \"errors\": [\"what to fix\"]} when it isn't.</task>\n</poml>\n",
+    ),
+    (
+        "example_planner.poml",
+        "<poml>\n<role>You are a planner.</role>\n<task>Restate the decision the user is facing in one sentence so other agents can each study one side of it.</task>\n</poml>\n",
+    ),
+    (
+        "example_pros.poml",
+        "<poml>\n<role>You are an optimist.</role>\n<task>List the three strongest arguments in favour of the decision.</task>\n</poml>\n",
+    ),
+    (
+        "example_cons.poml",
+        "<poml>\n<role>You are a sceptic.</role>\n<task>List the three strongest arguments against the decision described by the planner.</task>\n</poml>\n",
+    ),
+];
+
+fn row(agent_type: AgentType, name: &str, files: &str, on_success: i32, on_failure: i32) -> AgentRow {
+    AgentRow {
+        agent_type,
+        files: files.to_string(),
+        on_success: Some(on_success),
+        on_failure: Some(on_failure),
+        display_name: Some(name.to_string()),
+        ..AgentRow::default()
+    }
+}
+
+fn workflow(name: &str, comment: &str, rows: Vec<AgentRow>) -> WorkflowConfig {
+    WorkflowConfig {
+        name: name.to_string(),
+        maximum_traversals: 10,
+        comments: vec![format!("# {}", comment)],
+        rows,
+        ..WorkflowConfig::default()
+    }
+}
+
+/// One workflow per agent type and routing pattern
+pub fn example_workflows() -> Vec<WorkflowConfig> {
+    let mut planner = row(
+        AgentType::Agent,
+        "Planner",
+        "role:system:example_planner.poml;role:user:example_user_input.poml",
+        1,
+        -1,
+    );
+    // The cons agent sees the plan as well as the pros agent's output
+    planner.output_injections = vec!["output1:input3".to_string()];
+
+    vec![
+        workflow(
+            "example_chat",
+            "Example: a single agent that answers and stops",
+            vec![row(
+                AgentType::Agent,
+                "Assistant",
+                "role:system:example_chat_system.poml;role:user:example_user_input.poml",
+                -1,
+                -1,
+            )],
+        ),
+        workflow(
+            "example_chain",
+            "Example: two agents in sequence, the first hands its output to the second",
+            vec![
+                row(
+                    AgentType::Agent,
+                    "Researcher",
+                    "role:system:example_researcher.poml;role:user:example_user_input.poml",
+                    1,
+                    -1,
+                ),
+                row(AgentType::Agent, "Summarizer", "role:system:example_summarizer.poml", -1, -1),
+            ],
+        ),
+        workflow(
+            "example_validator_loop",
+            "Example: a validator sends the writer back until the output passes",
+            vec![
+                row(
+                    AgentType::Agent,
+                    "Writer",
+                    "role:system:example_writer.poml;role:user:example_user_input.poml",
+                    1,
+                    -1,
+                ),
+                row(AgentType::Validator, "Reviewer", "role:system:example_reviewer.poml", -1, 0),
+            ],
+        ),
+        workflow(
+            "example_fan_out",
+            "Example: a planner feeds two parallel agents that each study one side",
+            vec![
+                planner,
+                // The group runs as one step and continues from its last member
+                AgentRow {
+                    parallel_group: Some(1),
+                    ..row(AgentType::ParallelAgent, "Pros", "role:system:example_pros.poml", 2, -1)
+                },
+                AgentRow {
+                    parallel_group: Some(1),
+                    ..row(AgentType::ParallelAgent, "Cons", "role:system:example_cons.poml", -1, -1)
+                },
+            ],
+        ),
+    ]
+}
+
+/// Add the example workflows to `config` and their POML files to `prompts_dir`.
+/// Workflows and files that already exist are left alone, so running this twice
+/// changes nothing. Returns what was created.
+pub fn write_examples(config: &Path, prompts_dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut created = Vec::new();
+
+    fs::create_dir_all(prompts_dir)?;
+    for (file, content) in EXAMPLE_PROMPTS {
+        let path = prompts_dir.join(file);
+        if !path.exists() {
+            fs::write(&path, content)?;
+            created.push(path.display().to_string());
+        }
+    }
+
+    for name in append_missing_to(&example_workflows(), config)? {
+        created.push(format!("workflow '{}' in {}", name, config.display()));
+    }
+
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nm_config::{parse_nm_multiple, render_all_nm};
+
+    #[test]
+    fn test_examples_parse_and_reference_prompts() {
+        let dir = std::env::temp_dir().join(format!("nm_examples_{}", std::process::id()));
+        let config = dir.join("config.nm");
+        let prompts = dir.join("prompts");
+        fs::create_dir_all(&dir).unwrap();
+        let existing = WorkflowConfig { name: "mine".into(), ..WorkflowConfig::default() };
+        // Hand-written touches a rewrite would lose
+        let original = format!("# my own notes\n\n{}\n\n# kept as typed", render_all_nm(&[existing]));
+        fs::write(&config, &original).unwrap();
+
+        let created = write_examples(&config, &prompts).unwrap();
+        assert_eq!(created.len(), EXAMPLE_PROMPTS.len() + 4);
+        assert!(fs::read_to_string(&config).unwrap().starts_with(&original));
+
+        let workflows = parse_nm_multiple(&fs::read_to_string(&config).unwrap()).unwrap();
+        assert_eq!(workflows[0].name, "mine");
+        assert_eq!(workflows.len(), 5);
+        for wf in &workflows[1..] {
+            let reloaded = example_workflows().into_iter().find(|e| e.name == wf.name).unwrap();
+            assert_eq!(wf.rows.len(), reloaded.rows.len());
+            for row in &wf.rows {
+                for file in crate::nm_config::poml_files(row) {
+                    assert!(prompts.join(&file).is_file(), "{} references missing {}", wf.name, file);
+                }
+            }
+        }
+        let types: Vec<AgentType> = workflows.iter().flat_map(|w| w.rows.iter().map(|r| r.agent_type)).collect();
+        assert!(types.contains(&AgentType::Validator));
+        assert!(types.contains(&AgentType::ParallelAgent));
+        let fan_out = workflows.iter().find(|w| w.name == "example_fan_out").unwrap();
+        assert_eq!(crate::nm_config::parallel_group(fan_out, 1), vec![1, 2]);
+
+        // A second run finds everything in place
+        assert!(write_examples(&config, &prompts).unwrap().is_empty());
+        let again = parse_nm_multiple(&fs::read_to_string(&config).unwrap()).unwrap();
+        assert_eq!(again.len(), 5);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::nm_config::{route_target, AgentRow, AgentType, WorkflowConfig, END_NODE};
+use serde::{Deserialize, Serialize};
+
+/// A workflow as nodes and edges, the shape the web graph editor reads and writes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphView {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// One agent row. `id` is its 0-based index in the workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: usize,
+    #[serde(rename = "type")]
+    pub agent_type: String, // "agent", "validator" or "parallel"
+    pub model: String,      // the workflow's model; agents don't have their own yet
+    pub files: String,      // role:file mappings, as in config.nm
+}
+
+/// A route between agents. `to` is `None` when the route ends the workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: Option<usize>,
+    pub condition: String, // "next" for agents, "success" or "failure" for validators
+}
+
+fn type_name(agent_type: AgentType) -> &'static str {
+    match agent_type {
+        AgentType::Agent => "agent",
+        AgentType::Validator => "validator",
+        AgentType::ParallelAgent => "parallel",
+    }
+}
+
+impl From<&WorkflowConfig> for GraphView {
+    fn from(cfg: &WorkflowConfig) -> Self {
+        let count = cfg.rows.len();
+        let mut nodes = Vec::with_capacity(count);
+        let mut edges = Vec::new();
+        for (id, row) in cfg.rows.iter().enumerate() {
+            nodes.push(GraphNode {
+                id,
+                agent_type: type_name(row.agent_type).to_string(),
+                model: cfg.model.clone(),
+                files: row.files.clone(),
+            });
+            if row.agent_type == AgentType::Validator {
+                for (condition, route) in [("success", row.on_success), ("failure", row.on_failure)] {
+                    edges.push(GraphEdge { from: id, to: route_target(route, count), condition: condition.to_string() });
+                }
+            } else {
+                let next = Some(id + 1).filter(|n| *n < count);
+                edges.push(GraphEdge { from: id, to: next, condition: "next".to_string() });
+            }
+        }
+        GraphView { nodes, edges }
+    }
+}
+
+impl GraphView {
+    /// Write the graph back onto `cfg`. Rows keep the settings the graph doesn't
+    /// carry (iterations, injections, ...); new nodes get defaults. Nothing changes
+    /// when the graph is invalid.
+    pub fn apply(&self, cfg: &mut WorkflowConfig) -> Result<(), String> {
+        if self.nodes.is_empty() {
+            return Err("A workflow needs at least one node".to_string());
+        }
+        let mut nodes: Vec<&GraphNode> = self.nodes.iter().collect();
+        nodes.sort_by_key(|n| n.id);
+        if let Some((expected, node)) = nodes.iter().enumerate().find(|(i, n)| n.id != *i) {
+            return Err(format!("Node ids must run 0..{} without gaps, found {} where {} was expected", nodes.len(), node.id, expected));
+        }
+        let model = &nodes[0].model;
+        if let Some(node) = nodes.iter().find(|n| &n.model != model) {
+            return Err(format!("Node {} uses model '{}' but the workflow uses '{}'; per-agent models aren't supported", node.id, node.model, model));
+        }
+
+        let count = nodes.len();
+        let mut rows: Vec<AgentRow> = Vec::with_capacity(count);
+        for node in &nodes {
+            let agent_type = AgentType::from_name(&node.agent_type)
+                .ok_or_else(|| format!("Node {} has unknown type '{}'", node.id, node.agent_type))?;
+            let mut row = cfg.rows.get(node.id).cloned().unwrap_or_default();
+            row.agent_type = agent_type;
+            row.files = node.files.clone();
+            if agent_type != AgentType::Validator {
+                row.on_success = None;
+                row.on_failure = None;
+            }
+            rows.push(row);
+        }
+
+        for edge in &self.edges {
+            if edge.from >= count {
+                return Err(format!("Edge from unknown node {}", edge.from));
+            }
+            if let Some(to) = edge.to.filter(|to| *to >= count) {
+                return Err(format!("Edge from node {} to unknown node {}", edge.from, to));
+            }
+            let row = &mut rows[edge.from];
+            let route = Some(edge.to.map_or(END_NODE, |to| to as i32));
+            match (row.agent_type, edge.condition.as_str()) {
+                (AgentType::Validator, "success") => row.on_success = route,
+                (AgentType::Validator, "failure") => row.on_failure = route,
+                (AgentType::Validator, other) => {
+                    return Err(format!("Validator {} routes by 'success' or 'failure', not '{}'", edge.from, other));
+                }
+                (_, "next") => {
+                    let next = Some(edge.from + 1).filter(|n| *n < count);
+                    if edge.to != next {
+                        return Err(format!("Agent {} always passes to the next node; it can't route elsewhere", edge.from));
+                    }
+                }
+                (_, other) => {
+                    return Err(format!("Agent {} only has a 'next' edge, not '{}'", edge.from, other));
+                }
+            }
+        }
+
+        cfg.active_agent_index = cfg.active_agent_index.min(count - 1);
+        cfg.model = model.clone();
+        cfg.rows = rows;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review_loop() -> WorkflowConfig {
+        let validator = AgentRow {
+            agent_type: AgentType::Validator,
+            files: "system:prompts/check.poml".to_string(),
+            on_success: Some(END_NODE),
+            on_failure: Some(0),
+            ..Default::default()
+        };
+        let writer = AgentRow {
+            files: "system:prompts/write.poml".to_string(),
+            max_iterations: 7,
+            ..Default::default()
+        };
+        WorkflowConfig { name: "review".to_string(), rows: vec![writer, validator], ..Default::default() }
+    }
+
+    #[test]
+    fn test_graph_view_round_trips_through_json() {
+        let cfg = review_loop();
+        let view = GraphView::from(&cfg);
+        assert_eq!(view.nodes.len(), 2);
+        assert_eq!(view.nodes[1].agent_type, "validator");
+        assert!(view.edges.contains(&GraphEdge { from: 0, to: Some(1), condition: "next".to_string() }));
+        assert!(view.edges.contains(&GraphEdge { from: 1, to: None, condition: "success".to_string() }));
+        assert!(view.edges.contains(&GraphEdge { from: 1, to: Some(0), condition: "failure".to_string() }));
+
+        let json = serde_json::to_string(&view).unwrap();
+        assert!(json.contains("\"type\":\"validator\""));
+        let parsed: GraphView = serde_json::from_str(&json).unwrap();
+        let mut applied = WorkflowConfig { name: "review".to_string(), ..Default::default() };
+        parsed.apply(&mut applied).unwrap();
+        assert_eq!(GraphView::from(&applied), view);
+    }
+
+    #[test]
+    fn test_graph_edits_apply_and_keep_row_settings() {
+        let mut cfg = review_loop();
+        let mut view = GraphView::from(&cfg);
+        // Send failures to a new fixer agent instead of back to the writer
+        view.nodes.push(GraphNode { id: 2, agent_type: "agent".to_string(), model: cfg.model.clone(), files: "system:prompts/fix.poml".to_string() });
+        view.edges.retain(|e| e.condition != "failure");
+        view.edges.push(GraphEdge { from: 1, to: Some(2), condition: "failure".to_string() });
+        view.apply(&mut cfg).unwrap();
+
+        assert_eq!(cfg.rows.len(), 3);
+        assert_eq!(cfg.rows[0].max_iterations, 7);
+        assert_eq!(cfg.rows[1].on_failure, Some(2));
+        assert_eq!(cfg.rows[2].files, "system:prompts/fix.poml");
+
+        // Agents can't skip ahead, and nothing is applied when the graph is rejected
+        let mut bad = GraphView::from(&cfg);
+        bad.edges[0].to = Some(2);
+        assert!(bad.apply(&mut cfg).is_err());
+        assert_eq!(cfg.rows.len(), 3);
+        assert_eq!(GraphView::from(&cfg).edges[0].to, Some(1));
+    }
+}
@@ -0,0 +1,173 @@
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Where cached responses are kept, relative to the working directory
+pub const CACHE_DIR: &str = ".neonmachines_data/llm_cache";
+
+/// Set once by `--cache`; agents skip the cache entirely until then
+static CACHE: OnceLock<LlmCache> = OnceLock::new();
+
+/// Set by `--no-network`: only cached responses are used, nothing is sent to the provider
+static NETWORK_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Successful LLM responses on disk, one JSON file per distinct request
+#[derive(Debug, Clone)]
+pub struct LlmCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl LlmCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self { dir: dir.into(), ttl }
+    }
+
+    fn path_for(&self, request: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        request.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    /// The stored response for this request body, unless it is missing or older than
+    /// the TTL. The whole body (model, temperature, stop, tools...) is the key, so
+    /// changing any of it never returns an old answer.
+    pub fn get(&self, request: &Value) -> Option<Value> {
+        let request = request.to_string();
+        let path = self.path_for(&request);
+        let age = std::fs::metadata(&path).ok()?.modified().ok()?.elapsed().unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+        let entry: Value = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        // The file name is only a hash; the full request guards against collisions
+        (entry["request"].as_str() == Some(request.as_str())).then(|| entry["response"].clone())
+    }
+
+    pub fn put(&self, request: &Value, response: &Value) -> std::io::Result<()> {
+        let request = request.to_string();
+        std::fs::create_dir_all(&self.dir)?;
+        let entry = json!({
+            "request": request,
+            "stored_at": SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+            "response": response,
+        });
+        std::fs::write(self.path_for(&request), entry.to_string())
+    }
+}
+
+/// Turn on the response cache for the rest of the run
+pub fn enable(ttl: Duration) {
+    let _ = CACHE.set(LlmCache::new(CACHE_DIR, ttl));
+}
+
+/// The response cache, when `--cache` is on
+pub fn global() -> Option<&'static LlmCache> {
+    CACHE.get()
+}
+
+/// Stop LLM requests for the rest of the run
+pub fn disable_network() {
+    NETWORK_DISABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn network_disabled() -> bool {
+    NETWORK_DISABLED.load(Ordering::SeqCst)
+}
+
+/// Answer from `cache` when it holds `request` (the body `chat_request_body` builds),
+/// otherwise call `fetch` and store a successful result that is `usable`. A retry of
+/// an unusable answer passes `lookup` false: it skips the stored answer but still
+/// stores its own under the same request. With no cache this is just `fetch`.
+pub async fn cached<F, Fut, E>(
+    cache: Option<&LlmCache>,
+    request: &Value,
+    lookup: bool,
+    usable: impl Fn(&Value) -> bool,
+    fetch: F,
+) -> Result<Value, E>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Value, E>>,
+{
+    let Some(cache) = cache else {
+        return fetch().await;
+    };
+    if let Some(response) = cache.get(request).filter(|_| lookup) {
+        tracing::debug!("LLM cache hit for model {}", request["model"]);
+        return Ok(response);
+    }
+    let response = fetch().await?;
+    if !usable(&response) {
+        return Ok(response);
+    }
+    if let Err(e) = cache.put(request, &response) {
+        tracing::warn!("Failed to store LLM response in cache: {}", e);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ChatRequestOptions;
+    use llmgraph::models::tools::Message;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_cache_hit_skips_network_call() {
+        let dir = std::env::temp_dir().join(format!("nm_llm_cache_{}", std::process::id()));
+        let cache = LlmCache::new(&dir, Duration::from_secs(60));
+        let messages = vec![Message { role: "user".into(), content: Some("hello".into()), tool_calls: None }];
+        let request = |model: &str, temperature: f32, options: &ChatRequestOptions| {
+            crate::error::chat_request_body(model, temperature, options, &messages, None)
+        };
+        let plain = ChatRequestOptions::default();
+        let calls = AtomicUsize::new(0);
+        let fetch = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, String>(json!({ "response": { "choices": [] } }))
+        };
+
+        let first = cached(Some(&cache), &request("model-a", 0.7, &plain), true, |_| true, fetch).await.unwrap();
+        let second = cached(Some(&cache), &request("model-a", 0.7, &plain), true, |_| true, fetch).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A different temperature or model is a different request
+        cached(Some(&cache), &request("model-a", 0.2, &plain), true, |_| true, fetch).await.unwrap();
+        cached(Some(&cache), &request("model-b", 0.7, &plain), true, |_| true, fetch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // So are different stop sequences or a token cap
+        let stopped = ChatRequestOptions { stop: vec!["END".into()], ..Default::default() };
+        let capped = ChatRequestOptions { max_tokens: Some(16), ..Default::default() };
+        cached(Some(&cache), &request("model-a", 0.7, &stopped), true, |_| true, fetch).await.unwrap();
+        cached(Some(&cache), &request("model-a", 0.7, &capped), true, |_| true, fetch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // Expired entries are fetched again
+        let expired = LlmCache::new(&dir, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        cached(Some(&expired), &request("model-a", 0.7, &plain), true, |_| true, fetch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+
+        // A blank answer isn't stored; its retry is, under the same request
+        let fresh = ChatRequestOptions { seed: Some(7), ..Default::default() };
+        let answers = std::sync::Mutex::new(vec![json!({ "text": "" }), json!({ "text": "ok" })]);
+        let answer = || async { Ok::<_, String>(answers.lock().unwrap().remove(0)) };
+        let usable = |r: &Value| r["text"] != "";
+        let blank = cached(Some(&cache), &request("model-a", 0.7, &fresh), true, usable, answer).await.unwrap();
+        assert_eq!(blank["text"], "");
+        assert!(cache.get(&request("model-a", 0.7, &fresh)).is_none());
+        let retried = cached(Some(&cache), &request("model-a", 0.7, &fresh), false, usable, answer).await.unwrap();
+        assert_eq!(cache.get(&request("model-a", 0.7, &fresh)), Some(retried));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
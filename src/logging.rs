@@ -0,0 +1,82 @@
+use std::sync::OnceLock;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle for swapping the active `EnvFilter` while the program runs
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Set once by `init_logging`; `/log level` is a no-op report until then
+static FILTER_HANDLE: OnceLock<FilterHandle> = OnceLock::new();
+
+/// Wrap `filter` so it can be replaced later, remembering the handle for `set_level`
+pub fn reloadable(filter: EnvFilter) -> reload::Layer<EnvFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(filter);
+    let _ = FILTER_HANDLE.set(handle);
+    layer
+}
+
+/// Change the global log level, returning the filter now in effect
+pub fn set_level(level: &str) -> Result<String, String> {
+    let handle = FILTER_HANDLE.get().ok_or("Logging is not initialized")?;
+    set_level_on(handle, level)
+}
+
+/// The filter currently in effect, e.g. `debug`
+pub fn current_level() -> Option<String> {
+    FILTER_HANDLE.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+fn set_level_on(handle: &FilterHandle, level: &str) -> Result<String, String> {
+    let level = level.trim().to_lowercase();
+    level
+        .parse::<LevelFilter>()
+        .map_err(|_| format!("Unknown log level '{}'. Use trace, debug, info, warn, error or off", level))?;
+    handle
+        .reload(EnvFilter::new(&level))
+        .map_err(|e| format!("Failed to change log level: {}", e))?;
+    handle
+        .with_current(|filter| filter.to_string())
+        .map_err(|e| format!("Failed to read log level: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    struct CountDebug(Arc<AtomicUsize>);
+
+    impl<S: tracing::Subscriber> Layer<S> for CountDebug {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            if *event.metadata().level() == tracing::Level::DEBUG {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reload_changes_debug_output() {
+        let seen = Arc::new(AtomicUsize::new(0));
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(CountDebug(seen.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("hidden at info");
+            assert_eq!(seen.load(Ordering::SeqCst), 0);
+
+            assert_eq!(set_level_on(&handle, "DEBUG").unwrap(), "debug");
+            tracing::debug!("shown at debug");
+            assert_eq!(seen.load(Ordering::SeqCst), 1);
+
+            assert!(set_level_on(&handle, "loud").is_err());
+            assert_eq!(set_level_on(&handle, "warn").unwrap(), "warn");
+            tracing::debug!("hidden again");
+            assert_eq!(seen.load(Ordering::SeqCst), 1);
+        });
+    }
+}
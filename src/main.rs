@@ -17,6 +17,19 @@ mod workflow_ui;
 mod state;
 mod web;
 mod metrics;
+mod secrets;
+mod logging;
+mod examples;
+mod llm_cache;
+mod banner;
+mod watch;
+mod pricing;
+mod theme;
+mod graph_view;
+mod profiler;
+mod snippets;
+mod model_picker;
+mod atomic_write;
 
 use color_eyre::Result;
 use crossterm::event;
@@ -34,14 +47,16 @@ use cli::{AppMode, Cli};
 use clap::Parser;
 use poml::handle_poml_execution;
 use nmmcp::{load_all_extensions, get_extensions_directory};
-use runner::run_workflow;
 use tracing::{error, warn, info, instrument};
 use tracing_appender::{non_blocking, rolling};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use warp::Filter;
 use std::fs;
 use std::path::Path;
 
-
+/// How long shutdown waits for a cancelled workflow to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[instrument]
 fn init_logging(cli: &Cli) -> Result<()> {
@@ -52,13 +67,17 @@ fn init_logging(cli: &Cli) -> Result<()> {
         rolling::daily("logs", "neonmachines.log")
     };
     let (_non_blocking, guard) = non_blocking(file_appender);
-    tracing_subscriber::fmt()
-        .compact()
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_thread_names(true)
-        .with_writer(std::io::stdout)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+    // The filter sits behind a reload layer so `/log level` can change it at runtime
+    tracing_subscriber::registry()
+        .with(logging::reloadable(tracing_subscriber::EnvFilter::from_default_env()))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_thread_names(true)
+                .with_writer(std::io::stdout),
+        )
         .init();
     tracing::info!("Logging initialized with level: {}", cli.log_level);
     if let Some(log_file) = &cli.log_file {
@@ -85,12 +104,22 @@ impl Default for Cli {
             verbose: false,
             theme,
             avatar: None,
+            auth_token: None,
             rate_limit: 60,
             enable_rate_limit: false,
             poml_file: None,
             working_dir: None,
             log_file: None,
             experimental: false,
+            show_timestamps: false,
+            no_metrics: false,
+            cache: false,
+            cache_ttl: 3600,
+            quiet: false,
+            no_emoji: false,
+            watch: None,
+            no_network: false,
+            autosave_secs: 30,
         }
     }
 }
@@ -122,14 +151,27 @@ async fn main() -> Result<()> {
             Err(e) => {
                 error!("POML execution failed: {}", e);
                 eprintln!("POML execution failed: {}", e);
+                std::process::exit(1);
             }
         }
         return Ok(());
     }
+    banner::set_no_emoji(cli.no_emoji);
     if cli.enable_rate_limit {
         info!("Rate limiting enabled with limit: {} requests/minute", cli.rate_limit);
-        println!("Rate limiting enabled with limit: {} requests/minute", cli.rate_limit);
+        if !cli.quiet {
+            println!("Rate limiting enabled with limit: {} requests/minute", cli.rate_limit);
+        }
+    }
+    if cli.cache {
+        llm_cache::enable(Duration::from_secs(cli.cache_ttl));
+        info!("LLM response cache enabled in {} (ttl {}s)", llm_cache::CACHE_DIR, cli.cache_ttl);
     }
+    if cli.no_network {
+        llm_cache::disable_network();
+        info!("Network disabled; LLM requests are answered from the cache or fail");
+    }
+    nm_config::set_config_path(cli.get_config_file());
     let mode = cli.get_mode();
     info!("Running in {:?} mode", mode);
     match mode {
@@ -143,7 +185,9 @@ async fn main() -> Result<()> {
 async fn run_tui(cli: Cli) -> Result<()> {
     let mut terminal = setup_terminal()?;
     let log_file = cli.log_file.clone().unwrap_or_else(|| PathBuf::from("neonmachines.log"));
-    println!("Logging to file: {}", log_file.display());
+    if !cli.quiet {
+        println!("Logging to file: {}", log_file.display());
+    }
     let loaded_workflows = load_all_nm().unwrap_or_else(|_| preset_workflows());
     let mut workflows = HashMap::new();
     for wf in loaded_workflows {
@@ -154,15 +198,64 @@ async fn run_tui(cli: Cli) -> Result<()> {
         .next()
         .map(|name| name.clone())
         .unwrap_or_else(|| "default".to_string());
-    let metrics_collector = Arc::new(tokio::sync::Mutex::new(
-        crate::metrics::metrics_collector::MetricsCollector::new(),
-    ));
+    let metrics_collector = if cli.no_metrics {
+        None
+    } else {
+        Some(Arc::new(tokio::sync::Mutex::new(
+            crate::metrics::metrics_collector::MetricsCollector::new(),
+        )))
+    };
+    if let Some(collector) = &metrics_collector {
+        if let Err(e) = collector.lock().await.load_historical_data_from_file().await {
+            println!("Warning: Could not load historical metrics: {}", e);
+        }
+        // Periodically snapshot metrics so history survives crashes
+        let metrics_autosave = collector.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(
+                crate::metrics::metrics_collector::AUTOSAVE_INTERVAL_SECS,
+            ));
+            ticker.tick().await; // first tick completes immediately
+            loop {
+                ticker.tick().await;
+                let _ = metrics_autosave.lock().await.snapshot_and_save().await;
+            }
+        });
+    }
     let (tx_cmd, mut rx_cmd) = mpsc::unbounded_channel();
     let (tx_evt, rx_evt) = mpsc::unbounded_channel();
     let metrics_clone = metrics_collector.clone();
-    tokio::spawn(async move {
-        while let Some(cmd) = rx_cmd.recv().await {
-            run_workflow(cmd, tx_evt.clone(), Some(metrics_clone.clone())).await;
+    let cancel = runner::CancelToken::default();
+    let runner_cancel = cancel.clone();
+    // Each command gets its own token so `--watch` can stop a run without shutting down
+    let current_run = Arc::new(std::sync::Mutex::new(runner::CancelToken::default()));
+    let runner_current = current_run.clone();
+    // Re-runs from `--watch` get their own channel; while one is going its own writes
+    // don't start another
+    let (tx_watch_run, mut rx_watch_run) = mpsc::unbounded_channel();
+    let watch_run_active = Arc::new(AtomicBool::new(false));
+    let runner_watch_run = watch_run_active.clone();
+    let runner_task = tokio::spawn(async move {
+        let mut chat_session = None;
+        let mut last_run = None;
+        loop {
+            // Commands still queued at shutdown are dropped
+            let (cmd, from_watch) = tokio::select! {
+                cmd = rx_cmd.recv() => (cmd, false),
+                Some(cmd) = rx_watch_run.recv() => (Some(cmd), true),
+                _ = runner_cancel.cancelled() => (None, false),
+            };
+            let Some(cmd) = cmd else { break };
+            let run_cancel = runner::CancelToken::default();
+            *runner_current.lock().unwrap() = run_cancel.clone();
+            // Shutdown cancels the current token after `cancel`, so checking here can't miss it
+            if runner_cancel.is_cancelled() {
+                break;
+            }
+            runner::run_command(cmd, &mut chat_session, &mut last_run, tx_evt.clone(), metrics_clone.clone(), run_cancel).await;
+            if from_watch {
+                runner_watch_run.store(false, Ordering::SeqCst);
+            }
         }
     });
     let mut app = App::new(
@@ -170,11 +263,50 @@ async fn run_tui(cli: Cli) -> Result<()> {
         rx_evt,
         workflows,
         active_name,
-        Some(metrics_collector.clone()),
+        metrics_collector.clone(),
     );
+    app.show_timestamps = cli.show_timestamps;
+    app.role_styles = theme::RoleStyles::load(Path::new(theme::THEME_CONFIG)).unwrap_or_else(|e| {
+        warn!("{}; using the default message colors", e);
+        theme::RoleStyles::default()
+    });
+    app.autosave_interval = (cli.autosave_secs > 0).then(|| Duration::from_secs(cli.autosave_secs));
+    // Report missing POML files up front instead of at first run
+    let mut workflow_names: Vec<String> = app.workflows.keys().cloned().collect();
+    workflow_names.sort();
+    for name in workflow_names {
+        let missing = nm_config::missing_poml_files(&app.workflows[&name]);
+        for file in missing {
+            app.add_message(
+                "error",
+                format!("Workflow '{}' references missing POML file: prompts/{}", name, file),
+            );
+        }
+    }
+    // Debounced changes under `--watch` arrive on `rerun_rx`; the watcher lives until exit
+    let (rerun_tx, mut rerun_rx) = mpsc::unbounded_channel();
+    let _watcher = match &cli.watch {
+        Some(path) => {
+            let (change_tx, change_rx) = mpsc::unbounded_channel();
+            match watch::watch_path(path, watch_run_active.clone(), change_tx) {
+                Ok(watcher) => {
+                    tokio::spawn(watch::debounce_changes(change_rx, watch::WATCH_DEBOUNCE, move |changed| {
+                        let _ = rerun_tx.send(changed);
+                    }));
+                    app.add_message("system", format!("Watching {} for changes", path.display()));
+                    Some(watcher)
+                }
+                Err(e) => {
+                    app.add_message("error", format!("Cannot watch {}: {}", path.display(), e));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
     if let Err(e) = app.load_history_from_file() {
         println!("Warning: Could not load command history: {}", e);
-    } else {
+    } else if !cli.quiet {
         println!("Loaded {} commands from history", app.command_history.len());
     }
     
@@ -210,6 +342,7 @@ async fn run_tui(cli: Cli) -> Result<()> {
         }
         
         app.update_cached_metrics();
+        app.tick_spinner();
         terminal.draw(|f| app.render(f))?;
         
         // Handle events
@@ -223,26 +356,59 @@ async fn run_tui(cli: Cli) -> Result<()> {
         if app.process_events() {
             break;
         }
+        if let Some(path) = app.pending_edit.take() {
+            let status = tui::edit_file(&mut terminal, &path).map_err(|e| e.to_string());
+            app.finish_edit(&path, status);
+        }
         
         app.poll_async().await;
+        app.autosave_if_due();
+
+        // The new run queues behind the cancelled one, which stops at its next await point
+        while let Ok(changed) = rerun_rx.try_recv() {
+            // Changes that arrive while a watch re-run is going are dropped
+            if watch_run_active.load(Ordering::SeqCst) {
+                continue;
+            }
+            if let Some(cmd) = app.rerun_command(&changed) {
+                watch_run_active.store(true, Ordering::SeqCst);
+                current_run.lock().unwrap().cancel();
+                let _ = tx_watch_run.send(cmd);
+            }
+        }
     }
     
+    // Stop the running workflow and give it time to wind down before saving state
+    cancel.cancel();
+    current_run.lock().unwrap().cancel();
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, runner_task).await.is_err() {
+        warn!("Workflow did not stop within {}s; exiting anyway", SHUTDOWN_TIMEOUT.as_secs());
+    }
+
     // Cleanup and save state
-    app.persist_on_exit();
+    app.persist_on_exit().await;
     restore_terminal(terminal)?;
-    println!("Shutdown complete.");
+    if !cli.quiet {
+        println!("Shutdown complete.");
+    }
     Ok(())
 }
 
 async fn run_web(cli: Cli) -> Result<()> {
     info!("Starting web interface on http://{}:{}/", cli.get_host(), cli.get_port());
-    println!("🚀 Starting Neonmachines Web Interface");
-    println!("📍 URL: http://{}:{}/", cli.get_host(), cli.get_port());
+    for line in banner::web_banner(&cli.get_host(), cli.get_port(), cli.quiet, cli.no_emoji) {
+        println!("{}", line);
+    }
 
     let _app_state = crate::state::AppState::new();
     let addr = format!("{}:{}", cli.get_host(), cli.get_port());
 
+    // With --auth-token the websocket and /api routes need the token; pages and health checks stay open
+    let auth_token = cli.get_auth_token();
+    let auth = || web::require_token(auth_token.clone());
+
     let ws_route = warp::path("ws")
+        .and(auth())
         .and(warp::ws())
         .map(|ws: warp::ws::Ws| {
             ws.on_upgrade(move |socket| web::handle_websocket_connection(socket))
@@ -260,9 +426,11 @@ async fn run_web(cli: Cli) -> Result<()> {
         .and(warp::fs::file("web/graph-editor.html"));
 
     let metrics_route = warp::path!("api" / "metrics")
+        .and(auth())
         .and_then(get_metrics);
 
     let tracing_route = warp::path!("api" / "tracing")
+        .and(auth())
         .map(|| {
             let traces = vec![
                 serde_json::json!({
@@ -286,27 +454,16 @@ async fn run_web(cli: Cli) -> Result<()> {
         });
 
     let poml_files_route = warp::path!("api" / "poml-files")
-        .map(|| {
-            let mut poml_files = Vec::new();
-            let prompts_dir = Path::new("prompts");
-            
-            if prompts_dir.exists() {
-                for entry in fs::read_dir(prompts_dir).unwrap() {
-                    let entry = entry.unwrap();
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("poml") {
-                        if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
-                            poml_files.push(file_name.to_string());
-                        }
-                    }
-                }
-                poml_files.sort();
-            }
-            
-            warp::reply::json(&poml_files)
+        .and(auth())
+        .and(warp::query::<web::PomlFileQuery>())
+        .and_then(|query: web::PomlFileQuery| async move {
+            // A missing or unreadable prompts directory lists as empty
+            let files = web::get_poml_files().await.unwrap_or_default();
+            Ok::<_, warp::Rejection>(warp::reply::json(&web::paginate_poml_files(files, &query)))
         });
 
     let load_poml_route = warp::path!("api" / "load-poml")
+        .and(auth())
         .and(warp::query::<HashMap<String, String>>())
         .map(|params: HashMap<String, String>| {
             let file_name = params.get("file").cloned().unwrap_or_default();
@@ -317,7 +474,10 @@ async fn run_web(cli: Cli) -> Result<()> {
                 }));
             }
             
-            let file_path = Path::new("prompts").join(&file_name);
+            let file_path = match web::prompt_file_path(&file_name) {
+                Ok(path) => path,
+                Err(e) => return warp::reply::json(&serde_json::json!({ "error": e })),
+            };
             
             if !file_path.exists() {
                 return warp::reply::json(&serde_json::json!({
@@ -336,7 +496,65 @@ async fn run_web(cli: Cli) -> Result<()> {
             }
         });
 
-    let routes = root.or(create_route).or(ws_route).or(static_files).or(metrics_route).or(poml_files_route).or(load_poml_route).or(tracing_route);
+    let poml_vars_route = warp::path!("api" / "poml-vars")
+        .and(auth())
+        .and(warp::query::<HashMap<String, String>>())
+        .map(|params: HashMap<String, String>| {
+            let file_name = params.get("file").cloned().unwrap_or_default();
+
+            if file_name.is_empty() {
+                return warp::reply::json(&serde_json::json!({
+                    "error": "No file specified"
+                }));
+            }
+
+            let file_path = match web::prompt_file_path(&file_name) {
+                Ok(path) => path,
+                Err(e) => return warp::reply::json(&serde_json::json!({ "error": e })),
+            };
+
+            match fs::read_to_string(&file_path) {
+                Ok(content) => warp::reply::json(&serde_json::json!({
+                    "file": file_name,
+                    "variables": agents::poml_variables(&content)
+                })),
+                Err(e) => warp::reply::json(&serde_json::json!({
+                    "error": format!("Failed to read POML file: {}", e)
+                })),
+            }
+        });
+
+    let health_route = web::health_routes(
+        std::time::Instant::now(),
+        nm_config::config_path(),
+        PathBuf::from("prompts"),
+    );
+
+    let ui_config = web::UiConfig::from_cli(&cli);
+    let ui_config_route = warp::path!("api" / "ui-config")
+        .and(auth())
+        .map(move || warp::reply::json(&ui_config));
+
+    // Without --avatar the route 404s and the UI keeps its bundled avatar
+    let avatar_route = warp::path(web::AVATAR_ROUTE)
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then({
+            let avatar = cli.get_avatar();
+            move || {
+                let avatar = avatar.clone();
+                async move {
+                    match avatar {
+                        Some(path) => fs::read(&path)
+                            .map(|bytes| warp::reply::with_header(bytes, "content-type", web::avatar_content_type(&path)))
+                            .map_err(|_| warp::reject::not_found()),
+                        None => Err(warp::reject::not_found()),
+                    }
+                }
+            }
+        });
+
+    let routes = health_route.or(root).or(create_route).or(ws_route).or(static_files).or(metrics_route).or(poml_files_route).or(load_poml_route).or(poml_vars_route).or(ui_config_route).or(avatar_route).or(tracing_route).recover(web::handle_unauthorized);
 
 
     warp::serve(routes).run(addr.parse::<std::net::SocketAddr>()?).await;
@@ -388,17 +606,28 @@ async fn run_command(cli: Cli) -> Result<()> {
                 error!("POML execution failed: {}", String::from_utf8_lossy(&command_output.stderr));
                 eprintln!("POML execution failed:");
                 eprintln!("{}", String::from_utf8_lossy(&command_output.stderr));
+                std::process::exit(command_output.status.code().unwrap_or(1));
             }
         }
         Some(cli::Commands::Config { list_themes, list_providers, show, edit: _, validate: _, theme: _, provider: _ }) => {
             if *list_themes {
-                println!("Available themes: default, dark, light");
+                println!("Available themes: {}", cli::THEMES.join(", "));
             }
             if *list_providers {
                 println!("Available providers: openai, anthropic, local");
             }
             if *show {
-                println!("Configuration not yet implemented.");
+                let workflows = match load_all_nm() {
+                    Ok(workflows) => workflows,
+                    Err(e) => {
+                        eprintln!("Could not load {}: {} (showing built-in defaults)", nm_config::config_path().display(), e);
+                        preset_workflows()
+                    }
+                };
+                match serde_json::to_string_pretty(&cli.effective_config(&workflows)) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("Failed to serialize configuration: {}", e),
+                }
             }
         }
         Some(cli::Commands::Extension { list, install, uninstall, update, extension_type: _ }) => {
@@ -445,7 +674,7 @@ async fn run_command(cli: Cli) -> Result<()> {
         }
         Some(cli::Commands::Info { detailed, extensions, themes }) => {
             if *detailed {
-                println!("Neonmachines v{}", env!("CARGO_PKG_VERSION"));
+                println!("{}", commands::version_line());
                 println!("Built for graph-based AI orchestration");
                 println!("Extensions: NMMCP (NeonMachines Model Control Protocol)");
                 println!("Tools: Terminal execution, POML workflow execution");
@@ -456,7 +685,7 @@ async fn run_command(cli: Cli) -> Result<()> {
                 println!("Status: Ready for extension loading");
             }
             if *themes {
-                println!("Available Themes: default, dark, light");
+                println!("Available Themes: {}", cli::THEMES.join(", "));
             }
         }
         Some(cli::Commands::Test { provider, extensions, quick }) => {
@@ -484,6 +713,91 @@ async fn run_command(cli: Cli) -> Result<()> {
                 println!("Quick test completed successfully");
             }
         }
+        Some(cli::Commands::Batch { file, workflow, output }) => {
+            let text = fs::read_to_string(file)?;
+            let prompts = runner::batch_prompts(&text);
+            let workflows = load_all_nm().unwrap_or_else(|_| preset_workflows());
+            let cfg = match workflow {
+                Some(name) => workflows.iter().find(|w| &w.name == name),
+                None => workflows.first(),
+            };
+            let Some(cfg) = cfg.cloned() else {
+                eprintln!("Workflow not found: {}", workflow.as_deref().unwrap_or("<none loaded>"));
+                std::process::exit(1);
+            };
+
+            let (tx, mut rx) = mpsc::unbounded_channel::<AppEvent>();
+            let printer = tokio::spawn(async move {
+                while let Some(ev) = rx.recv().await {
+                    match ev {
+                        AppEvent::Log(line) if line.starts_with("[Batch]") => eprintln!("{}", line),
+                        AppEvent::Warning(line) => eprintln!("Warning: {}", line),
+                        AppEvent::Error(line) => eprintln!("Error: {}", line),
+                        _ => {}
+                    }
+                }
+            });
+
+            let limiter = rate_limiter::RateLimiter::new();
+            let rate_limit = cli.enable_rate_limit.then_some((&limiter, cli.rate_limit));
+            let run_tx = tx.clone();
+            let results = runner::run_batch(&prompts, rate_limit, &tx, |prompt| {
+                runner::run_workflow(
+                    runner::AppCommand::RunWorkflow {
+                        workflow_name: cfg.name.clone(),
+                        prompt,
+                        cfg: cfg.clone(),
+                        start_agent: None,
+                        variables: None,
+                    },
+                    run_tx.clone(),
+                    None,
+                    runner::CancelToken::default(),
+                )
+            })
+            .await;
+            drop(run_tx);
+            drop(tx);
+            let _ = printer.await;
+
+            let results_path = output.clone().unwrap_or_else(|| {
+                let mut name = file.clone().into_os_string();
+                name.push(".results.jsonl");
+                PathBuf::from(name)
+            });
+            runner::write_batch_results(&results_path, &results)?;
+            let failed = results.iter().filter(|r| r.error.is_some()).count();
+            println!(
+                "Batch finished: {} prompts, {} failed. Results written to {}",
+                results.len(),
+                failed,
+                results_path.display()
+            );
+        }
+        Some(cli::Commands::Examples) => {
+            let config = nm_config::config_path();
+            let created = examples::write_examples(&config, Path::new("prompts"))?;
+            if created.is_empty() {
+                println!("Example workflows are already in {}", config.display());
+            } else {
+                println!("Created:");
+                for item in &created {
+                    println!("  {}", item);
+                }
+                println!("Run one with /run example_chat <prompt>");
+            }
+        }
+        Some(cli::Commands::Tools { list }) => {
+            if *list {
+                print!("{}", tools::describe_tools(&tools::builtin_tool_specs()));
+            } else {
+                println!("Tool commands:");
+                println!("  --list          List the built-in tools with their parameters");
+            }
+        }
+        Some(cli::Commands::Completions { shell }) => {
+            Cli::write_completions(*shell, &mut std::io::stdout());
+        }
         _ => {
             println!("Command not yet implemented.");
         }
@@ -512,7 +826,7 @@ fn load_default_theme() -> std::io::Result<String> {
     use std::io::Read;
     use std::path::Path;
     
-    let config_path = Path::new(".neonmachines_data").join("theme_config.json");
+    let config_path = Path::new(theme::THEME_CONFIG);
     if config_path.exists() {
         let mut file = File::open(config_path)?;
         let mut contents = String::new();
@@ -219,6 +219,16 @@ pub struct PerformanceMetrics {
     pub memory_usage_mb: f64,
     pub cpu_usage_percent: f64,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub estimated_cost_usd: f64, // summed over runs, from `pricing.toml` and reported token usage
+    #[serde(default)]
+    pub token_count: u64,
+    #[serde(default)]
+    pub prompt_tokens: u64, // token_count split as the provider reported it
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub llm_calls: u64, // completions that reported usage
 }
 
 impl Default for PerformanceMetrics {
@@ -234,6 +244,11 @@ impl Default for PerformanceMetrics {
             memory_usage_mb: 0.0,
             cpu_usage_percent: 0.0,
             timestamp: Utc::now(),
+            estimated_cost_usd: 0.0,
+            token_count: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            llm_calls: 0,
         }
     }
 }
@@ -391,6 +406,9 @@ pub fn generate_alerts(metrics: &PerformanceMetrics) -> Vec<PerformanceAlert> {
     alerts
 }
 
+/// How often the TUI snapshots and saves historical metrics
+pub const AUTOSAVE_INTERVAL_SECS: u64 = 60;
+
 pub struct MetricsCollector {
     metrics: Arc<RwLock<PerformanceMetrics>>,
     _data_dir: PathBuf, // Changed to private to avoid unused field warning
@@ -400,6 +418,11 @@ pub struct MetricsCollector {
 impl MetricsCollector {
     pub fn new() -> Self {
         let data_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join(".neonmachines_data");
+        Self::with_data_dir(data_dir)
+    }
+
+    /// Collector that persists historical data under `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Self {
         fs::create_dir_all(&data_dir).unwrap_or_else(|_| {});
         
         Self {
@@ -422,6 +445,16 @@ impl MetricsCollector {
         metrics.record_request(duration, success);
     }
 
+    /// Add a finished run's token usage and estimated spend
+    pub async fn record_cost(&self, cost: &crate::pricing::CostEstimate) {
+        let mut metrics = self.metrics.write().await;
+        metrics.estimated_cost_usd += cost.cost_usd;
+        metrics.token_count += cost.total_tokens();
+        metrics.prompt_tokens += cost.prompt_tokens;
+        metrics.completion_tokens += cost.completion_tokens;
+        metrics.llm_calls += cost.calls;
+    }
+
     pub async fn get_metrics(&self) -> PerformanceMetrics {
         let metrics = self.metrics.read().await;
         metrics.clone()
@@ -448,11 +481,14 @@ impl MetricsCollector {
         // Use try_read to avoid blocking, fallback to empty string if lock is unavailable
         if let Ok(metrics) = self.metrics.try_read() {
             format!(
-                "Requests: {}, Success: {}, Error Rate: {:.2}%, Avg Time: {}ms",
+                "Requests: {}, Success: {}, Error Rate: {:.2}%, Avg Time: {}ms, Tokens: {} prompt / {} completion, Est. Cost: ${:.4}",
                 metrics.request_count,
                 metrics.success_count,
                 metrics.get_error_rate() * 100.0,
-                metrics.average_response_time.num_milliseconds()
+                metrics.average_response_time.num_milliseconds(),
+                metrics.prompt_tokens,
+                metrics.completion_tokens,
+                metrics.estimated_cost_usd
             )
         } else {
             "Metrics temporarily unavailable".to_string()
@@ -460,7 +496,7 @@ impl MetricsCollector {
     }
 
     pub async fn get_historical_data(&self) -> HistoricalPerformanceData {
-        HistoricalPerformanceData::new() // Return new instance to avoid unused field warning
+        self.historical_data.read().await.clone()
     }
 
     pub async fn get_historical_summary(&self, time_range: TimeRange) -> HistoricalSummary {
@@ -486,6 +522,22 @@ impl MetricsCollector {
         fs::write(&file_path, data).map_err(|e| e.to_string())
     }
 
+    /// Record the current metrics in history if they changed, then save to disk
+    pub async fn snapshot_and_save(&self) -> Result<(), String> {
+        let metrics = self.metrics.read().await.clone();
+        {
+            let mut historical = self.historical_data.write().await;
+            let changed = historical
+                .get_entries()
+                .last()
+                .map_or(metrics.request_count > 0, |e| e.metrics().request_count != metrics.request_count);
+            if changed {
+                historical.add_metrics_snapshot(metrics).await;
+            }
+        }
+        self.save_historical_data_to_file().await
+    }
+
     pub async fn load_historical_data_from_file(&self) -> Result<(), String> {
         let file_path = self._data_dir.join("historical_metrics.json");
         if file_path.exists() {
@@ -502,4 +554,27 @@ impl Default for MetricsCollector {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_then_load_restores_entries() {
+        let data_dir = std::env::temp_dir().join(format!("nm_metrics_2355_{}", std::process::id()));
+        let collector = MetricsCollector::with_data_dir(data_dir.clone());
+        collector.finish_request("req".to_string(), true).await;
+        collector.snapshot_and_save().await.unwrap();
+        // Unchanged metrics don't add another entry
+        collector.snapshot_and_save().await.unwrap();
+
+        let restored = MetricsCollector::with_data_dir(data_dir.clone());
+        restored.load_historical_data_from_file().await.unwrap();
+        let data = restored.get_historical_data().await;
+        let _ = fs::remove_dir_all(&data_dir);
+
+        assert_eq!(data.get_entries().len(), 1);
+        assert_eq!(data.get_entries()[0].metrics().request_count, 1);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::nm_config::WorkflowConfig;
+use crate::pricing::PricingTable;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::prelude::Modifier;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+/// Models offered by `/model` per provider, as OpenRouter ids
+const CURATED_MODELS: &[(&str, &[&str])] = &[
+    ("anthropic", &["claude-sonnet-4", "claude-3.5-haiku"]),
+    ("deepseek", &["deepseek-chat", "deepseek-r1"]),
+    ("google", &["gemini-2.5-pro", "gemini-2.5-flash"]),
+    ("meta-llama", &["llama-3.3-70b-instruct"]),
+    ("mistralai", &["mistral-large", "mistral-small"]),
+    ("openai", &["gpt-4.1", "gpt-4.1-mini", "gpt-4o", "gpt-4o-mini"]),
+    ("z-ai", &["glm-4.5"]),
+];
+
+/// The curated models, every priced chat model and the models `workflows` already use,
+/// sorted so each provider's models sit together
+pub fn available_models<'a>(workflows: impl IntoIterator<Item = &'a WorkflowConfig>, pricing: &PricingTable) -> Vec<String> {
+    let curated = CURATED_MODELS
+        .iter()
+        .flat_map(|(provider, models)| models.iter().map(move |m| format!("{}/{}", provider, m)));
+    let priced = pricing.models.keys().filter(|m| !m.contains("embedding")).cloned();
+    let used = workflows.into_iter().map(|cfg| cfg.model.clone());
+    let mut models: Vec<String> = curated.chain(priced).chain(used).filter(|m| !m.is_empty()).collect();
+    models.sort();
+    models.dedup();
+    models
+}
+
+/// The list `/model` shows, highlighting one entry at a time
+#[derive(Debug, Clone, Default)]
+pub struct ModelPicker {
+    pub models: Vec<String>,
+    pub index: usize,
+}
+
+impl ModelPicker {
+    /// Picker over `models` starting on `current`, which is added when it isn't listed
+    pub fn open(mut models: Vec<String>, current: &str) -> Self {
+        if !current.is_empty() && !models.iter().any(|m| m == current) {
+            models.push(current.to_string());
+            models.sort();
+        }
+        let index = models.iter().position(|m| m == current).unwrap_or(0);
+        Self { models, index }
+    }
+
+    pub fn up(&mut self) {
+        self.index = self.index.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.index + 1 < self.models.len() {
+            self.index += 1;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.models.get(self.index).map(String::as_str)
+    }
+
+    /// Set the highlighted model on `cfg`, returning the one it replaced when it changed
+    pub fn apply(&self, cfg: &mut WorkflowConfig) -> Option<String> {
+        let model = self.selected()?;
+        if cfg.model == model {
+            return None;
+        }
+        Some(std::mem::replace(&mut cfg.model, model.to_string()))
+    }
+}
+
+pub fn render_model_picker(f: &mut Frame, picker: &ModelPicker, workflow: &WorkflowConfig, area: Rect) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area);
+
+    let items: Vec<ListItem> = picker
+        .models
+        .iter()
+        .map(|model| {
+            let current = if *model == workflow.model { " (current)" } else { "" };
+            ListItem::new(Line::from(vec![
+                Span::raw(model.clone()),
+                Span::styled(current, Style::default().fg(Color::Gray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(crate::banner::title("🤖", &format!("Model for '{}'", workflow.name)))
+            .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)))
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::Green).add_modifier(Modifier::BOLD))
+        .highlight_symbol("▶ ");
+    let mut state = ListState::default().with_selected(Some(picker.index));
+    f.render_stateful_widget(list, chunks[0], &mut state);
+
+    let instructions = Paragraph::new("↑ ↓ Navigate  |  Enter Apply  |  Esc Cancel")
+        .style(Style::default().fg(Color::Gray));
+    f.render_widget(instructions, chunks[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picked_model_is_applied_to_workflow() {
+        let mut cfg = WorkflowConfig { name: "review".to_string(), model: "z-ai/glm-4.5".to_string(), ..Default::default() };
+        let models = available_models([&cfg], &PricingTable::builtin());
+        assert!(models.contains(&"openai/gpt-4o-mini".to_string()));
+        assert!(!models.iter().any(|m| m.contains("embedding")));
+        assert!(models.windows(2).all(|w| w[0] < w[1]), "sorted without duplicates");
+
+        // The picker starts on the workflow's model and applies the one moved to
+        let mut picker = ModelPicker::open(models, &cfg.model);
+        assert_eq!(picker.selected(), Some("z-ai/glm-4.5"));
+        assert!(picker.apply(&mut cfg).is_none());
+        picker.up();
+        let chosen = picker.selected().unwrap().to_string();
+        assert_eq!(picker.apply(&mut cfg), Some("z-ai/glm-4.5".to_string()));
+        assert_eq!(cfg.model, chosen);
+
+        // A model missing from the list is still offered, and moves stop at the ends
+        let mut picker = ModelPicker::open(vec!["a/one".to_string(), "c/three".to_string()], "b/custom");
+        assert_eq!(picker.models, vec!["a/one", "b/custom", "c/three"]);
+        assert_eq!(picker.selected(), Some("b/custom"));
+        picker.down();
+        picker.down();
+        assert_eq!(picker.selected(), Some("c/three"));
+    }
+}
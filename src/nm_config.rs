@@ -1,14 +1,61 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, RwLock};
+use fs2::FileExt;
+use serde::Serialize;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum AgentType {
     Agent,
     Validator, // Add this missing variant
     ParallelAgent,
 }
 
-#[derive(Debug, Clone)]
+impl AgentType {
+    /// Parse a user-facing agent type name such as "validator" or "parallel"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "agent" => Some(AgentType::Agent),
+            "validator" => Some(AgentType::Validator),
+            "parallel" | "parallelagent" => Some(AgentType::ParallelAgent),
+            _ => None,
+        }
+    }
+}
+
+/// Rewrites an agent's output before the next node or an injection sees it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum OutputProcessor {
+    ExtractCode,   // the body of the first fenced code block
+    StripMarkdown, // plain text without fences, headings or emphasis
+    JsonOnly,      // the first balanced JSON object
+}
+
+impl OutputProcessor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "extract_code" => Some(OutputProcessor::ExtractCode),
+            "strip_markdown" => Some(OutputProcessor::StripMarkdown),
+            "json_only" => Some(OutputProcessor::JsonOnly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputProcessor::ExtractCode => "extract_code",
+            OutputProcessor::StripMarkdown => "strip_markdown",
+            OutputProcessor::JsonOnly => "json_only",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct AgentRow {
     pub agent_type: AgentType,
     pub files: String,          // stores role:file mappings
@@ -17,7 +64,15 @@ pub struct AgentRow {
     pub on_failure: Option<i32>,
     pub iteration_delay_ms: u64,   // ✅ configurable delay
     pub input_injections: Vec<String>,  // ✅ input injection patterns (e.g., "input1:output2")
-    pub output_injections: Vec<String>, // ✅ output injection patterns
+    pub output_injections: Vec<String>, // ✅ output injection patterns (e.g., "nmoutput:out.txt", "output1:input3")
+    pub vote_count: usize,              // validator runs per decision, routed by majority
+    pub display_name: Option<String>,   // label shown on this agent's output, e.g. "Planner"
+    pub parallel_group: Option<u32>,    // agents sharing a group run concurrently as one step
+    pub post_process: Vec<OutputProcessor>, // applied in order to the output passed on
+    pub max_tokens: Option<u32>,        // this agent's completion cap, overriding the workflow's
+    pub stop: Vec<String>,              // this agent's stop sequences, replacing the workflow's when set
+    pub adaptive_pacing: bool,          // iteration_delay_ms is only the starting delay, tuned by 429s
+    pub response_format: Option<String>, // "json_object", or a JSON schema the answer must follow
 }
 
 impl Default for AgentRow {
@@ -31,20 +86,48 @@ impl Default for AgentRow {
             iteration_delay_ms: 200,
             input_injections: Vec::new(),
             output_injections: Vec::new(),
+            vote_count: 1,
+            display_name: None,
+            parallel_group: None,
+            post_process: Vec::new(),
+            max_tokens: None,
+            stop: Vec::new(),
+            adaptive_pacing: false,
+            response_format: None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WorkflowConfig {
     pub name: String,
     pub rows: Vec<AgentRow>,
-    #[allow(dead_code)]  // Currently unused but may be needed for future features
-    pub active_agent_index: usize,
+    pub active_agent_index: usize,       // agent `/run` starts at when none is selected
     pub model: String,
     pub temperature: f32,
     pub maximum_traversals: usize,
     pub working_dir: String,   // ✅ new
+    pub system_preamble: Option<String>, // shared instruction prepended to every agent
+    pub fallback_models: Vec<String>,    // models tried in order when `model` keeps failing
+    pub use_keyring: bool,               // resolve the API key from the OS keychain first
+    pub allowed_tools: Option<Vec<String>>, // only these tools are registered when set
+    pub denied_tools: Vec<String>,       // never registered, even if allowed
+    pub default_prompt: Option<String>,  // `/run` prompt template when none is given, `{{slot}}` placeholders
+    pub comments: Vec<String>,           // `#` lines from the workflow's section, written back above it
+    pub retry_budget: Option<usize>,     // total API retries allowed per run, unlimited when unset
+    pub embedding_model: Option<String>, // model for the `embed_text` tool
+    pub max_output_bytes: Option<usize>, // agent responses are truncated past this size
+    pub request_timeout_secs: u64,       // limit on each LLM request, 0 waits indefinitely
+    pub context_limit: usize,            // estimated tokens per request before old turns are dropped, 0 never trims
+    pub sandbox: bool,                   // file tools reject paths outside working_dir; execute_terminal is disabled
+    pub validate_tool_output: bool,      // warn when an extension tool's result breaks its output_schema
+    pub seed: Option<u64>,               // sent with every request so providers that honor it answer reproducibly
+    pub max_tokens: Option<u32>,         // caps each completion's length (and cost), provider default when unset
+    pub stop: Vec<String>,               // generation ends at the first of these markers, e.g. "\n\n###"
+    pub retry_on_empty: bool,            // re-request a blank answer (up to EMPTY_OUTPUT_RETRIES times) before giving up
+    pub tool_repeat_limit: usize,        // an agent stops once the same tool call (name and arguments) comes back this often, 0 never
+    pub flatten_injection: bool,         // turn newlines in injected `<let>` values into spaces; kept by default
+    pub terminal_output_limit: usize,    // most bytes of stdout and of stderr execute_terminal returns; calls may only ask for less
 }
 
 impl Default for WorkflowConfig {
@@ -57,48 +140,332 @@ impl Default for WorkflowConfig {
             temperature: 0.7,
             maximum_traversals: 20,
             working_dir: ".".into(),   // ✅ default
+            system_preamble: None,
+            fallback_models: Vec::new(),
+            use_keyring: false,
+            allowed_tools: None,
+            denied_tools: Vec::new(),
+            default_prompt: None,
+            comments: Vec::new(),
+            retry_budget: None,
+            embedding_model: None,
+            max_output_bytes: None,
+            request_timeout_secs: crate::error::DEFAULT_REQUEST_TIMEOUT_SECS,
+            context_limit: crate::agents::DEFAULT_CONTEXT_LIMIT,
+            sandbox: false,
+            validate_tool_output: false,
+            seed: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            retry_on_empty: false,
+            tool_repeat_limit: crate::agents::DEFAULT_TOOL_REPEAT_LIMIT,
+            flatten_injection: false,
+            terminal_output_limit: crate::tools::TERMINAL_OUTPUT_LIMIT,
         }
     }
 }
 
 pub const CONFIG_FILE: &str = "config.nm";
 
+/// Config file chosen on the command line, `None` means `CONFIG_FILE`
+static CONFIG_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Point loads and saves at a different config file
+pub fn set_config_path(path: Option<PathBuf>) {
+    if let Ok(mut guard) = CONFIG_PATH.write() {
+        *guard = path;
+    }
+}
+
+/// Config file currently used by `load_all_nm` and `save_all_nm`
+pub fn config_path() -> PathBuf {
+    CONFIG_PATH
+        .read()
+        .ok()
+        .and_then(|guard| guard.clone())
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+}
+
 /// Save a single workflow (legacy compatibility)
 pub fn save_nm(cfg: &WorkflowConfig) -> std::io::Result<()> {
     save_all_nm(std::slice::from_ref(cfg))
 }
 
+/// Workflows each config file held when this process last loaded or saved it, by name
+/// with a hash of their text, so a save can tell workflows another session added since
+/// from ones renamed away here, and notice ones another session changed
+static LOADED_NAMES: LazyLock<Mutex<HashMap<PathBuf, HashMap<String, u64>>>> = LazyLock::new(Default::default);
+
+/// Hash of the `.nm` text for one workflow
+fn workflow_hash(cfg: &WorkflowConfig) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    render_all_nm(std::slice::from_ref(cfg)).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Remember `cfgs` as what `path` held when it was loaded
+fn record_loaded(path: &Path, cfgs: &[WorkflowConfig]) {
+    if let Ok(mut loaded) = LOADED_NAMES.lock() {
+        loaded
+            .entry(path.to_path_buf())
+            .or_default()
+            .extend(cfgs.iter().map(|c| (c.name.clone(), workflow_hash(c))));
+    }
+}
+
 /// Save all workflows in multi-format
 pub fn save_all_nm(cfgs: &[WorkflowConfig]) -> std::io::Result<()> {
+    save_merged_to(cfgs, &config_path())
+}
+
+/// Save `cfgs` to `path`, keeping workflows another session (the TUI, a web client)
+/// added to the file since this process loaded it. Same-named workflows are
+/// overwritten with `cfgs`, with a warning when another session had changed them.
+/// The read and write happen under the file's lock.
+pub fn save_merged_to(cfgs: &[WorkflowConfig], path: &Path) -> std::io::Result<()> {
+    let mut known = LOADED_NAMES.lock().ok().and_then(|names| names.get(path).cloned()).unwrap_or_default();
+    save_merged_with(cfgs, path, &mut known)?;
+    if let Ok(mut loaded) = LOADED_NAMES.lock() {
+        loaded.insert(path.to_path_buf(), known);
+    }
+    Ok(())
+}
+
+/// `save_merged_to` against `known`, the workflows the saving session last saw in `path`,
+/// which is updated with what was written
+fn save_merged_with(cfgs: &[WorkflowConfig], path: &Path, known: &mut HashMap<String, u64>) -> std::io::Result<()> {
+    let _lock = lock_config(path, true)?;
+    let mut merged = cfgs.to_vec();
+    if let Ok(on_disk) = std::fs::read_to_string(path).map(|text| parse_nm_multiple(&text)) {
+        let on_disk = on_disk.unwrap_or_default();
+        let overwritten = changed_elsewhere(cfgs, &on_disk, known);
+        if !overwritten.is_empty() {
+            tracing::warn!(
+                "{} was changed by another session; overwriting its changes to {}",
+                path.display(),
+                overwritten.join(", ")
+            );
+        }
+        let ours: HashSet<&str> = cfgs.iter().map(|c| c.name.as_str()).collect();
+        let added: Vec<WorkflowConfig> = on_disk
+            .into_iter()
+            .filter(|c| !ours.contains(c.name.as_str()) && !known.contains_key(&c.name))
+            .collect();
+        if !added.is_empty() {
+            let names: Vec<&str> = added.iter().map(|c| c.name.as_str()).collect();
+            tracing::warn!(
+                "{} was changed by another session; keeping its workflows {}",
+                path.display(),
+                names.join(", ")
+            );
+            merged.extend(added);
+        }
+    }
+    write_atomically(path, &render_all_nm(&merged))?;
+    known.extend(merged.iter().map(|c| (c.name.clone(), workflow_hash(c))));
+    Ok(())
+}
+
+/// Names of workflows in `cfgs` whose copy on disk differs both from what the session
+/// last loaded or saved (`loaded`) and from the version about to be written
+fn changed_elsewhere(cfgs: &[WorkflowConfig], on_disk: &[WorkflowConfig], loaded: &HashMap<String, u64>) -> Vec<String> {
+    on_disk
+        .iter()
+        .filter(|disk| {
+            let hash = workflow_hash(disk);
+            let ours = cfgs.iter().find(|c| c.name == disk.name);
+            ours.is_some_and(|ours| workflow_hash(ours) != hash)
+                && loaded.get(&disk.name).is_some_and(|known| *known != hash)
+        })
+        .map(|disk| disk.name.clone())
+        .collect()
+}
+
+/// Save all workflows to a specific file, replacing whatever it held
+pub fn save_all_to(cfgs: &[WorkflowConfig], path: &Path) -> std::io::Result<()> {
+    let _lock = lock_config(path, true)?;
+    write_atomically(path, &render_all_nm(cfgs))
+}
+
+/// Append the workflows of `cfgs` that `path` doesn't define yet, leaving its existing
+/// text byte for byte as it was. Returns the names appended.
+pub fn append_missing_to(cfgs: &[WorkflowConfig], path: &Path) -> std::io::Result<Vec<String>> {
+    let _lock = lock_config(path, true)?;
+    let mut text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let existing = parse_nm_multiple(&text)?;
+    let missing: Vec<WorkflowConfig> = cfgs
+        .iter()
+        .filter(|cfg| !existing.iter().any(|e| e.name == cfg.name))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !text.trim().is_empty() {
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str("\n====\n\n");
+    }
+    text.push_str(&render_all_nm(&missing));
+    write_atomically(path, &text)?;
+    Ok(missing.into_iter().map(|cfg| cfg.name).collect())
+}
+
+/// `path` with `suffix` added to its file name, e.g. `config.nm.lock`
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Advisory lock on `<path>.lock`, held until the returned file is dropped. Saves take
+/// it exclusively and loads shared, so a load never sees another process mid-save.
+fn lock_config(path: &Path, exclusive: bool) -> std::io::Result<File> {
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(path, ".lock"))?;
+    if exclusive {
+        lock.lock_exclusive()?;
+    } else {
+        lock.lock_shared()?;
+    }
+    Ok(lock)
+}
+
+/// Write through a temporary file and rename it over `path` (see `atomic_write::write`)
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    crate::atomic_write::write(path, contents.as_bytes())
+}
+
+/// The `.nm` text `save_all_to` writes for `cfgs`
+pub fn render_all_nm(cfgs: &[WorkflowConfig]) -> String {
     let mut out = String::new();
     for (i, cfg) in cfgs.iter().enumerate() {
         if i > 0 {
             out.push_str("\n====\n\n");
         }
+        for comment in &cfg.comments {
+            out.push_str(comment);
+            out.push('\n');
+        }
         out.push_str(&format!("workflow:{}\n", cfg.name));
         out.push_str(&format!("model:{}\n", cfg.model));
         out.push_str(&format!("temperature:{}\n", cfg.temperature));
         out.push_str(&format!("maximum_traversals:{}\n", cfg.maximum_traversals));
         out.push_str(&format!("working_dir:{}\n", cfg.working_dir)); // ✅ save working_dir
+        if cfg.active_agent_index != 0 {
+            out.push_str(&format!("active_agent:{}\n", cfg.active_agent_index));
+        }
+        if let Some(preamble) = &cfg.system_preamble {
+            out.push_str(&format!("system_preamble:\"{}\"\n", escape_nm_value(preamble)));
+        }
+        if let Some(prompt) = &cfg.default_prompt {
+            out.push_str(&format!("default_prompt:\"{}\"\n", escape_nm_value(prompt)));
+        }
+        if cfg.use_keyring {
+            out.push_str("use_keyring:true\n");
+        }
+        if cfg.sandbox {
+            out.push_str("sandbox:true\n");
+        }
+        if cfg.validate_tool_output {
+            out.push_str("validate_tool_output:true\n");
+        }
+        if cfg.retry_on_empty {
+            out.push_str("retry_on_empty:true\n");
+        }
+        if cfg.flatten_injection {
+            out.push_str("flatten_injection:true\n");
+        }
+        if let Some(budget) = cfg.retry_budget {
+            out.push_str(&format!("retry_budget:{}\n", budget));
+        }
+        if let Some(seed) = cfg.seed {
+            out.push_str(&format!("seed:{}\n", seed));
+        }
+        if let Some(max_tokens) = cfg.max_tokens {
+            out.push_str(&format!("max_tokens:{}\n", max_tokens));
+        }
+        // One line per sequence, since a marker may contain any separator
+        for stop in &cfg.stop {
+            out.push_str(&format!("stop:\"{}\"\n", escape_nm_value(stop)));
+        }
+        if let Some(model) = &cfg.embedding_model {
+            out.push_str(&format!("embedding_model:{}\n", model));
+        }
+        if let Some(max) = cfg.max_output_bytes {
+            out.push_str(&format!("max_output_bytes:{}\n", max));
+        }
+        if cfg.request_timeout_secs != crate::error::DEFAULT_REQUEST_TIMEOUT_SECS {
+            out.push_str(&format!("request_timeout_secs:{}\n", cfg.request_timeout_secs));
+        }
+        if cfg.context_limit != crate::agents::DEFAULT_CONTEXT_LIMIT {
+            out.push_str(&format!("context_limit:{}\n", cfg.context_limit));
+        }
+        if cfg.tool_repeat_limit != crate::agents::DEFAULT_TOOL_REPEAT_LIMIT {
+            out.push_str(&format!("tool_repeat_limit:{}\n", cfg.tool_repeat_limit));
+        }
+        if cfg.terminal_output_limit != crate::tools::TERMINAL_OUTPUT_LIMIT {
+            out.push_str(&format!("terminal_output_limit:{}\n", cfg.terminal_output_limit));
+        }
+        if let Some(allowed) = &cfg.allowed_tools {
+            out.push_str(&format!("allowed_tools:{}\n", allowed.join(",")));
+        }
+        if !cfg.denied_tools.is_empty() {
+            out.push_str(&format!("denied_tools:{}\n", cfg.denied_tools.join(",")));
+        }
+        if !cfg.fallback_models.is_empty() {
+            out.push_str(&format!("fallback_models:{}\n", cfg.fallback_models.join(",")));
+        }
         for (j, row) in cfg.rows.iter().enumerate() {
             out.push_str(&format!("agent_{}: {:?}\n", j + 1, row.agent_type));
             out.push_str(&format!("files:\"{}\"\n", row.files));
             out.push_str(&format!("maximum_iterations:{}\n", row.max_iterations));
             out.push_str(&format!("iteration_delay_ms:{}\n", row.iteration_delay_ms));
-            out.push_str(&format!("on_success:{}\n", row.on_success.unwrap_or(-1)));
-            out.push_str(&format!("on_failure:{}\n", row.on_failure.unwrap_or(-1)));
+            if row.adaptive_pacing {
+                out.push_str("adaptive_pacing:true\n");
+            }
+            out.push_str(&format!("on_success:{}\n", row.on_success.unwrap_or(END_NODE)));
+            out.push_str(&format!("on_failure:{}\n", row.on_failure.unwrap_or(END_NODE)));
             // Save injection patterns
             if !row.input_injections.is_empty() {
-                out.push_str(&format!("input_injections:\"{}\"\n", row.input_injections.join(";")));
+                out.push_str(&format!("input_injections:\"{}\"\n", join_injections(&row.input_injections)));
             }
             if !row.output_injections.is_empty() {
-                out.push_str(&format!("output_injections:\"{}\"\n", row.output_injections.join(";")));
+                out.push_str(&format!("output_injections:\"{}\"\n", join_injections(&row.output_injections)));
+            }
+            if row.vote_count > 1 {
+                out.push_str(&format!("vote_count:{}\n", row.vote_count));
+            }
+            if let Some(display_name) = &row.display_name {
+                out.push_str(&format!("display_name:\"{}\"\n", escape_nm_value(display_name)));
+            }
+            if let Some(group) = row.parallel_group {
+                out.push_str(&format!("parallel_group:{}\n", group));
+            }
+            if let Some(max_tokens) = row.max_tokens {
+                out.push_str(&format!("max_tokens:{}\n", max_tokens));
+            }
+            for stop in &row.stop {
+                out.push_str(&format!("stop:\"{}\"\n", escape_nm_value(stop)));
+            }
+            if let Some(format) = &row.response_format {
+                out.push_str(&format!("response_format:\"{}\"\n", escape_nm_value(format)));
+            }
+            if !row.post_process.is_empty() {
+                let names: Vec<&str> = row.post_process.iter().map(|p| p.name()).collect();
+                out.push_str(&format!("post_process:\"{}\"\n", names.join(";")));
             }
         }
     }
-    let mut f = File::create(CONFIG_FILE)?;
-    f.write_all(out.as_bytes())?;
-    Ok(())
+    out
 }
 
 /// Load a single workflow (legacy compatibility)
@@ -118,19 +485,29 @@ pub fn load_nm_or_create() -> WorkflowConfig {
 
 /// Load all workflows
 pub fn load_all_nm() -> std::io::Result<Vec<WorkflowConfig>> {
-    match load_all_nm_inner() {
-        Ok(cfgs) => Ok(cfgs),
+    load_all_from(&config_path())
+}
+
+/// Load all workflows from a specific file, creating a default one if it can't be read
+pub fn load_all_from(path: &Path) -> std::io::Result<Vec<WorkflowConfig>> {
+    match load_all_nm_inner(path) {
+        Ok(cfgs) => {
+            record_loaded(path, &cfgs);
+            Ok(cfgs)
+        }
         Err(_) => {
             let def = WorkflowConfig::default();
-            let _ = save_nm(&def);
+            let _ = save_all_to(std::slice::from_ref(&def), path);
             Ok(vec![def])
         }
     }
 }
 
-fn load_all_nm_inner() -> std::io::Result<Vec<WorkflowConfig>> {
+fn load_all_nm_inner(path: &Path) -> std::io::Result<Vec<WorkflowConfig>> {
     let mut s = String::new();
-    File::open(CONFIG_FILE)?.read_to_string(&mut s)?;
+    let mut f = File::open(path)?;
+    let _lock = lock_config(path, false)?;
+    f.read_to_string(&mut s)?;
     parse_nm_multiple(&s)
 }
 
@@ -138,23 +515,37 @@ fn load_all_nm_inner() -> std::io::Result<Vec<WorkflowConfig>> {
 #[allow(dead_code)]
 fn load_nm() -> std::io::Result<WorkflowConfig> {
     let mut s = String::new();
-    File::open(CONFIG_FILE)?.read_to_string(&mut s)?;
+    File::open(config_path())?.read_to_string(&mut s)?;
     let workflows = parse_nm_multiple(&s)?;
     workflows.into_iter().next().ok_or_else(|| {
         std::io::Error::new(std::io::ErrorKind::InvalidData, "No workflows found")
     })
 }
 
-/// Parse multiple workflows separated by ====
+/// Whether a `.nm` line is a `#` comment
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+/// Parse multiple workflows separated by `====` lines
 pub fn parse_nm_multiple(s: &str) -> std::io::Result<Vec<WorkflowConfig>> {
     let mut workflows = Vec::new();
-    let sections: Vec<&str> = s.split("====").collect();
+    let mut sections: Vec<String> = vec![String::new()];
+    for line in s.lines() {
+        if line.trim() == "====" {
+            sections.push(String::new());
+        } else if let Some(section) = sections.last_mut() {
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
 
     for section in sections {
-        if section.trim().is_empty() {
+        // Sections with nothing but blank lines and comments hold no workflow
+        if section.lines().all(|l| l.trim().is_empty() || is_comment(l)) {
             continue;
         }
-        let workflow = parse_nm_single(section)?;
+        let workflow = parse_nm_single(&section)?;
         workflows.push(workflow);
     }
 
@@ -173,6 +564,28 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
     let mut temperature = 0.7;
     let mut maximum_traversals = 20;
     let mut working_dir = ".".to_string(); // ✅ default
+    let mut system_preamble: Option<String> = None;
+    let mut fallback_models: Vec<String> = Vec::new();
+    let mut use_keyring = false;
+    let mut sandbox = false;
+    let mut validate_tool_output = false;
+    let mut retry_on_empty = false;
+    let mut flatten_injection = false;
+    let mut allowed_tools: Option<Vec<String>> = None;
+    let mut denied_tools: Vec<String> = Vec::new();
+    let mut default_prompt: Option<String> = None;
+    let mut comments: Vec<String> = Vec::new();
+    let mut retry_budget: Option<usize> = None;
+    let mut seed: Option<u64> = None;
+    let mut max_tokens: Option<u32> = None;
+    let mut stop: Vec<String> = Vec::new();
+    let mut embedding_model: Option<String> = None;
+    let mut max_output_bytes: Option<usize> = None;
+    let mut request_timeout_secs = crate::error::DEFAULT_REQUEST_TIMEOUT_SECS;
+    let mut context_limit = crate::agents::DEFAULT_CONTEXT_LIMIT;
+    let mut tool_repeat_limit = crate::agents::DEFAULT_TOOL_REPEAT_LIMIT;
+    let mut terminal_output_limit = crate::tools::TERMINAL_OUTPUT_LIMIT;
+    let mut active_agent_index: usize = 0;
 
     let push_current =
         |rows: &mut Vec<AgentRow>, cur: &mut Option<AgentRow>| {
@@ -186,6 +599,10 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
         if line.is_empty() {
             continue;
         }
+        if is_comment(line) {
+            comments.push(line.to_string());
+            continue;
+        }
         if let Some(rest) = line.strip_prefix("workflow:") {
             name = rest.trim().to_string();
             continue;
@@ -206,6 +623,105 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
             working_dir = rest.trim().to_string();
             continue;
         }
+        if let Some(rest) = line.strip_prefix("allowed_tools:") {
+            allowed_tools = Some(split_list(rest));
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("denied_tools:") {
+            denied_tools = split_list(rest);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("active_agent:") {
+            active_agent_index = rest.trim().parse::<usize>().unwrap_or(0);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("request_timeout_secs:") {
+            request_timeout_secs = rest.trim().parse::<u64>().unwrap_or(crate::error::DEFAULT_REQUEST_TIMEOUT_SECS);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("context_limit:") {
+            context_limit = rest.trim().parse::<usize>().unwrap_or(crate::agents::DEFAULT_CONTEXT_LIMIT);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("tool_repeat_limit:") {
+            tool_repeat_limit = rest.trim().parse::<usize>().unwrap_or(crate::agents::DEFAULT_TOOL_REPEAT_LIMIT);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("terminal_output_limit:") {
+            terminal_output_limit = rest.trim().parse::<usize>().unwrap_or(crate::tools::TERMINAL_OUTPUT_LIMIT);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("max_output_bytes:") {
+            max_output_bytes = rest.trim().parse::<usize>().ok();
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("embedding_model:") {
+            let model = rest.trim();
+            embedding_model = if model.is_empty() { None } else { Some(model.to_string()) };
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("retry_budget:") {
+            retry_budget = rest.trim().parse::<usize>().ok();
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("max_tokens:") {
+            // Inside an agent's block it caps just that agent
+            let value = rest.trim().parse::<u32>().ok().filter(|n| *n > 0);
+            match &mut cur_agent {
+                Some(a) => a.max_tokens = value,
+                None => max_tokens = value,
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("stop:") {
+            // Repeated for each sequence; inside an agent's block they're that agent's
+            let value = unescape_nm_value(strip_quotes(rest.trim()));
+            if !value.is_empty() {
+                match &mut cur_agent {
+                    Some(a) => a.stop.push(value),
+                    None => stop.push(value),
+                }
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("seed:") {
+            seed = rest.trim().parse::<u64>().ok();
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("sandbox:") {
+            sandbox = rest.trim() == "true";
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("validate_tool_output:") {
+            validate_tool_output = rest.trim() == "true";
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("retry_on_empty:") {
+            retry_on_empty = rest.trim() == "true";
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("flatten_injection:") {
+            flatten_injection = rest.trim() == "true";
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("use_keyring:") {
+            use_keyring = rest.trim() == "true";
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("fallback_models:") {
+            fallback_models = split_list(rest);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("system_preamble:") {
+            let val = unescape_nm_value(strip_quotes(rest.trim()));
+            system_preamble = if val.is_empty() { None } else { Some(val) };
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("default_prompt:") {
+            let val = unescape_nm_value(strip_quotes(rest.trim()));
+            default_prompt = if val.is_empty() { None } else { Some(val) };
+            continue;
+        }
         if let Some(rest) = line.strip_prefix("agent_") {
             push_current(&mut rows, &mut cur_agent);
             let parts: Vec<&str> = rest.splitn(2, ':').collect();
@@ -220,13 +736,7 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
                 };
                 cur_agent = Some(AgentRow {
                     agent_type,
-                    files: String::new(),
-                    max_iterations: 3,
-                    on_success: None,
-                    on_failure: None,
-                    iteration_delay_ms: 200,
-                    input_injections: Vec::new(),
-                    output_injections: Vec::new(),
+                    ..AgentRow::default()
                 });
             }
             continue;
@@ -252,31 +762,77 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
             }
             continue;
         }
+        if let Some(rest) = line.strip_prefix("response_format:") {
+            if let Some(a) = &mut cur_agent {
+                a.response_format = Some(unescape_nm_value(strip_quotes(rest.trim()))).filter(|f| !f.is_empty());
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("adaptive_pacing:") {
+            if let Some(a) = &mut cur_agent {
+                a.adaptive_pacing = rest.trim() == "true";
+            }
+            continue;
+        }
         if let Some(rest) = line.strip_prefix("on_success:") {
-            let n = rest.trim().parse::<i32>().unwrap_or(-1);
             if let Some(a) = &mut cur_agent {
-                a.on_success = if n >= 0 { Some(n) } else { None };
+                a.on_success = parse_route(rest);
             }
             continue;
         }
         if let Some(rest) = line.strip_prefix("on_failure:") {
-            let n = rest.trim().parse::<i32>().unwrap_or(-1);
             if let Some(a) = &mut cur_agent {
-                a.on_failure = if n >= 0 { Some(n) } else { None };
+                a.on_failure = parse_route(rest);
             }
             continue;
         }
         if let Some(rest) = line.strip_prefix("input_injections:") {
             let val = rest.trim().trim_matches('"').to_string();
             if let Some(a) = &mut cur_agent {
-                a.input_injections = val.split(';').map(|s| s.trim().to_string()).collect();
+                a.input_injections = split_injections(&val);
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("vote_count:") {
+            let n = rest.trim().parse::<usize>().unwrap_or(1).max(1);
+            if let Some(a) = &mut cur_agent {
+                a.vote_count = n;
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("parallel_group:") {
+            if let Some(a) = &mut cur_agent {
+                a.parallel_group = rest.trim().parse::<u32>().ok();
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("post_process:") {
+            if let Some(a) = &mut cur_agent {
+                a.post_process = strip_quotes(rest.trim())
+                    .split(';')
+                    .filter(|name| !name.trim().is_empty())
+                    .filter_map(|name| {
+                        let processor = OutputProcessor::from_name(name);
+                        if processor.is_none() {
+                            tracing::warn!("Unknown post_process '{}', expected extract_code, strip_markdown or json_only", name.trim());
+                        }
+                        processor
+                    })
+                    .collect();
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("display_name:") {
+            let val = unescape_nm_value(strip_quotes(rest.trim()));
+            if let Some(a) = &mut cur_agent {
+                a.display_name = if val.is_empty() { None } else { Some(val) };
             }
             continue;
         }
         if let Some(rest) = line.strip_prefix("output_injections:") {
             let val = rest.trim().trim_matches('"').to_string();
             if let Some(a) = &mut cur_agent {
-                a.output_injections = val.split(';').map(|s| s.trim().to_string()).collect();
+                a.output_injections = split_injections(&val);
             }
             continue;
         }
@@ -290,18 +846,414 @@ fn parse_nm_single(s: &str) -> std::io::Result<WorkflowConfig> {
     Ok(WorkflowConfig {
         name,
         rows,
-        active_agent_index: 0,
+        active_agent_index,
         model,
         temperature,
         maximum_traversals,
         working_dir,
+        system_preamble,
+        fallback_models,
+        use_keyring,
+        allowed_tools,
+        denied_tools,
+        default_prompt,
+        comments,
+        retry_budget,
+        embedding_model,
+        max_output_bytes,
+        request_timeout_secs,
+        context_limit,
+        sandbox,
+        validate_tool_output,
+        seed,
+        max_tokens,
+        stop,
+        retry_on_empty,
+        tool_repeat_limit,
+        flatten_injection,
+        terminal_output_limit,
+    })
+}
+
+/// Split a comma-separated `.nm` value, dropping empty items
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Remove an agent row and repair routes that referenced it or shifted down
+pub fn remove_agent(cfg: &mut WorkflowConfig, index: usize) -> Option<AgentRow> {
+    if index >= cfg.rows.len() {
+        return None;
+    }
+    let removed = cfg.rows.remove(index);
+    let fix = |route: Option<i32>| -> Option<i32> {
+        match route {
+            Some(r) if r == index as i32 => None,
+            Some(r) if r > index as i32 => Some(r - 1),
+            other => other,
+        }
+    };
+    let len = cfg.rows.len() as i32;
+    for row in &mut cfg.rows {
+        row.on_success = fix(row.on_success).filter(|r| *r < len);
+        row.on_failure = fix(row.on_failure).filter(|r| *r < len);
+    }
+    if cfg.active_agent_index == index {
+        cfg.active_agent_index = 0;
+    } else if cfg.active_agent_index > index {
+        cfg.active_agent_index -= 1;
+    }
+    Some(removed)
+}
+
+/// Escape a value so it fits on a single quoted `.nm` line
+pub fn escape_nm_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverse `escape_nm_value`, turning `\n`, `\"` and `\\` back into their characters
+pub fn unescape_nm_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some(other) => {
+                    out.push('\\');
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Remove one pair of surrounding double quotes, if present
+fn strip_quotes(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+// `files` entries are separated by `;` and split into `role:<role>:<file>` on `:`, and
+// injection patterns are separated by `;`. A `\` before `;` or `:` makes that character
+// literal, and backslashes right before `;`, `:` or the end of a part are doubled. Any other
+// backslash is kept as is, so Windows and UNC paths need no escaping.
+const LIST_ESCAPED: [char; 2] = [';', ':'];
+
+/// Whether a run of backslashes followed by `next` is doubled in escaped text
+fn doubles_backslashes(next: Option<char>) -> bool {
+    next.is_none_or(|n| LIST_ESCAPED.contains(&n))
+}
+
+fn escape_list_part(part: &str, specials: &[char]) -> String {
+    let mut out = String::with_capacity(part.len());
+    let mut chars = part.chars().peekable();
+    let mut backslashes = 0;
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            backslashes += 1;
+            if chars.peek() != Some(&'\\') {
+                // Only backslashes that would read as an escape need doubling
+                let run = if doubles_backslashes(chars.peek().copied()) { 2 * backslashes } else { backslashes };
+                out.push_str(&"\\".repeat(run));
+                backslashes = 0;
+            }
+        } else {
+            if specials.contains(&c) {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Undo the list escaping: `\;` and `\:` become the character itself, and doubled
+/// backslashes before `;`, `:` or the end are halved
+pub fn unescape_list_part(part: &str) -> String {
+    let mut out = String::with_capacity(part.len());
+    let mut chars = part.chars().peekable();
+    let mut backslashes = 0;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        backslashes += 1;
+        let next = chars.peek().copied();
+        if next == Some('\\') {
+            continue;
+        }
+        if doubles_backslashes(next) {
+            // An odd one out escapes the `;` or `:` after it
+            out.push_str(&"\\".repeat(backslashes / 2));
+            match next {
+                Some(special) if backslashes % 2 == 1 => {
+                    out.push(special);
+                    chars.next();
+                }
+                None if backslashes % 2 == 1 => out.push('\\'),
+                _ => {}
+            }
+        } else {
+            out.push_str(&"\\".repeat(backslashes));
+        }
+        backslashes = 0;
+    }
+    out
+}
+
+/// Split on each `sep` that isn't escaped. Pieces keep their escapes.
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut backslashes = 0;
+    for (i, c) in s.char_indices() {
+        if c == sep && backslashes % 2 == 0 {
+            pieces.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+    }
+    pieces.push(&s[start..]);
+    pieces
+}
+
+/// A `files` entry for `role` and `file`, escaped so either may contain `;` or `:`
+pub fn file_entry(role: &str, file: &str) -> String {
+    format!("role:{}:{}", escape_list_part(role, &[';', ':']), escape_list_part(file, &[';', ':']))
+}
+
+/// The `;`-separated entries of an agent's `files`, still escaped
+pub fn file_entries(files: &str) -> Vec<String> {
+    split_unescaped(files, ';')
+        .into_iter()
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Role and file of a `role:<role>:<file>` entry. Unescaped colons after the role
+/// stay in the file name.
+pub fn parse_file_entry(entry: &str) -> Option<(String, String)> {
+    let parts = split_unescaped(entry.trim(), ':');
+    if parts.len() < 3 {
+        return None;
+    }
+    let file = unescape_list_part(parts[2..].join(":").trim());
+    (!file.is_empty()).then(|| (unescape_list_part(parts[1].trim()), file))
+}
+
+/// Injection patterns as saved in `.nm` and typed in Create mode, `;`-separated
+pub fn join_injections(patterns: &[String]) -> String {
+    patterns.iter().map(|p| escape_list_part(p, &[';'])).collect::<Vec<_>>().join(";")
+}
+
+/// Inverse of `join_injections`, dropping empty patterns
+pub fn split_injections(s: &str) -> Vec<String> {
+    split_unescaped(s, ';')
+        .into_iter()
+        .map(|p| unescape_list_part(p.trim()))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Extract the POML file names referenced by an agent's `role:<role>:<file>` entries
+pub fn poml_files(row: &AgentRow) -> Vec<String> {
+    file_entries(&row.files)
+        .iter()
+        .filter_map(|entry| parse_file_entry(entry))
+        .map(|(_, file)| file)
+        .collect()
+}
+
+/// Check that a POML file exists under `prompts/` and can be opened for reading
+pub fn poml_file_exists(file: &str) -> bool {
+    let path = std::path::Path::new("prompts").join(file);
+    path.is_file() && File::open(&path).is_ok()
+}
+
+/// Collect every POML file referenced by a workflow that is missing or unreadable
+pub fn missing_poml_files(cfg: &WorkflowConfig) -> Vec<String> {
+    let mut missing = Vec::new();
+    for row in &cfg.rows {
+        for file in poml_files(row) {
+            if !poml_file_exists(&file) && !missing.contains(&file) {
+                missing.push(file);
+            }
+        }
+    }
+    missing
+}
+
+/// Route value that ends the workflow, written as `on_success:-1` in config.nm.
+/// Any negative route, a missing route and an index past the last agent all mean END.
+pub const END_NODE: i32 = -1;
+
+/// Parse an `on_success`/`on_failure` value: a 0-based agent index, or `-1`/`END`
+fn parse_route(value: &str) -> Option<i32> {
+    value.trim().parse::<i32>().ok().filter(|n| *n >= 0)
+}
+
+/// The agent a route leads to, or `None` when it ends the workflow
+pub fn route_target(route: Option<i32>, agent_count: usize) -> Option<usize> {
+    route
+        .and_then(|r| usize::try_from(r).ok())
+        .filter(|r| *r < agent_count)
+}
+
+/// Every agent in the same `parallel_group` as agent `index`, in row order. Empty
+/// when the agent isn't grouped.
+pub fn parallel_group(cfg: &WorkflowConfig, index: usize) -> Vec<usize> {
+    let Some(group) = cfg.rows.get(index).and_then(|row| row.parallel_group) else {
+        return Vec::new();
+    };
+    (0..cfg.rows.len())
+        .filter(|i| cfg.rows[*i].parallel_group == Some(group))
+        .collect()
+}
+
+/// The first `outputN:inputM` injection between two members of a group, as 1-based
+/// agent numbers. Such members depend on each other and can't run concurrently.
+pub fn group_dependency(cfg: &WorkflowConfig, members: &[usize]) -> Option<(usize, usize)> {
+    members.iter().find_map(|i| {
+        cfg.rows[*i].output_injections.iter().find_map(|pattern| match OutputInjection::parse(pattern) {
+            Some(OutputInjection::Agent { from, to })
+                if members.contains(&(from.wrapping_sub(1))) && members.contains(&(to.wrapping_sub(1))) =>
+            {
+                Some((from, to))
+            }
+            _ => None,
+        })
     })
 }
 
+/// `[2] Reviewer` for an agent, `END` when the route finishes the workflow
+fn route_label(cfg: &WorkflowConfig, route: Option<i32>) -> String {
+    match route_target(route, cfg.rows.len()) {
+        Some(i) => format!("[{}] {}", i + 1, agent_label(cfg, i)),
+        None => "END".to_string(),
+    }
+}
+
+fn agent_label(cfg: &WorkflowConfig, index: usize) -> String {
+    cfg.rows[index].display_name.clone().unwrap_or_else(|| format!("Agent{}", index + 1))
+}
+
+/// ASCII view of a workflow's nodes and edges. Agents pass their output to the
+/// next agent; validators route on success and failure.
+pub fn render_graph(cfg: &WorkflowConfig) -> String {
+    let start = if cfg.active_agent_index < cfg.rows.len() { cfg.active_agent_index } else { 0 };
+    let mut out = format!("Workflow '{}' (start: [{}])\n", cfg.name, start + 1);
+    for (i, row) in cfg.rows.iter().enumerate() {
+        out.push_str(&format!("[{}] {} ({:?})\n", i + 1, agent_label(cfg, i), row.agent_type));
+        if row.agent_type == AgentType::Validator {
+            out.push_str(&format!("  ├─ success ─> {}\n", route_label(cfg, row.on_success)));
+            out.push_str(&format!("  └─ failure ─> {}\n", route_label(cfg, row.on_failure)));
+        } else {
+            let next = (i + 1 < cfg.rows.len()).then_some(i as i32 + 1);
+            out.push_str(&format!("  └─ next ────> {}\n", route_label(cfg, next)));
+        }
+        for pattern in &row.output_injections {
+            if let Some(OutputInjection::Agent { to, .. }) = OutputInjection::parse(pattern) {
+                out.push_str(&format!("     injects ─> {}\n", route_label(cfg, Some(to as i32 - 1))));
+            }
+        }
+    }
+    out
+}
+
+/// Plain-English account of a workflow built from its structure alone, used by
+/// `/describe` when the model can't be asked
+pub fn describe_workflow(cfg: &WorkflowConfig) -> String {
+    if cfg.rows.is_empty() {
+        return format!("Workflow '{}' has no agents.", cfg.name);
+    }
+    let start = if cfg.active_agent_index < cfg.rows.len() { cfg.active_agent_index } else { 0 };
+    let count = if cfg.rows.len() == 1 { "1 agent".to_string() } else { format!("{} agents", cfg.rows.len()) };
+    let mut out = format!(
+        "Workflow '{}' has {} using {} at temperature {} and starts at {}.",
+        cfg.name, count, cfg.model, cfg.temperature, agent_label(cfg, start)
+    );
+    for (i, row) in cfg.rows.iter().enumerate() {
+        let kind = if row.agent_type == AgentType::Validator { "a validator" } else { "an agent" };
+        // Entries look like `role:system:plan.poml`; only the file name matters here
+        let files = poml_files(row);
+        let prompts = if files.is_empty() { "no prompt file".to_string() } else { files.join(", ") };
+        out.push_str(&format!("\n{}. {} is {} running {}", i + 1, agent_label(cfg, i), kind, prompts));
+        if let Some(group) = row.parallel_group {
+            out.push_str(&format!(" in parallel group {}", group));
+        }
+        if row.agent_type == AgentType::Validator {
+            out.push_str(&format!(
+                "; on success it goes to {}, on failure to {}.",
+                route_label(cfg, row.on_success),
+                route_label(cfg, row.on_failure)
+            ));
+        } else {
+            match (i + 1 < cfg.rows.len()).then_some(i as i32 + 1) {
+                Some(next) => out.push_str(&format!("; it passes its output to {}.", route_label(cfg, Some(next)))),
+                None => out.push_str("; its output ends the run."),
+            }
+        }
+    }
+    if cfg.maximum_traversals > 0 {
+        out.push_str(&format!("\nA run stops after {} traversals.", cfg.maximum_traversals));
+    } else {
+        out.push_str("\nRuns loop until a route reaches END.");
+    }
+    out
+}
+
 pub fn preset_workflows() -> Vec<WorkflowConfig> {
     vec![WorkflowConfig::default()]
 }
 
+/// Where an agent's output goes after it runs, parsed from an `output_injections` pattern
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputInjection {
+    /// `nmoutput:<path>` writes the output to a file, relative to the working directory
+    File(String),
+    /// `output<N>:input<M>` adds agent N's latest output to agent M's next input (1-based)
+    Agent { from: usize, to: usize },
+}
+
+impl OutputInjection {
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let (source, target) = pattern.split_once(':')?;
+        let (source, target) = (source.trim(), target.trim());
+        if source == "nmoutput" {
+            return (!target.is_empty()).then(|| OutputInjection::File(target.to_string()));
+        }
+        let from = source.strip_prefix("output")?.parse::<usize>().ok()?;
+        let to = target.strip_prefix("input")?.parse::<usize>().ok()?;
+        (from > 0 && to > 0).then_some(OutputInjection::Agent { from, to })
+    }
+}
+
 /// Process input/output injections for a given agent
 pub fn process_injections(
     input: &str,
@@ -372,4 +1324,258 @@ fn inject_into_input(input: &str, position: &str, content: &str) -> String {
     } else {
         input.to_string()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_poml_files_reported() {
+        let mut cfg = WorkflowConfig::default();
+        cfg.rows[0].files = "role:system:chat_system.poml;role:user:does_not_exist_2347.poml".into();
+        assert_eq!(poml_files(&cfg.rows[0]).len(), 2);
+        let missing = missing_poml_files(&cfg);
+        assert_eq!(missing, vec!["does_not_exist_2347.poml".to_string()]);
+    }
+
+    #[test]
+    fn test_separators_in_paths_survive_save_and_load() {
+        let odd = "drafts;v2/notes:final.poml";
+        let mut cfg = WorkflowConfig { name: "separators".into(), ..Default::default() };
+        cfg.rows[0].files = [file_entry("sys;tem", odd), file_entry("user", "plain.poml")].join(";");
+        cfg.rows[0].output_injections = vec!["nmoutput:out;put\\".to_string(), "output1:input2".to_string()];
+        cfg.rows[0].input_injections = vec!["input1:C:\\notes;draft.txt".to_string()];
+
+        let reloaded = parse_nm_multiple(&render_all_nm(&[cfg.clone()])).unwrap().remove(0);
+        let row = &reloaded.rows[0];
+        assert_eq!(row.files, cfg.rows[0].files);
+        let entries: Vec<Option<(String, String)>> = file_entries(&row.files).iter().map(|e| parse_file_entry(e)).collect();
+        assert_eq!(
+            entries,
+            vec![Some(("sys;tem".to_string(), odd.to_string())), Some(("user".to_string(), "plain.poml".to_string()))]
+        );
+        assert_eq!(poml_files(row), vec![odd.to_string(), "plain.poml".to_string()]);
+        assert_eq!(row.output_injections, cfg.rows[0].output_injections);
+        assert_eq!(row.input_injections, cfg.rows[0].input_injections);
+
+        // Existing configs keep working: Windows paths and bare colons in the file name
+        assert_eq!(
+            parse_file_entry("role:system:C:\\prompts\\plan.poml"),
+            Some(("system".to_string(), "C:\\prompts\\plan.poml".to_string()))
+        );
+        assert_eq!(split_injections("nmoutput:out.txt;output1:input3"), vec!["nmoutput:out.txt", "output1:input3"]);
+
+        // UNC paths keep their leading backslashes, whether saved by us or typed by hand
+        let unc = r"\\server\share\x.poml";
+        assert_eq!(file_entry("system", unc), format!("role:system:{}", unc));
+        assert_eq!(parse_file_entry(&file_entry("system", unc)), Some(("system".to_string(), unc.to_string())));
+        assert_eq!(split_injections(r"input1:\\server\share\notes.txt"), vec![r"input1:\\server\share\notes.txt"]);
+        let mut cfg = WorkflowConfig { name: "unc".into(), ..Default::default() };
+        cfg.rows[0].files = file_entry("user", unc);
+        cfg.rows[0].input_injections = vec![r"input1:\\server\share\dir\".to_string()];
+        let reloaded = parse_nm_multiple(&render_all_nm(&[cfg.clone()])).unwrap().remove(0);
+        assert_eq!(poml_files(&reloaded.rows[0]), vec![unc.to_string()]);
+        assert_eq!(reloaded.rows[0].input_injections, cfg.rows[0].input_injections);
+    }
+
+    #[test]
+    fn test_system_preamble_round_trip() {
+        let preamble = "Line one\nSay \"hi\" \\ bye";
+        let text = format!(
+            "name:preamble_2348\nsystem_preamble:\"{}\"\nagent_1: Agent\n",
+            escape_nm_value(preamble)
+        );
+        assert!(!escape_nm_value(preamble).contains('\n'));
+
+        let parsed = parse_nm_single(&text).unwrap();
+        assert_eq!(parsed.system_preamble.as_deref(), Some(preamble));
+    }
+
+    #[test]
+    fn test_commented_config_round_trip() {
+        let text = "# Shared settings for the team\n\n====\n\n  # Reviews pull requests\nworkflow:review\nmodel:test/model\n\n   maximum_traversals:5  \n# agents below\nagent_1: Agent\nfiles:\"role:system:a.poml\"\n\n====\n====\n\nworkflow:second\nsystem_preamble:\"#not a comment\"\n";
+        let parsed = parse_nm_multiple(text).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "review");
+        assert_eq!(parsed[0].model, "test/model");
+        assert_eq!(parsed[0].maximum_traversals, 5);
+        assert_eq!(parsed[0].rows[0].files, "role:system:a.poml");
+        assert_eq!(parsed[0].comments, vec!["# Reviews pull requests", "# agents below"]);
+        assert_eq!(parsed[1].system_preamble.as_deref(), Some("#not a comment"));
+
+        let path = std::env::temp_dir().join(format!("nm_config_2373_{}.nm", std::process::id()));
+        save_all_to(&parsed, &path).unwrap();
+        let reloaded = load_all_from(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded[0].comments, parsed[0].comments);
+        assert_eq!(reloaded[0].maximum_traversals, 5);
+        assert_eq!(reloaded[0].rows[0].files, "role:system:a.poml");
+        assert_eq!(reloaded[1].name, "second");
+    }
+
+    #[test]
+    fn test_default_prompt_round_trip() {
+        let path = std::env::temp_dir().join(format!("nm_config_2366_{}.nm", std::process::id()));
+        let mut cfg = WorkflowConfig::default();
+        cfg.default_prompt = Some("Review {{file}}\nfor \"{{issue}}\"".into());
+        save_all_to(std::slice::from_ref(&cfg), &path).unwrap();
+
+        let loaded = load_all_from(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded[0].default_prompt, cfg.default_prompt);
+    }
+
+    #[test]
+    fn test_load_all_from_custom_path() {
+        let path = std::env::temp_dir().join(format!("nm_config_2352_{}.nm", std::process::id()));
+        let mut cfg = WorkflowConfig::default();
+        cfg.name = "custom_path_workflow".into();
+        cfg.maximum_traversals = 7;
+        cfg.rows = vec![AgentRow::default(), AgentRow::default()];
+        cfg.active_agent_index = 1;
+        save_all_to(std::slice::from_ref(&cfg), &path).unwrap();
+
+        let loaded = load_all_from(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name, "custom_path_workflow");
+        assert_eq!(loaded[0].maximum_traversals, 7);
+        assert_eq!(loaded[0].active_agent_index, 1);
+    }
+
+    #[test]
+    fn test_concurrent_saves_keep_both_sessions_workflows() {
+        let path = std::env::temp_dir().join(format!("nm_concurrent_{}.nm", std::process::id()));
+        let named = |name: &str| WorkflowConfig { name: name.into(), ..WorkflowConfig::default() };
+        save_all_to(&[named("shared")], &path).unwrap();
+        let loaded = load_all_from(&path).unwrap();
+        let known = LOADED_NAMES.lock().unwrap().get(&path).cloned().unwrap();
+
+        // Two sessions (separate processes, each with its own record of the file) started
+        // from the same file each add a workflow and keep saving
+        let savers: Vec<_> = ["tui", "web"]
+            .into_iter()
+            .map(|name| {
+                let mut mine = loaded.clone();
+                mine.push(named(name));
+                let path = path.clone();
+                let mut known = known.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        save_merged_with(&mine, &path, &mut known).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for saver in savers {
+            saver.join().unwrap();
+        }
+        let names = |path: &Path| {
+            let mut names: Vec<String> =
+                parse_nm_multiple(&std::fs::read_to_string(path).unwrap()).unwrap().into_iter().map(|c| c.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&path), vec!["shared", "tui", "web"]);
+
+        // A workflow this process loaded and no longer has was renamed, not added elsewhere
+        save_merged_to(&[named("renamed"), named("tui"), named("web")], &path).unwrap();
+        assert_eq!(names(&path), vec!["renamed", "tui", "web"]);
+
+        // Names this process saved count as its own, so renaming one again drops the old name
+        save_merged_to(&[named("renamed again"), named("tui"), named("web")], &path).unwrap();
+        assert_eq!(names(&path), vec!["renamed again", "tui", "web"]);
+
+        // A workflow changed on disk since the last save is reported before it's overwritten
+        let loaded = LOADED_NAMES.lock().unwrap().get(&path).cloned().unwrap();
+        let theirs = WorkflowConfig { maximum_traversals: 3, ..named("tui") };
+        let ours = WorkflowConfig { maximum_traversals: 9, ..named("tui") };
+        assert_eq!(changed_elsewhere(std::slice::from_ref(&ours), std::slice::from_ref(&theirs), &loaded), vec!["tui"]);
+        assert!(changed_elsewhere(std::slice::from_ref(&theirs), std::slice::from_ref(&theirs), &loaded).is_empty());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(sibling_path(&path, ".lock"));
+    }
+
+    #[test]
+    fn test_parse_output_injections() {
+        assert_eq!(
+            OutputInjection::parse("nmoutput:out/result.txt"),
+            Some(OutputInjection::File("out/result.txt".into()))
+        );
+        assert_eq!(
+            OutputInjection::parse("output2:input3"),
+            Some(OutputInjection::Agent { from: 2, to: 3 })
+        );
+        assert_eq!(OutputInjection::parse("nmoutput:"), None);
+        assert_eq!(OutputInjection::parse("output0:input1"), None);
+        assert_eq!(OutputInjection::parse("input1:output2"), None);
+    }
+
+    #[test]
+    fn test_remove_agent_fixes_routes() {
+        let mut cfg = WorkflowConfig::default();
+        cfg.rows = vec![AgentRow::default(), AgentRow::default(), AgentRow::default(), AgentRow::default()];
+        cfg.rows[0].on_success = Some(3);
+        cfg.rows[0].on_failure = Some(1);
+        cfg.rows[2].on_success = Some(0);
+        cfg.rows[3].on_failure = Some(7);
+
+        let removed = remove_agent(&mut cfg, 1);
+        assert!(removed.is_some());
+        assert_eq!(cfg.rows.len(), 3);
+        // Route to agent 3 shifts down, route to the removed agent is cleared
+        assert_eq!(cfg.rows[0].on_success, Some(2));
+        assert_eq!(cfg.rows[0].on_failure, None);
+        assert_eq!(cfg.rows[1].on_success, Some(0));
+        // Routes already past the end are dropped
+        assert_eq!(cfg.rows[2].on_failure, None);
+
+        assert!(remove_agent(&mut cfg, 5).is_none());
+    }
+
+    #[test]
+    fn test_render_graph_shows_routes() {
+        let text = "workflow:review\nagent_1: Agent\nfiles:\"role:system:a.poml\"\ndisplay_name:\"Writer\"\n\
+output_injections:\"output1:input3\"\nagent_2: Validator\nfiles:\"role:system:b.poml\"\non_success:END\non_failure:0\n\
+agent_3: ParallelAgent\nfiles:\"role:system:c.poml\"\non_success:9\n";
+        let cfg = parse_nm_multiple(text).unwrap().remove(0);
+        assert_eq!(cfg.rows[1].on_success, None);
+        assert_eq!(route_target(Some(9), cfg.rows.len()), None);
+        assert_eq!(route_target(Some(END_NODE), cfg.rows.len()), None);
+
+        assert_eq!(
+            render_graph(&cfg),
+            "Workflow 'review' (start: [1])\n\
+[1] Writer (Agent)\n\
+\x20 └─ next ────> [2] Agent2\n\
+\x20    injects ─> [3] Agent3\n\
+[2] Agent2 (Validator)\n\
+\x20 ├─ success ─> END\n\
+\x20 └─ failure ─> [1] Writer\n\
+[3] Agent3 (ParallelAgent)\n\
+\x20 └─ next ────> END\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_workflow_without_model() {
+        let text = "workflow:review\nmodel:openai/gpt-4o\ntemperature:0.2\nmaximum_traversals:8\n\
+agent_1: Agent\nfiles:\"role:system:a.poml\"\ndisplay_name:\"Writer\"\n\
+agent_2: Validator\nfiles:\"role:system:b.poml;role:user:rubric.poml\"\non_success:END\non_failure:0\n";
+        let cfg = parse_nm_multiple(text).unwrap().remove(0);
+
+        assert_eq!(
+            describe_workflow(&cfg),
+            "Workflow 'review' has 2 agents using openai/gpt-4o at temperature 0.2 and starts at Writer.\n\
+1. Writer is an agent running a.poml; it passes its output to [2] Agent2.\n\
+2. Agent2 is a validator running b.poml, rubric.poml; on success it goes to END, on failure to [1] Writer.\n\
+A run stops after 8 traversals."
+        );
+
+        let empty = WorkflowConfig { rows: Vec::new(), ..WorkflowConfig::default() };
+        assert_eq!(describe_workflow(&empty), "Workflow 'default' has no agents.");
+    }
+}
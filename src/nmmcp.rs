@@ -1,5 +1,8 @@
 use crate::runner::AppEvent;
+use std::io::Write;
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 use anyhow::Result;
 use std::collections::HashMap;
@@ -17,6 +20,9 @@ pub struct NMMCPExtension {
     pub dependencies: Vec<String>,
     pub tools: Vec<ExtensionTool>,
     pub capabilities: ExtensionCapabilities,
+    /// Directory the extension was loaded from; `entry_point` is relative to it
+    #[serde(skip)]
+    pub dir: PathBuf,
 }
 
 /// Represents a tool provided by an extension
@@ -76,7 +82,8 @@ impl NMMCPExtensionRegistry {
 
         // Load metadata
         let metadata_content = fs::read_to_string(&metadata_file).await?;
-        let extension: NMMCPExtension = serde_json::from_str(&metadata_content)?;
+        let mut extension: NMMCPExtension = serde_json::from_str(&metadata_content)?;
+        extension.dir = extension_dir.clone();
 
         // Validate extension entry point
         let entry_point = extension_dir.join(&extension.entry_point);
@@ -205,6 +212,86 @@ pub async fn load_all_extensions(tx: UnboundedSender<AppEvent>) -> Result<NMMCPE
     Ok(registry)
 }
 
+/// How long an extension tool may run before it is killed
+pub const EXTENSION_TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Run one of an extension's tools: spawn its entry point with the tool name as the
+/// only argument, write `args` as JSON to stdin and parse stdout as JSON.
+/// `.py` entry points run under `python3`; anything else is executed directly.
+/// The process is killed once `timeout` passes.
+pub fn call_extension_tool(
+    extension: &NMMCPExtension,
+    tool: &str,
+    args: &serde_json::Value,
+    timeout: Duration,
+) -> Result<serde_json::Value> {
+    if !extension.tools.iter().any(|t| t.name == tool) {
+        return Err(anyhow::anyhow!("Extension '{}' has no tool '{}'", extension.name, tool));
+    }
+
+    let entry_point = extension.dir.join(&extension.entry_point);
+    let mut command = if entry_point.extension().is_some_and(|ext| ext == "py") {
+        let mut command = Command::new("python3");
+        command.arg(&entry_point);
+        command
+    } else {
+        Command::new(&entry_point)
+    };
+    let mut child = command
+        .arg(tool)
+        .current_dir(&extension.dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start {}: {}", entry_point.display(), e))?;
+
+    // Feed stdin and drain the output on their own threads, so a large payload can't
+    // deadlock against a full stdout pipe
+    let stdin = child.stdin.take();
+    let payload = args.to_string();
+    let feeder = std::thread::spawn(move || {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(payload.as_bytes());
+        }
+    });
+    let stdout = crate::tools::drain_pipe(child.stdout.take());
+    let stderr = crate::tools::drain_pipe(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait()? {
+            Some(status) => break status,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow::anyhow!(
+                    "Extension '{}' tool '{}' timed out after {}s",
+                    extension.name,
+                    tool,
+                    timeout.as_secs_f64()
+                ));
+            }
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    };
+    let _ = feeder.join();
+    let (Ok(stdout), Ok(stderr)) = (stdout.join(), stderr.join()) else {
+        return Err(anyhow::anyhow!("Failed to read the output of extension '{}'", extension.name));
+    };
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Extension '{}' exited with {}: {}",
+            extension.name,
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        ));
+    }
+
+    serde_json::from_slice(&stdout).map_err(|e| {
+        anyhow::anyhow!("Extension '{}' returned invalid JSON: {}", extension.name, e)
+    })
+}
+
 /// Validate extension metadata
 pub fn validate_extension_metadata(metadata: &NMMCPExtension) -> Result<()> {
     if metadata.name.is_empty() {
@@ -267,4 +354,55 @@ mod tests {
             println!("Extension directory: {}", dir.display());
         }
     }
+
+    #[tokio::test]
+    async fn test_call_extension_tool_runs_entry_point() {
+        let dir = std::env::temp_dir().join(format!("nmmcp_echo_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("nmmcp.json"), r#"{
+  "name": "echo",
+  "version": "1.0.0",
+  "description": "Echoes its input",
+  "author": "Test",
+  "entry_point": "main.py",
+  "dependencies": [],
+  "tools": [{
+    "name": "echo_tool",
+    "description": "Echo the input back",
+    "parameters": { "required": ["input"], "optional": [], "types": { "input": "string" } },
+    "input_schema": { "type": "object", "properties": { "input": { "type": "string" } } },
+    "output_schema": { "type": "object" }
+  }],
+  "capabilities": { "model_control": false, "tool_integration": true, "file_operations": false, "system_access": false }
+}"#).unwrap();
+        std::fs::write(dir.join("main.py"), "import json, sys\n\
+data = json.load(sys.stdin)\n\
+json.dump({\"tool\": sys.argv[1], \"result\": data[\"input\"].upper()}, sys.stdout)\n").unwrap();
+
+        let (tx, _) = tokio::sync::mpsc::unbounded_channel();
+        let mut registry = NMMCPExtensionRegistry::new(tx);
+        registry.load_extension(&dir).await.unwrap();
+        let extension = registry.get_extension("echo").expect("extension should load");
+
+        let args = serde_json::json!({ "input": "hello" });
+        let result = call_extension_tool(extension, "echo_tool", &args, EXTENSION_TOOL_TIMEOUT).unwrap();
+        assert_eq!(result, serde_json::json!({ "tool": "echo_tool", "result": "HELLO" }));
+
+        // Input larger than a pipe buffer goes in while the output comes out
+        let big = "x".repeat(1 << 20);
+        let result = call_extension_tool(extension, "echo_tool", &serde_json::json!({ "input": big }), EXTENSION_TOOL_TIMEOUT).unwrap();
+        assert_eq!(result["result"].as_str().map(str::len), Some(1 << 20));
+
+        let missing = call_extension_tool(extension, "nope", &serde_json::json!({}), EXTENSION_TOOL_TIMEOUT);
+        assert!(missing.unwrap_err().to_string().contains("no tool 'nope'"));
+
+        // A hung extension is killed at the deadline
+        std::fs::write(dir.join("main.py"), "import time\ntime.sleep(30)\n").unwrap();
+        let started = Instant::now();
+        let hung = call_extension_tool(extension, "echo_tool", &args, Duration::from_millis(200));
+        assert!(hung.unwrap_err().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Per-model prices read from the working directory, overriding the built-in ones
+pub const PRICING_FILE: &str = "pricing.toml";
+
+/// Built-in prices in USD per million tokens as (model, input, output); add or
+/// correct models in `pricing.toml`
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("z-ai/glm-4.5", 0.60, 2.20),
+    ("openai/gpt-4o", 2.50, 10.00),
+    ("openai/gpt-4o-mini", 0.15, 0.60),
+    ("openai/text-embedding-3-small", 0.02, 0.0),
+];
+
+/// Token counts from a completion's `usage` field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(skip)]
+    pub calls: u64, // responses whose usage was added in
+}
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.input_per_million
+            + usage.completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Prices by model name. `pricing.toml` has one table per model:
+///
+/// ```toml
+/// [models."openai/gpt-4o"]
+/// input_per_million = 2.5
+/// output_per_million = 10.0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PricingTable {
+    #[serde(default)]
+    pub models: HashMap<String, ModelPrice>,
+}
+
+impl PricingTable {
+    /// The built-in prices with any in `path` on top. A missing file keeps the
+    /// built-ins; an unreadable one is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut table = Self::builtin();
+        if !path.exists() {
+            return Ok(table);
+        }
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let overrides: PricingTable = toml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        table.models.extend(overrides.models);
+        Ok(table)
+    }
+
+    pub fn builtin() -> Self {
+        let models = DEFAULT_PRICES
+            .iter()
+            .map(|(model, input, output)| {
+                (model.to_string(), ModelPrice { input_per_million: *input, output_per_million: *output })
+            })
+            .collect();
+        Self { models }
+    }
+
+    /// Priced total for `usage`, with the models that have no price listed separately
+    pub fn estimate(&self, usage: &HashMap<String, TokenUsage>) -> CostEstimate {
+        let mut estimate = CostEstimate::default();
+        for (model, tokens) in usage {
+            estimate.prompt_tokens += tokens.prompt_tokens;
+            estimate.completion_tokens += tokens.completion_tokens;
+            estimate.calls += tokens.calls;
+            match self.models.get(model) {
+                Some(price) => estimate.cost_usd += price.cost(tokens),
+                None => estimate.unpriced_models.push(model.clone()),
+            }
+        }
+        estimate.unpriced_models.sort();
+        estimate
+    }
+}
+
+/// What a run spent, as far as the pricing table knows
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostEstimate {
+    pub cost_usd: f64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub calls: u64, // LLM calls that reported usage
+    pub unpriced_models: Vec<String>,
+}
+
+impl CostEstimate {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Token totals for the run summary, e.g. `1200 prompt + 340 completion tokens over 3 calls`
+    pub fn usage_line(&self) -> String {
+        format!(
+            "{} prompt + {} completion tokens over {} call{}",
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.calls,
+            if self.calls == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Token usage per model for one workflow run, shared by all of its agents
+#[derive(Debug, Clone, Default)]
+pub struct RunUsage(Arc<Mutex<HashMap<String, TokenUsage>>>);
+
+impl RunUsage {
+    /// Add a response's `usage` field to `model`'s total; responses without one are skipped
+    pub fn record(&self, model: &str, usage: &Value) {
+        let Ok(tokens) = serde_json::from_value::<TokenUsage>(usage.clone()) else {
+            return;
+        };
+        if let Ok(mut totals) = self.0.lock() {
+            let total = totals.entry(model.to_string()).or_default();
+            total.prompt_tokens += tokens.prompt_tokens;
+            total.completion_tokens += tokens.completion_tokens;
+            total.calls += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, TokenUsage> {
+        self.0.lock().map(|totals| totals.clone()).unwrap_or_default()
+    }
+
+    /// The totals so far, starting again from zero; a chat session prices each message this way
+    pub fn take(&self) -> HashMap<String, TokenUsage> {
+        self.0.lock().map(|mut totals| std::mem::take(&mut *totals)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cost_from_token_counts_and_prices() {
+        let table: PricingTable = toml::from_str(
+            "[models.\"acme/large\"]\ninput_per_million = 2.5\noutput_per_million = 10.0\n",
+        )
+        .unwrap();
+
+        let usage = RunUsage::default();
+        usage.record("acme/large", &json!({ "prompt_tokens": 1000, "completion_tokens": 400, "total_tokens": 1400 }));
+        usage.record("acme/large", &json!({ "prompt_tokens": 1000, "completion_tokens": 100 }));
+        usage.record("acme/unknown", &json!({ "prompt_tokens": 50, "completion_tokens": 5 }));
+        usage.record("acme/large", &Value::Null);
+
+        let estimate = table.estimate(&usage.snapshot());
+        // 2000 input tokens at $2.50/M plus 500 output tokens at $10/M
+        assert!((estimate.cost_usd - 0.01).abs() < 1e-12);
+        assert_eq!(estimate.total_tokens(), 2555);
+        assert_eq!(estimate.unpriced_models, vec!["acme/unknown".to_string()]);
+
+        assert!(PricingTable::builtin().models.contains_key("openai/gpt-4o"));
+    }
+
+    #[test]
+    fn test_usage_totals_from_completion_responses() {
+        // Two replies as the provider sends them; agents record each one's `usage`
+        let replies = [
+            json!({
+                "id": "gen-1",
+                "choices": [{ "message": { "role": "assistant", "content": "Plan ready" } }],
+                "usage": { "prompt_tokens": 812, "completion_tokens": 64, "total_tokens": 876, "prompt_tokens_details": { "cached_tokens": 0 } }
+            }),
+            json!({
+                "id": "gen-2",
+                "choices": [{ "message": { "role": "assistant", "content": "Looks good" } }],
+                "usage": { "prompt_tokens": 390, "completion_tokens": 16, "total_tokens": 406 }
+            }),
+            json!({ "id": "gen-3", "choices": [] }),
+        ];
+        let usage = RunUsage::default();
+        for reply in &replies {
+            usage.record("openai/gpt-4o-mini", &reply["usage"]);
+        }
+
+        let totals = usage.snapshot()["openai/gpt-4o-mini"];
+        assert_eq!(totals, TokenUsage { prompt_tokens: 1202, completion_tokens: 80, calls: 2 });
+        let estimate = PricingTable::builtin().estimate(&usage.snapshot());
+        assert_eq!(estimate.total_tokens(), 1282);
+        assert_eq!(estimate.usage_line(), "1202 prompt + 80 completion tokens over 2 calls");
+
+        assert_eq!(usage.take().len(), 1);
+        assert!(usage.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Set by `/profile on`; runs started afterwards record their phase timings
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(on: bool) {
+    PROFILING.store(on, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    PROFILING.load(Ordering::SeqCst)
+}
+
+/// Where a run spends its time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    PomlRender, // rendering an agent's prompt files
+    LlmCall,    // one request to the model, retries and fallbacks included
+    ToolCall,   // one tool the model asked for
+    Routing,    // injections and the routing decision after a traversal
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::PomlRender, Phase::LlmCall, Phase::ToolCall, Phase::Routing];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::PomlRender => "POML rendering",
+            Phase::LlmCall => "LLM calls",
+            Phase::ToolCall => "Tool calls",
+            Phase::Routing => "Routing",
+        }
+    }
+}
+
+/// Wall-clock time per phase for one workflow run, shared by all of its agents.
+/// A disabled profiler (the default) records nothing.
+#[derive(Debug, Clone, Default)]
+pub struct RunProfiler(Option<Arc<Mutex<Vec<(Phase, String, Duration)>>>>);
+
+impl RunProfiler {
+    /// Recording when `/profile` is on
+    pub fn for_run() -> Self {
+        if enabled() { Self::recording() } else { Self::default() }
+    }
+
+    pub fn recording() -> Self {
+        Self(Some(Arc::default()))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn record(&self, phase: Phase, label: impl Into<String>, duration: Duration) {
+        if let Some(timings) = &self.0 {
+            if let Ok(mut timings) = timings.lock() {
+                timings.push((phase, label.into(), duration));
+            }
+        }
+    }
+
+    /// Time `phase` until the returned span is dropped
+    pub fn span(&self, phase: Phase, label: impl Into<String>) -> Span {
+        Span { profiler: self.clone(), phase, label: label.into(), start: Instant::now() }
+    }
+
+    pub fn timings(&self) -> Vec<(Phase, String, Duration)> {
+        self.0.as_ref().and_then(|t| t.lock().ok().map(|t| t.clone())).unwrap_or_default()
+    }
+
+    /// Total, count and slowest entry per phase, or `None` when not recording
+    pub fn report(&self) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let timings = self.timings();
+        let mut report = "Profile:".to_string();
+        for phase in Phase::ALL {
+            let entries: Vec<&(Phase, String, Duration)> = timings.iter().filter(|(p, _, _)| *p == phase).collect();
+            if entries.is_empty() {
+                continue;
+            }
+            let total: Duration = entries.iter().map(|(_, _, d)| *d).sum();
+            report.push_str(&format!("\n  {}: {} in {}", phase.name(), format_duration(total), entries.len()));
+            if let Some((_, label, slowest)) = entries.iter().max_by_key(|(_, _, d)| *d) {
+                report.push_str(&format!(" (slowest: {} {})", label, format_duration(*slowest)));
+            }
+        }
+        if timings.is_empty() {
+            report.push_str(" nothing recorded");
+        }
+        Some(report)
+    }
+}
+
+/// An open timing, recorded when dropped
+pub struct Span {
+    profiler: RunProfiler,
+    phase: Phase,
+    label: String,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.profiler.record(self.phase, std::mem::take(&mut self.label), self.start.elapsed());
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    if d >= Duration::from_secs(1) {
+        format!("{:.2}s", d.as_secs_f64())
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_separates_phases() {
+        let profiler = RunProfiler::recording();
+        {
+            let _render = profiler.span(Phase::PomlRender, "plan.poml");
+            std::thread::sleep(Duration::from_millis(3));
+        }
+        for tool in ["ls", "read_file_content"] {
+            let _tool = profiler.span(Phase::ToolCall, tool);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        profiler.record(Phase::LlmCall, "Agent1", Duration::from_millis(40));
+
+        let timings = profiler.timings();
+        let phases: Vec<Phase> = timings.iter().map(|(p, _, _)| *p).collect();
+        assert_eq!(phases, vec![Phase::PomlRender, Phase::ToolCall, Phase::ToolCall, Phase::LlmCall]);
+        assert!(timings.iter().all(|(_, _, d)| !d.is_zero()));
+
+        let report = profiler.report().unwrap();
+        assert!(report.contains("LLM calls: 40ms in 1 (slowest: Agent1 40ms)"), "{}", report);
+        assert!(report.contains("Tool calls: ") && report.contains(" in 2 "), "{}", report);
+        assert!(!report.contains("Routing"));
+
+        // A disabled profiler records nothing and has no report
+        let off = RunProfiler::default();
+        drop(off.span(Phase::Routing, "traversal 1"));
+        assert!(off.timings().is_empty());
+        assert!(off.report().is_none());
+    }
+}
@@ -17,7 +17,6 @@ struct RateLimit {
 }
 
 impl RateLimiter {
-    #[allow(dead_code)]
     pub fn new() -> Self {
         Self {
             limits: Arc::new(Mutex::new(HashMap::new())),
@@ -31,7 +30,6 @@ impl RateLimiter {
         }
     }
 
-    #[allow(dead_code)]
     pub async fn check_rate_limit(&self, key: &str, limit: u32, window: Duration) -> bool {
         let mut limits = self.limits.lock().await;
         
@@ -68,6 +66,48 @@ impl RateLimiter {
     }
 }
 
+/// Adaptive pacing never waits less than this between an agent's requests
+pub const PACING_MIN_DELAY_MS: u64 = 50;
+/// ...nor more than this
+pub const PACING_MAX_DELAY_MS: u64 = 30_000;
+/// Successful requests in a row before the delay is eased back down
+pub const PACING_SUCCESS_STREAK: u32 = 3;
+
+/// An agent's delay between requests that doubles after a 429 and shrinks by a
+/// quarter after every `PACING_SUCCESS_STREAK` successes, within the bounds above
+#[derive(Debug, Clone)]
+pub struct AdaptivePacer {
+    delay_ms: u64,
+    successes: u32, // since the last change
+}
+
+impl AdaptivePacer {
+    /// Start at the agent's `iteration_delay_ms`
+    pub fn new(base_delay_ms: u64) -> Self {
+        Self {
+            delay_ms: base_delay_ms.clamp(PACING_MIN_DELAY_MS, PACING_MAX_DELAY_MS),
+            successes: 0,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms)
+    }
+
+    pub fn on_rate_limited(&mut self) {
+        self.successes = 0;
+        self.delay_ms = (self.delay_ms * 2).min(PACING_MAX_DELAY_MS);
+    }
+
+    pub fn on_success(&mut self) {
+        self.successes += 1;
+        if self.successes >= PACING_SUCCESS_STREAK {
+            self.successes = 0;
+            self.delay_ms = (self.delay_ms * 3 / 4).max(PACING_MIN_DELAY_MS);
+        }
+    }
+}
+
 // Rate limiter functionality - simplified version
 // TODO: Implement proper tower-governor integration later
 #[allow(dead_code)]
@@ -97,4 +137,41 @@ mod tests {
         sleep(Duration::from_millis(1100)).await;
         assert!(limiter.check_rate_limit("test", 5, Duration::from_secs(1)).await);
     }
+
+    #[test]
+    fn test_adaptive_pacing_backs_off_then_recovers() {
+        let mut pacer = AdaptivePacer::new(200);
+        assert_eq!(pacer.delay(), Duration::from_millis(200));
+
+        // A burst of 429s doubles the delay each time, up to the cap
+        for _ in 0..3 {
+            pacer.on_rate_limited();
+        }
+        assert_eq!(pacer.delay(), Duration::from_millis(1600));
+        for _ in 0..20 {
+            pacer.on_rate_limited();
+        }
+        assert_eq!(pacer.delay(), Duration::from_millis(PACING_MAX_DELAY_MS));
+
+        // Isolated successes don't ease off; a streak does
+        pacer.on_success();
+        pacer.on_success();
+        assert_eq!(pacer.delay(), Duration::from_millis(PACING_MAX_DELAY_MS));
+        pacer.on_success();
+        assert_eq!(pacer.delay(), Duration::from_millis(22_500));
+
+        // A 429 mid-streak starts the count over
+        pacer.on_success();
+        pacer.on_success();
+        pacer.on_rate_limited();
+        pacer.on_success();
+        pacer.on_success();
+        assert_eq!(pacer.delay(), Duration::from_millis(PACING_MAX_DELAY_MS));
+
+        // Sustained success settles at the floor
+        for _ in 0..200 {
+            pacer.on_success();
+        }
+        assert_eq!(pacer.delay(), Duration::from_millis(PACING_MIN_DELAY_MS));
+    }
 }
@@ -1,11 +1,13 @@
 use crate::shared_history::SharedHistory;
-use crate::tools::builtin_tools_with_history;
+use crate::tools::{builtin_tools_with_history, mcp_tools, tool_is_enabled, EmbeddingSettings, SharedMemory};
 use llmgraph::Graph;
 use tokio::sync::mpsc::UnboundedSender;
-use crate::metrics::metrics_collector::MetricsCollector;
+use crate::metrics::metrics_collector::{AlertLevel, MetricsCollector};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::path::Path;
+use std::collections::HashMap;
+use crate::nm_config::OutputInjection;
 
 /// Generate MCP template content
 fn generate_mcp_template(name: &str) -> String {
@@ -196,6 +198,7 @@ fn create_tool_template_file(path: &str, content: &str) -> Result<(), Box<dyn st
     Ok(())
 }
 
+#[derive(Debug, Clone)]
 pub enum AppCommand {
     RunWorkflow {
         workflow_name: String,
@@ -213,28 +216,145 @@ pub enum AppCommand {
         template_type: String,
         template_name: String,
     },
+    /// One message of an interactive chat; the graph stays warm between messages
+    ChatMessage {
+        workflow_name: String,
+        prompt: String,
+        cfg: crate::nm_config::WorkflowConfig,
+        start_agent: Option<i32>,
+        variables: Option<std::collections::HashMap<String, String>>,
+    },
+    /// Drop the interactive chat graph and its history
+    EndChat,
+    /// `/describe`: summarize a workflow's structure in plain English
+    DescribeWorkflow {
+        cfg: crate::nm_config::WorkflowConfig,
+    },
+    /// `/retry`: send the last `RunWorkflow` again, unchanged
+    Retry,
+    /// `/compare`: run two workflows on the same prompt, one after the other
+    CompareWorkflows {
+        prompt: String,
+        first: crate::nm_config::WorkflowConfig,
+        second: crate::nm_config::WorkflowConfig,
+        variables: Option<std::collections::HashMap<String, String>>,
+    },
+}
+
+/// Severity attached to log lines so subscribers can filter noise
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Parse a level name such as "debug" or "WARN"
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "debug" | "trace" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    /// Infer a level from the prefixes used by plain `AppEvent::Log` lines
+    pub fn from_line(line: &str) -> Self {
+        if line.starts_with("[DEBUG]") || line.starts_with("[TOOL]") {
+            LogLevel::Debug
+        } else if line.starts_with("[WARN]") || line.starts_with("Warning") {
+            LogLevel::Warn
+        } else if line.starts_with("[ERROR]") || line.starts_with("Error") {
+            LogLevel::Error
+        } else {
+            LogLevel::Info
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
 }
 
 pub enum AppEvent {
     Log(String),
+    LeveledLog { level: LogLevel, msg: String },
     RunStart(String),
-    RunResult(String),
+    RunResult { agent: Option<String>, text: String },
+    /// Reasoning a model returned alongside its answer, kept out of routing and history
+    Reasoning { agent: Option<String>, text: String },
     RunEnd(String),
+    Warning(String),
     Error(String),
+    /// Each node run at the end of a traversal, paired with where it routed (`None` is END)
+    RoutingTrace(Vec<(usize, Option<i32>)>),
+    /// Traversals finished so far out of the expected total, see `progress_total`
+    Progress { current: usize, total: usize },
+}
+
+/// How a `run_workflow` call ended, for callers that need more than the event stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkflowOutcome {
+    pub final_output: String,
+    pub error: Option<String>,
+    pub traversals: usize,
+    pub node_reached: i32,
+    pub routing_trace: Vec<(usize, Option<i32>)>,
+    pub routing_path: String, // `routing_trace` as `format_routing_trace` renders it
+}
+
+/// Asks running workflows to stop. A cancelled workflow drops its in-flight agent
+/// step at the next await point and ends before starting another traversal.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<(std::sync::atomic::AtomicBool, tokio::sync::Notify)>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0 .0.store(true, std::sync::atomic::Ordering::SeqCst);
+        self.0 .1.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0 .0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Resolves once `cancel` has been called
+    pub async fn cancelled(&self) {
+        // Register before checking the flag so a cancel in between isn't missed
+        let notified = self.0 .1.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
 }
 
 pub async fn run_workflow(
     cmd: AppCommand,
     log_tx: UnboundedSender<AppEvent>,
     metrics: Option<Arc<Mutex<MetricsCollector>>>,
-) {
+    cancel: CancelToken,
+) -> WorkflowOutcome {
+    let mut outcome = WorkflowOutcome::default();
     match cmd {
         AppCommand::ShowHistory { agent_index, workflow_name, cfg: _ } => {
             let _ = log_tx.send(AppEvent::Log(format!(
                 "Showing history for workflow '{}', agent {:?}",
                 workflow_name, agent_index
             )));
-            let _ = log_tx.send(AppEvent::RunResult("History display not yet implemented".to_string()));
+            let _ = log_tx.send(AppEvent::RunResult {
+                agent: None,
+                text: "History display not yet implemented".to_string(),
+            });
         }
         AppCommand::CreateTemplate { template_type, template_name } => {
             let _ = log_tx.send(AppEvent::Log(format!(
@@ -257,9 +377,9 @@ pub async fn run_workflow(
                             )));
                         }
                         Err(e) => {
-                            let _ = log_tx.send(AppEvent::Error(format!(
-                                "Failed to create MCP template: {}", e
-                            )));
+                            let msg = format!("Failed to create MCP template: {}", e);
+                            outcome.error = Some(msg.clone());
+                            let _ = log_tx.send(AppEvent::Error(msg));
                         }
                     }
                 }
@@ -277,16 +397,16 @@ pub async fn run_workflow(
                             )));
                         }
                         Err(e) => {
-                            let _ = log_tx.send(AppEvent::Error(format!(
-                                "Failed to create tool template: {}", e
-                            )));
+                            let msg = format!("Failed to create tool template: {}", e);
+                            outcome.error = Some(msg.clone());
+                            let _ = log_tx.send(AppEvent::Error(msg));
                         }
                     }
                 }
                 _ => {
-                    let _ = log_tx.send(AppEvent::Error(format!(
-                        "Unknown template type: {}", template_type
-                    )));
+                    let msg = format!("Unknown template type: {}", template_type);
+                    outcome.error = Some(msg.clone());
+                    let _ = log_tx.send(AppEvent::Error(msg));
                 }
             }
         }
@@ -305,158 +425,1500 @@ pub async fn run_workflow(
                 "[SharedHistory] Initialized global shared history".to_string(),
             ));
 
-            // ✅ Register tools
-            let mut graph = Graph::new();
-            for (tool, func) in builtin_tools_with_history(
-                shared_history.clone(),
-                log_tx.clone(),
-                cfg.working_dir.clone(),
-            ) {
-                graph.register_tool(tool, func);
+            let extensions = load_extensions(&log_tx).await;
+            let usage = crate::pricing::RunUsage::default();
+            let (mut graph, agent_names) =
+                build_graph(&cfg, variables, &shared_history, extensions.as_ref(), &usage, &log_tx);
+            outcome = traverse(
+                &mut graph,
+                &cfg,
+                &agent_names,
+                initial_node(start_agent, &cfg),
+                prompt.clone(),
+                &log_tx,
+                metrics.as_ref(),
+                &cancel,
+            )
+            .await;
+
+            // ✅ Final metrics + alerts
+            let cost = estimate_run_cost(&usage.snapshot(), &log_tx);
+            if let Some(collector) = &metrics {
+                collector.lock().await.record_cost(&cost).await;
+            }
+            let summary = completion_summary(metrics.as_ref(), &cost, &log_tx).await;
+            let _ = log_tx.send(AppEvent::RunResult { agent: None, text: summary });
+            if let Some(profile) = graph.profiler.report() {
+                let _ = log_tx.send(AppEvent::RunResult { agent: None, text: profile });
             }
 
-            // Build graph nodes
-            for (i, row) in cfg.rows.iter().enumerate() {
-                let next_id = if i + 1 < cfg.rows.len() {
-                    Some((i + 1) as i32)
-                } else {
-                    None
-                };
+            record_run(&cfg, prompt, start_agent, &shared_history, &log_tx);
 
-                let files: Vec<String> = row
-                    .files
-                    .split(';')
-                    .map(|s| s.trim().to_string())
-                    .collect();
-
-                let agent: Box<dyn llmgraph::models::graph::Agent + Send + Sync> =
-                    if row.agent_type == crate::nm_config::AgentType::Validator {
-                        Box::new(crate::agents::PomlValidatorAgent::new(
-                            crate::agents::PomlAgent::new(
-                                &format!("ValidatorAgent{}", i + 1),
-                                files.clone(),
-                                cfg.model.clone(),
-                                cfg.temperature,
-                                row.max_iterations,
-                                log_tx.clone(),
-                                shared_history.clone(),
-                                variables.clone(), // Pass variables from workflow
-                            ),
-                            row.on_success.unwrap_or(-1),
-                            row.on_failure.unwrap_or(-1),
-                        ))
-                    } else {
-                        Box::new(crate::agents::PomlAgent::new(
-                            &format!("Agent{}", i + 1),
-                            files.clone(),
-                            cfg.model.clone(),
-                            cfg.temperature,
-                            row.max_iterations,
-                            log_tx.clone(),
-                            shared_history.clone(),
-                            variables.clone(), // Pass variables from workflow
-                        ))
-                    };
-
-                let chained = crate::agents::ChainedAgent::new(
-                    i as i32,
-                    agent,
-                    log_tx.clone(),
-                    next_id,
-                    row.max_iterations,
-                    row.iteration_delay_ms,
-                    shared_history.clone(),
-                );
-                graph.add_node(i as i32, Box::new(chained));
+            let _ = log_tx.send(AppEvent::RunEnd(workflow_name));
+        }
+        AppCommand::CompareWorkflows { prompt, first, second, variables } => {
+            let title = format!("{} vs {}", first.name, second.name);
+            let _ = log_tx.send(AppEvent::RunStart(title.clone()));
+            let configs = [first, second];
+            let runs = compare_workflows(&prompt, &configs, &log_tx, |cfg, prompt| {
+                let (variables, log_tx, metrics, cancel) = (variables.clone(), log_tx.clone(), metrics.clone(), cancel.clone());
+                async move {
+                    let shared_history = SharedHistory::new();
+                    let extensions = load_extensions(&log_tx).await;
+                    let usage = crate::pricing::RunUsage::default();
+                    let (mut graph, agent_names) =
+                        build_graph(cfg, variables, &shared_history, extensions.as_ref(), &usage, &log_tx);
+                    let start = initial_node(None, cfg);
+                    let outcome =
+                        traverse(&mut graph, cfg, &agent_names, start, prompt.clone(), &log_tx, metrics.as_ref(), &cancel).await;
+                    let cost = estimate_run_cost(&usage.snapshot(), &log_tx);
+                    if let Some(collector) = &metrics {
+                        collector.lock().await.record_cost(&cost).await;
+                    }
+                    record_run(cfg, prompt, None, &shared_history, &log_tx);
+                    (outcome, cost)
+                }
+            })
+            .await;
+            // The comparison fails when either side does
+            let failures: Vec<String> = configs
+                .iter()
+                .zip(&runs)
+                .filter_map(|(cfg, run)| run.outcome.error.as_ref().map(|e| format!("'{}' failed: {}", cfg.name, e)))
+                .collect();
+            outcome = runs.into_iter().last().map(|run| run.outcome).unwrap_or_default();
+            outcome.error = (!failures.is_empty()).then(|| failures.join("; "));
+            let _ = log_tx.send(AppEvent::RunEnd(title));
+        }
+        AppCommand::DescribeWorkflow { cfg } => {
+            let text = describe_with_model(&cfg, &log_tx).await;
+            let _ = log_tx.send(AppEvent::RunResult { agent: None, text });
+        }
+        AppCommand::ChatMessage { .. } | AppCommand::EndChat | AppCommand::Retry => {
+            let msg = "Interactive chat and retry commands need a session; send them through run_command".to_string();
+            outcome.error = Some(msg.clone());
+            let _ = log_tx.send(AppEvent::Error(msg));
+        }
+    }
+    outcome
+}
+
+/// Keep the inputs and history of a run of `cfg` so it can be replayed with /replay
+fn record_run(
+    cfg: &crate::nm_config::WorkflowConfig,
+    prompt: String,
+    start_agent: Option<i32>,
+    shared_history: &SharedHistory,
+    log_tx: &UnboundedSender<AppEvent>,
+) {
+    let record = crate::shared_history::RunRecord {
+        workflow: cfg.name.clone(),
+        prompt,
+        start_agent,
+        messages: shared_history.snapshot(),
+    };
+    let record_path = crate::shared_history::run_record_path(&cfg.name);
+    if let Err(e) = crate::shared_history::save_run_record(&record, &record_path) {
+        let _ = log_tx.send(AppEvent::Log(format!(
+            "[SharedHistory] Failed to save run history to {}: {}",
+            record_path.display(),
+            e
+        )));
+    }
+}
+
+/// A workflow graph kept alive between messages of an interactive chat (`/chat`)
+pub struct ChatSession {
+    workflow_name: String,
+    cfg: crate::nm_config::WorkflowConfig,
+    graph: WorkflowGraph,
+    agent_names: Vec<String>,
+    usage: crate::pricing::RunUsage, // tokens since the last message was priced
+}
+
+/// Like `run_workflow`, but keeps the interactive chat graph in `session` and the
+/// last `RunWorkflow` in `last_run` (for `/retry`) between calls
+pub async fn run_command(
+    cmd: AppCommand,
+    session: &mut Option<ChatSession>,
+    last_run: &mut Option<AppCommand>,
+    log_tx: UnboundedSender<AppEvent>,
+    metrics: Option<Arc<Mutex<MetricsCollector>>>,
+    cancel: CancelToken,
+) -> WorkflowOutcome {
+    let Some(cmd) = resolve_retry(cmd, last_run, &log_tx) else {
+        return WorkflowOutcome::default();
+    };
+    match cmd {
+        AppCommand::ChatMessage { workflow_name, prompt, cfg, start_agent, variables } => {
+            // Build the graph on the first message, or when chat moves to another workflow
+            if session.as_ref().is_none_or(|s| s.workflow_name != workflow_name) {
+                let shared_history = SharedHistory::new();
+                let extensions = load_extensions(&log_tx).await;
+                let usage = crate::pricing::RunUsage::default();
+                let (graph, agent_names) =
+                    build_graph(&cfg, variables, &shared_history, extensions.as_ref(), &usage, &log_tx);
+                let _ = log_tx.send(AppEvent::Log(format!(
+                    "[Chat] Started interactive session with workflow '{}'",
+                    workflow_name
+                )));
+                *session = Some(ChatSession { workflow_name: workflow_name.clone(), cfg, graph, agent_names, usage });
+            }
+            let Some(chat) = session.as_mut() else {
+                return WorkflowOutcome::default();
+            };
+
+            let _ = log_tx.send(AppEvent::RunStart(workflow_name.clone()));
+            let start = initial_node(start_agent, &chat.cfg);
+            let outcome = traverse(
+                &mut chat.graph,
+                &chat.cfg,
+                &chat.agent_names,
+                start,
+                prompt,
+                &log_tx,
+                metrics.as_ref(),
+                &cancel,
+            )
+            .await;
+
+            // Each message is priced on its own, like a workflow run
+            let cost = estimate_run_cost(&chat.usage.take(), &log_tx);
+            if let Some(collector) = &metrics {
+                collector.lock().await.record_cost(&cost).await;
+            }
+            if cost.total_tokens() > 0 {
+                let _ = log_tx.send(AppEvent::Log(format!(
+                    "[Chat] Usage: {}. Estimated cost: ${:.4}",
+                    cost.usage_line(),
+                    cost.cost_usd
+                )));
+            }
+            let _ = log_tx.send(AppEvent::RunEnd(workflow_name));
+            outcome
+        }
+        AppCommand::EndChat => {
+            if let Some(chat) = session.take() {
+                let _ = log_tx.send(AppEvent::Log(format!(
+                    "[Chat] Ended interactive session with workflow '{}'",
+                    chat.workflow_name
+                )));
             }
+            WorkflowOutcome::default()
+        }
+        other => run_workflow(other, log_tx, metrics, cancel).await,
+    }
+}
 
-            // ✅ Traversal loop
-            let mut current_node = start_agent.unwrap_or(0) as i32;
-            let mut current_input = prompt.clone();
-            let mut traversals = 0;
-            // Allow infinite looping when max_traversals is 0, otherwise use the limit
-            let max_traversals = if cfg.maximum_traversals == 0 { 
-                u32::MAX 
-            } else { 
-                cfg.maximum_traversals as u32
+/// Swap `Retry` for the last `RunWorkflow`, and remember each `RunWorkflow` that
+/// passes. `None` when there is nothing to retry yet.
+fn resolve_retry(
+    cmd: AppCommand,
+    last_run: &mut Option<AppCommand>,
+    log_tx: &UnboundedSender<AppEvent>,
+) -> Option<AppCommand> {
+    match cmd {
+        AppCommand::Retry => {
+            let Some(last) = last_run.clone() else {
+                let _ = log_tx.send(AppEvent::Warning("Nothing to retry: no workflow has run yet".to_string()));
+                return None;
             };
+            if let AppCommand::RunWorkflow { workflow_name, prompt, .. } = &last {
+                let _ = log_tx.send(AppEvent::Log(format!(
+                    "[retry] Re-running workflow '{}' with prompt: {}",
+                    workflow_name, prompt
+                )));
+            }
+            Some(last)
+        }
+        AppCommand::RunWorkflow { .. } => {
+            *last_run = Some(cmd.clone());
+            Some(cmd)
+        }
+        other => Some(other),
+    }
+}
 
-            let metrics_collector = metrics.unwrap_or_else(|| Arc::new(Mutex::new(MetricsCollector::new())));
-            let _request_id = metrics_collector
-                .lock().await
-                .start_request("workflow_execution".to_string()).await;
+/// Ask the workflow's model to describe it from `describe_workflow` and `render_graph`.
+/// Answers from the cache when it can; with `--no-network` or a failed call the
+/// structural description is returned as is.
+async fn describe_with_model(cfg: &crate::nm_config::WorkflowConfig, log_tx: &UnboundedSender<AppEvent>) -> String {
+    use llmgraph::models::tools::Message;
+    let structural = crate::nm_config::describe_workflow(cfg);
+    let messages = vec![
+        Message {
+            role: "system".into(),
+            content: Some(
+                "You document multi-agent workflows. In a few plain-English sentences, explain to someone new \
+                 what the workflow below does: the likely job of each agent judging by its prompt files, how \
+                 work moves between them, and when a run ends."
+                    .into(),
+            ),
+            tool_calls: None,
+        },
+        Message {
+            role: "user".into(),
+            content: Some(format!("{}\n\n{}", structural, crate::nm_config::render_graph(cfg))),
+            tool_calls: None,
+        },
+    ];
 
-            // Use u32::MAX for true infinite looping or a large number for practical limits
-            while traversals < max_traversals {
-                traversals += 1;
+    let options = crate::error::ChatRequestOptions {
+        seed: cfg.seed,
+        max_tokens: cfg.max_tokens,
+        stop: cfg.stop.clone(),
+        response_format: None,
+    };
+    let request = crate::error::chat_request_body(&cfg.model, cfg.temperature, &options, &messages, None);
+    let fetch = || async {
+        if crate::llm_cache::network_disabled() {
+            return Err(crate::error::NeonmachinesError::Network("not sent, --no-network is set".to_string()));
+        }
+        crate::error::generate_with_retry(
+            crate::agents::CHAT_COMPLETIONS_URL.to_string(),
+            crate::secrets::resolve_api_key(crate::secrets::DEFAULT_PROVIDER, cfg.use_keyring),
+            cfg.model.clone(),
+            cfg.temperature,
+            options.clone(),
+            messages.clone(),
+            None,
+            None,
+            None,
+        )
+        .await
+    };
+    let response = crate::llm_cache::cached(
+        crate::llm_cache::global(),
+        &request,
+        true,
+        |r| !crate::agents::is_blank_response(r),
+        fetch,
+    )
+    .await;
+    let described = response.as_ref().ok().and_then(|r| r["response"]["choices"][0]["message"]["content"].as_str());
+    match described {
+        Some(text) => text.trim().to_string(),
+        None => {
+            if let Err(e) = &response {
+                let _ = log_tx.send(AppEvent::Warning(format!(
+                    "Could not ask {} to describe '{}', showing its structure instead: {}",
+                    cfg.model, cfg.name, e
+                )));
+            }
+            structural
+        }
+    }
+}
 
-                let msg = format!(
-                    "Traversal {}: Starting at node {} with input: {}",
-                    traversals, current_node, current_input
-                );
-                let _ = log_tx.send(AppEvent::Log(msg.clone()));
+/// Installed MCP extensions whose tools get registered on the graph. Loading is
+/// quiet; only the number of extensions found is logged.
+async fn load_extensions(log_tx: &UnboundedSender<AppEvent>) -> Option<crate::nmmcp::NMMCPExtensionRegistry> {
+    let registry = crate::nmmcp::load_all_extensions(tokio::sync::mpsc::unbounded_channel().0).await.ok()?;
+    let count = registry.get_extensions().len();
+    if count > 0 {
+        let _ = log_tx.send(AppEvent::Log(format!("[MCP] Registering tools from {} extensions", count)));
+    }
+    Some(registry)
+}
 
-                let step_start = std::time::Instant::now();
-                let step_output = graph.run(current_node, &current_input).await;
-                let _step_duration = step_start.elapsed();
+/// A workflow's agents. Most share one graph; each agent in a `parallel_group`
+/// gets a graph of its own so the group's agents can run at the same time.
+pub struct WorkflowGraph {
+    main: Graph,
+    grouped: HashMap<i32, Graph>,
+    profiler: crate::profiler::RunProfiler, // shared with every agent, recording when `/profile` is on
+}
 
-                let _ = metrics_collector
-                    .lock().await
-                    .finish_request(format!("step_{}", traversals), true).await;
+impl WorkflowGraph {
+    /// Run a single agent
+    async fn run(&mut self, node: i32, input: &str) -> String {
+        match self.grouped.get_mut(&node) {
+            Some(graph) => graph.run(node, input).await,
+            None => self.main.run(node, input).await,
+        }
+    }
+
+    /// Run the grouped agents `nodes` concurrently, `inputs[i]` going to `nodes[i]`.
+    /// Outputs come back in the same order.
+    async fn run_group(&mut self, nodes: &[usize], inputs: &[String]) -> Vec<String> {
+        let mut graphs: Vec<(i32, &mut Graph)> = self
+            .grouped
+            .iter_mut()
+            .filter(|(id, _)| usize::try_from(**id).is_ok_and(|id| nodes.contains(&id)))
+            .map(|(id, graph)| (*id, graph))
+            .collect();
+        graphs.sort_by_key(|(id, _)| *id);
+        futures_util::future::join_all(
+            graphs
+                .into_iter()
+                .zip(inputs)
+                .map(|((id, graph), input)| graph.run(id, input)),
+        )
+        .await
+    }
+}
+
+/// Register the workflow's tools and add one node per agent row, returning the
+/// graph and each node's label for step results
+fn build_graph(
+    cfg: &crate::nm_config::WorkflowConfig,
+    variables: Option<std::collections::HashMap<String, String>>,
+    shared_history: &SharedHistory,
+    extensions: Option<&crate::nmmcp::NMMCPExtensionRegistry>,
+    usage: &crate::pricing::RunUsage,
+    log_tx: &UnboundedSender<AppEvent>,
+) -> (WorkflowGraph, Vec<String>) {
+    // ✅ Register tools; memory is scoped to this workflow and shared by its graphs
+    let memory = SharedMemory::default();
+    let new_graph = || {
+        let mut graph = Graph::new();
+        for (tool, func) in builtin_tools_with_history(
+            shared_history.clone(),
+            memory.clone(),
+            EmbeddingSettings::from_config(cfg),
+            log_tx.clone(),
+            cfg.working_dir.clone(),
+            cfg.sandbox,
+            cfg.terminal_output_limit,
+        )
+        .into_iter()
+        .chain(extensions.map(|registry| mcp_tools(registry, log_tx.clone(), cfg.validate_tool_output)).unwrap_or_default())
+        {
+            if !tool_is_enabled(&tool.function.name, cfg.allowed_tools.as_deref(), &cfg.denied_tools) {
+                continue;
+            }
+            graph.register_tool(tool, func);
+        }
+        graph
+    };
+    let profiler = crate::profiler::RunProfiler::for_run();
+    let mut graph = WorkflowGraph { main: new_graph(), grouped: HashMap::new(), profiler: profiler.clone() };
+
+    // One retry budget shared by every agent in this graph
+    let retry_budget = cfg.retry_budget.map(crate::error::RetryBudget::new);
+
+    if cfg.seed.is_some() {
+        let unseeded: Vec<&String> = std::iter::once(&cfg.model)
+            .chain(cfg.fallback_models.iter())
+            .filter(|model| !crate::error::seed_honored(model))
+            .collect();
+        if !unseeded.is_empty() {
+            let _ = log_tx.send(AppEvent::Warning(format!(
+                "Workflow '{}' sets a seed, but {:?} may ignore it; determinism isn't guaranteed",
+                cfg.name, unseeded
+            )));
+        }
+    }
+
+    let mut agent_names: Vec<String> = Vec::new();
+    for (i, row) in cfg.rows.iter().enumerate() {
+        let next_id = if i + 1 < cfg.rows.len() {
+            Some((i + 1) as i32)
+        } else {
+            None
+        };
+
+        let files = crate::nm_config::file_entries(&row.files);
+
+        let is_validator = row.agent_type == crate::nm_config::AgentType::Validator;
+        let agent_name = row.display_name.clone().unwrap_or_else(|| {
+            format!("{}{}", if is_validator { "ValidatorAgent" } else { "Agent" }, i + 1)
+        });
+        agent_names.push(agent_name.clone());
+        let mut poml_agent = crate::agents::PomlAgent::new(
+            &agent_name,
+            files.clone(),
+            cfg.model.clone(),
+            cfg.temperature,
+            row.max_iterations,
+            log_tx.clone(),
+            shared_history.clone(),
+            variables.clone(), // Pass variables from workflow
+        );
+        poml_agent.system_preamble = cfg.system_preamble.clone();
+        poml_agent.fallback_models = cfg.fallback_models.clone();
+        poml_agent.use_keyring = cfg.use_keyring;
+        poml_agent.retry_budget = retry_budget.clone();
+        poml_agent.max_output_bytes = cfg.max_output_bytes;
+        poml_agent.request_timeout_secs = cfg.request_timeout_secs;
+        poml_agent.context_limit = cfg.context_limit;
+        poml_agent.seed = cfg.seed;
+        poml_agent.max_tokens = row.max_tokens.or(cfg.max_tokens);
+        poml_agent.stop = if row.stop.is_empty() { cfg.stop.clone() } else { row.stop.clone() };
+        poml_agent.usage = usage.clone();
+        poml_agent.post_process = row.post_process.clone();
+        poml_agent.iteration_delay_ms = row.iteration_delay_ms;
+        poml_agent.retry_on_empty = cfg.retry_on_empty;
+        poml_agent.tool_repeat_limit = cfg.tool_repeat_limit;
+        poml_agent.flatten_injection = cfg.flatten_injection;
+        poml_agent.response_format = match row.response_format.as_deref().map(crate::agents::response_format_param) {
+            Some(Ok(format)) => Some(format),
+            Some(Err(e)) => {
+                let _ = log_tx.send(AppEvent::Warning(format!("[{}] {}; sending no response_format", agent_name, e)));
+                None
+            }
+            None => None,
+        };
+        poml_agent.pacer = row.adaptive_pacing.then(|| crate::rate_limiter::AdaptivePacer::new(row.iteration_delay_ms));
+        poml_agent.profiler = profiler.clone();
+
+        let agent: Box<dyn llmgraph::models::graph::Agent + Send + Sync> = if is_validator {
+            let mut validator = crate::agents::PomlValidatorAgent::new(
+                poml_agent,
+                row.on_success.unwrap_or(crate::nm_config::END_NODE),
+                row.on_failure.unwrap_or(crate::nm_config::END_NODE),
+            );
+            validator.vote_count = row.vote_count.max(1);
+            Box::new(validator)
+        } else {
+            Box::new(poml_agent)
+        };
+
+        let mut chained = crate::agents::ChainedAgent::new(
+            i as i32,
+            agent,
+            log_tx.clone(),
+            next_id,
+            row.max_iterations,
+            row.iteration_delay_ms,
+            shared_history.clone(),
+        );
+        chained.max_output_bytes = cfg.max_output_bytes;
+        if row.parallel_group.is_some() {
+            let mut own = new_graph();
+            own.add_node(i as i32, Box::new(chained));
+            graph.grouped.insert(i as i32, own);
+        } else {
+            graph.main.add_node(i as i32, Box::new(chained));
+        }
+    }
+    (graph, agent_names)
+}
 
-                // Log step result
-                let _ = log_tx.send(AppEvent::RunResult(format!(
-                    "Traversal {} (node {}):\n{}",
-                    traversals, current_node, step_output
+/// Denominator for run progress: `maximum_traversals` when it's set. Unlimited runs
+/// count one traversal per node, as in a straight pass, and the current count once a
+/// run loops past that, so the ratio never moves backwards.
+fn progress_total(cfg: &crate::nm_config::WorkflowConfig, current: usize) -> usize {
+    match cfg.maximum_traversals as usize {
+        0 => cfg.rows.len().max(1).max(current),
+        limit => limit,
+    }
+}
+
+/// Walk the graph from `start` until it ends or hits `maximum_traversals`.
+///
+/// Reaching any agent of a `parallel_group` runs the whole group concurrently as
+/// one traversal, each member getting the same input plus its own injections. The
+/// outputs are merged in row order and passed on. Only the last member's route
+/// counts; without one the run continues after the group's last row. Stops early,
+/// reporting an error, once `cancel` fires.
+#[allow(clippy::too_many_arguments)]
+async fn traverse(
+    graph: &mut WorkflowGraph,
+    cfg: &crate::nm_config::WorkflowConfig,
+    agent_names: &[String],
+    start: i32,
+    prompt: String,
+    log_tx: &UnboundedSender<AppEvent>,
+    metrics: Option<&Arc<Mutex<MetricsCollector>>>,
+    cancel: &CancelToken,
+) -> WorkflowOutcome {
+    let mut outcome = WorkflowOutcome::default();
+    let mut current_node = start;
+    let mut current_input = prompt;
+    let mut traversals = 0;
+    let mut finished = false;
+    let mut cancelled = false;
+    // Output injections: each agent's latest output, and text queued for an agent's next input
+    let mut latest_outputs: HashMap<usize, String> = HashMap::new();
+    let mut pending_inputs: HashMap<usize, Vec<String>> = HashMap::new();
+    // Allow infinite looping when max_traversals is 0, otherwise use the limit
+    let max_traversals = if cfg.maximum_traversals == 0 { 
+        u32::MAX 
+    } else { 
+        cfg.maximum_traversals as u32
+    };
+
+    // `None` means metrics are off (`--no-metrics`), so every collection call is skipped
+    if let Some(collector) = metrics {
+        let _request_id = collector
+            .lock().await
+            .start_request("workflow_execution".to_string()).await;
+    }
+
+    // Use u32::MAX for true infinite looping or a large number for practical limits
+    while traversals < max_traversals {
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+        traversals += 1;
+        outcome.node_reached = current_node;
+
+        let msg = format!(
+            "Traversal {}: Starting at node {} with input: {}",
+            traversals, current_node, current_input
+        );
+        let _ = log_tx.send(AppEvent::Log(msg.clone()));
+
+        let mut members = usize::try_from(current_node)
+            .map(|i| crate::nm_config::parallel_group(cfg, i))
+            .unwrap_or_default();
+        if let Some((from, to)) = crate::nm_config::group_dependency(cfg, &members) {
+            let _ = log_tx.send(AppEvent::Warning(format!(
+                "Agent {} feeds agent {} in the same parallel group; running them one after another",
+                from, to
+            )));
+            members.clear();
+        }
+        if members.len() < 2 {
+            members = vec![current_node as usize];
+        }
+
+        let inputs: Vec<String> = members
+            .iter()
+            .map(|i| match pending_inputs.remove(&(i + 1)) {
+                Some(injected) => format!("{}\n\n{}", current_input, injected.join("\n\n")),
+                None => current_input.clone(),
+            })
+            .collect();
+
+        let step_start = std::time::Instant::now();
+        let step = async {
+            if members.len() > 1 {
+                let _ = log_tx.send(AppEvent::Log(format!(
+                    "Traversal {}: running nodes {:?} in parallel",
+                    traversals, members
                 )));
+                graph.run_group(&members, &inputs).await
+            } else {
+                vec![graph.run(current_node, &inputs[0]).await]
+            }
+        };
+        // Tools run synchronously, so dropping the step never interrupts a file write
+        let outputs = tokio::select! {
+            outputs = step => outputs,
+            _ = cancel.cancelled() => {
+                cancelled = true;
+                break;
+            }
+        };
+        let _step_duration = step_start.elapsed();
+        let _ = log_tx.send(AppEvent::Progress {
+            current: traversals as usize,
+            total: progress_total(cfg, traversals as usize),
+        });
+
+        // Recorded when this traversal continues or breaks
+        let _routing = graph.profiler.span(crate::profiler::Phase::Routing, format!("traversal {}", traversals));
+
+        for (node, output) in members.iter().zip(&outputs) {
+            if let Some(row) = cfg.rows.get(*node) {
+                // Files and other agents get the text without the routing marker
+                let output = strip_route_marker(output);
+                latest_outputs.insert(node + 1, output.to_string());
+                apply_output_injections(
+                    &row.output_injections,
+                    output,
+                    &latest_outputs,
+                    &cfg.working_dir,
+                    cfg.sandbox,
+                    &mut pending_inputs,
+                    log_tx,
+                );
+            }
+        }
+
+        if let Some(collector) = metrics {
+            collector
+                .lock().await
+                .finish_request(format!("step_{}", traversals), true).await;
+        }
+
+        // Log step result
+        for (node, output) in members.iter().zip(&outputs) {
+            let _ = log_tx.send(AppEvent::RunResult {
+                agent: agent_names.get(*node).cloned(),
+                text: format!("Traversal {} (node {}):\n{}", traversals, node, output),
+            });
+        }
+
+        // Group members before the last are recorded in row order, leading to the next one
+        for pair in members.windows(2) {
+            outcome.routing_trace.push((pair[0], Some(pair[1] as i32)));
+        }
+
+        // A group continues from its last member, with the merged outputs as input
+        let last_node = *members.last().unwrap_or(&0) as i32;
+        let step_output = outputs.last().cloned().unwrap_or_default();
+        let merged = (members.len() > 1).then(|| merge_group_outputs(&members, &outputs, agent_names));
+        outcome.final_output = outputs
+            .iter()
+            .find(|output| output.starts_with("Error:"))
+            .or(merged.as_ref())
+            .unwrap_or(&step_output)
+            .clone();
 
-                // Detect explicit routing marker
-                if let Some(route_idx) = step_output.rfind("\n__ROUTE__=") {
-                    let route_str = &step_output[route_idx + 11..];
-                    if let Ok(next) = route_str.trim().parse::<i32>() {
-                        current_node = next;
-                        current_input = step_output[..route_idx].trim().to_string();
+        // Detect explicit routing marker
+        if let Some(route_idx) = step_output.rfind("\n__ROUTE__=") {
+            let route_str = &step_output[route_idx + 11..];
+            if let Ok(next) = route_str.trim().parse::<i32>() {
+                match crate::nm_config::route_target(Some(next), cfg.rows.len()) {
+                    Some(target) => {
+                        outcome.routing_trace.push((last_node as usize, Some(target as i32)));
+                        current_node = target as i32;
+                        current_input = merged.unwrap_or_else(|| step_output[..route_idx].trim().to_string());
                         continue;
                     }
+                    None => {
+                        let _ = log_tx.send(AppEvent::Log(format!(
+                            "Traversal {}: route {} reached END",
+                            traversals, next
+                        )));
+                        outcome.routing_trace.push((last_node as usize, None));
+                        finished = true;
+                        break;
+                    }
                 }
+            }
+        }
 
-                // Default routing: go to next node if it exists
-                if (current_node as usize) + 1 < cfg.rows.len() {
-                    current_node += 1;
-                    current_input = step_output.clone();
-                    continue;
-                }
+        let step_output = merged.unwrap_or(step_output);
 
-                // No next node → stop unless we want infinite looping
-                if cfg.maximum_traversals != 0 {
-                    break;
-                }
-                
-                // For infinite looping, restart from node 0
-                current_node = 0;
-                current_input = step_output.clone();
+        // Default routing: go to next node if it exists
+        if (last_node as usize) + 1 < cfg.rows.len() {
+            outcome.routing_trace.push((last_node as usize, Some(last_node + 1)));
+            current_node = last_node + 1;
+            current_input = step_output.clone();
+            continue;
+        }
+
+        // No next node → stop unless we want infinite looping
+        if cfg.maximum_traversals != 0 {
+            outcome.routing_trace.push((last_node as usize, None));
+            finished = true;
+            break;
+        }
+        
+        // For infinite looping, restart from node 0
+        outcome.routing_trace.push((last_node as usize, Some(0)));
+        current_node = 0;
+        current_input = step_output.clone();
+    }
+
+    if !finished && !cancelled && traversals >= max_traversals {
+        let _ = log_tx.send(AppEvent::Warning(format!(
+            "Workflow '{}' stopped after reaching maximum_traversals ({})",
+            cfg.name, cfg.maximum_traversals
+        )));
+    }
+
+    // Agents report failures as output starting with "Error:"
+    outcome.traversals = traversals as usize;
+    if cancelled {
+        let msg = format!("Workflow '{}' cancelled at node {}", cfg.name, outcome.node_reached);
+        let _ = log_tx.send(AppEvent::Warning(msg.clone()));
+        outcome.error = Some(msg);
+    } else if outcome.final_output.starts_with("Error:") {
+        outcome.error = Some(outcome.final_output.clone());
+    }
+    outcome.routing_path = format_routing_trace(&outcome.routing_trace, &cfg.rows);
+    let _ = log_tx.send(AppEvent::RoutingTrace(outcome.routing_trace.clone()));
+    outcome
+}
+
+/// A routing trace as a readable path, e.g. `node 0 → node 2 (validator success) → END`.
+/// Validator hops are labelled from `rows`; pass no rows for a bare path. A trace whose
+/// last hop isn't END was cut short, by cancellation or `maximum_traversals`.
+pub fn format_routing_trace(trace: &[(usize, Option<i32>)], rows: &[crate::nm_config::AgentRow]) -> String {
+    let mut hops: Vec<String> = trace
+        .iter()
+        .map(|(node, next)| {
+            let target = next.and_then(|n| usize::try_from(n).ok());
+            let label = rows
+                .get(*node)
+                .filter(|row| row.agent_type == crate::nm_config::AgentType::Validator)
+                .and_then(|row| {
+                    if crate::nm_config::route_target(row.on_success, rows.len()) == target {
+                        Some("validator success")
+                    } else if crate::nm_config::route_target(row.on_failure, rows.len()) == target {
+                        Some("validator failure")
+                    } else {
+                        None
+                    }
+                });
+            match label {
+                Some(label) => format!("node {} ({})", node, label),
+                None => format!("node {}", node),
             }
+        })
+        .collect();
+    match trace.last() {
+        Some((_, None)) => hops.push("END".to_string()),
+        Some((_, Some(_))) => hops.push("stopped".to_string()),
+        None => {}
+    }
+    hops.join(" → ")
+}
 
-            // ✅ Final metrics + alerts
-            let final_metrics = metrics_collector.lock().await.get_metrics().await;
-            let alerts = metrics_collector.lock().await.get_alerts().await;
+/// `output` without the trailing `__ROUTE__=N` line a routing agent appends
+fn strip_route_marker(output: &str) -> &str {
+    output.rfind("\n__ROUTE__=").map_or(output, |idx| &output[..idx])
+}
 
-            for alert in alerts {
-                let _ = log_tx.send(AppEvent::Log(format!(
-                    "[ALERT][{}] {}",
-                    alert.level,
-                    alert.message
+/// The outputs of a parallel group as one input for the next agent, each under its
+/// agent's name and without its routing marker
+fn merge_group_outputs(members: &[usize], outputs: &[String], agent_names: &[String]) -> String {
+    members
+        .iter()
+        .zip(outputs)
+        .map(|(node, output)| {
+            let text = strip_route_marker(output);
+            let name = agent_names.get(*node).cloned().unwrap_or_else(|| format!("Agent{}", node + 1));
+            format!("[{}]\n{}", name, text.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Apply an agent's `output_injections` right after it produced `output`: write
+/// `nmoutput:` files and queue `outputN:inputM` text for agent M's next run
+fn apply_output_injections(
+    patterns: &[String],
+    output: &str,
+    latest_outputs: &HashMap<usize, String>,
+    working_dir: &str,
+    sandbox: bool,
+    pending_inputs: &mut HashMap<usize, Vec<String>>,
+    log_tx: &UnboundedSender<AppEvent>,
+) {
+    for pattern in patterns {
+        match OutputInjection::parse(pattern) {
+            Some(OutputInjection::File(path)) => {
+                // The same sandbox rules as `write_file`
+                let path = match crate::tools::checked_path(working_dir, &path, sandbox) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        let _ = log_tx.send(AppEvent::Warning(format!(
+                            "[Injection] Not writing output to '{}': {}",
+                            path, e
+                        )));
+                        continue;
+                    }
+                };
+                let written = match path.parent() {
+                    Some(dir) => std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, output)),
+                    None => std::fs::write(&path, output),
+                };
+                match written {
+                    Ok(()) => {
+                        let _ = log_tx.send(AppEvent::Log(format!(
+                            "[Injection] Wrote output to {}",
+                            path.display()
+                        )));
+                    }
+                    Err(e) => {
+                        let _ = log_tx.send(AppEvent::Warning(format!(
+                            "[Injection] Failed to write output to {}: {}",
+                            path.display(),
+                            e
+                        )));
+                    }
+                }
+            }
+            Some(OutputInjection::Agent { from, to }) => {
+                if let Some(text) = latest_outputs.get(&from) {
+                    pending_inputs.entry(to).or_default().push(text.clone());
+                    let _ = log_tx.send(AppEvent::Log(format!(
+                        "[Injection] Queued output of agent {} for agent {}",
+                        from, to
+                    )));
+                }
+            }
+            None => {
+                let _ = log_tx.send(AppEvent::Warning(format!(
+                    "[Injection] Ignoring unrecognized output injection '{}'",
+                    pattern
                 )));
             }
+        }
+    }
+}
+
+/// Price a run's token usage with `pricing.toml`, warning about models it can't price
+fn estimate_run_cost(
+    usage: &HashMap<String, crate::pricing::TokenUsage>,
+    log_tx: &UnboundedSender<AppEvent>,
+) -> crate::pricing::CostEstimate {
+    let table = crate::pricing::PricingTable::load(Path::new(crate::pricing::PRICING_FILE)).unwrap_or_else(|e| {
+        let _ = log_tx.send(AppEvent::Warning(format!("{}; using built-in prices", e)));
+        crate::pricing::PricingTable::builtin()
+    });
+    let estimate = table.estimate(usage);
+    if !estimate.unpriced_models.is_empty() {
+        let _ = log_tx.send(AppEvent::Warning(format!(
+            "No price for {} in {}; their tokens are left out of the cost estimate",
+            estimate.unpriced_models.join(", "),
+            crate::pricing::PRICING_FILE
+        )));
+    }
+    estimate
+}
+
+/// Report the collector's alerts and build the closing line of a run. The cost is
+/// only mentioned once the provider has reported token usage.
+async fn completion_summary(
+    metrics: Option<&Arc<Mutex<MetricsCollector>>>,
+    cost: &crate::pricing::CostEstimate,
+    log_tx: &UnboundedSender<AppEvent>,
+) -> String {
+    let cost_note = if cost.total_tokens() > 0 {
+        format!(" Usage: {}. Estimated cost: ${:.4} ({} tokens)", cost.usage_line(), cost.cost_usd, cost.total_tokens())
+    } else {
+        String::new()
+    };
+    let Some(collector) = metrics else {
+        return format!("Workflow completed. Metrics disabled.{}", cost_note);
+    };
+    let final_metrics = collector.lock().await.get_metrics().await;
+    let alerts = collector.lock().await.get_alerts().await;
+
+    for alert in alerts {
+        let line = format!("[ALERT][{}] {}", alert.level, alert.message);
+        let event = match alert.level {
+            AlertLevel::Warning => AppEvent::Warning(line),
+            _ => AppEvent::Log(line),
+        };
+        let _ = log_tx.send(event);
+    }
+
+    format!(
+        "Workflow completed. Metrics: {} requests, {:.2}% success rate, avg {:.2}ms response time.{}",
+        final_metrics.request_count,
+        final_metrics.get_success_rate() * 100.0,
+        final_metrics.average_response_time.num_milliseconds(),
+        cost_note
+    )
+}
+
+/// Agent a run begins at: an explicit `start_agent` wins, then the workflow's
+/// `active_agent_index` when it points at an existing agent, then agent 0
+fn initial_node(start_agent: Option<i32>, cfg: &crate::nm_config::WorkflowConfig) -> i32 {
+    start_agent.unwrap_or_else(|| {
+        if cfg.active_agent_index < cfg.rows.len() {
+            cfg.active_agent_index as i32
+        } else {
+            0
+        }
+    })
+}
 
-            let _ = log_tx.send(AppEvent::RunResult(format!(
-                "Workflow completed. Metrics: {} requests, {:.2}% success rate, avg {:.2}ms response time",
-                final_metrics.request_count,
-                final_metrics.get_success_rate() * 100.0,
-                final_metrics.average_response_time.num_milliseconds()
+/// One prompt's result from a batch run, written as a line of the results file.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BatchResult {
+    pub prompt: String,
+    pub output: String,
+    pub error: Option<String>,
+    pub routing_trace: Vec<(usize, Option<i32>)>,
+    pub routing_path: String,
+}
+
+/// Every non-empty line of a batch file is a separate prompt.
+pub fn batch_prompts(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Run `prompts` one after another through `run_one`, logging `[Batch] i/n` progress.
+/// When a rate limit is given, each prompt waits for a free slot in the per-minute window.
+pub async fn run_batch<F, Fut>(
+    prompts: &[String],
+    rate_limit: Option<(&crate::rate_limiter::RateLimiter, u32)>,
+    log_tx: &UnboundedSender<AppEvent>,
+    mut run_one: F,
+) -> Vec<BatchResult>
+where
+    F: FnMut(String) -> Fut,
+    Fut: std::future::Future<Output = WorkflowOutcome>,
+{
+    let mut results = Vec::with_capacity(prompts.len());
+    for (i, prompt) in prompts.iter().enumerate() {
+        if let Some((limiter, limit)) = rate_limit {
+            while !limiter.check_rate_limit("batch", limit, std::time::Duration::from_secs(60)).await {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+        let _ = log_tx.send(AppEvent::Log(format!("[Batch] {}/{}", i + 1, prompts.len())));
+        let outcome = run_one(prompt.clone()).await;
+        if let Some(err) = &outcome.error {
+            let _ = log_tx.send(AppEvent::Warning(format!(
+                "[Batch] prompt {} failed: {}",
+                i + 1,
+                err
             )));
+        }
+        results.push(BatchResult {
+            prompt: prompt.clone(),
+            output: outcome.final_output,
+            error: outcome.error,
+            routing_trace: outcome.routing_trace,
+            routing_path: outcome.routing_path,
+        });
+    }
+    results
+}
 
-            let _ = log_tx.send(AppEvent::RunEnd(workflow_name));
+/// One side of a `/compare`
+#[derive(Debug, Clone, Default)]
+pub struct ComparedRun {
+    pub outcome: WorkflowOutcome,
+    pub cost: crate::pricing::CostEstimate,
+    pub elapsed: std::time::Duration,
+}
+
+/// Run each workflow in `configs` on the same `prompt` through `run_one`, one after
+/// another like a batch so they never compete for the provider's rate limit. Each
+/// output is sent labelled `A: <name>`, `B: <name>`, followed by how B differs from A.
+pub async fn compare_workflows<'a, F, Fut>(
+    prompt: &str,
+    configs: &'a [crate::nm_config::WorkflowConfig],
+    log_tx: &UnboundedSender<AppEvent>,
+    mut run_one: F,
+) -> Vec<ComparedRun>
+where
+    F: FnMut(&'a crate::nm_config::WorkflowConfig, String) -> Fut,
+    Fut: std::future::Future<Output = (WorkflowOutcome, crate::pricing::CostEstimate)>,
+{
+    let mut runs = Vec::with_capacity(configs.len());
+    for (i, cfg) in configs.iter().enumerate() {
+        let _ = log_tx.send(AppEvent::Log(format!("[Compare] {}/{}: '{}'", i + 1, configs.len(), cfg.name)));
+        let start = std::time::Instant::now();
+        let (outcome, cost) = run_one(cfg, prompt.to_string()).await;
+        runs.push(ComparedRun { outcome, cost, elapsed: start.elapsed() });
+    }
+
+    for (i, (cfg, run)) in configs.iter().zip(&runs).enumerate() {
+        let label = (b'A' + i as u8) as char;
+        let text = match &run.outcome.error {
+            Some(error) => format!("Error: {}", error),
+            None => run.outcome.final_output.clone(),
+        };
+        let _ = log_tx.send(AppEvent::RunResult {
+            agent: Some(format!("{}: {}", label, cfg.name)),
+            text: format!(
+                "{}\n({:.1}s, {} tokens, ${:.4})",
+                text,
+                run.elapsed.as_secs_f64(),
+                run.cost.total_tokens(),
+                run.cost.cost_usd
+            ),
+        });
+    }
+    if let [a, b] = runs.as_slice() {
+        let _ = log_tx.send(AppEvent::RunResult {
+            agent: None,
+            text: format!(
+                "B vs A: {:+.1}s, {:+} tokens, {}${:.4}",
+                b.elapsed.as_secs_f64() - a.elapsed.as_secs_f64(),
+                b.cost.total_tokens() as i64 - a.cost.total_tokens() as i64,
+                if b.cost.cost_usd < a.cost.cost_usd { "-" } else { "+" },
+                (b.cost.cost_usd - a.cost.cost_usd).abs()
+            ),
+        });
+    }
+    runs
+}
+
+/// Write batch results as JSON lines, one object per prompt.
+pub fn write_batch_results(path: &Path, results: &[BatchResult]) -> std::io::Result<()> {
+    let mut out = String::new();
+    for result in results {
+        out.push_str(&serde_json::to_string(result).map_err(std::io::Error::other)?);
+        out.push('\n');
+    }
+    std::fs::write(path, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_injection_writes_file() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let dir = std::env::temp_dir().join(format!("nm_output_injection_{}", std::process::id()));
+        let patterns = vec!["nmoutput:reports/summary.txt".to_string()];
+        let mut pending = HashMap::new();
+
+        apply_output_injections(
+            &patterns,
+            "final summary",
+            &HashMap::new(),
+            dir.to_str().unwrap(),
+            false,
+            &mut pending,
+            &tx,
+        );
+
+        let written = std::fs::read_to_string(dir.join("reports/summary.txt"));
+        assert_eq!(written.unwrap(), "final summary");
+        assert!(pending.is_empty());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Log(_))));
+
+        // With the sandbox on, paths leaving the working directory are refused
+        let escaping = vec!["nmoutput:../escaped_2386.txt".to_string()];
+        apply_output_injections(&escaping, "secret", &HashMap::new(), dir.to_str().unwrap(), true, &mut pending, &tx);
+        assert!(!dir.join("../escaped_2386.txt").exists());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Warning(line)) if line.contains("outside the working directory")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_output_injection_feeds_another_agent() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let patterns = vec!["output1:input3".to_string(), "bogus".to_string()];
+        let latest = HashMap::from([(1, "plan from agent 1".to_string())]);
+        let mut pending = HashMap::new();
+
+        apply_output_injections(&patterns, "plan from agent 1", &latest, ".", false, &mut pending, &tx);
+        assert_eq!(pending.get(&3), Some(&vec!["plan from agent 1".to_string()]));
+
+        let mut saw_warning = false;
+        while let Ok(ev) = rx.try_recv() {
+            saw_warning |= matches!(ev, AppEvent::Warning(line) if line.contains("bogus"));
+        }
+        assert!(saw_warning);
+    }
+
+    #[tokio::test]
+    async fn test_chat_commands_need_a_session() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let outcome = run_workflow(AppCommand::EndChat, tx.clone(), None, CancelToken::default()).await;
+        assert!(outcome.error.is_some());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Error(_))));
+
+        // Ending a chat that never started is a no-op
+        let mut session = None;
+        let outcome = run_command(AppCommand::EndChat, &mut session, &mut None, tx, None, CancelToken::default()).await;
+        assert_eq!(outcome, WorkflowOutcome::default());
+        assert!(session.is_none());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_retry_reissues_last_run() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut last_run = None;
+        assert!(resolve_retry(AppCommand::Retry, &mut last_run, &tx).is_none());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Warning(_))));
+
+        let run = AppCommand::RunWorkflow {
+            workflow_name: "review".into(),
+            prompt: "check main.rs".into(),
+            cfg: crate::nm_config::WorkflowConfig::default(),
+            start_agent: Some(1),
+            variables: Some(HashMap::from([("lang".to_string(), "rust".to_string())])),
+        };
+        let sent = resolve_retry(run.clone(), &mut last_run, &tx).unwrap();
+        assert_eq!(format!("{:?}", sent), format!("{:?}", run));
+
+        // Other commands in between don't replace what gets retried
+        resolve_retry(AppCommand::EndChat, &mut last_run, &tx);
+        let retried = resolve_retry(AppCommand::Retry, &mut last_run, &tx).unwrap();
+        assert_eq!(format!("{:?}", retried), format!("{:?}", run));
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Log(line)) if line.contains("'review'") && line.contains("check main.rs")));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_metrics_skip_the_collector() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let no_usage = crate::pricing::CostEstimate::default();
+        let summary = completion_summary(None, &no_usage, &tx).await;
+        assert_eq!(summary, "Workflow completed. Metrics disabled.");
+        assert!(rx.try_recv().is_err());
+
+        let collector = Arc::new(Mutex::new(MetricsCollector::new()));
+        let summary = completion_summary(Some(&collector), &no_usage, &tx).await;
+        assert!(summary.starts_with("Workflow completed. Metrics: 0 requests"));
+
+        let used = crate::pricing::CostEstimate { prompt_tokens: 90, completion_tokens: 10, calls: 1, ..Default::default() };
+        let summary = completion_summary(None, &used, &tx).await;
+        assert_eq!(summary, "Workflow completed. Metrics disabled. Usage: 90 prompt + 10 completion tokens over 1 call. Estimated cost: $0.0000 (100 tokens)");
+    }
+
+    #[test]
+    fn test_initial_node_honors_configured_start() {
+        use crate::nm_config::{AgentRow, WorkflowConfig};
+        let mut cfg = WorkflowConfig::default();
+        cfg.rows = vec![AgentRow::default(), AgentRow::default(), AgentRow::default()];
+        assert_eq!(initial_node(None, &cfg), 0);
+
+        cfg.active_agent_index = 2;
+        assert_eq!(initial_node(None, &cfg), 2);
+        assert_eq!(initial_node(Some(1), &cfg), 1);
+
+        // A stale index past the last agent falls back to the first
+        cfg.active_agent_index = 7;
+        assert_eq!(initial_node(None, &cfg), 0);
+    }
+
+    #[tokio::test]
+    async fn test_outcome_reports_forced_error() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let outcome = run_workflow(
+            AppCommand::CreateTemplate {
+                template_type: "not_a_template_type".to_string(),
+                template_name: "outcome_2378".to_string(),
+            },
+            tx,
+            None,
+            CancelToken::default(),
+        )
+        .await;
+
+        let error = outcome.error.expect("forced error should be reported");
+        assert!(error.contains("not_a_template_type"));
+        assert_eq!(outcome.traversals, 0);
+
+        // The event stream still carries the same error
+        let mut saw_error = false;
+        while let Ok(ev) = rx.try_recv() {
+            if let AppEvent::Error(line) = ev {
+                saw_error = line == error;
+            }
         }
+        assert!(saw_error);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_collects_each_prompt() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let prompts = batch_prompts("summarize the logs\n\n  draft a reply  \n");
+        assert_eq!(prompts, vec!["summarize the logs", "draft a reply"]);
+
+        let limiter = crate::rate_limiter::RateLimiter::new();
+        let results = run_batch(&prompts, Some((&limiter, 10)), &tx, |prompt| async move {
+            WorkflowOutcome {
+                final_output: format!("done: {}", prompt),
+                ..Default::default()
+            }
+        })
+        .await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].output, "done: summarize the logs");
+        assert_eq!(results[1].output, "done: draft a reply");
+
+        let mut progress = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let AppEvent::Log(line) = ev {
+                progress.push(line);
+            }
+        }
+        assert_eq!(progress, vec!["[Batch] 1/2", "[Batch] 2/2"]);
+
+        let path = std::env::temp_dir().join(format!("nm_batch_{}.jsonl", std::process::id()));
+        write_batch_results(&path, &results).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        assert!(written.contains("\"output\":\"done: draft a reply\""));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Waits until every agent sharing `barrier` has started, so it only finishes
+    /// when its group runs concurrently
+    struct BarrierAgent {
+        name: String,
+        barrier: Arc<tokio::sync::Barrier>,
     }
-}
\ No newline at end of file
+
+    #[async_trait::async_trait]
+    impl llmgraph::models::graph::Agent for BarrierAgent {
+        async fn run(
+            &mut self,
+            input: &str,
+            _tool_registry: &(dyn llmgraph::models::tools::ToolRegistryTrait + Send + Sync),
+        ) -> (String, Option<i32>) {
+            self.barrier.wait().await;
+            (format!("{} saw {}", self.name, input), None)
+        }
+
+        fn get_name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_group_runs_concurrently() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let grouped_row = crate::nm_config::AgentRow {
+            parallel_group: Some(1),
+            ..crate::nm_config::AgentRow::default()
+        };
+        let cfg = crate::nm_config::WorkflowConfig {
+            name: "fan_out".into(),
+            maximum_traversals: 5,
+            rows: vec![grouped_row.clone(), grouped_row],
+            ..crate::nm_config::WorkflowConfig::default()
+        };
+
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let mut graph = WorkflowGraph { main: Graph::new(), grouped: HashMap::new(), profiler: Default::default() };
+        for (id, name) in ["Pros", "Cons"].iter().enumerate() {
+            let mut own = Graph::new();
+            own.add_node(id as i32, Box::new(BarrierAgent { name: name.to_string(), barrier: barrier.clone() }));
+            graph.grouped.insert(id as i32, own);
+        }
+        let names = vec!["Pros".to_string(), "Cons".to_string()];
+
+        // Run one after another, the first agent would wait on the barrier forever
+        let outcome = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            traverse(&mut graph, &cfg, &names, 0, "topic".into(), &tx, None, &CancelToken::default()),
+        )
+        .await
+        .expect("grouped agents did not run concurrently");
+
+        assert_eq!(outcome.traversals, 1);
+        assert_eq!(outcome.final_output, "[Pros]\nPros saw topic\n\n[Cons]\nCons saw topic");
+        assert_eq!(crate::nm_config::parallel_group(&cfg, 1), vec![0, 1]);
+    }
+
+    /// Signals `started` and then never finishes, like a hung LLM call
+    struct HangingAgent {
+        started: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl llmgraph::models::graph::Agent for HangingAgent {
+        async fn run(
+            &mut self,
+            _input: &str,
+            _tool_registry: &(dyn llmgraph::models::tools::ToolRegistryTrait + Send + Sync),
+        ) -> (String, Option<i32>) {
+            self.started.notify_one();
+            std::future::pending().await
+        }
+
+        fn get_name(&self) -> &str {
+            "Hanging"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_workflow() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let cfg = crate::nm_config::WorkflowConfig {
+            name: "stuck".into(),
+            ..crate::nm_config::WorkflowConfig::default()
+        };
+        let started = Arc::new(tokio::sync::Notify::new());
+        let mut main = Graph::new();
+        main.add_node(0, Box::new(HangingAgent { started: started.clone() }));
+        let mut graph = WorkflowGraph { main, grouped: HashMap::new(), profiler: Default::default() };
+        let names = vec!["Hanging".to_string()];
+        let cancel = CancelToken::default();
+
+        let shutdown = async {
+            started.notified().await;
+            cancel.cancel();
+        };
+        let (outcome, ()) = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            async { tokio::join!(traverse(&mut graph, &cfg, &names, 0, "go".into(), &tx, None, &cancel), shutdown) },
+        )
+        .await
+        .expect("running workflow ignored the cancellation");
+
+        assert_eq!(outcome.traversals, 1);
+        assert_eq!(outcome.error.as_deref(), Some("Workflow 'stuck' cancelled at node 0"));
+        let mut saw_warning = false;
+        while let Ok(ev) = rx.try_recv() {
+            saw_warning |= matches!(ev, AppEvent::Warning(line) if line.contains("cancelled"));
+        }
+        assert!(saw_warning);
+
+        // A workflow started after shutdown doesn't run any agent
+        let outcome = traverse(&mut graph, &cfg, &names, 0, "go".into(), &tx, None, &cancel).await;
+        assert_eq!(outcome.traversals, 0);
+    }
+
+    /// Replies with its scripted outputs in order, routing markers included
+    struct ScriptedAgent {
+        replies: std::collections::VecDeque<String>,
+    }
+
+    #[async_trait::async_trait]
+    impl llmgraph::models::graph::Agent for ScriptedAgent {
+        async fn run(
+            &mut self,
+            _input: &str,
+            _tool_registry: &(dyn llmgraph::models::tools::ToolRegistryTrait + Send + Sync),
+        ) -> (String, Option<i32>) {
+            (self.replies.pop_front().unwrap_or_default(), None)
+        }
+
+        fn get_name(&self) -> &str {
+            "Scripted"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routing_trace_follows_scripted_traversal() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let validator = crate::nm_config::AgentRow {
+            agent_type: crate::nm_config::AgentType::Validator,
+            on_success: None,
+            on_failure: Some(1),
+            ..crate::nm_config::AgentRow::default()
+        };
+        let cfg = crate::nm_config::WorkflowConfig {
+            name: "review".into(),
+            rows: vec![crate::nm_config::AgentRow::default(), crate::nm_config::AgentRow::default(), validator],
+            ..crate::nm_config::WorkflowConfig::default()
+        };
+
+        // Writer skips to the validator, which sends it to the fixer once and then passes
+        let script: [&[&str]; 3] = [
+            &["draft\n__ROUTE__=2"],
+            &["fixed"],
+            &["needs work\n__ROUTE__=1", "looks good\n__ROUTE__=-1"],
+        ];
+        let mut main = Graph::new();
+        for (id, replies) in script.iter().enumerate() {
+            let replies = replies.iter().map(|r| r.to_string()).collect();
+            main.add_node(id as i32, Box::new(ScriptedAgent { replies }));
+        }
+        let mut graph = WorkflowGraph { main, grouped: HashMap::new(), profiler: Default::default() };
+        let names = vec!["Writer".to_string(), "Fixer".to_string(), "Reviewer".to_string()];
+
+        let outcome = traverse(&mut graph, &cfg, &names, 0, "essay".into(), &tx, None, &CancelToken::default()).await;
+
+        let expected = vec![(0, Some(2)), (2, Some(1)), (1, Some(2)), (2, None)];
+        assert_eq!(outcome.routing_trace, expected);
+        assert_eq!(
+            outcome.routing_path,
+            "node 0 → node 2 (validator failure) → node 1 → node 2 (validator success) → END"
+        );
+
+        let mut emitted = None;
+        while let Ok(ev) = rx.try_recv() {
+            if let AppEvent::RoutingTrace(trace) = ev {
+                emitted = Some(trace);
+            }
+        }
+        assert_eq!(emitted, Some(expected));
+        assert_eq!(format_routing_trace(&[(0, Some(1))], &[]), "node 0 → stopped");
+    }
+
+    #[tokio::test]
+    async fn test_routed_output_is_injected_without_its_marker() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let dir = std::env::temp_dir().join(format!("nm_route_injection_{}", std::process::id()));
+        let writer = crate::nm_config::AgentRow {
+            output_injections: vec!["nmoutput:draft.txt".to_string()],
+            ..crate::nm_config::AgentRow::default()
+        };
+        let cfg = crate::nm_config::WorkflowConfig {
+            name: "draft".into(),
+            working_dir: dir.display().to_string(),
+            rows: vec![writer, crate::nm_config::AgentRow::default()],
+            ..crate::nm_config::WorkflowConfig::default()
+        };
+        let mut main = Graph::new();
+        main.add_node(0, Box::new(ScriptedAgent { replies: vec!["draft\n__ROUTE__=1".to_string()].into() }));
+        main.add_node(1, Box::new(ScriptedAgent { replies: vec!["done".to_string()].into() }));
+        let mut graph = WorkflowGraph { main, grouped: HashMap::new(), profiler: Default::default() };
+        let names = vec!["Writer".to_string(), "Editor".to_string()];
+
+        traverse(&mut graph, &cfg, &names, 0, "essay".into(), &tx, None, &CancelToken::default()).await;
+
+        let written = std::fs::read_to_string(dir.join("draft.txt"));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(written.unwrap(), "draft");
+    }
+
+    #[tokio::test]
+    async fn test_compare_sends_same_prompt_and_labels_both_results() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let configs = ["terse", "verbose"].map(|name| crate::nm_config::WorkflowConfig { name: name.into(), ..Default::default() });
+        let mut prompts = Vec::new();
+
+        let runs = compare_workflows("name a colour", &configs, &tx, |cfg, prompt| {
+            prompts.push((cfg.name.clone(), prompt.clone()));
+            let tokens = if cfg.name == "terse" { 10 } else { 40 };
+            async move {
+                let outcome = WorkflowOutcome { final_output: format!("answer to {}", prompt), ..Default::default() };
+                let cost = crate::pricing::CostEstimate { prompt_tokens: tokens, ..Default::default() };
+                (outcome, cost)
+            }
+        })
+        .await;
+
+        assert_eq!(runs.len(), 2);
+        assert_eq!(
+            prompts,
+            vec![("terse".to_string(), "name a colour".to_string()), ("verbose".to_string(), "name a colour".to_string())]
+        );
+        let mut results = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let AppEvent::RunResult { agent, text } = ev {
+                results.push((agent, text));
+            }
+        }
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0.as_deref(), Some("A: terse"));
+        assert_eq!(results[1].0.as_deref(), Some("B: verbose"));
+        assert!(results[1].1.starts_with("answer to name a colour\n("));
+        assert!(results[2].1.contains("+30 tokens"), "{}", results[2].1);
+    }
+
+    #[tokio::test]
+    async fn test_progress_counts_up_each_traversal() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let validator = crate::nm_config::AgentRow {
+            agent_type: crate::nm_config::AgentType::Validator,
+            on_success: None,
+            on_failure: Some(0),
+            ..crate::nm_config::AgentRow::default()
+        };
+        let cfg = crate::nm_config::WorkflowConfig {
+            name: "review".into(),
+            maximum_traversals: 6,
+            rows: vec![crate::nm_config::AgentRow::default(), validator],
+            ..crate::nm_config::WorkflowConfig::default()
+        };
+        // The validator sends the draft back once, so the run takes four traversals
+        let script: [&[&str]; 2] = [&["draft", "second draft"], &["no\n__ROUTE__=0", "yes\n__ROUTE__=-1"]];
+        let mut main = Graph::new();
+        for (id, replies) in script.iter().enumerate() {
+            let replies = replies.iter().map(|r| r.to_string()).collect();
+            main.add_node(id as i32, Box::new(ScriptedAgent { replies }));
+        }
+        let mut graph = WorkflowGraph { main, grouped: HashMap::new(), profiler: Default::default() };
+        let names = vec!["Writer".to_string(), "Reviewer".to_string()];
+
+        let outcome = traverse(&mut graph, &cfg, &names, 0, "essay".into(), &tx, None, &CancelToken::default()).await;
+        assert_eq!(outcome.traversals, 4);
+
+        let mut progress = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let AppEvent::Progress { current, total } = ev {
+                progress.push((current, total));
+            }
+        }
+        // Measured against maximum_traversals from the first step, so looping never shrinks the bar
+        assert_eq!(progress, vec![(1, 6), (2, 6), (3, 6), (4, 6)]);
+        assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        // Unlimited runs count a straight pass, then grow with the run
+        let unlimited = crate::nm_config::WorkflowConfig { maximum_traversals: 0, ..cfg };
+        let totals: Vec<usize> = (1..=4).map(|current| progress_total(&unlimited, current)).collect();
+        assert_eq!(totals, vec![2, 2, 3, 4]);
+        let ratios: Vec<f64> = totals.iter().zip(1..).map(|(total, current)| current as f64 / *total as f64).collect();
+        assert!(ratios.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
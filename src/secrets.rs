@@ -0,0 +1,44 @@
+use std::env;
+
+/// Service name under which API keys are stored in the OS keychain
+#[cfg(feature = "keyring")]
+pub const KEYRING_SERVICE: &str = "neonmachines";
+
+/// Provider used for keyring lookups while the endpoint is fixed to OpenRouter
+pub const DEFAULT_PROVIDER: &str = "openrouter";
+
+/// Resolve the API key for `provider`, preferring the keyring when enabled and falling back to `API_KEY`
+pub fn resolve_api_key(provider: &str, use_keyring: bool) -> String {
+    if use_keyring {
+        if let Some(key) = keyring_get(provider) {
+            return key;
+        }
+    }
+    env::var("API_KEY").unwrap_or_default()
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_get(provider: &str) -> Option<String> {
+    keyring::Entry::new(KEYRING_SERVICE, provider)
+        .and_then(|entry| entry.get_password())
+        .ok()
+        .filter(|key| !key.is_empty())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_get(_provider: &str) -> Option<String> {
+    None
+}
+
+/// Store an API key for `provider` in the OS keychain
+#[cfg(feature = "keyring")]
+pub fn keyring_set(provider: &str, key: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, provider)
+        .and_then(|entry| entry.set_password(key))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn keyring_set(_provider: &str, _key: &str) -> Result<(), String> {
+    Err("keyring support is not enabled; rebuild with `--features keyring`".to_string())
+}
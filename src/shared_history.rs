@@ -1,4 +1,6 @@
 use llmgraph::models::tools::Message;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
@@ -19,6 +21,23 @@ impl SharedHistory {
         }
     }
 
+    /// A separate history starting from a copy of this one, for runs whose messages
+    /// shouldn't all be kept
+    pub fn scratch(&self) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(self.snapshot())),
+        }
+    }
+
+    /// Copy of every message recorded so far
+    pub fn snapshot(&self) -> Vec<Message> {
+        if let Ok(history) = self.inner.lock() {
+            history.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_last(&self, n: usize) -> Vec<Message> {
         if let Ok(history) = self.inner.lock() {
@@ -46,4 +65,39 @@ impl SharedHistory {
             Vec::new()
         }
     }
-}
\ No newline at end of file
+}
+
+/// The inputs and shared history of a finished run, kept so it can be replayed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub workflow: String,
+    pub prompt: String,
+    pub start_agent: Option<i32>,
+    pub messages: Vec<Message>,
+}
+
+/// Where the last run of `workflow` is stored
+pub fn run_record_path(workflow: &str) -> PathBuf {
+    let safe: String = workflow
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    PathBuf::from(".neonmachines_data").join("history").join(format!("{}.json", safe))
+}
+
+pub fn save_run_record(record: &RunRecord, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_string_pretty(record)?;
+    std::fs::write(path, data)
+}
+
+/// Load a stored run, `Ok(None)` if the workflow has never been run
+pub fn load_run_record(path: &Path) -> std::io::Result<Option<RunRecord>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&data)?))
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Reusable POML fragments as (name, description, body), added with `/snippet add`
+const SNIPPETS: &[(&str, &str, &str)] = &[
+    (
+        "validator-schema",
+        "Reply format the validator agent parses",
+        "<output-format>\nReply only with JSON: {\"valid\": true} when the input meets every requirement, or {\"valid\": false, \"errors\": [\"what to fix\"]} when it doesn't. Don't add any other text.\n</output-format>",
+    ),
+    (
+        "json-extraction",
+        "Answer with bare JSON that tools and injections can read",
+        "<output-format>\nReturn a single JSON object and nothing else: no Markdown fences, no explanation. Use null for values the input doesn't give.\n</output-format>",
+    ),
+    (
+        "step-by-step",
+        "Reason through the task before answering",
+        "<task>\nWork through the problem step by step. Number each step, check it against the input before moving on, then give the final answer on its own line starting with \"Answer:\".\n</task>",
+    ),
+];
+
+/// Body of the snippet called `name`
+pub fn find(name: &str) -> Option<&'static str> {
+    SNIPPETS.iter().find(|(n, _, _)| *n == name).map(|(_, _, body)| *body)
+}
+
+/// One line per snippet, for `/snippet list`
+pub fn list() -> String {
+    SNIPPETS
+        .iter()
+        .map(|(name, description, _)| format!("  {:<18} {}", name, description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Write snippet `name` as a new prompt file `dir/file`, bare so it can be pulled in
+/// with `<include>`. `file` must stay inside `dir`, and an existing file is never overwritten.
+pub fn write_snippet(name: &str, dir: &Path, file: &str) -> Result<PathBuf, String> {
+    let body = find(name).ok_or_else(|| format!("Unknown snippet '{}'. Use /snippet list to see them.", name))?;
+    if !Path::new(file).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("'{}' must be a relative path inside {}", file, dir.display()));
+    }
+    let path = dir.join(file);
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, format!("{}\n", body)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet_add_writes_prompt_file() {
+        let dir = std::env::temp_dir().join(format!("nm_snippets_{}", std::process::id()));
+        let path = write_snippet("validator-schema", &dir, "check.poml").unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written, format!("{}\n", find("validator-schema").unwrap()));
+        assert!(written.contains("{\"valid\": false, \"errors\""));
+
+        // Existing prompts are left alone, and unknown names are reported
+        fs::write(&path, "mine").unwrap();
+        assert!(write_snippet("step-by-step", &dir, "check.poml").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "mine");
+        assert!(write_snippet("nope", &dir, "other.poml").unwrap_err().contains("Unknown snippet 'nope'"));
+
+        // Files outside the prompts directory are refused
+        assert!(write_snippet("step-by-step", &dir, "/tmp/escape.poml").unwrap_err().contains("relative path"));
+        assert!(write_snippet("step-by-step", &dir, "../escape.poml").is_err());
+        assert!(!dir.parent().unwrap().join("escape.poml").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,100 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Theme file read at startup: `default_theme` picks the `--theme`, `roles` styles chat messages
+pub const THEME_CONFIG: &str = ".neonmachines_data/theme_config.json";
+
+/// Built-in chat palette as (role, color, modifiers)
+const DEFAULT_ROLE_STYLES: &[(&str, Color, Modifier)] = &[
+    ("you", Color::Cyan, Modifier::BOLD),
+    ("system", Color::Gray, Modifier::ITALIC),
+    ("progress", Color::Yellow, Modifier::empty()),
+    ("tool", Color::Magenta, Modifier::empty()),
+    ("agent", Color::Green, Modifier::empty()),
+    ("warning", Color::LightYellow, Modifier::BOLD),
+    ("error", Color::Red, Modifier::BOLD),
+    ("reasoning", Color::DarkGray, Modifier::ITALIC),
+];
+
+/// One entry under `roles`, e.g. `{"color": "blue", "modifiers": ["bold"]}`. Colors are
+/// names, `#rrggbb` or 0-255 indices.
+#[derive(Debug, Deserialize)]
+struct RoleStyleSpec {
+    color: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ThemeFile {
+    #[serde(default)]
+    roles: HashMap<String, RoleStyleSpec>,
+}
+
+/// Chat message styles by `from` value, or by agent name for agent output
+#[derive(Debug, Clone)]
+pub struct RoleStyles(HashMap<String, Style>);
+
+impl Default for RoleStyles {
+    fn default() -> Self {
+        Self(
+            DEFAULT_ROLE_STYLES
+                .iter()
+                .map(|(role, color, modifier)| (role.to_string(), Style::default().fg(*color).add_modifier(*modifier)))
+                .collect(),
+        )
+    }
+}
+
+impl RoleStyles {
+    /// The default palette with the `roles` in `path` on top. A missing file keeps
+    /// the defaults; an unreadable one, or an unknown color or modifier, is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let file: ThemeFile = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let mut styles = Self::default();
+        for (role, spec) in file.roles {
+            let mut style = Style::default();
+            if let Some(color) = &spec.color {
+                let color = Color::from_str(color).map_err(|_| format!("unknown color '{}' for role '{}'", color, role))?;
+                style = style.fg(color);
+            }
+            for name in &spec.modifiers {
+                style = style.add_modifier(parse_modifier(name).ok_or_else(|| format!("unknown modifier '{}' for role '{}'", name, role))?);
+            }
+            styles.0.insert(role, style);
+        }
+        Ok(styles)
+    }
+
+    /// Style for `role`, white when the theme doesn't know it
+    pub fn style_for(&self, role: &str) -> Style {
+        self.get(role).unwrap_or_else(|| Style::default().fg(Color::White))
+    }
+
+    pub fn get(&self, role: &str) -> Option<Style> {
+        self.0.get(role).copied()
+    }
+}
+
+fn parse_modifier(name: &str) -> Option<Modifier> {
+    match name.to_lowercase().as_str() {
+        "bold" => Some(Modifier::BOLD),
+        "dim" => Some(Modifier::DIM),
+        "italic" => Some(Modifier::ITALIC),
+        "underlined" | "underline" => Some(Modifier::UNDERLINED),
+        "reversed" => Some(Modifier::REVERSED),
+        "crossed_out" => Some(Modifier::CROSSED_OUT),
+        _ => None,
+    }
+}
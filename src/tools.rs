@@ -5,9 +5,123 @@ use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::UnboundedSender;
 use std::process::{Command, Stdio};
 
+/// Key-value scratchpad shared by all agents in one workflow run
+pub type SharedMemory = Arc<Mutex<HashMap<String, Value>>>;
+
+/// Embeddings model used when a workflow doesn't set `embedding_model`
+pub const DEFAULT_EMBEDDING_MODEL: &str = "openai/text-embedding-3-small";
+const EMBEDDINGS_URL: &str = "https://openrouter.ai/api/v1/embeddings";
+
+/// Bytes of stdout and of stderr `execute_terminal` returns unless the workflow sets
+/// `terminal_output_limit`; a call's `max_output_bytes` can only lower it
+pub const TERMINAL_OUTPUT_LIMIT: usize = 32768;
+/// Longest window `watch_file` blocks for, and the bytes it returns by default
+pub const WATCH_FILE_MAX_SECS: u64 = 60;
+pub const WATCH_FILE_OUTPUT_LIMIT: usize = 32768;
+const WATCH_FILE_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Where the `embed_text` tool sends its requests
+#[derive(Debug, Clone)]
+pub struct EmbeddingSettings {
+    pub model: String,
+    pub use_keyring: bool,
+}
+
+impl Default for EmbeddingSettings {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            use_keyring: false,
+        }
+    }
+}
+
+impl EmbeddingSettings {
+    pub fn from_config(cfg: &crate::nm_config::WorkflowConfig) -> Self {
+        Self {
+            model: cfg.embedding_model.clone().unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string()),
+            use_keyring: cfg.use_keyring,
+        }
+    }
+}
+
+/// Why a tool call failed. Reaches the model as `{"error": ..., "error_kind": ...}`
+/// so validator and router agents can tell a missing file from a refused one.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ToolError {
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    PermissionDenied(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("{0}")]
+    Io(String),
+
+    #[error("{0}")]
+    Timeout(String),
+}
+
+impl ToolError {
+    /// Classify an I/O failure by its kind, keeping `context` in the message
+    pub fn io(context: impl std::fmt::Display, err: std::io::Error) -> Self {
+        Self::from_io_kind(err.kind(), format!("{}: {}", context, err))
+    }
+
+    fn from_io_kind(kind: std::io::ErrorKind, message: String) -> Self {
+        use std::io::ErrorKind;
+        match kind {
+            ErrorKind::NotFound => ToolError::NotFound(message),
+            ErrorKind::PermissionDenied => ToolError::PermissionDenied(message),
+            ErrorKind::InvalidInput => ToolError::InvalidArgument(message),
+            ErrorKind::TimedOut => ToolError::Timeout(message),
+            _ => ToolError::Io(message),
+        }
+    }
+
+    /// The `error_kind` reported to agents
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ToolError::NotFound(_) => "not_found",
+            ToolError::PermissionDenied(_) => "permission_denied",
+            ToolError::InvalidArgument(_) => "invalid_argument",
+            ToolError::Io(_) => "io",
+            ToolError::Timeout(_) => "timeout",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "error": self.to_string(), "error_kind": self.kind() })
+    }
+}
+
+impl From<std::io::Error> for ToolError {
+    fn from(err: std::io::Error) -> Self {
+        Self::from_io_kind(err.kind(), err.to_string())
+    }
+}
+
+/// Tool functions fail with a string, so the structured error travels as its JSON
+impl From<ToolError> for String {
+    fn from(err: ToolError) -> Self {
+        err.to_json().to_string()
+    }
+}
+
+/// A required string argument, or `InvalidArgument` when it is missing
+fn required_str<'a>(args: &'a Value, name: &str) -> Result<&'a str, ToolError> {
+    args[name]
+        .as_str()
+        .ok_or_else(|| ToolError::InvalidArgument(format!("Missing '{}' parameter", name)))
+}
+
 /// Helper to define properties
 fn prop(typ: &str, desc: &str) -> Property {
     Property {
@@ -27,11 +141,388 @@ fn resolve_path(working_dir: &str, path: &str) -> PathBuf {
     }
 }
 
+/// Tool output as text: valid UTF-8 is passed through, anything else is base64-encoded
+/// so binary data is never mangled. The second value is the `encoding` to report.
+fn encode_output(bytes: &[u8]) -> (String, &'static str) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), "utf-8"),
+        Err(_) => (base64_encode(bytes), "base64"),
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// Drop a trailing `\n` or `\r\n`, as `str::lines` does
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Resolve `path` against `working_dir`. With `sandbox` on, the path is canonicalized
+/// (following symlinks and `..`) and rejected unless it stays inside `working_dir`.
+pub(crate) fn checked_path(working_dir: &str, path: &str, sandbox: bool) -> Result<PathBuf, ToolError> {
+    let full_path = resolve_path(working_dir, path);
+    if !sandbox {
+        return Ok(full_path);
+    }
+    let root = fs::canonicalize(working_dir)
+        .map_err(|e| ToolError::io(format!("Cannot resolve working directory {}", working_dir), e))?;
+    let canonical = canonicalize_existing_prefix(&full_path)
+        .map_err(|e| ToolError::io(format!("Cannot resolve path {}", path), e))?;
+    if canonical.starts_with(&root) {
+        Ok(canonical)
+    } else {
+        Err(ToolError::PermissionDenied(format!(
+            "Path '{}' is outside the working directory {} (sandbox is on)",
+            path,
+            root.display()
+        )))
+    }
+}
+
+/// `path` as given, for the tools that have always opened it relative to the process
+/// directory; with `sandbox` on it is resolved and checked like `checked_path`
+fn given_path(working_dir: &str, path: &str, sandbox: bool) -> Result<PathBuf, ToolError> {
+    if sandbox {
+        checked_path(working_dir, path, sandbox)
+    } else {
+        Ok(PathBuf::from(path))
+    }
+}
+
+/// Canonicalize the longest existing ancestor of `path` and append the rest, which
+/// can't hold symlinks since it doesn't exist yet. Lets `mkdir`/`write_file` targets
+/// be checked before they are created.
+fn canonicalize_existing_prefix(path: &Path) -> std::io::Result<PathBuf> {
+    use std::path::Component;
+    let mut existing = path;
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                rest.push(last);
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+    let mut resolved = fs::canonicalize(if existing.as_os_str().is_empty() { Path::new(".") } else { existing })?;
+    for component in rest.into_iter().rev() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(part) => resolved.push(part),
+            _ => {}
+        }
+    }
+    Ok(resolved)
+}
+
+/// First `n` lines of a file as raw bytes, reading no further than needed
+fn head_lines(path: &Path, n: usize) -> std::io::Result<Vec<Vec<u8>>> {
+    use std::io::BufRead;
+    let mut reader = std::io::BufReader::new(fs::File::open(path)?);
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    while lines.len() < n {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        lines.push(strip_line_ending(&line).to_vec());
+    }
+    Ok(lines)
+}
+
+/// Last `n` lines of a file as raw bytes, read backwards in chunks from the end
+fn tail_lines(path: &Path, n: usize) -> std::io::Result<Vec<Vec<u8>>> {
+    use std::io::{Read, Seek, SeekFrom};
+    const CHUNK: u64 = 8192;
+
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut pos = len;
+    let mut buf: Vec<u8> = Vec::new();
+
+    // Stop once we have more than n newlines, ignoring one trailing newline
+    loop {
+        let trailing = usize::from(buf.last() == Some(&b'\n'));
+        let newlines = buf.iter().filter(|b| **b == b'\n').count();
+        if pos == 0 || newlines > n + trailing {
+            break;
+        }
+        let read = CHUNK.min(pos);
+        pos -= read;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut chunk = vec![0u8; read as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+    }
+
+    let body = buf.strip_suffix(b"\n").unwrap_or(&buf);
+    let lines: Vec<&[u8]> = if body.is_empty() { Vec::new() } else { body.split(|b| *b == b'\n').collect() };
+    // The first line may be partial when we stopped mid-file, but it is dropped by the skip
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|l| strip_line_ending(l).to_vec()).collect())
+}
+
+/// Bytes appended to `path` during `window`, polling from its current end. Stops
+/// early once more than `max_bytes` arrived; the bool is whether any were cut off.
+/// A file truncated mid-watch (a rotated log) is read again from the start.
+fn watch_appended(path: &Path, window: std::time::Duration, max_bytes: usize) -> std::io::Result<(Vec<u8>, bool)> {
+    use std::io::{Read, Seek, SeekFrom};
+    let deadline = std::time::Instant::now() + window;
+    let mut file = fs::File::open(path)?;
+    let mut pos = file.seek(SeekFrom::End(0))?;
+    let mut collected = Vec::new();
+    loop {
+        let len = fs::metadata(path)?.len();
+        if len < pos {
+            pos = 0;
+        }
+        if len > pos {
+            file.seek(SeekFrom::Start(pos))?;
+            let read = (&mut file).take((max_bytes + 1 - collected.len()) as u64).read_to_end(&mut collected)?;
+            pos += read as u64;
+            if collected.len() > max_bytes {
+                collected.truncate(max_bytes);
+                return Ok((collected, true));
+            }
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return Ok((collected, false));
+        }
+        std::thread::sleep(WATCH_FILE_POLL.min(deadline - now));
+    }
+}
+
+/// Line, word, byte and char counts for a file, streamed in chunks so large files
+/// are never held in memory. `binary` is set when the content is not valid UTF-8.
+fn file_stats(path: &Path) -> std::io::Result<Value> {
+    use std::io::Read;
+    let mut file = fs::File::open(path)?;
+    let modified = file.metadata()?.modified().ok()
+        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339());
+
+    let (mut lines, mut words, mut bytes, mut chars) = (0u64, 0u64, 0u64, 0u64);
+    let mut in_word = false;
+    let mut last_byte = b'\n';
+    let mut binary = false;
+    // Bytes of a UTF-8 sequence split across two chunks
+    let mut pending: Vec<u8> = Vec::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        let data = &chunk[..read];
+        bytes += read as u64;
+        last_byte = data[read - 1];
+        for &b in data {
+            if b == b'\n' {
+                lines += 1;
+            }
+            // Continuation bytes don't start a new char
+            if b & 0xC0 != 0x80 {
+                chars += 1;
+            }
+            if b.is_ascii_whitespace() {
+                in_word = false;
+            } else if !in_word {
+                in_word = true;
+                words += 1;
+            }
+        }
+        if !binary {
+            pending.extend_from_slice(data);
+            match std::str::from_utf8(&pending) {
+                Ok(_) => pending.clear(),
+                Err(e) if e.error_len().is_none() => {
+                    pending.drain(..e.valid_up_to());
+                }
+                Err(_) => binary = true,
+            }
+        }
+    }
+    // An unfinished sequence at EOF is invalid too
+    if !pending.is_empty() {
+        binary = true;
+    }
+    // Count a final line without a trailing newline, like `str::lines`
+    if last_byte != b'\n' {
+        lines += 1;
+    }
+
+    Ok(json!({
+        "path": path.display().to_string(),
+        "lines": lines,
+        "words": words,
+        "bytes": bytes,
+        "chars": chars,
+        "modified": modified,
+        "binary": binary
+    }))
+}
+
+/// Write `chunks` to `path`. Replacing goes through `atomic_write`, so readers never see
+/// a half-written file; with `append` the chunks are added to the end of the file in place,
+/// so appenders running at once each keep their data. Returns the bytes of `chunks` written.
+fn write_chunks<'a>(path: &Path, chunks: impl IntoIterator<Item = &'a [u8]>, append: bool) -> Result<usize, ToolError> {
+    use std::io::Write;
+    write_chunks_with(path, chunks, append, |file, chunk| file.write_all(chunk))
+}
+
+/// `write_chunks` with the chunk write supplied, so tests can make it fail part way
+fn write_chunks_with<'a, F>(path: &Path, chunks: impl IntoIterator<Item = &'a [u8]>, append: bool, mut write: F) -> Result<usize, ToolError>
+where
+    F: FnMut(&mut fs::File, &[u8]) -> std::io::Result<()>,
+{
+    if !append {
+        return crate::atomic_write::write_chunks_with(path, chunks, write).map_err(|(e, written)| {
+            ToolError::io(
+                format!("Failed to write {} after {} bytes; the file was left unchanged", path.display(), written),
+                e,
+            )
+        });
+    }
+    // One write on a file opened for append, so other appenders cannot land in the middle
+    let data: Vec<u8> = chunks.into_iter().flatten().copied().collect();
+    let result = (|| {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        write(&mut file, &data)?;
+        file.sync_all()
+    })();
+    result
+        .map(|_| data.len())
+        .map_err(|e| ToolError::io(format!("Failed to append to {}", path.display()), e))
+}
+
+/// Append a command's untruncated output to the trace log, when tracing is on
+fn trace_full_output(command: &str, output: &std::process::Output) {
+    use std::io::Write;
+    if !Path::new(crate::error::TRACE_LOG).exists() {
+        return;
+    }
+    let entry = format!(
+        "\nexecute_terminal full output - Command: {}\nTimestamp: {}\n--- stdout ---\n{}\n--- stderr ---\n{}\n",
+        command,
+        chrono::Utc::now(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    if let Ok(mut file) = fs::OpenOptions::new().append(true).open(crate::error::TRACE_LOG) {
+        let _ = file.write_all(entry.as_bytes());
+    }
+}
+
+/// Read a child's pipe to the end on its own thread
+pub(crate) fn drain_pipe<R: std::io::Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Render a minijinja template with JSON data. The environment has no loader, so
+/// templates can't include or import files, and nothing in it touches the network.
+fn render_template(template: &str, data: &Value) -> Result<String, String> {
+    let env = minijinja::Environment::new();
+    env.render_str(template, minijinja::Value::from_serialize(data))
+        .map_err(|e| e.to_string())
+}
+
+/// Cosine similarity of two equal-length, non-zero vectors
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> Result<f64, String> {
+    if a.len() != b.len() {
+        return Err(format!("Vectors differ in length: {} vs {}", a.len(), b.len()));
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Err("Cosine similarity is undefined for empty or zero vectors".to_string());
+    }
+    Ok(dot / (norm_a * norm_b))
+}
+
+/// Parse a JSON array of numbers, as passed to `cosine_similarity`
+fn number_array(value: &Value, name: &str) -> Result<Vec<f64>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("'{}' must be an array of numbers", name))?
+        .iter()
+        .map(|v| v.as_f64().ok_or_else(|| format!("'{}' must be an array of numbers", name)))
+        .collect()
+}
+
+/// Embed `text` through `fetch` (the HTTP call) and pull the vector out of the
+/// OpenAI-style `{ "data": [{ "embedding": [...] }] }` response
+async fn embed_text_with<F, Fut>(text: &str, fetch: F) -> Result<Vec<f64>, String>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: std::future::Future<Output = Result<Value, crate::error::NeonmachinesError>>,
+{
+    let response = fetch(text.to_string()).await.map_err(|e| e.to_string())?;
+    number_array(&response["data"][0]["embedding"], "embedding")
+}
+
+/// Run the future `make` builds to completion from synchronous tool code. On a
+/// multi-thread runtime the worker hands off its other tasks while it waits; anywhere
+/// else (`block_in_place` panics on a current-thread runtime) the future runs on a
+/// thread of its own with its own runtime.
+fn wait_for<F, Fut>(make: F) -> Result<Fut::Output, String>
+where
+    F: Fn() -> Fut + Sync,
+    Fut: std::future::Future,
+    Fut::Output: Send,
+{
+    use tokio::runtime::{Handle, RuntimeFlavor};
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            Ok(tokio::task::block_in_place(|| handle.block_on(make())))
+        }
+        _ => std::thread::scope(|scope| {
+            scope
+                .spawn(|| -> Result<Fut::Output, String> {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(|e| format!("Failed to start a runtime: {}", e))?;
+                    Ok(runtime.block_on(make()))
+                })
+                .join()
+                .map_err(|_| "The request thread panicked".to_string())?
+        }),
+    }
+}
+
+/// Whether a tool passes a workflow's allow/deny lists; the deny list wins
+pub fn tool_is_enabled(name: &str, allowed: Option<&[String]>, denied: &[String]) -> bool {
+    if denied.iter().any(|d| d == name) {
+        return false;
+    }
+    allowed.is_none_or(|list| list.iter().any(|a| a == name))
+}
+
 /// Built-in + extended tools
 pub fn builtin_tools_with_history(
     _shared_history: SharedHistory,
+    memory: SharedMemory,
+    embedding: EmbeddingSettings,
     tx: UnboundedSender<AppEvent>,
     working_dir: String,
+    sandbox: bool,
+    terminal_output_limit: usize,
 ) -> Vec<(Tool, Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>)> {
     let mut tools: Vec<(Tool, Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>)> = Vec::new();
 
@@ -86,11 +577,11 @@ pub fn builtin_tools_with_history(
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
                 let path = args["path"].as_str().unwrap_or(".");
-                let full_path = resolve_path(&wd, path);
+                let full_path = checked_path(&wd, path, sandbox)?;
                 let entries = fs::read_dir(&full_path)
-                    .map_err(|e| e.to_string())?
-                    .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()).map_err(|e| e.to_string()))
-                    .collect::<Result<Vec<_>, _>>()?;
+                    .map_err(ToolError::from)?
+                    .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()).map_err(ToolError::from))
+                    .collect::<Result<Vec<_>, ToolError>>()?;
                 let result = json!({ "entries": entries });
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][ls] result = {}", result)));
                 Ok(result)
@@ -118,12 +609,12 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
-                let full_path = resolve_path(&wd, path);
+                let path = required_str(&args, "path")?;
+                let full_path = checked_path(&wd, path, sandbox)?;
                 let result = if full_path.exists() {
                     json!({ "status": "exists", "path": full_path })
                 } else {
-                    fs::create_dir_all(&full_path).map_err(|e| e.to_string())?;
+                    fs::create_dir_all(&full_path).map_err(ToolError::from)?;
                     json!({ "status": "ok", "path": full_path })
                 };
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][mkdir] result = {}", result)));
@@ -152,10 +643,10 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
-                let full_path = resolve_path(&wd, path);
+                let path = required_str(&args, "path")?;
+                let full_path = checked_path(&wd, path, sandbox)?;
                 fs::OpenOptions::new().create(true).write(true).open(&full_path)
-                    .map_err(|e| e.to_string())?;
+                    .map_err(ToolError::from)?;
                 let result = json!({ "status": "ok", "path": full_path });
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][touch] result = {}", result)));
                 Ok(result)
@@ -183,9 +674,9 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
-                let full_path = resolve_path(&wd, path);
-                fs::remove_file(&full_path).map_err(|e| e.to_string())?;
+                let path = required_str(&args, "path")?;
+                let full_path = checked_path(&wd, path, sandbox)?;
+                fs::remove_file(&full_path).map_err(ToolError::from)?;
                 let result = json!({ "status": "ok", "path": full_path });
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][delete_file] result = {}", result)));
                 Ok(result)
@@ -200,6 +691,7 @@ pub fn builtin_tools_with_history(
     // write_file
     {
         let tx_clone = tx.clone();
+        let wd = working_dir.clone();
         let mut props = HashMap::new();
         props.insert("path".into(), prop("string", "File path to write"));
         props.insert("content".into(), prop("string", "Content to write"));
@@ -218,33 +710,13 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
-                let content = args["content"].as_str().ok_or("Missing content")?;
+                let path = required_str(&args, "path")?;
+                let content = required_str(&args, "content")?;
                 let append = args["append"].as_bool().unwrap_or(false);
+                let full_path = given_path(&wd, path, sandbox)?;
 
-                let mut file = if append {
-                    std::fs::OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(path)
-                        .map_err(|e| e.to_string())?
-                } else {
-                    std::fs::OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(path)
-                        .map_err(|e| e.to_string())?
-                };
-
-                use std::io::Write;
-                let mut total_bytes = 0;
-                let mut chunks = 0;
-                for chunk in content.as_bytes().chunks(8192) {
-                    file.write_all(chunk).map_err(|e| e.to_string())?;
-                    total_bytes += chunk.len();
-                    chunks += 1;
-                }
+                let chunks = content.as_bytes().chunks(8192).count();
+                let total_bytes = write_chunks(&full_path, content.as_bytes().chunks(8192), append)?;
 
                 let result = json!({
                     "status": "ok",
@@ -261,6 +733,7 @@ pub fn builtin_tools_with_history(
     // write_file_parts
     {
         let tx_clone = tx.clone();
+        let wd = working_dir.clone();
         let mut props = HashMap::new();
         props.insert("path".into(), prop("string", "File path to write"));
         props.insert("parts".into(), prop("array", "Array of content parts to write sequentially"));
@@ -278,25 +751,20 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
-                let parts = args["parts"].as_array().ok_or("Missing parts")?;
-                let mut file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(path)
-                    .map_err(|e| e.to_string())?;
-                use std::io::Write;
-                for (i, part) in parts.iter().enumerate() {
-                    if let Some(s) = part.as_str() {
-                        file.write_all(s.as_bytes()).map_err(|e| e.to_string())?;
-                        let _ = tx_clone.send(AppEvent::Log(format!(
-                            "[TOOL][write_file_parts] wrote part {} ({} bytes) to {}",
-                            i + 1,
-                            s.len(),
-                            path
-                        )));
-                    }
+                let path = required_str(&args, "path")?;
+                let parts = args["parts"]
+                    .as_array()
+                    .ok_or_else(|| ToolError::InvalidArgument("Missing 'parts' parameter".to_string()))?;
+                let full_path = given_path(&wd, path, sandbox)?;
+                let texts: Vec<(usize, &str)> = parts.iter().enumerate().filter_map(|(i, p)| Some((i, p.as_str()?))).collect();
+                write_chunks(&full_path, texts.iter().map(|(_, s)| s.as_bytes()), false)?;
+                for (i, s) in &texts {
+                    let _ = tx_clone.send(AppEvent::Log(format!(
+                        "[TOOL][write_file_parts] wrote part {} ({} bytes) to {}",
+                        i + 1,
+                        s.len(),
+                        path
+                    )));
                 }
                 let result = json!({ "status": "ok", "path": path, "parts": parts.len() });
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][write_file_parts] result = {}", result)));
@@ -312,6 +780,7 @@ pub fn builtin_tools_with_history(
     // read_file_content
     {
         let tx_clone = tx.clone();
+        let wd = working_dir.clone();
         let mut props = HashMap::new();
         props.insert("path".into(), prop("string", "File path to read"));
         props.insert("start_line".into(), prop("integer", "Optional start line (0-based)"));
@@ -335,14 +804,36 @@ pub fn builtin_tools_with_history(
 
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let path = args["path"].as_str().ok_or("Missing path")?;
+                let path = required_str(&args, "path")?;
                 let start_line = args["start_line"].as_i64().unwrap_or(0).max(0) as usize;
                 let end_line = args["end_line"].as_i64().unwrap_or(-1);
                 let radius = args["radius"].as_i64().unwrap_or(0).max(0) as usize;
                 let line = args["line"].as_i64().unwrap_or(-1);
                 let max_bytes = args["max_bytes"].as_i64().unwrap_or(8192).max(1) as usize;
 
-                let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+                let bytes = fs::read(given_path(&wd, path, sandbox)?).map_err(ToolError::from)?;
+                let content = match String::from_utf8(bytes) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        // Line ranges mean nothing for binary data; return the leading bytes
+                        let bytes = e.into_bytes();
+                        let shown = &bytes[..bytes.len().min(max_bytes)];
+                        let (content, encoding) = encode_output(shown);
+                        let result = json!({
+                            "path": path,
+                            "encoding": encoding,
+                            "total_bytes": bytes.len(),
+                            "truncated": shown.len() < bytes.len(),
+                            "content": content
+                        });
+                        let _ = tx_clone.send(AppEvent::Log(format!(
+                            "[TOOL][read_file_content] result = {} base64 bytes of {}",
+                            shown.len(),
+                            path
+                        )));
+                        return Ok(result);
+                    }
+                };
                 let lines: Vec<&str> = content.lines().collect();
                 let total_lines = lines.len();
 
@@ -373,6 +864,7 @@ pub fn builtin_tools_with_history(
                     "lines": selected.len(),
                     "start": start,
                     "end": end,
+                    "encoding": "utf-8",
                     "content": result_str
                 });
                 let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][read_file_content] result = {}", result)));
@@ -382,6 +874,139 @@ pub fn builtin_tools_with_history(
         tools.push((tool, func));
     }
 
+    // head / tail
+    for (name, desc) in [
+        ("head", "Return the first N lines of a file (default 20)"),
+        ("tail", "Return the last N lines of a file (default 20)"),
+    ] {
+        let tx_clone = tx.clone();
+        let wd = working_dir.clone();
+        let mut props = HashMap::new();
+        props.insert("path".into(), prop("string", "File path to read"));
+        props.insert("lines".into(), prop("integer", "Number of lines to return (default 20)"));
+
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: name.into(),
+                description: desc.into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["path".into()],
+                },
+            },
+        };
+
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let path = required_str(&args, "path")?;
+                let n = args["lines"].as_i64().unwrap_or(20).max(0) as usize;
+                let full_path = checked_path(&wd, path, sandbox)?;
+                let lines = if name == "head" {
+                    head_lines(&full_path, n)
+                } else {
+                    tail_lines(&full_path, n)
+                }
+                .map_err(ToolError::from)?;
+                let (content, encoding) = encode_output(&lines.join(&b'\n'));
+
+                let result = json!({
+                    "path": full_path.display().to_string(),
+                    "lines": lines.len(),
+                    "encoding": encoding,
+                    "content": content
+                });
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][{}] result = {}", name, result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // watch_file
+    {
+        let tx_clone = tx.clone();
+        let wd = working_dir.clone();
+        let mut props = HashMap::new();
+        props.insert("path".into(), prop("string", "File to watch, e.g. a log written by a command started with execute_terminal"));
+        props.insert("duration_secs".into(), prop("integer", &format!("How long to watch (default 5, at most {})", WATCH_FILE_MAX_SECS)));
+        props.insert("max_bytes".into(), prop("integer", &format!("Maximum bytes to return (default {})", WATCH_FILE_OUTPUT_LIMIT)));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "watch_file".into(),
+                description: "Like tail -f: wait for duration_secs and return the lines appended to a file meanwhile".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["path".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let path = required_str(&args, "path")?;
+                let secs = args["duration_secs"].as_u64().unwrap_or(5).min(WATCH_FILE_MAX_SECS);
+                let max_bytes = args["max_bytes"].as_u64().map_or(WATCH_FILE_OUTPUT_LIMIT, |n| n.max(1) as usize);
+                let full_path = checked_path(&wd, path, sandbox)?;
+                let (bytes, truncated) = watch_appended(&full_path, std::time::Duration::from_secs(secs), max_bytes)
+                    .map_err(|e| ToolError::io(full_path.display(), e))?;
+                let lines: Vec<&[u8]> = if bytes.is_empty() {
+                    Vec::new()
+                } else {
+                    bytes.strip_suffix(b"\n").unwrap_or(&bytes).split(|b| *b == b'\n').map(strip_line_ending).collect()
+                };
+                let (content, encoding) = encode_output(&lines.join(&b'\n'));
+
+                let result = json!({
+                    "path": full_path.display().to_string(),
+                    "duration_secs": secs,
+                    "lines": lines.len(),
+                    "bytes": bytes.len(),
+                    "truncated": truncated,
+                    "encoding": encoding,
+                    "content": content
+                });
+                let _ = tx_clone.send(AppEvent::Log(format!(
+                    "[TOOL][watch_file] result = {} lines appended to {} in {}s",
+                    lines.len(),
+                    path,
+                    secs
+                )));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // file_stats
+    {
+        let tx_clone = tx.clone();
+        let wd = working_dir.clone();
+        let mut props = HashMap::new();
+        props.insert("path".into(), prop("string", "File to inspect"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "file_stats".into(),
+                description: "Count lines, words, bytes and chars of a file and report its last-modified time and whether it is binary (non-UTF8)".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["path".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let path = required_str(&args, "path")?;
+                let full_path = checked_path(&wd, path, sandbox)?;
+                let result = file_stats(&full_path).map_err(ToolError::from)?;
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][file_stats] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
     // -------------------------
     // String Manipulation Tools
     // -------------------------
@@ -452,6 +1077,201 @@ pub fn builtin_tools_with_history(
         tools.push((tool, func));
     }
 
+    // render_template
+    {
+        let tx_clone = tx.clone();
+        let mut props = HashMap::new();
+        props.insert("template".into(), prop("string", "Jinja-style template, e.g. 'Hello {{ name }}' or '{% for x in items %}{{ x }}{% endfor %}'"));
+        props.insert("data".into(), prop("object", "Values available to the template"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "render_template".into(),
+                description: "Render a template with JSON data (variables, loops, conditionals); no file or network access".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["template".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let template = args["template"].as_str().ok_or("Missing template")?;
+                let data = args.get("data").cloned().unwrap_or_else(|| json!({}));
+                let rendered = render_template(template, &data)?;
+                let result = json!({ "result": rendered });
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][render_template] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // -------------------------
+    // Similarity Tools
+    // -------------------------
+
+    // embed_text
+    {
+        let tx_clone = tx.clone();
+        let settings = embedding.clone();
+        let mut props = HashMap::new();
+        props.insert("text".into(), prop("string", "Text to embed"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "embed_text".into(),
+                description: "Get an embedding vector for a piece of text from the provider's embeddings endpoint".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["text".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let text = args["text"].as_str().ok_or("Missing text")?;
+                let api_key = crate::secrets::resolve_api_key(crate::secrets::DEFAULT_PROVIDER, settings.use_keyring);
+                let model = settings.model.clone();
+                let embedding = wait_for(|| {
+                    embed_text_with(text, |input| {
+                        crate::error::embed_with_retry(EMBEDDINGS_URL.to_string(), api_key.clone(), model.clone(), input, None)
+                    })
+                })??;
+                let result = json!({ "model": model, "dimensions": embedding.len(), "embedding": embedding });
+                let _ = tx_clone.send(AppEvent::Log(format!(
+                    "[TOOL][embed_text] result = {}",
+                    json!({ "model": settings.model, "dimensions": embedding.len() })
+                )));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // cosine_similarity
+    {
+        let tx_clone = tx.clone();
+        let mut props = HashMap::new();
+        props.insert("a".into(), prop("array", "First vector"));
+        props.insert("b".into(), prop("array", "Second vector, same length as a"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "cosine_similarity".into(),
+                description: "Cosine similarity of two number arrays, from -1 (opposite) to 1 (identical direction)".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["a".into(), "b".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let a = number_array(&args["a"], "a")?;
+                let b = number_array(&args["b"], "b")?;
+                let result = json!({ "similarity": cosine_similarity(&a, &b)? });
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][cosine_similarity] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // -------------------------
+    // Memory Tools
+    // -------------------------
+
+    // memory_set
+    {
+        let tx_clone = tx.clone();
+        let mem = memory.clone();
+        let mut props = HashMap::new();
+        props.insert("key".into(), prop("string", "Key to store the value under"));
+        props.insert("value".into(), prop("string", "Value to store"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "memory_set".into(),
+                description: "Store a value in the workflow's shared memory so later agents can read it".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["key".into(), "value".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let key = args["key"].as_str().ok_or("Missing key")?;
+                let value = args.get("value").cloned().ok_or("Missing value")?;
+                mem.lock().map_err(|e| e.to_string())?.insert(key.to_string(), value);
+                let result = json!({ "status": "ok", "key": key });
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][memory_set] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // memory_get
+    {
+        let tx_clone = tx.clone();
+        let mem = memory.clone();
+        let mut props = HashMap::new();
+        props.insert("key".into(), prop("string", "Key to look up"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "memory_get".into(),
+                description: "Read a value from the workflow's shared memory".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["key".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let key = args["key"].as_str().ok_or("Missing key")?;
+                let value = mem.lock().map_err(|e| e.to_string())?.get(key).cloned();
+                let result = match value {
+                    Some(value) => json!({ "found": true, "key": key, "value": value }),
+                    None => json!({ "found": false, "key": key }),
+                };
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][memory_get] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // memory_list
+    {
+        let tx_clone = tx.clone();
+        let mem = memory.clone();
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "memory_list".into(),
+                description: "List the keys stored in the workflow's shared memory".into(),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: HashMap::new(),
+                    required: vec![],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |_args| {
+                let mut keys: Vec<String> = mem.lock().map_err(|e| e.to_string())?.keys().cloned().collect();
+                keys.sort();
+                let result = json!({ "keys": keys });
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][memory_list] result = {}", result)));
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
     // -------------------------
     // Terminal/Command Execution Tool
     // -------------------------
@@ -464,6 +1284,7 @@ pub fn builtin_tools_with_history(
         props.insert("command".into(), prop("string", "The terminal/bash command to execute. Example: 'ls -la', 'cat file.txt', 'mkdir new_dir'"));
         props.insert("working_directory".into(), prop("string", "Optional working directory where the command should be executed. If not provided, uses current directory"));
         props.insert("timeout_seconds".into(), prop("integer", "Optional timeout in seconds. Default is 30 seconds"));
+        props.insert("max_output_bytes".into(), prop("integer", &format!("Optional lower cap on the bytes returned for each of stdout and stderr. At most {}", terminal_output_limit)));
         let tool = Tool {
             tool_type: "function".into(),
             function: Function {
@@ -478,11 +1299,22 @@ pub fn builtin_tools_with_history(
         };
         let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
             Box::new(move |args| {
-                let command = args["command"].as_str().ok_or("Missing 'command' parameter")?;
-                let working_dir = args["working_directory"].as_str()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| wd.clone());
+                let command = required_str(&args, "command")?;
+                // A shell command can reach any path, so the sandbox can't confine it
+                if sandbox {
+                    return Err(ToolError::PermissionDenied(
+                        "execute_terminal is disabled while the sandbox is on".to_string(),
+                    )
+                    .into());
+                }
+                let working_dir = match args["working_directory"].as_str() {
+                    Some(dir) => checked_path(&wd, dir, sandbox)?.display().to_string(),
+                    None => wd.clone(),
+                };
                 let timeout = args["timeout_seconds"].as_u64().unwrap_or(30);
+                let limit = args["max_output_bytes"]
+                    .as_u64()
+                    .map_or(terminal_output_limit, |n| (n as usize).min(terminal_output_limit));
 
                 let mut cmd = Command::new("sh");
                 cmd.arg("-c").arg(command);
@@ -493,26 +1325,65 @@ pub fn builtin_tools_with_history(
                 cmd.stderr(Stdio::piped());
 
                 // Spawn the process
-                let child = cmd.spawn()
-                    .map_err(|e| format!("Failed to start command: {}", e))?;
+                let mut child = cmd.spawn()
+                    .map_err(|e| ToolError::io("Failed to start command", e))?;
 
-                // Wait for the process to complete with timeout
-                let result = std::thread::spawn(move || {
-                    child.wait_with_output()
-                }).join()
-                .map_err(|_| "Failed to wait for command execution".to_string())?;
+                // Drain both pipes while waiting so a chatty command can't block on a full pipe
+                let stdout = drain_pipe(child.stdout.take());
+                let stderr = drain_pipe(child.stderr.take());
+                let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout);
+                let status = loop {
+                    match child.try_wait().map_err(|e| ToolError::io("Failed to wait for command", e))? {
+                        Some(status) => break status,
+                        None if std::time::Instant::now() >= deadline => {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            let err = ToolError::Timeout(format!("Command timed out after {}s: {}", timeout, command));
+                            let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][execute_terminal] error = {}", err)));
+                            return Err(err.into());
+                        }
+                        None => std::thread::sleep(std::time::Duration::from_millis(20)),
+                    }
+                };
+                let result = match (stdout.join(), stderr.join()) {
+                    (Ok(stdout), Ok(stderr)) => Ok(std::process::Output { status, stdout, stderr }),
+                    _ => Err(ToolError::Io("Failed to read command output".to_string())),
+                };
 
                 match result {
                     Ok(output) => {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        let stderr = String::from_utf8_lossy(&output.stderr);
+                        // Both streams share one encoding so agents decode them the same way
+                        let (stdout, stderr, encoding) = match (
+                            std::str::from_utf8(&output.stdout),
+                            std::str::from_utf8(&output.stderr),
+                        ) {
+                            (Ok(out), Ok(err)) => (
+                                crate::agents::truncate_output(out, Some(limit)),
+                                crate::agents::truncate_output(err, Some(limit)),
+                                "utf-8",
+                            ),
+                            // A marker would corrupt the base64, so `truncated` and the byte counts tell instead
+                            _ => (
+                                base64_encode(&output.stdout[..output.stdout.len().min(limit)]),
+                                base64_encode(&output.stderr[..output.stderr.len().min(limit)]),
+                                "base64",
+                            ),
+                        };
+                        let truncated = output.stdout.len() > limit || output.stderr.len() > limit;
+                        if truncated {
+                            trace_full_output(command, &output);
+                        }
                         let exit_code = output.status.code().unwrap_or(-1);
 
                         let result = json!({
                             "success": output.status.success(),
                             "exit_code": exit_code,
-                            "stdout": stdout.to_string(),
-                            "stderr": stderr.to_string(),
+                            "encoding": encoding,
+                            "stdout": stdout,
+                            "stderr": stderr,
+                            "stdout_bytes": output.stdout.len(),
+                            "stderr_bytes": output.stderr.len(),
+                            "truncated": truncated,
                             "command": command,
                             "working_directory": working_dir,
                             "timeout_used": timeout
@@ -521,9 +1392,8 @@ pub fn builtin_tools_with_history(
                         Ok(result)
                     }
                     Err(e) => {
-                        let error_msg = format!("Command execution failed: {}", e);
-                        let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][execute_terminal] error = {}", error_msg)));
-                        Err(error_msg)
+                        let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][execute_terminal] error = {}", e)));
+                        Err(e.into())
                     }
                 }
             });
@@ -531,4 +1401,556 @@ pub fn builtin_tools_with_history(
     }
 
     tools
-}
\ No newline at end of file
+}
+
+/// The definition of every built-in tool, for listing them. They're built against
+/// throwaway state and never called.
+pub fn builtin_tool_specs() -> Vec<Tool> {
+    let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+    builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, ".".into(), false, TERMINAL_OUTPUT_LIMIT)
+        .into_iter()
+        .map(|(tool, _)| tool)
+        .collect()
+}
+
+/// Each tool's name and description, then its parameters as `name (type, required): description`
+pub fn describe_tools(tools: &[Tool]) -> String {
+    let mut out = String::new();
+    for tool in tools {
+        let function = &tool.function;
+        out.push_str(&format!("{} - {}\n", function.name, function.description));
+        let mut params: Vec<(&String, &Property)> = function.parameters.properties.iter().collect();
+        params.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, property) in params {
+            let required = if function.parameters.required.contains(name) { ", required" } else { "" };
+            out.push_str(&format!(
+                "    {} ({}{}): {}\n",
+                name,
+                property.prop_type,
+                required,
+                property.description.as_deref().unwrap_or("")
+            ));
+        }
+    }
+    out
+}
+
+/// Ways `result` breaks a tool's declared `output_schema`, empty when it conforms.
+/// A missing (null) schema accepts anything.
+pub fn output_schema_violations(schema: &Value, result: &Value) -> Vec<String> {
+    if schema.is_null() {
+        return Vec::new();
+    }
+    match jsonschema::validator_for(schema) {
+        Ok(validator) => validator
+            .iter_errors(result)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() { e.to_string() } else { format!("{}: {}", path, e) }
+            })
+            .collect(),
+        Err(e) => vec![format!("output_schema is not a valid JSON Schema: {}", e)],
+    }
+}
+
+/// Warn on the event stream when an extension tool's result doesn't match its schema
+fn check_tool_output(name: &str, schema: &Value, result: &Value, tx: &UnboundedSender<AppEvent>) {
+    let violations = output_schema_violations(schema, result);
+    if !violations.is_empty() {
+        let _ = tx.send(AppEvent::Warning(format!(
+            "Tool '{}' returned output that doesn't match its output_schema: {}",
+            name,
+            violations.join("; ")
+        )));
+    }
+}
+
+/// Name an extension tool is registered under; tool names may only hold
+/// letters, digits, `_` and `-`
+pub fn mcp_tool_name(extension: &str, tool: &str) -> String {
+    format!("mcp_{}_{}", extension, tool)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Tools backed by installed MCP extensions: the generic `mcp_call`, plus one tool
+/// per extension tool using the parameters from its `input_schema`. With
+/// `validate_output`, results are checked against each tool's `output_schema`.
+pub fn mcp_tools(
+    registry: &crate::nmmcp::NMMCPExtensionRegistry,
+    tx: UnboundedSender<AppEvent>,
+    validate_output: bool,
+) -> Vec<(Tool, Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>)> {
+    let mut tools: Vec<(Tool, Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>)> = Vec::new();
+    let extensions = Arc::new(registry.get_extensions().clone());
+    if extensions.is_empty() {
+        return tools;
+    }
+
+    // mcp_call
+    {
+        let tx_clone = tx.clone();
+        let exts = extensions.clone();
+        let mut available: Vec<String> = registry.list_all_tools().into_iter().map(|(name, _)| name).collect();
+        available.sort();
+        let mut props = HashMap::new();
+        props.insert("extension".into(), prop("string", "Extension name"));
+        props.insert("tool".into(), prop("string", "Tool name within the extension"));
+        props.insert("args".into(), prop("object", "Arguments passed to the tool as JSON"));
+        let tool = Tool {
+            tool_type: "function".into(),
+            function: Function {
+                name: "mcp_call".into(),
+                description: format!("Call a tool from an installed MCP extension. Available: {}", available.join(", ")),
+                parameters: Parameters {
+                    param_type: "object".into(),
+                    properties: props,
+                    required: vec!["extension".into(), "tool".into()],
+                },
+            },
+        };
+        let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+            Box::new(move |args| {
+                let ext_name = args["extension"].as_str().ok_or("Missing extension")?;
+                let tool_name = args["tool"].as_str().ok_or("Missing tool")?;
+                let extension = exts.get(ext_name).ok_or_else(|| format!("Extension not found: {}", ext_name))?;
+                let input = if args["args"].is_null() { json!({}) } else { args["args"].clone() };
+                let result = crate::nmmcp::call_extension_tool(extension, tool_name, &input, crate::nmmcp::EXTENSION_TOOL_TIMEOUT).map_err(|e| e.to_string())?;
+                let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][mcp_call] {}:{} result = {}", ext_name, tool_name, result)));
+                if let Some(ext_tool) = extension.tools.iter().find(|t| t.name == tool_name).filter(|_| validate_output) {
+                    check_tool_output(&format!("{}:{}", ext_name, tool_name), &ext_tool.output_schema, &result, &tx_clone);
+                }
+                Ok(result)
+            });
+        tools.push((tool, func));
+    }
+
+    // One tool per extension tool
+    for extension in extensions.values() {
+        for ext_tool in &extension.tools {
+            let tx_clone = tx.clone();
+            let ext = extension.clone();
+            let tool_name = ext_tool.name.clone();
+            let output_schema = validate_output.then(|| ext_tool.output_schema.clone());
+            let name = mcp_tool_name(&extension.name, &ext_tool.name);
+            let mut props = HashMap::new();
+            if let Some(schema_props) = ext_tool.input_schema["properties"].as_object() {
+                for (key, schema) in schema_props {
+                    let typ = schema["type"].as_str().unwrap_or("string");
+                    let desc = schema["description"].as_str().unwrap_or("");
+                    props.insert(key.clone(), prop(typ, desc));
+                }
+            }
+            let tool = Tool {
+                tool_type: "function".into(),
+                function: Function {
+                    name: name.clone(),
+                    description: ext_tool.description.clone(),
+                    parameters: Parameters {
+                        param_type: "object".into(),
+                        properties: props,
+                        required: ext_tool.parameters.required.clone(),
+                    },
+                },
+            };
+            let func: Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync> =
+                Box::new(move |args| {
+                    let result = crate::nmmcp::call_extension_tool(&ext, &tool_name, &args, crate::nmmcp::EXTENSION_TOOL_TIMEOUT).map_err(|e| e.to_string())?;
+                    let _ = tx_clone.send(AppEvent::Log(format!("[TOOL][{}] result = {}", name, result)));
+                    if let Some(schema) = &output_schema {
+                        check_tool_output(&name, schema, &result, &tx_clone);
+                    }
+                    Ok(result)
+                });
+            tools.push((tool, func));
+        }
+    }
+
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_tool_is_not_registered() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let denied = vec!["execute_terminal".to_string()];
+        let names: Vec<String> = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, ".".into(), false, TERMINAL_OUTPUT_LIMIT)
+            .into_iter()
+            .filter(|(tool, _)| tool_is_enabled(&tool.function.name, None, &denied))
+            .map(|(tool, _)| tool.function.name)
+            .collect();
+        assert!(!names.iter().any(|n| n == "execute_terminal"));
+        assert!(names.iter().any(|n| n == "ls"));
+
+        // The deny list wins over the allow list
+        let allowed = vec!["ls".to_string(), "execute_terminal".to_string()];
+        assert!(tool_is_enabled("ls", Some(&allowed), &denied));
+        assert!(!tool_is_enabled("execute_terminal", Some(&allowed), &denied));
+        assert!(!tool_is_enabled("pwd", Some(&allowed), &denied));
+    }
+
+    #[test]
+    fn test_tool_list_names_every_builtin() {
+        let specs = builtin_tool_specs();
+        let names: Vec<&str> = specs.iter().map(|t| t.function.name.as_str()).collect();
+        for expected in [
+            "pwd", "ls", "mkdir", "touch", "delete_file", "write_file", "write_file_parts",
+            "read_file_content", "head", "tail", "watch_file", "file_stats", "to_upper", "to_lower",
+            "trim", "reverse", "yes_no_paragraphs", "render_template", "embed_text",
+            "cosine_similarity", "memory_set", "memory_get", "memory_list", "execute_terminal",
+        ] {
+            assert!(names.contains(&expected), "{} is not listed", expected);
+        }
+
+        let listing = describe_tools(&specs);
+        assert!(listing.contains("pwd - Print current working directory\n"));
+        assert!(listing.contains("    text (string, required): Input text\n"));
+    }
+
+    #[test]
+    fn test_head_and_tail_lines() {
+        let path = std::env::temp_dir().join(format!("nm_tools_2361_{}.txt", std::process::id()));
+        let content: String = (1..=30000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, content).unwrap();
+
+        let head = head_lines(&path, 3).unwrap();
+        let tail = tail_lines(&path, 3).unwrap();
+        let all = tail_lines(&path, 50000).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(head, [b"line 1".as_slice(), b"line 2", b"line 3"]);
+        assert_eq!(tail, [b"line 29998".as_slice(), b"line 29999", b"line 30000"]);
+        assert_eq!(all.len(), 30000);
+        assert_eq!(all[0], b"line 1");
+    }
+
+    #[test]
+    fn test_watch_file_returns_lines_appended_during_window() {
+        use std::io::Write;
+        let dir = std::env::temp_dir().join(format!("nm_watch_file_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("build.log"), "already there\n").unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, dir.display().to_string(), false, TERMINAL_OUTPUT_LIMIT);
+        let (_, watch) = tools.iter().find(|(tool, _)| tool.function.name == "watch_file").unwrap();
+
+        let log = dir.join("build.log");
+        let writer = std::thread::spawn(move || {
+            for line in ["Compiling neonmachines", "Finished dev profile"] {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                let mut f = fs::OpenOptions::new().append(true).open(&log).unwrap();
+                writeln!(f, "{}", line).unwrap();
+            }
+        });
+        let result = watch(json!({ "path": "build.log", "duration_secs": 1 })).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(result["lines"], 2);
+        assert_eq!(result["content"], "Compiling neonmachines\nFinished dev profile");
+        assert_eq!(result["truncated"], false);
+
+        // Appends past max_bytes end the watch early and are cut off
+        let log = dir.join("build.log");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            fs::OpenOptions::new().append(true).open(&log).unwrap().write_all(&[b'x'; 100]).unwrap();
+        });
+        let (bytes, truncated) = watch_appended(&dir.join("build.log"), std::time::Duration::from_secs(5), 10).unwrap();
+        writer.join().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(bytes, vec![b'x'; 10]);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_binary_command_output_is_base64() {
+        use base64::Engine;
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, ".".into(), false, TERMINAL_OUTPUT_LIMIT);
+        let (_, execute) = tools.iter().find(|(tool, _)| tool.function.name == "execute_terminal").unwrap();
+
+        let binary = execute(json!({ "command": "printf '\\377\\376ok'" })).unwrap();
+        assert_eq!(binary["encoding"], "base64");
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(binary["stdout"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(decoded, b"\xff\xfeok");
+
+        let text = execute(json!({ "command": "printf 'plain'" })).unwrap();
+        assert_eq!(text["encoding"], "utf-8");
+        assert_eq!(text["stdout"], "plain");
+    }
+
+    #[test]
+    fn test_terminal_output_is_capped() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, ".".into(), false, TERMINAL_OUTPUT_LIMIT);
+        let (_, execute) = tools.iter().find(|(tool, _)| tool.function.name == "execute_terminal").unwrap();
+
+        let noisy = execute(json!({ "command": "head -c 40000 /dev/zero | tr '\\0' a" })).unwrap();
+        assert_eq!(noisy["truncated"], true);
+        assert_eq!(noisy["stdout_bytes"], 40000);
+        let stdout = noisy["stdout"].as_str().unwrap();
+        assert!(stdout.starts_with(&"a".repeat(TERMINAL_OUTPUT_LIMIT)));
+        assert!(stdout.ends_with(&format!("[truncated {} bytes]", 40000 - TERMINAL_OUTPUT_LIMIT)));
+
+        let capped = execute(json!({ "command": "printf 'abcdef'", "max_output_bytes": 3 })).unwrap();
+        assert_eq!(capped["stdout"], "abc\n[truncated 3 bytes]");
+
+        // A call can lower the workflow's cap but never raise it
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, ".".into(), false, 4);
+        let (_, strict) = tools.iter().find(|(tool, _)| tool.function.name == "execute_terminal").unwrap();
+        let raised = strict(json!({ "command": "printf 'abcdef'", "max_output_bytes": 1000 })).unwrap();
+        assert_eq!(raised["stdout"], "abcd\n[truncated 2 bytes]");
+
+        let quiet = execute(json!({ "command": "printf 'short'" })).unwrap();
+        assert_eq!(quiet["truncated"], false);
+        assert_eq!(quiet["stdout"], "short");
+    }
+
+    #[test]
+    fn test_sandbox_rejects_paths_outside_working_dir() {
+        let dir = std::env::temp_dir().join(format!("nm_sandbox_{}", std::process::id()));
+        fs::create_dir_all(dir.join("inner")).unwrap();
+        fs::write(dir.join("inner/notes.txt"), "inside").unwrap();
+        let wd = dir.display().to_string();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, wd.clone(), true, TERMINAL_OUTPUT_LIMIT);
+        let tool = |name: &str| &tools.iter().find(|(tool, _)| tool.function.name == name).unwrap().1;
+
+        // Traversal and absolute paths are refused by reading and writing tools alike
+        for path in ["../../etc/passwd", "inner/../../outside.txt", "/etc/passwd"] {
+            let err = tool("read_file_content")(json!({ "path": path })).unwrap_err();
+            assert!(err.contains("outside the working directory"), "{}: {}", path, err);
+            let err = tool("write_file")(json!({ "path": path, "content": "x" })).unwrap_err();
+            assert!(err.contains("outside the working directory"), "{}: {}", path, err);
+        }
+        assert!(!dir.parent().unwrap().join("outside.txt").exists());
+
+        // Paths that stay inside work, including ones that don't exist yet
+        let read = tool("read_file_content")(json!({ "path": "inner/../inner/notes.txt" })).unwrap();
+        assert!(read["content"].as_str().unwrap().contains("inside"));
+        tool("write_file")(json!({ "path": "inner/new.txt", "content": "ok" })).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("inner/new.txt")).unwrap(), "ok");
+        tool("mkdir")(json!({ "path": "made/deeper" })).unwrap();
+        assert!(dir.join("made/deeper").is_dir());
+
+        // Symlinks can't be used to step outside either
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc_link")).unwrap();
+            let err = tool("read_file_content")(json!({ "path": "etc_link/passwd" })).unwrap_err();
+            assert!(err.contains("outside the working directory"));
+        }
+
+        // Shell commands can't be confined, so they are refused outright
+        let err = tool("execute_terminal")(json!({ "command": "cat /etc/passwd" })).unwrap_err();
+        assert!(err.contains("disabled while the sandbox is on"), "{}", err);
+
+        // Without the sandbox, absolute paths are allowed and write/read paths are used as given
+        assert_eq!(checked_path(&wd, "/tmp", false).unwrap(), PathBuf::from("/tmp"));
+        assert_eq!(given_path(&wd, "notes.txt", false).unwrap(), PathBuf::from("notes.txt"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_tool_errors_report_their_kind() {
+        let dir = std::env::temp_dir().join(format!("nm_tool_error_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let tools = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, dir.display().to_string(), true, TERMINAL_OUTPUT_LIMIT);
+        let error = |name: &str, args: Value| {
+            let (_, func) = tools.iter().find(|(t, _)| t.function.name == name).unwrap();
+            serde_json::from_str::<Value>(&func(args).unwrap_err()).unwrap()
+        };
+
+        let missing = error("read_file_content", json!({ "path": "missing.txt" }));
+        assert_eq!(missing["error_kind"], "not_found");
+        assert!(missing["error"].as_str().unwrap().contains("No such file"));
+        assert_eq!(error("delete_file", json!({ "path": "missing.txt" }))["error_kind"], "not_found");
+
+        let outside = error("write_file", json!({ "path": "/etc/passwd", "content": "x" }));
+        assert_eq!(outside["error_kind"], "permission_denied");
+        assert!(outside["error"].as_str().unwrap().contains("outside the working directory"));
+
+        assert_eq!(error("head", json!({}))["error_kind"], "invalid_argument");
+
+        // execute_terminal is refused under the sandbox, so time it out without one
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let open = builtin_tools_with_history(SharedHistory::new(), SharedMemory::default(), EmbeddingSettings::default(), tx, dir.display().to_string(), false, TERMINAL_OUTPUT_LIMIT);
+        let (_, terminal) = open.iter().find(|(t, _)| t.function.name == "execute_terminal").unwrap();
+        let timeout: Value = serde_json::from_str(&terminal(json!({ "command": "sleep 5", "timeout_seconds": 0 })).unwrap_err()).unwrap();
+        assert_eq!(timeout["error_kind"], "timeout");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_memory_set_get_list() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let memory = SharedMemory::default();
+        let tools = builtin_tools_with_history(SharedHistory::new(), memory.clone(), EmbeddingSettings::default(), tx, ".".into(), false, TERMINAL_OUTPUT_LIMIT);
+        let call = |name: &str, args: Value| {
+            let (_, func) = tools.iter().find(|(t, _)| t.function.name == name).unwrap();
+            func(args).unwrap()
+        };
+
+        call("memory_set", json!({ "key": "plan", "value": { "steps": ["a", "b"] } }));
+        call("memory_set", json!({ "key": "goal", "value": "ship it" }));
+
+        let got = call("memory_get", json!({ "key": "plan" }));
+        assert_eq!(got["found"], json!(true));
+        assert_eq!(got["value"]["steps"], json!(["a", "b"]));
+        assert_eq!(call("memory_get", json!({ "key": "missing" }))["found"], json!(false));
+        assert_eq!(call("memory_list", json!({}))["keys"], json!(["goal", "plan"]));
+        assert_eq!(memory.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_file_stats_counts() {
+        let dir = std::env::temp_dir();
+        let text_path = dir.join(format!("nm_tools_2368_{}.txt", std::process::id()));
+        let bin_path = dir.join(format!("nm_tools_2368_{}.bin", std::process::id()));
+        fs::write(&text_path, "hello wörld\n  two  words \nlast").unwrap();
+        fs::write(&bin_path, [0x66u8, 0x6f, 0xff, 0xfe, 0x0a]).unwrap();
+
+        let text = file_stats(&text_path).unwrap();
+        let bin = file_stats(&bin_path).unwrap();
+        let _ = fs::remove_file(&text_path);
+        let _ = fs::remove_file(&bin_path);
+
+        assert_eq!(text["lines"], json!(3));
+        assert_eq!(text["words"], json!(5));
+        assert_eq!(text["bytes"], json!(31));
+        assert_eq!(text["chars"], json!(30));
+        assert_eq!(text["binary"], json!(false));
+        assert!(text["modified"].is_string());
+
+        assert_eq!(bin["lines"], json!(1));
+        assert_eq!(bin["bytes"], json!(5));
+        assert_eq!(bin["binary"], json!(true));
+    }
+
+    #[test]
+    fn test_failed_write_leaves_original_intact() {
+        let dir = std::env::temp_dir().join(format!("nm_atomic_write_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        fs::write(&path, "original").unwrap();
+
+        // The disk "fills up" on the second chunk
+        let mut calls = 0;
+        let err = write_chunks_with(&path, [&b"new "[..], b"content"], false, |file, chunk| {
+            calls += 1;
+            if calls == 2 {
+                return Err(std::io::Error::other("no space left on device"));
+            }
+            std::io::Write::write_all(file, chunk)
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), "io");
+        assert!(err.to_string().contains("after 4 bytes"), "{}", err);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "temp file should be removed");
+
+        assert_eq!(write_chunks(&path, [&b" + more"[..]], true).unwrap(), 7);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original + more");
+        write_chunks(&path, [&b"replaced"[..]], false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "replaced");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_render_template() {
+        let data = json!({
+            "name": "neon",
+            "items": ["a", "b", "c"],
+            "admin": false
+        });
+        assert_eq!(render_template("Hello {{ name }}!", &data).unwrap(), "Hello neon!");
+        assert_eq!(
+            render_template("{% for x in items %}{{ x }}{% if not loop.last %},{% endif %}{% endfor %}", &data).unwrap(),
+            "a,b,c"
+        );
+        assert_eq!(
+            render_template("{% if admin %}root{% else %}user{% endif %}", &data).unwrap(),
+            "user"
+        );
+        // No loader is configured, so templates can't pull in files
+        assert!(render_template("{% include 'Cargo.toml' %}", &data).is_err());
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]).unwrap() - 1.0).abs() < 1e-12);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 2.0]).unwrap().abs() < 1e-12);
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[-1.0, -2.0, -3.0]).unwrap() + 1.0).abs() < 1e-12);
+        // 1*4 + 2*5 + 3*6 = 32, |a| = sqrt(14), |b| = sqrt(77)
+        let expected = 32.0 / (14.0f64.sqrt() * 77.0f64.sqrt());
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]).unwrap() - expected).abs() < 1e-12);
+        assert!(cosine_similarity(&[1.0], &[1.0, 2.0]).is_err());
+        assert!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]).is_err());
+    }
+
+    #[test]
+    fn test_tool_output_checked_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "result": { "type": "string" } },
+            "required": ["result"]
+        });
+        assert!(output_schema_violations(&schema, &json!({ "result": "HELLO" })).is_empty());
+        assert!(output_schema_violations(&Value::Null, &json!(42)).is_empty());
+
+        let violations = output_schema_violations(&schema, &json!({ "result": 7 }));
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("/result: "), "{}", violations[0]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        check_tool_output("echo:echo_tool", &schema, &json!({ "result": "ok" }), &tx);
+        assert!(rx.try_recv().is_err());
+        check_tool_output("echo:echo_tool", &schema, &json!({ "answer": "ok" }), &tx);
+        match rx.try_recv() {
+            Ok(AppEvent::Warning(line)) => {
+                assert!(line.starts_with("Tool 'echo:echo_tool' returned output that doesn't match its output_schema"));
+                assert!(line.contains("result"));
+            }
+            _ => panic!("expected a schema warning"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_text_with_mocked_provider() {
+        let embedding = embed_text_with("hello", |input| async move {
+            assert_eq!(input, "hello");
+            Ok(json!({ "data": [{ "index": 0, "embedding": [0.5, -0.25, 1.0] }] }))
+        })
+        .await
+        .unwrap();
+        assert_eq!(embedding, vec![0.5, -0.25, 1.0]);
+
+        let failed = embed_text_with("hello", |_| async {
+            Err(crate::error::NeonmachinesError::Unexpected("provider down".to_string()))
+        })
+        .await;
+        assert!(failed.unwrap_err().contains("provider down"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_works_on_a_current_thread_runtime() {
+        let value = wait_for(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            7
+        });
+        assert_eq!(value, Ok(7));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wait_for_works_on_a_multi_thread_runtime() {
+        assert_eq!(wait_for(|| async { 7 }), Ok(7));
+    }
+}
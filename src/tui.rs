@@ -1,15 +1,18 @@
 use color_eyre::Result;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::{cursor, execute, terminal};
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
 
 pub type DefaultTerminal = Terminal<CrosstermBackend<io::Stdout>>;
 
 pub fn setup_terminal() -> Result<DefaultTerminal> {
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    execute!(stdout, terminal::EnterAlternateScreen, EnableMouseCapture, cursor::Hide)?;
     let backend = CrosstermBackend::new(stdout);
     let terminal = Terminal::new(backend)?;
     Ok(terminal)
@@ -20,8 +23,60 @@ pub fn restore_terminal(mut terminal: DefaultTerminal) -> Result<()> {
     crossterm::execute!(
         terminal.backend_mut(),
         terminal::LeaveAlternateScreen,
+        DisableMouseCapture,
         cursor::Show
     )?;
     terminal::disable_raw_mode()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Editor used when neither `$VISUAL` nor `$EDITOR` is set
+#[cfg(windows)]
+pub const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+pub const DEFAULT_EDITOR: &str = "vi";
+
+/// Command that opens `path` in `editor` (e.g. `$EDITOR`), which may carry its own
+/// arguments like `code --wait`. Unset or blank falls back to `DEFAULT_EDITOR`.
+pub fn editor_command(editor: Option<&str>, path: &Path) -> Command {
+    let editor = editor.map(str::trim).filter(|e| !e.is_empty()).unwrap_or(DEFAULT_EDITOR);
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or(DEFAULT_EDITOR));
+    command.args(words).arg(path);
+    command
+}
+
+/// Run the user's editor on `path` with the TUI suspended, the way `git commit` does,
+/// and redraw from scratch afterwards
+pub fn edit_file(terminal: &mut DefaultTerminal, path: &Path) -> Result<ExitStatus> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).ok();
+    execute!(terminal.backend_mut(), terminal::LeaveAlternateScreen, DisableMouseCapture, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+
+    let status = editor_command(editor.as_deref(), path).status();
+
+    terminal::enable_raw_mode()?;
+    execute!(terminal.backend_mut(), terminal::EnterAlternateScreen, EnableMouseCapture, cursor::Hide)?;
+    terminal.clear()?;
+    Ok(status?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command_splits_args_and_falls_back() {
+        let path = Path::new("prompts/plan.poml");
+        let command = editor_command(Some("code --wait"), path);
+        assert_eq!(command.get_program(), "code");
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args, vec![std::ffi::OsStr::new("--wait"), path.as_os_str()]);
+
+        for unset in [None, Some(""), Some("  ")] {
+            let command = editor_command(unset, path);
+            assert_eq!(command.get_program(), DEFAULT_EDITOR);
+            assert_eq!(command.get_args().collect::<Vec<_>>(), vec![path.as_os_str()]);
+        }
+    }
+}
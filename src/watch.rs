@@ -0,0 +1,105 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+/// Quiet period after the last change before `--watch` re-runs, so one editor save
+/// (often several writes) triggers a single run
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Where runs keep history, the response cache and the trace log
+const DATA_DIR: &str = ".neonmachines_data";
+
+/// Changes a run makes itself and that shouldn't re-run it: anything under
+/// `.neonmachines_data`, hidden files such as `.nm_*` and `.*.tmp` writes, and the
+/// `config.nm.lock` taken while saving
+pub fn is_ignored(path: &Path) -> bool {
+    path.components().any(|c| c.as_os_str() == DATA_DIR)
+        || path.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            name.starts_with('.') || name.ends_with(".lock")
+        })
+}
+
+/// Watch `path`, recursively when it is a directory, and send every created, modified
+/// or removed path on `tx`, except ignored ones and any made while `paused` is set
+/// (a re-run writing its own output). Changes stop once the returned watcher is dropped.
+pub fn watch_path(path: &Path, paused: Arc<AtomicBool>, tx: UnboundedSender<PathBuf>) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else { return };
+        if paused.load(Ordering::SeqCst) {
+            return;
+        }
+        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            for changed in event.paths.into_iter().filter(|p| !is_ignored(p)) {
+                let _ = tx.send(changed);
+            }
+        }
+    })?;
+    watcher.watch(path, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+/// Call `on_change` once per burst of changes, with the last path changed, after
+/// `debounce` passes without another. Returns when the sender side closes.
+pub async fn debounce_changes<F: FnMut(PathBuf)>(
+    mut rx: UnboundedReceiver<PathBuf>,
+    debounce: Duration,
+    mut on_change: F,
+) {
+    while let Some(mut last) = rx.recv().await {
+        loop {
+            match tokio::time::timeout(debounce, rx.recv()).await {
+                Ok(Some(changed)) => last = changed,
+                Ok(None) => {
+                    on_change(last);
+                    return;
+                }
+                Err(_) => break,
+            }
+        }
+        on_change(last);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_change_burst_triggers_one_rerun() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let (rerun_tx, mut rerun_rx) = tokio::sync::mpsc::unbounded_channel();
+        let debouncer = tokio::spawn(debounce_changes(rx, Duration::from_millis(50), move |changed| {
+            let _ = rerun_tx.send(changed);
+        }));
+
+        // An editor save: several events for the same file in quick succession
+        for _ in 0..3 {
+            tx.send(PathBuf::from("prompts/plan.poml")).unwrap();
+        }
+        tx.send(PathBuf::from("prompts/review.poml")).unwrap();
+        let first = tokio::time::timeout(Duration::from_secs(5), rerun_rx.recv()).await.unwrap();
+        assert_eq!(first, Some(PathBuf::from("prompts/review.poml")));
+
+        // A later change is a new burst and re-runs again
+        tx.send(PathBuf::from("prompts/plan.poml")).unwrap();
+        drop(tx);
+        debouncer.await.unwrap();
+        assert_eq!(rerun_rx.recv().await, Some(PathBuf::from("prompts/plan.poml")));
+        assert_eq!(rerun_rx.recv().await, None);
+    }
+
+    #[test]
+    fn test_run_output_is_ignored() {
+        assert!(is_ignored(Path::new("project/.neonmachines_data/history/main.json")));
+        assert!(is_ignored(Path::new(".neonmachines_data/llm_cache/ab12.json")));
+        assert!(is_ignored(Path::new("project/.nm_scratch")));
+        assert!(is_ignored(Path::new("project/config.nm.lock")));
+        assert!(is_ignored(Path::new("prompts/.plan.poml.4242.0.tmp")));
+        assert!(!is_ignored(Path::new("prompts/plan.poml")));
+        assert!(!is_ignored(Path::new("./prompts/plan.poml")));
+    }
+}
@@ -3,13 +3,139 @@ use futures_util::stream::StreamExt;
 use futures_util::sink::SinkExt;
 use tokio::sync::{mpsc, Mutex};
 use crate::app::App;
-use crate::runner::{AppEvent, AppCommand};
+use crate::runner::{AppEvent, AppCommand, LogLevel};
 use crate::nm_config::{load_all_nm, preset_workflows, WorkflowConfig};
+use crate::graph_view::GraphView;
 use std::collections::HashMap;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+
+/// Path the `--avatar` image is served under
+pub const AVATAR_ROUTE: &str = "avatar";
+
+/// What `/api/ui-config` tells the frontend to apply on load
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UiConfig {
+    pub theme: String,
+    /// Stylesheet under `web/` that implements the theme
+    pub stylesheet: String,
+    /// URL of the custom avatar, when `--avatar` was given
+    pub avatar: Option<String>,
+}
+
+impl UiConfig {
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        let theme = cli.get_theme();
+        Self {
+            stylesheet: theme_stylesheet(&theme).to_string(),
+            theme,
+            avatar: cli.get_avatar().map(|_| format!("/{}", AVATAR_ROUTE)),
+        }
+    }
+}
+
+/// Stylesheet for a `--theme` name; the UI's own look is the light paper theme
+fn theme_stylesheet(theme: &str) -> &'static str {
+    match theme {
+        "dark" => "static/css/main.css",
+        _ => "static/css/paper-theme.css",
+    }
+}
+
+/// `Content-Type` for an avatar, from its (already validated) extension
+pub fn avatar_content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        _ => "image/png",
+    }
+}
+
+/// `/healthz` (the process is up) and `/readyz` (the workflow file at `config` parses
+/// and the `prompts` directory can be listed). Both are unauthenticated and touch
+/// nothing beyond those two paths; `/readyz` answers 503 when a check fails.
+pub fn health_routes(
+    started: std::time::Instant,
+    config: std::path::PathBuf,
+    prompts: std::path::PathBuf,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    let healthz = warp::path!("healthz").and(warp::get()).map(move || {
+        warp::reply::json(&serde_json::json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_seconds": started.elapsed().as_secs(),
+        }))
+    });
+    let readyz = warp::path!("readyz").and(warp::get()).map(move || {
+        let config_check = fs::read_to_string(&config)
+            .and_then(|text| crate::nm_config::parse_nm_multiple(&text))
+            .map(|_| "ok".to_string())
+            .unwrap_or_else(|e| format!("{}: {}", config.display(), e));
+        let prompts_check = fs::read_dir(&prompts)
+            .map(|_| "ok".to_string())
+            .unwrap_or_else(|e| format!("{}: {}", prompts.display(), e));
+        let ready = config_check == "ok" && prompts_check == "ok";
+        let body = serde_json::json!({
+            "status": if ready { "ok" } else { "unavailable" },
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_seconds": started.elapsed().as_secs(),
+            "checks": { "config": config_check, "prompts": prompts_check },
+        });
+        let status = if ready { warp::http::StatusCode::OK } else { warp::http::StatusCode::SERVICE_UNAVAILABLE };
+        warp::reply::with_status(warp::reply::json(&body), status)
+    });
+    healthz.or(readyz)
+}
+
+/// Rejection for a request without the `--auth-token`
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Whether a request carries the `expected` token, as `?token=` or `Authorization: Bearer`.
+/// With no token configured every request passes.
+pub fn token_matches(expected: Option<&str>, query: Option<&str>, authorization: Option<&str>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let bearer = authorization.and_then(|value| value.strip_prefix("Bearer ")).map(str::trim);
+    [query, bearer].into_iter().flatten().any(|given| {
+        // Compare every byte so the time taken doesn't reveal how much of the token matched
+        given.len() == expected.len()
+            && given.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    })
+}
+
+/// Passes requests that carry `token`, rejecting the rest with `Unauthorized`.
+/// Place it after the path filters so unrelated routes still 404.
+pub fn require_token(token: Option<String>) -> impl warp::Filter<Extract = (), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    warp::query::<HashMap<String, String>>()
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |query: HashMap<String, String>, authorization: Option<String>| {
+            let allowed = token_matches(token.as_deref(), query.get("token").map(String::as_str), authorization.as_deref());
+            async move {
+                if allowed { Ok(()) } else { Err(warp::reject::custom(Unauthorized)) }
+            }
+        })
+        .untuple_one()
+}
+
+/// Answer `Unauthorized` rejections with a 401; anything else falls through
+pub async fn handle_unauthorized(err: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        let body = warp::reply::json(&serde_json::json!({ "error": "Missing or invalid auth token" }));
+        Ok(warp::reply::with_status(body, warp::http::StatusCode::UNAUTHORIZED))
+    } else {
+        Err(err)
+    }
+}
 
 /// Process input for preprompting with secondary agent support
 /// Handles inputs in the format: "primary task input2=\"secondary task\""
@@ -239,6 +365,21 @@ struct UiResponse {
     data: serde_json::Value,
 }
 
+/// Decode a client message, or build the error reply explaining why it was rejected
+fn decode_ui_command(text: &str) -> Result<UiCommand, UiResponse> {
+    serde_json::from_str::<UiCommand>(text).map_err(|e| UiResponse {
+        status: "error".to_string(),
+        data: serde_json::Value::String(format!("invalid command JSON: {}", e)),
+    })
+}
+
+fn unknown_command_response(command: &str) -> UiResponse {
+    UiResponse {
+        status: "error".to_string(),
+        data: serde_json::Value::String(format!("unknown command: {}", command)),
+    }
+}
+
 #[derive(Serialize)]
 struct WebMetrics {
     requests_count: u64,
@@ -279,9 +420,56 @@ pub async fn get_poml_files() -> Result<Vec<String>, Box<dyn std::error::Error +
     Ok(poml_files)
 }
 
+/// Page size for POML file listings when no `limit` is given
+pub const POML_PAGE_LIMIT: usize = 100;
+
+/// Which slice of the POML file listing to return; `filter` keeps names
+/// containing it, ignoring case
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PomlFileQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub filter: Option<String>,
+}
+
+/// One page of POML files; `total` counts every file matching the filter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PomlFilePage {
+    pub files: Vec<String>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// Filter the sorted `files` and cut out the page `query` asks for
+pub fn paginate_poml_files(files: Vec<String>, query: &PomlFileQuery) -> PomlFilePage {
+    let filter = query.filter.as_deref().unwrap_or("").to_lowercase();
+    let matching: Vec<String> = files
+        .into_iter()
+        .filter(|name| name.to_lowercase().contains(&filter))
+        .collect();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(POML_PAGE_LIMIT);
+    PomlFilePage {
+        total: matching.len(),
+        files: matching.into_iter().skip(offset).take(limit).collect(),
+        offset,
+        limit,
+    }
+}
+
+/// `file_name` resolved under prompts/; anything but a plain relative path (a `..`,
+/// a root or a drive prefix) is refused
+pub fn prompt_file_path(file_name: &str) -> Result<PathBuf, String> {
+    if !Path::new(file_name).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("'{}' must be a relative path inside prompts/", file_name));
+    }
+    Ok(Path::new("prompts").join(file_name))
+}
+
 /// Load POML file content
 pub async fn load_poml_file(file_name: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let file_path = Path::new("prompts").join(file_name);
+    let file_path = prompt_file_path(file_name)?;
     
     if !file_path.exists() {
         return Err(format!("POML file not found: {}", file_name).into());
@@ -311,6 +499,50 @@ fn generate_temp_poml_file(content: &str) -> String {
     temp_filename
 }
 
+/// Convert an app event into a client response, dropping log lines below `min_level`
+fn event_to_response(event: AppEvent, min_level: LogLevel) -> Option<UiResponse> {
+    let (status, line) = match event {
+        AppEvent::Log(line) => {
+            if LogLevel::from_line(&line) < min_level {
+                return None;
+            }
+            ("log", line)
+        }
+        AppEvent::LeveledLog { level, msg } => {
+            if level < min_level {
+                return None;
+            }
+            ("log", format!("[{}] {}", level.as_str(), msg))
+        }
+        AppEvent::RunStart(name) => ("run_start", name),
+        AppEvent::RunResult { agent, text } => match agent {
+            Some(agent) => ("run_result", format!("{}: {}", agent, text)),
+            None => ("run_result", text),
+        },
+        AppEvent::Reasoning { agent, text } => match agent {
+            Some(agent) => ("reasoning", format!("{}: {}", agent, text)),
+            None => ("reasoning", text),
+        },
+        AppEvent::RunEnd(name) => ("run_end", name),
+        AppEvent::Warning(line) => {
+            if LogLevel::Warn < min_level {
+                return None;
+            }
+            ("warning", line)
+        }
+        AppEvent::Error(line) => ("error", line),
+        AppEvent::RoutingTrace(trace) => ("routing_trace", crate::runner::format_routing_trace(&trace, &[])),
+        AppEvent::Progress { current, total } => {
+            let percent = if total == 0 { 0 } else { (current * 100 / total).min(100) };
+            return Some(UiResponse {
+                status: "progress".to_string(),
+                data: serde_json::json!({ "current": current, "total": total, "percent": percent }),
+            });
+        }
+    };
+    Some(UiResponse { status: status.to_string(), data: serde_json::Value::String(line) })
+}
+
 pub async fn handle_websocket_connection(ws: WebSocket) {
     let (mut tx, mut rx) = ws.split();
 
@@ -325,9 +557,35 @@ pub async fn handle_websocket_connection(ws: WebSocket) {
     let metrics_collector = Arc::new(tokio::sync::Mutex::new(crate::metrics::metrics_collector::MetricsCollector::new()));
     let app = Arc::new(Mutex::new(App::new(tx_cmd, rx_evt, workflows, active_name, Some(metrics_collector.clone()))));
 
+    // Cancelled when the socket closes; `stop` cancels only the run in progress
+    let connection = crate::runner::CancelToken::default();
+    let runner_connection = connection.clone();
+    let current_run = Arc::new(std::sync::Mutex::new(crate::runner::CancelToken::default()));
+    let runner_current = current_run.clone();
     tokio::spawn(async move {
-        while let Some(cmd) = rx_cmd.recv().await {
-            crate::runner::run_workflow(cmd, tx_evt.clone(), Some(metrics_collector.clone())).await;
+        let mut chat_session = None;
+        let mut last_run = None;
+        loop {
+            let cmd = tokio::select! {
+                cmd = rx_cmd.recv() => cmd,
+                _ = runner_connection.cancelled() => None,
+            };
+            let Some(cmd) = cmd else { break };
+            let run_cancel = crate::runner::CancelToken::default();
+            *runner_current.lock().unwrap() = run_cancel.clone();
+            // The close cancels the current token after `connection`, so checking here can't miss it
+            if runner_connection.is_cancelled() {
+                break;
+            }
+            crate::runner::run_command(
+                cmd,
+                &mut chat_session,
+                &mut last_run,
+                tx_evt.clone(),
+                Some(metrics_collector.clone()),
+                run_cancel,
+            )
+            .await;
         }
     });
 
@@ -346,17 +604,19 @@ pub async fn handle_websocket_connection(ws: WebSocket) {
     let app_clone = app.clone();
     let ws_tx_clone = ws_tx.clone();
 
+    // Minimum log level forwarded to this client, changed via `set_log_level`
+    let log_level = Arc::new(std::sync::Mutex::new(LogLevel::Debug));
+    let log_level_clone = log_level.clone();
+
     // Task to handle app events and forward to WebSocket
     tokio::spawn(async move {
         let mut app = app_clone.lock().await;
         while let Some(event) = app.rx.recv().await {
-            let msg = match event {
-                AppEvent::Log(line) => Message::text(serde_json::to_string(&UiResponse { status: "log".to_string(), data: serde_json::Value::String(line) }).unwrap()),
-                AppEvent::RunStart(name) => Message::text(serde_json::to_string(&UiResponse { status: "run_start".to_string(), data: serde_json::Value::String(name) }).unwrap()),
-                AppEvent::RunResult(line) => Message::text(serde_json::to_string(&UiResponse { status: "run_result".to_string(), data: serde_json::Value::String(line) }).unwrap()),
-                AppEvent::RunEnd(name) => Message::text(serde_json::to_string(&UiResponse { status: "run_end".to_string(), data: serde_json::Value::String(name) }).unwrap()),
-                AppEvent::Error(line) => Message::text(serde_json::to_string(&UiResponse { status: "error".to_string(), data: serde_json::Value::String(line) }).unwrap()),
+            let min_level = *log_level_clone.lock().unwrap();
+            let Some(response) = event_to_response(event, min_level) else {
+                continue;
             };
+            let msg = Message::text(serde_json::to_string(&response).unwrap());
             if ws_tx_clone.send(msg).is_err() {
                 // connection closed
                 break;
@@ -369,320 +629,391 @@ pub async fn handle_websocket_connection(ws: WebSocket) {
         if let Ok(msg) = result {
             if msg.is_text() {
                 if let Ok(text) = msg.to_str() {
-                    if let Ok(cmd) = serde_json::from_str::<UiCommand>(text) {
-                        let mut app = app.lock().await;
-                        match cmd.command.as_str() {
-                            "submit" => {
-                                if let Some(input) = cmd.payload.as_str() {
-                                    // Handle preprompting with secondary agent inputs
-                                    let processed_input = process_preprompting_input(input);
-                                    app.input = processed_input;
-                                    app.submit();
+                    let cmd = match decode_ui_command(text) {
+                        Ok(cmd) => cmd,
+                        Err(response) => {
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    // Handled before locking the app since the event task holds it
+                    if cmd.command == "set_log_level" {
+                        let requested = cmd.payload.as_str()
+                            .or_else(|| cmd.payload.get("level").and_then(|v| v.as_str()))
+                            .unwrap_or("");
+                        let response = match LogLevel::parse(requested) {
+                            Some(level) => {
+                                *log_level.lock().unwrap() = level;
+                                UiResponse {
+                                    status: "log_level".to_string(),
+                                    data: serde_json::Value::String(level.as_str().to_lowercase()),
                                 }
                             }
-                            "add_node" => {
-                                // Logic to add a node based on payload
-                                // This requires expanding App's functionality
+                            None => UiResponse {
+                                status: "error".to_string(),
+                                data: serde_json::Value::String(format!("Unknown log level: {}", requested)),
+                            },
+                        };
+                        let msg = Message::text(serde_json::to_string(&response).unwrap());
+                        if ws_tx.send(msg).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    if cmd.command == "stop" {
+                        current_run.lock().unwrap().cancel();
+                        continue;
+                    }
+                    let mut app = app.lock().await;
+                    match cmd.command.as_str() {
+                        "submit" => {
+                            if let Some(input) = cmd.payload.as_str() {
+                                // Handle preprompting with secondary agent inputs
+                                let processed_input = process_preprompting_input(input);
+                                app.input = processed_input;
+                                app.submit();
                             }
-                            "get_poml_files" => {
-                                // Get list of POML files
-                                match get_poml_files().await {
-                                    Ok(files) => {
-                                        let files_json = serde_json::to_value(files).unwrap();
-                                        let response = UiResponse {
-                                            status: "poml_files".to_string(),
-                                            data: files_json,
-                                        };
-                                        let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                        if ws_tx.send(msg).is_err() {
-                                            break;
+                        }
+                        "add_node" => {
+                            // Logic to add a node based on payload
+                            // This requires expanding App's functionality
+                        }
+                        "get_graph" | "set_graph" => {
+                            let name = cmd.payload.get("workflow").and_then(|v| v.as_str())
+                                .map(str::to_string)
+                                .unwrap_or_else(|| app.active_workflow.clone());
+                            let response = match app.workflows.get(&name) {
+                                None => UiResponse {
+                                    status: "error".to_string(),
+                                    data: serde_json::Value::String(format!("Workflow '{}' not found", name)),
+                                },
+                                Some(cfg) if cmd.command == "get_graph" => UiResponse {
+                                    status: "graph".to_string(),
+                                    data: serde_json::to_value(GraphView::from(cfg)).unwrap(),
+                                },
+                                Some(cfg) => {
+                                    // Edits go to a copy that replaces the loaded workflow only once it's saved
+                                    let mut updated = cfg.clone();
+                                    let saved = serde_json::from_value::<GraphView>(cmd.payload.get("graph").cloned().unwrap_or_default())
+                                        .map_err(|e| format!("Invalid graph: {}", e))
+                                        .and_then(|graph| graph.apply(&mut updated))
+                                        .and_then(|()| {
+                                            let all: Vec<WorkflowConfig> = app.workflows.iter()
+                                                .map(|(key, wf)| if *key == name { updated.clone() } else { wf.clone() })
+                                                .collect();
+                                            crate::nm_config::save_all_nm(&all)
+                                                .map_err(|e| format!("Failed to save workflows: {}", e))
+                                        });
+                                    match saved {
+                                        Ok(()) => {
+                                            let graph = GraphView::from(&updated);
+                                            app.workflows.insert(name.clone(), updated);
+                                            UiResponse {
+                                                status: "graph".to_string(),
+                                                data: serde_json::to_value(graph).unwrap(),
+                                            }
                                         }
-                                    }
-                                    Err(e) => {
-                                        let response = UiResponse {
+                                        Err(e) => UiResponse {
                                             status: "error".to_string(),
-                                            data: serde_json::Value::String(format!("Failed to load POML files: {}", e)),
-                                        };
-                                        let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                        if ws_tx.send(msg).is_err() {
-                                            break;
-                                        }
+                                            data: serde_json::Value::String(e),
+                                        },
                                     }
                                 }
+                            };
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
                             }
-                            "load_poml" => {
-                                // Load a specific POML file
-                                if let Some(file_name) = cmd.payload.get("file").and_then(|v| v.as_str()) {
-                                    match load_poml_file(file_name).await {
-                                        Ok(content) => {
-                                            let response = UiResponse {
-                                                status: "poml_content".to_string(),
-                                                data: serde_json::Value::String(content),
-                                            };
-                                            let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                            if ws_tx.send(msg).is_err() {
-                                                break;
-                                            }
-                                        }
-                                        Err(e) => {
-                                            let response = UiResponse {
-                                                status: "error".to_string(),
-                                                data: serde_json::Value::String(format!("Failed to load POML file: {}", e)),
-                                            };
-                                            let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                            if ws_tx.send(msg).is_err() {
-                                                break;
-                                            }
-                                        }
+                        }
+                        "get_poml_files" => {
+                            // Older clients send no payload and get the first page
+                            let query: PomlFileQuery = serde_json::from_value(cmd.payload.clone()).unwrap_or_default();
+                            match get_poml_files().await {
+                                Ok(files) => {
+                                    let files_json = serde_json::to_value(paginate_poml_files(files, &query)).unwrap();
+                                    let response = UiResponse {
+                                        status: "poml_files".to_string(),
+                                        data: files_json,
+                                    };
+                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                    if ws_tx.send(msg).is_err() {
+                                        break;
+                                    }
+                                }
+                                Err(e) => {
+                                    let response = UiResponse {
+                                        status: "error".to_string(),
+                                        data: serde_json::Value::String(format!("Failed to load POML files: {}", e)),
+                                    };
+                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                    if ws_tx.send(msg).is_err() {
+                                        break;
                                     }
                                 }
                             }
-                            "run_workflow" => {
-                                // Run a specific workflow
-                                if let Some(workflow_name) = cmd.payload.get("workflow_name").and_then(|v| v.as_str()) {
-                                    let prompt = cmd.payload.get("prompt").and_then(|v| v.as_str()).unwrap_or("");
-                                    
-                                    // Get the workflow configuration
-                                    if let Some(cfg) = app.workflows.get(workflow_name) {
-                                        let _ = app.tx.send(AppCommand::RunWorkflow {
-                                            workflow_name: workflow_name.to_string(),
-                                            prompt: prompt.to_string(),
-                                            cfg: cfg.clone(),
-                                            start_agent: None,
-                                            variables: Some(app.variables.clone()),
-                                        });
-                                        
+                        }
+                        "load_poml" => {
+                            // Load a specific POML file
+                            if let Some(file_name) = cmd.payload.get("file").and_then(|v| v.as_str()) {
+                                match load_poml_file(file_name).await {
+                                    Ok(content) => {
                                         let response = UiResponse {
-                                            status: "workflow_run_started".to_string(),
-                                            data: serde_json::Value::String(format!("Started workflow: {}", workflow_name)),
+                                            status: "poml_content".to_string(),
+                                            data: serde_json::Value::String(content),
                                         };
                                         let msg = Message::text(serde_json::to_string(&response).unwrap());
                                         if ws_tx.send(msg).is_err() {
                                             break;
                                         }
-                                    } else {
+                                    }
+                                    Err(e) => {
                                         let response = UiResponse {
                                             status: "error".to_string(),
-                                            data: serde_json::Value::String(format!("Workflow '{}' not found", workflow_name)),
+                                            data: serde_json::Value::String(format!("Failed to load POML file: {}", e)),
                                         };
                                         let msg = Message::text(serde_json::to_string(&response).unwrap());
                                         if ws_tx.send(msg).is_err() {
                                             break;
                                         }
                                     }
+                                }
+                            }
+                        }
+                        "run_workflow" => {
+                            // Run a specific workflow
+                            if let Some(workflow_name) = cmd.payload.get("workflow_name").and_then(|v| v.as_str()) {
+                                let prompt = cmd.payload.get("prompt").and_then(|v| v.as_str()).unwrap_or("");
+                                
+                                // Get the workflow configuration
+                                if let Some(cfg) = app.workflows.get(workflow_name) {
+                                    let _ = app.tx.send(AppCommand::RunWorkflow {
+                                        workflow_name: workflow_name.to_string(),
+                                        prompt: prompt.to_string(),
+                                        cfg: cfg.clone(),
+                                        start_agent: None,
+                                        variables: Some(app.variables.clone()),
+                                    });
+                                    
+                                    let response = UiResponse {
+                                        status: "workflow_run_started".to_string(),
+                                        data: serde_json::Value::String(format!("Started workflow: {}", workflow_name)),
+                                    };
+                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                    if ws_tx.send(msg).is_err() {
+                                        break;
+                                    }
                                 } else {
                                     let response = UiResponse {
                                         status: "error".to_string(),
-                                        data: serde_json::Value::String("Missing workflow_name parameter".to_string()),
+                                        data: serde_json::Value::String(format!("Workflow '{}' not found", workflow_name)),
                                     };
                                     let msg = Message::text(serde_json::to_string(&response).unwrap());
                                     if ws_tx.send(msg).is_err() {
                                         break;
                                     }
                                 }
-                            }
-                            "run_all_workflows" => {
-                                // Run all available workflows
-                                let workflow_names: Vec<String> = app.workflows.keys().cloned().collect();
-                                let mut started_count = 0;
-                                
-                                for workflow_name in &workflow_names {
-                                    if let Some(cfg) = app.workflows.get(workflow_name) {
-                                        let _ = app.tx.send(AppCommand::RunWorkflow {
-                                            workflow_name: workflow_name.to_string(),
-                                            prompt: "Run all".to_string(),
-                                            cfg: cfg.clone(),
-                                            start_agent: None,
-                                            variables: Some(app.variables.clone()),
-                                        });
-                                        started_count += 1;
-                                    }
-                                }
-                                
+                            } else {
                                 let response = UiResponse {
-                                    status: "all_workflows_run_started".to_string(),
-                                    data: serde_json::Value::String(format!("Started {} workflows", started_count)),
+                                    status: "error".to_string(),
+                                    data: serde_json::Value::String("Missing workflow_name parameter".to_string()),
                                 };
                                 let msg = Message::text(serde_json::to_string(&response).unwrap());
                                 if ws_tx.send(msg).is_err() {
                                     break;
                                 }
                             }
-                            "run_poml" => {
-                                // Run POML content by creating a temporary workflow
-                                let content = if let Some(content_str) = cmd.payload.get("content").and_then(|v| v.as_str()) {
-                                    content_str.to_string()
-                                } else if let Some(obj) = cmd.payload.get("content") {
-                                    // Handle object payload with content and format
-                                    obj.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string()
-                                } else {
-                                    "".to_string()
-                                };
-                                
-                                // Get format parameter if provided
-                                let _format = if let Some(obj) = cmd.payload.get("content") {
-                                    obj.get("format").and_then(|v| v.as_str()).unwrap_or("html").to_string()
-                                } else {
-                                    "html".to_string()
-                                };
-                                
-                                if !content.is_empty() {
-                                    // Try to create a simple workflow from POML content
-                                    let temp_workflow_name = "temp_poml_workflow";
-                                    
-                                    // Create a basic workflow config
-                                    let temp_cfg = WorkflowConfig {
-                                        name: temp_workflow_name.to_string(),
-                                        model: "gpt-3.5-turbo".to_string(),
-                                        temperature: 0.7,
-                                        maximum_traversals: 10,
-                                        working_dir: ".".to_string(),
-                                        active_agent_index: 0,
-                                        rows: vec![crate::nm_config::AgentRow {
-                                            agent_type: crate::nm_config::AgentType::Agent,
-                                            files: format!("user:temp_poml:{}", generate_temp_poml_file(&content)),
-                                            max_iterations: 3,
-                                            iteration_delay_ms: 200,
-                                            on_success: None,
-                                            on_failure: None,
-                                            input_injections: Vec::new(),
-                                            output_injections: Vec::new(),
-                                        }],
-                                    };
-                                    
-                                    // Save the temp workflow
-                                    app.workflows.insert(temp_workflow_name.to_string(), temp_cfg.clone());
-                                    
-                                    // Run the temp workflow
+                        }
+                        "run_all_workflows" => {
+                            // Run all available workflows
+                            let workflow_names: Vec<String> = app.workflows.keys().cloned().collect();
+                            let mut started_count = 0;
+                            
+                            for workflow_name in &workflow_names {
+                                if let Some(cfg) = app.workflows.get(workflow_name) {
                                     let _ = app.tx.send(AppCommand::RunWorkflow {
-                                        workflow_name: temp_workflow_name.to_string(),
-                                        prompt: cmd.payload.get("prompt").and_then(|v| v.as_str()).unwrap_or("Execute POML workflow").to_string(),
-                                        cfg: temp_cfg,
+                                        workflow_name: workflow_name.to_string(),
+                                        prompt: "Run all".to_string(),
+                                        cfg: cfg.clone(),
                                         start_agent: None,
                                         variables: Some(app.variables.clone()),
                                     });
-                                    
-                                    let response = UiResponse {
-                                        status: "poml_run_started".to_string(),
-                                        data: serde_json::Value::String("POML workflow started".to_string()),
-                                    };
-                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                    if ws_tx.send(msg).is_err() {
-                                        break;
-                                    }
+                                    started_count += 1;
                                 }
                             }
-                            "save_poml" => {
-                                // Save POML content (placeholder - would need file system access)
+                            
+                            let response = UiResponse {
+                                status: "all_workflows_run_started".to_string(),
+                                data: serde_json::Value::String(format!("Started {} workflows", started_count)),
+                            };
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
+                            }
+                        }
+                        "run_poml" => {
+                            // Run POML content by creating a temporary workflow
+                            let content = if let Some(content_str) = cmd.payload.get("content").and_then(|v| v.as_str()) {
+                                content_str.to_string()
+                            } else if let Some(obj) = cmd.payload.get("content") {
+                                // Handle object payload with content and format
+                                obj.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string()
+                            } else {
+                                "".to_string()
+                            };
+                            
+                            // Get format parameter if provided
+                            let _format = if let Some(obj) = cmd.payload.get("content") {
+                                obj.get("format").and_then(|v| v.as_str()).unwrap_or("html").to_string()
+                            } else {
+                                "html".to_string()
+                            };
+                            
+                            if !content.is_empty() {
+                                // Try to create a simple workflow from POML content
+                                let temp_workflow_name = "temp_poml_workflow";
+                                
+                                // Create a basic workflow config
+                                let temp_cfg = WorkflowConfig {
+                                    name: temp_workflow_name.to_string(),
+                                    model: "gpt-3.5-turbo".to_string(),
+                                    temperature: 0.7,
+                                    maximum_traversals: 10,
+                                    working_dir: ".".to_string(),
+                                    active_agent_index: 0,
+                                    rows: vec![crate::nm_config::AgentRow {
+                                        agent_type: crate::nm_config::AgentType::Agent,
+                                        files: format!("user:temp_poml:{}", generate_temp_poml_file(&content)),
+                                        max_iterations: 3,
+                                        ..crate::nm_config::AgentRow::default()
+                                    }],
+                                    ..WorkflowConfig::default()
+                                };
+                                
+                                // Save the temp workflow
+                                app.workflows.insert(temp_workflow_name.to_string(), temp_cfg.clone());
+                                
+                                // Run the temp workflow
+                                let _ = app.tx.send(AppCommand::RunWorkflow {
+                                    workflow_name: temp_workflow_name.to_string(),
+                                    prompt: cmd.payload.get("prompt").and_then(|v| v.as_str()).unwrap_or("Execute POML workflow").to_string(),
+                                    cfg: temp_cfg,
+                                    start_agent: None,
+                                    variables: Some(app.variables.clone()),
+                                });
+                                
                                 let response = UiResponse {
-                                    status: "poml_save_success".to_string(),
-                                    data: serde_json::Value::String("POML save functionality not yet implemented".to_string()),
+                                    status: "poml_run_started".to_string(),
+                                    data: serde_json::Value::String("POML workflow started".to_string()),
                                 };
                                 let msg = Message::text(serde_json::to_string(&response).unwrap());
                                 if ws_tx.send(msg).is_err() {
                                     break;
                                 }
                             }
-                            "validate_poml" => {
-                                // Validate POML content (placeholder)
+                        }
+                        "save_poml" => {
+                            // Save POML content (placeholder - would need file system access)
+                            let response = UiResponse {
+                                status: "poml_save_success".to_string(),
+                                data: serde_json::Value::String("POML save functionality not yet implemented".to_string()),
+                            };
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
+                            }
+                        }
+                        "validate_poml" => {
+                            // Validate POML content (placeholder)
+                            let response = UiResponse {
+                                status: "poml_validate_success".to_string(),
+                                data: serde_json::Value::String("POML validation functionality not yet implemented".to_string()),
+                            };
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
+                            }
+                        }
+                        "send_poml_to_editor" => {
+                            // Send POML content to the editor
+                            if let Some(content) = cmd.payload.get("content").and_then(|v| v.as_str()) {
+                                let file_name = cmd.payload.get("file_name").and_then(|v| v.as_str()).unwrap_or("");
                                 let response = UiResponse {
-                                    status: "poml_validate_success".to_string(),
-                                    data: serde_json::Value::String("POML validation functionality not yet implemented".to_string()),
+                                    status: "load_poml_content".to_string(),
+                                    data: serde_json::json!({
+                                        "content": content,
+                                        "file_name": file_name
+                                    }),
                                 };
                                 let msg = Message::text(serde_json::to_string(&response).unwrap());
                                 if ws_tx.send(msg).is_err() {
                                     break;
                                 }
                             }
-                            "send_poml_to_editor" => {
-                                // Send POML content to the editor
-                                if let Some(content) = cmd.payload.get("content").and_then(|v| v.as_str()) {
-                                    let file_name = cmd.payload.get("file_name").and_then(|v| v.as_str()).unwrap_or("");
-                                    let response = UiResponse {
-                                        status: "load_poml_content".to_string(),
-                                        data: serde_json::json!({
-                                            "content": content,
-                                            "file_name": file_name
-                                        }),
-                                    };
-                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                    if ws_tx.send(msg).is_err() {
-                                        break;
-                                    }
+                        }
+                        "create_template" => {
+                            // Create MCP or tool template
+                            let template_type = cmd.payload.get("type").and_then(|v| v.as_str()).unwrap_or("");
+                            let template_name = cmd.payload.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                            
+                            if template_type.is_empty() || template_name.is_empty() {
+                                let response = UiResponse {
+                                    status: "error".to_string(),
+                                    data: serde_json::Value::String("Missing template type or name".to_string()),
+                                };
+                                let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                if ws_tx.send(msg).is_err() {
+                                    break;
                                 }
-                            }
-                            "create_template" => {
-                                // Create MCP or tool template
-                                let template_type = cmd.payload.get("type").and_then(|v| v.as_str()).unwrap_or("");
-                                let template_name = cmd.payload.get("name").and_then(|v| v.as_str()).unwrap_or("");
-                                
-                                if template_type.is_empty() || template_name.is_empty() {
-                                    let response = UiResponse {
-                                        status: "error".to_string(),
-                                        data: serde_json::Value::String("Missing template type or name".to_string()),
-                                    };
-                                    let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                    if ws_tx.send(msg).is_err() {
-                                        break;
-                                    }
-                                } else {
-                                    match template_type {
-                                        "mcp" => {
-                                            // Create MCP template
-                                            let template_content = generate_mcp_template(template_name);
-                                            let file_path = format!("extensions/ext_{}", template_name);
-                                            
-                                            // Create directory and files
-                                            if let Err(e) = create_mcp_template_structure(&file_path, &template_content) {
-                                                let response = UiResponse {
-                                                    status: "error".to_string(),
-                                                    data: serde_json::Value::String(format!("Failed to create MCP template: {}", e)),
-                                                };
-                                                let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                                if ws_tx.send(msg).is_err() {
-                                                    break;
-                                                }
-                                            } else {
-                                                let response = UiResponse {
-                                                    status: "template_created".to_string(),
-                                                    data: serde_json::Value::String(format!("MCP template '{}' created at {}", template_name, file_path)),
-                                                };
-                                                let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                                if ws_tx.send(msg).is_err() {
-                                                    break;
-                                                }
+                            } else {
+                                match template_type {
+                                    "mcp" => {
+                                        // Create MCP template
+                                        let template_content = generate_mcp_template(template_name);
+                                        let file_path = format!("extensions/ext_{}", template_name);
+                                        
+                                        // Create directory and files
+                                        if let Err(e) = create_mcp_template_structure(&file_path, &template_content) {
+                                            let response = UiResponse {
+                                                status: "error".to_string(),
+                                                data: serde_json::Value::String(format!("Failed to create MCP template: {}", e)),
+                                            };
+                                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                            if ws_tx.send(msg).is_err() {
+                                                break;
                                             }
-                                        }
-                                        "tool" => {
-                                            // Create tool template
-                                            let template_content = generate_tool_template(template_name);
-                                            let file_path = format!("prompts/{}_tool.poml", template_name);
-                                            
-                                            // Create tool file
-                                            if let Err(e) = create_tool_template_file(&file_path, &template_content) {
-                                                let response = UiResponse {
-                                                    status: "error".to_string(),
-                                                    data: serde_json::Value::String(format!("Failed to create tool template: {}", e)),
-                                                };
-                                                let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                                if ws_tx.send(msg).is_err() {
-                                                    break;
-                                                }
-                                            } else {
-                                                let response = UiResponse {
-                                                    status: "template_created".to_string(),
-                                                    data: serde_json::Value::String(format!("Tool template '{}' created at {}", template_name, file_path)),
-                                                };
-                                                let msg = Message::text(serde_json::to_string(&response).unwrap());
-                                                if ws_tx.send(msg).is_err() {
-                                                    break;
-                                                }
+                                        } else {
+                                            let response = UiResponse {
+                                                status: "template_created".to_string(),
+                                                data: serde_json::Value::String(format!("MCP template '{}' created at {}", template_name, file_path)),
+                                            };
+                                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                            if ws_tx.send(msg).is_err() {
+                                                break;
                                             }
                                         }
-                                        _ => {
+                                    }
+                                    "tool" => {
+                                        // Create tool template
+                                        let template_content = generate_tool_template(template_name);
+                                        let file_path = format!("prompts/{}_tool.poml", template_name);
+                                        
+                                        // Create tool file
+                                        if let Err(e) = create_tool_template_file(&file_path, &template_content) {
                                             let response = UiResponse {
                                                 status: "error".to_string(),
-                                                data: serde_json::Value::String(format!("Unknown template type: {}", template_type)),
+                                                data: serde_json::Value::String(format!("Failed to create tool template: {}", e)),
+                                            };
+                                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                            if ws_tx.send(msg).is_err() {
+                                                break;
+                                            }
+                                        } else {
+                                            let response = UiResponse {
+                                                status: "template_created".to_string(),
+                                                data: serde_json::Value::String(format!("Tool template '{}' created at {}", template_name, file_path)),
                                             };
                                             let msg = Message::text(serde_json::to_string(&response).unwrap());
                                             if ws_tx.send(msg).is_err() {
@@ -690,11 +1021,25 @@ pub async fn handle_websocket_connection(ws: WebSocket) {
                                             }
                                         }
                                     }
+                                    _ => {
+                                        let response = UiResponse {
+                                            status: "error".to_string(),
+                                            data: serde_json::Value::String(format!("Unknown template type: {}", template_type)),
+                                        };
+                                        let msg = Message::text(serde_json::to_string(&response).unwrap());
+                                        if ws_tx.send(msg).is_err() {
+                                            break;
+                                        }
+                                    }
                                 }
                             }
-                            // Handle other commands like "connect_nodes", "delete_node", etc.
-                            _ => {
-                                // unhandled command
+                        }
+                        // Handle other commands like "connect_nodes", "delete_node", etc.
+                        other => {
+                            let response = unknown_command_response(other);
+                            let msg = Message::text(serde_json::to_string(&response).unwrap());
+                            if ws_tx.send(msg).is_err() {
+                                break;
                             }
                         }
                     }
@@ -702,4 +1047,177 @@ pub async fn handle_websocket_connection(ws: WebSocket) {
             }
         }
     }
+
+    // Nobody is left to read a run's output, so stop it
+    connection.cancel();
+    current_run.lock().unwrap().cancel();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_file_path_stays_inside_prompts() {
+        assert_eq!(prompt_file_path("sub/a.poml").unwrap(), Path::new("prompts").join("sub/a.poml"));
+        assert!(prompt_file_path("../config.nm").is_err());
+        assert!(prompt_file_path("sub/../../secret.poml").is_err());
+        assert!(prompt_file_path("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_warn_subscription_drops_debug_lines() {
+        let debug_line = AppEvent::Log("[DEBUG] Running POML file: ./prompts/a.poml".to_string());
+        let tool_line = AppEvent::Log("[TOOL][ls] result = ok".to_string());
+        let leveled = AppEvent::LeveledLog { level: LogLevel::Debug, msg: "details".to_string() };
+        let warn_line = AppEvent::LeveledLog { level: LogLevel::Warn, msg: "careful".to_string() };
+
+        assert!(event_to_response(debug_line, LogLevel::Warn).is_none());
+        assert!(event_to_response(tool_line, LogLevel::Warn).is_none());
+        assert!(event_to_response(leveled, LogLevel::Warn).is_none());
+
+        let kept = event_to_response(warn_line, LogLevel::Warn).unwrap();
+        assert_eq!(kept.status, "log");
+        assert_eq!(kept.data, serde_json::Value::String("[WARN] careful".to_string()));
+
+        let error = event_to_response(AppEvent::Error("boom".to_string()), LogLevel::Error).unwrap();
+        assert_eq!(error.status, "error");
+
+        let warning = event_to_response(AppEvent::Warning("slow".to_string()), LogLevel::Warn).unwrap();
+        assert_eq!(warning.status, "warning");
+        assert!(event_to_response(AppEvent::Warning("slow".to_string()), LogLevel::Error).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_health_and_readiness_endpoints() {
+        let dir = std::env::temp_dir().join(format!("nm_health_{}", std::process::id()));
+        let prompts = dir.join("prompts");
+        std::fs::create_dir_all(&prompts).unwrap();
+        let config = dir.join("config.nm");
+        std::fs::write(&config, "workflow:default\nagent_1: Agent\n").unwrap();
+        let routes = health_routes(std::time::Instant::now(), config.clone(), prompts.clone());
+        let get = |path: &'static str| warp::test::request().method("GET").path(path).reply(&routes);
+
+        let health = get("/healthz").await;
+        assert_eq!(health.status(), 200);
+        let json: serde_json::Value = serde_json::from_slice(health.body()).unwrap();
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert!(json["uptime_seconds"].is_u64());
+
+        let ready = get("/readyz").await;
+        assert_eq!(ready.status(), 200);
+        let json: serde_json::Value = serde_json::from_slice(ready.body()).unwrap();
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["checks"], serde_json::json!({ "config": "ok", "prompts": "ok" }));
+
+        // Without a prompts directory the server is alive but not ready
+        std::fs::remove_dir_all(&prompts).unwrap();
+        let ready = get("/readyz").await;
+        assert_eq!(ready.status(), 503);
+        let json: serde_json::Value = serde_json::from_slice(ready.body()).unwrap();
+        assert_eq!(json["status"], "unavailable");
+        assert_eq!(json["checks"]["config"], "ok");
+        assert_ne!(json["checks"]["prompts"], "ok");
+        assert_eq!(get("/healthz").await.status(), 200);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_rejects_missing_or_wrong_token() {
+        use warp::Filter;
+        let api = |token: Option<&str>| {
+            warp::path!("api" / "metrics")
+                .and(require_token(token.map(str::to_string)))
+                .map(|| "ok")
+                .recover(handle_unauthorized)
+        };
+        let routes = api(Some("s3cret"));
+
+        let missing = warp::test::request().path("/api/metrics").reply(&routes).await;
+        assert_eq!(missing.status(), 401);
+        let json: serde_json::Value = serde_json::from_slice(missing.body()).unwrap();
+        assert_eq!(json["error"], "Missing or invalid auth token");
+        assert_eq!(warp::test::request().path("/api/metrics?token=guess").reply(&routes).await.status(), 401);
+        let wrong_header = warp::test::request().path("/api/metrics").header("authorization", "Bearer s3cre").reply(&routes).await;
+        assert_eq!(wrong_header.status(), 401);
+
+        assert_eq!(warp::test::request().path("/api/metrics?token=s3cret").reply(&routes).await.status(), 200);
+        let header = warp::test::request().path("/api/metrics").header("authorization", "Bearer s3cret").reply(&routes).await;
+        assert_eq!(header.status(), 200);
+        assert_eq!(header.body(), "ok");
+
+        // Auth is off unless a token is configured
+        assert_eq!(warp::test::request().path("/api/metrics").reply(&api(None)).await.status(), 200);
+    }
+
+    #[test]
+    fn test_garbage_command_gets_error_reply() {
+        let Err(reply) = decode_ui_command("this is not json") else {
+            panic!("garbage should not decode");
+        };
+        assert_eq!(reply.status, "error");
+        assert!(reply.data.as_str().unwrap().starts_with("invalid command JSON: "));
+
+        // Well-formed JSON missing required fields is rejected the same way
+        assert!(decode_ui_command(r#"{"command":"submit"}"#).is_err());
+        assert!(decode_ui_command(r#"{"command":"submit","payload":"hi"}"#).is_ok());
+
+        let reply = unknown_command_response("launch_rockets");
+        assert_eq!(reply.status, "error");
+        assert_eq!(reply.data, serde_json::Value::String("unknown command: launch_rockets".to_string()));
+    }
+
+    #[test]
+    fn test_ui_config_reflects_cli_theme() {
+        use clap::Parser;
+        let cli = crate::cli::Cli::parse_from(["neonmachines", "--web", "--theme", "dark"]);
+        let config = UiConfig::from_cli(&cli);
+        assert_eq!(config.theme, "dark");
+        assert_eq!(config.stylesheet, "static/css/main.css");
+        assert_eq!(config.avatar, None);
+
+        let cli = crate::cli::Cli::parse_from(["neonmachines", "web", "--theme", "light", "--avatar", "me.JPG"]);
+        let json = serde_json::to_value(UiConfig::from_cli(&cli)).unwrap();
+        assert_eq!(json["theme"], "light");
+        assert_eq!(json["stylesheet"], "static/css/paper-theme.css");
+        assert_eq!(json["avatar"], "/avatar");
+        assert_eq!(avatar_content_type(Path::new("me.JPG")), "image/jpeg");
+
+        // Anything that isn't an image is refused before the server starts
+        let cli = crate::cli::Cli::parse_from(["neonmachines", "--web", "--avatar", "Cargo.toml"]);
+        assert!(cli.validate().unwrap_err().to_string().contains("Avatar must be an image"));
+        let cli = crate::cli::Cli::parse_from(["neonmachines", "--web", "--theme", "neon"]);
+        assert!(cli.validate().is_err());
+    }
+
+    #[test]
+    fn test_poml_file_paging() {
+        let files: Vec<String> = (0..25).map(|i| format!("prompt_{:02}.poml", i)).chain(["Review.poml".to_string()]).collect();
+        let page = |offset, limit, filter: Option<&str>| {
+            paginate_poml_files(
+                files.clone(),
+                &PomlFileQuery { offset, limit, filter: filter.map(str::to_string) },
+            )
+        };
+
+        let first = page(None, Some(10), None);
+        assert_eq!(first.total, 26);
+        assert_eq!(first.files.len(), 10);
+        assert_eq!(first.files[0], "prompt_00.poml");
+
+        // The last page is short, and paging past the end is empty rather than an error
+        let last = page(Some(20), Some(10), None);
+        assert_eq!(last.files, ["prompt_20.poml", "prompt_21.poml", "prompt_22.poml", "prompt_23.poml", "prompt_24.poml", "Review.poml"]);
+        assert_eq!((last.offset, last.limit, last.total), (20, 10, 26));
+        assert!(page(Some(26), Some(10), None).files.is_empty());
+
+        // The total reflects the filter, which ignores case
+        let filtered = page(Some(1), Some(1), Some("PROMPT_1"));
+        assert_eq!(filtered.total, 10);
+        assert_eq!(filtered.files, ["prompt_11.poml"]);
+        assert_eq!(page(None, None, Some("review")).files, ["Review.poml"]);
+        assert_eq!(page(None, None, None).limit, POML_PAGE_LIMIT);
+    }
 }
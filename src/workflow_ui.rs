@@ -1,16 +1,28 @@
 use crate::nm_config::WorkflowConfig;
-use ratatui::layout::{Rect, Layout};
+use ratatui::layout::{Position, Rect, Layout};
 use ratatui::prelude::{Constraint, Modifier};
 use ratatui::style::{Color, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
 use ratatui::Frame;
 
+/// Index of the workflow entry drawn at (`column`, `row`) when `count` entries are
+/// listed in `area`, the same rectangle passed to `render_workflow`
+pub fn workflow_index_at(area: Rect, count: usize, column: u16, row: u16) -> Option<usize> {
+    // Entries sit one per row inside the list's border
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    if !inner.contains(Position::new(column, row)) {
+        return None;
+    }
+    let index = (row - inner.y) as usize;
+    (index < count).then_some(index)
+}
+
 pub fn render_workflow(f: &mut Frame, workflows: &[WorkflowConfig], index: usize, area: Rect) {
     if workflows.is_empty() {
         // No workflows available
         let empty_text = vec![
-            Line::from("📋 No workflows available"),
+            Line::from(crate::banner::title("📋", "No workflows available")),
             Line::from(""),
             Line::from("Create a new workflow with /create <name>"),
             Line::from(""),
@@ -60,14 +72,14 @@ pub fn render_workflow(f: &mut Frame, workflows: &[WorkflowConfig], index: usize
     let list = List::new(items)
         .block(Block::default()
             .borders(Borders::ALL)
-            .title(format!("🔄 Workflows (Selected: {})", workflows[index].name))
+            .title(crate::banner::title("🔄", &format!("Workflows (Selected: {})", workflows[index].name)))
             .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
     
     f.render_widget(list, area);
     
     // Add navigation instructions at the bottom
     let instructions = vec![
-        Line::from("← → Navigate  |  Enter Select  |  Esc Exit"),
+        Line::from("← → Navigate  |  Enter / double-click Select  |  Esc Exit"),
     ];
     
     let instructions_area = Layout::default()
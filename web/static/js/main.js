@@ -8,7 +8,13 @@ document.addEventListener('DOMContentLoaded', () => {
     const avatarUpload = document.getElementById('avatar-upload');
     const userName = document.getElementById('user-name');
 
-    const socket = new WebSocket('ws://' + location.host + '/ws');
+    // Pass on ?token= from the page URL when the server runs with --auth-token
+    const authToken = new URLSearchParams(location.search).get('token');
+    const withToken = (url) => authToken
+        ? url + (url.includes('?') ? '&' : '?') + 'token=' + encodeURIComponent(authToken)
+        : url;
+
+    const socket = new WebSocket('ws://' + location.host + withToken('/ws'));
 
     socket.onopen = () => addMessage('system', 'Connected to the server.');
     socket.onclose = () => addMessage('system', 'Disconnected from the server.');
@@ -46,6 +52,21 @@ document.addEventListener('DOMContentLoaded', () => {
         }
     }
 
+    // Apply the theme and avatar chosen with --theme / --avatar
+    fetch(withToken('/api/ui-config'))
+        .then(response => response.json())
+        .then(config => {
+            const themeLink = document.getElementById('theme-link');
+            if (themeLink && config.stylesheet) {
+                themeLink.href = config.stylesheet;
+            }
+            // An avatar the user uploaded still wins over the server default
+            if (userAvatar && config.avatar && !localStorage.getItem('userAvatar')) {
+                userAvatar.src = config.avatar;
+            }
+        })
+        .catch(error => console.error('Failed to load UI config:', error));
+
     // Load saved user name from localStorage or set default
     const savedUserName = localStorage.getItem('userName') || 'User';
     if (userName) {
@@ -210,7 +231,7 @@ document.addEventListener('DOMContentLoaded', () => {
             if (savedAvatar) {
                 avatarElement.src = savedAvatar;
             } else {
-                avatarElement.src = 'static/default-avatar.png'; // Default user avatar
+                avatarElement.src = userAvatar ? userAvatar.src : 'static/default-avatar.png'; // Default or --avatar
             }
             avatarElement.alt = 'You';
         } else if (from === 'agent' || from === 'result') {
@@ -495,11 +516,11 @@ document.addEventListener('DOMContentLoaded', () => {
         });
         
         // Also try to load from server
-        fetch('/api/poml-files')
+        fetch(withToken('/api/poml-files?limit=1000'))
             .then(response => response.json())
-            .then(files => {
+            .then(page => {
                 // Add any additional files from server
-                files.forEach(file => {
+                page.files.forEach(file => {
                     if (!defaultFiles.includes(file)) {
                         const option = document.createElement('option');
                         option.value = file;
@@ -688,7 +709,7 @@ workflow:
                 addMessage('system', `Created sample POML file: ${selectedFile}`);
                 
                 // Also try to load from server in background
-                fetch(`/api/load-poml?file=${encodeURIComponent(selectedFile)}`)
+                fetch(withToken(`/api/load-poml?file=${encodeURIComponent(selectedFile)}`))
                     .then(response => response.json())
                     .then(data => {
                         if (!data.error && data.content) {
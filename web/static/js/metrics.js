@@ -2,7 +2,7 @@ document.addEventListener('DOMContentLoaded', () => {
     const metricsData = document.getElementById('metrics-data');
 
     function fetchMetrics() {
-        fetch('/api/metrics')
+        fetch('/api/metrics' + location.search)
             .then(response => response.json())
             .then(data => {
                 metricsData.innerHTML = `
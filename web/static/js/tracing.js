@@ -2,7 +2,7 @@ document.addEventListener('DOMContentLoaded', () => {
     const tracesContainer = document.getElementById('traces-container');
 
     function fetchTraces() {
-        fetch('/api/tracing')
+        fetch('/api/tracing' + location.search)
             .then(response => response.json())
             .then(data => {
                 if (data.length === 0) {